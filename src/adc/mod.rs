@@ -0,0 +1,669 @@
+//! Analog to Digital Converter (ADC)
+//!
+//! ```rust, ignore
+//! let mut adc = Adc::adc1(device.ADC1, &clocks, &mut rcc.ahb);
+//! let vdda_mv = adc.measure_vdda_mv();
+//! let pin = gpioa.PA0.into_analog(&mut gpioa.moder, &mut gpioa.pupdr, &mut syscfg, vdda_mv);
+//!
+//! let value: u16 = block!(adc.read(&mut pin)).unwrap();
+//! ```
+//!
+//! Sampling time and input mode are kept per channel in [`Config`]:
+//!
+//! ```rust, ignore
+//! let config = Config::default()
+//!     .sample_time(SampleTime::Cycles12_5)
+//!     .channel_sample_time::<PA0<Analog>>(SampleTime::Cycles247_5)
+//!     // PC0 against PC1
+//!     .differential::<PC0<Analog>>();
+//! adc.configure(config);
+//! ```
+//!
+//! Differential channel takes both pins of pair, and its result is converted to signed value,
+//! e.g. for bridge sensors:
+//!
+//! ```rust, ignore
+//! let mut bridge = DifferentialPair::new(pc0, pc1);
+//! let sample = adc.read_differential(&mut bridge);
+//! let mv = adc.to_differential_millivolts(sample);
+//! ```
+//!
+//! See Reference manual Ch. 18
+
+use core::ptr;
+
+use cortex_m::asm;
+use embedded_hal::adc::{Channel, OneShot};
+use stm32l4::stm32l4x5::{adc1, ADC1, ADC123_COMMON};
+
+use crate::common::RawRegister;
+use crate::gpio::{Analog, PA0, PA1, PA2, PA3, PA4, PA5, PA6, PA7, PB0, PB1, PC0, PC1, PC2, PC3, PC4, PC5};
+use crate::rcc::{Clocks, AHB};
+
+pub mod scan_dma;
+pub use self::scan_dma::{ScanDma, Trigger};
+
+/// Voltage regulator start-up time in microseconds
+const ADC_VREG_STARTUP_US: u32 = 20;
+
+/// Temperature sensor raw value at 30 °C, VDDA = 3.0 V
+const TS_CAL1: *const u16 = 0x1FFF_75A8 as *const u16;
+/// Temperature sensor raw value at 110 °C, VDDA = 3.0 V
+const TS_CAL2: *const u16 = 0x1FFF_75CA as *const u16;
+/// Internal reference voltage raw value at VDDA = 3.0 V
+const VREFINT_CAL: *const u16 = 0x1FFF_75AA as *const u16;
+/// VDDA at which factory calibration values are taken, in mV
+const VDDA_CAL_MV: u32 = 3000;
+const TS_CAL1_TEMP: f32 = 30.0;
+const TS_CAL2_TEMP: f32 = 110.0;
+
+/// Maximal length of regular sequence
+pub const MAX_SEQUENCE_LEN: usize = 16;
+/// Number of ADC1 channels, including internal ones
+pub const CHANNELS: usize = 19;
+
+/// Sampling time in ADC clock cycles
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum SampleTime {
+    /// 2.5 cycles
+    Cycles2_5 = 0b000,
+    /// 6.5 cycles
+    Cycles6_5 = 0b001,
+    /// 12.5 cycles
+    Cycles12_5 = 0b010,
+    /// 24.5 cycles
+    Cycles24_5 = 0b011,
+    /// 47.5 cycles
+    Cycles47_5 = 0b100,
+    /// 92.5 cycles
+    Cycles92_5 = 0b101,
+    /// 247.5 cycles
+    Cycles247_5 = 0b110,
+    /// 640.5 cycles
+    Cycles640_5 = 0b111,
+}
+
+/// Conversion resolution
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum Resolution {
+    /// 12 bits
+    Bits12 = 0b00,
+    /// 10 bits
+    Bits10 = 0b01,
+    /// 8 bits
+    Bits8 = 0b10,
+    /// 6 bits
+    Bits6 = 0b11,
+}
+
+mod sealed {
+    /// Implemented by HAL only
+    pub trait Sealed {}
+}
+
+/// Channel which can be converted in differential mode.
+///
+/// Negative input of channel `n` is channel `n + 1`.
+///
+/// Note: it is sealed and cannot be implemented outside of HAL.
+pub trait Differential: Channel<ADC1, ID = u8> + sealed::Sealed {
+    /// Pin of negative input
+    type Negative: Channel<ADC1, ID = u8>;
+}
+
+/// Positive and negative pins of differential channel
+pub struct DifferentialPair<P: Differential> {
+    positive: P,
+    negative: P::Negative,
+}
+
+impl<P: Differential> DifferentialPair<P> {
+    /// Pairs `positive` pin with its `negative` counterpart
+    pub fn new(positive: P, negative: P::Negative) -> Self {
+        Self {
+            positive,
+            negative,
+        }
+    }
+
+    /// Returns channel of pair, which is channel of positive input
+    pub fn channel(&self) -> u8 {
+        P::channel()
+    }
+
+    /// Releases pins
+    pub fn free(self) -> (P, P::Negative) {
+        (self.positive, self.negative)
+    }
+}
+
+/// Converts raw differential `sample` of `bits` resolution to signed mV of input difference.
+///
+/// Result is offset by half of full scale, which corresponds to zero difference,
+/// while full scale spans from `-vdda_mv` to `+vdda_mv`.
+pub fn differential_millivolts(sample: u16, bits: u32, vdda_mv: u16) -> i16 {
+    let half = 1i32 << (bits - 1);
+    ((sample as i32 - half) * vdda_mv as i32 / half) as i16
+}
+
+impl_raw_register!(adc1::SMPR1, adc1::SMPR2, adc1::SQR1, adc1::SQR2, adc1::SQR3, adc1::SQR4,);
+
+/// Writes sampling times of all channels into SMPR1 and SMPR2, 10 channels per register.
+fn write_sample_times(smpr: [&dyn RawRegister; 2], sample_times: &[SampleTime; CHANNELS]) {
+    let mut bits = [0u32; 2];
+    for (channel, &sample_time) in sample_times.iter().enumerate() {
+        bits[channel / 10] |= (sample_time as u32) << (3 * (channel % 10));
+    }
+
+    smpr[0].write_bits(bits[0]);
+    smpr[1].write_bits(bits[1]);
+}
+
+/// Replaces sampling time of `channel` in SMPR1 or SMPR2.
+fn write_channel_sample_time(smpr: [&dyn RawRegister; 2], channel: u8, sample_time: SampleTime) {
+    let offset = 3 * (channel as u32 % 10);
+    let register = smpr[channel as usize / 10];
+    register.write_bits((register.read_bits() & !(0b111 << offset)) | ((sample_time as u32) << offset));
+}
+
+/// Writes regular sequence of `channels` into SQR1..SQR4.
+fn write_sequence(sqr: [&dyn RawRegister; 4], channels: &[u8]) {
+    // SQ1..SQ4 in SQR1 after L field, then 5 per register
+    let mut bits = [(channels.len() as u32 - 1), 0, 0, 0];
+    for (idx, &channel) in channels.iter().enumerate() {
+        let position = idx + 1;
+        let offset = 6 * (position % 5) as u32;
+        bits[position / 5] |= (channel as u32) << offset;
+    }
+
+    for (register, bits) in sqr.iter().zip(bits.iter()) {
+        register.write_bits(*bits);
+    }
+}
+
+/// Per-channel configuration
+#[derive(Clone, Copy)]
+pub struct Config {
+    sample_times: [SampleTime; CHANNELS],
+    // DIFSEL bitmap
+    differential: u32,
+}
+
+impl Default for Config {
+    fn default() -> Self {
+        Config {
+            sample_times: [SampleTime::Cycles47_5; CHANNELS],
+            differential: 0,
+        }
+    }
+}
+
+impl Config {
+    /// Sets sampling time of all channels
+    pub fn sample_time(mut self, sample_time: SampleTime) -> Self {
+        self.sample_times = [sample_time; CHANNELS];
+        self
+    }
+
+    /// Sets sampling time of channel connected to `PIN`
+    pub fn channel_sample_time<PIN: Channel<ADC1, ID = u8>>(mut self, sample_time: SampleTime) -> Self {
+        self.sample_times[PIN::channel() as usize] = sample_time;
+        self
+    }
+
+    /// Converts channel connected to `PIN` in differential mode
+    pub fn differential<PIN: Differential>(mut self) -> Self {
+        self.differential |= 1 << PIN::channel();
+        self
+    }
+
+    /// Converts channel connected to `PIN` in single-ended mode
+    pub fn single_ended<PIN: Differential>(mut self) -> Self {
+        self.differential &= !(1 << PIN::channel());
+        self
+    }
+
+    /// Returns sampling time of `channel`
+    pub fn get_sample_time(&self, channel: u8) -> SampleTime {
+        self.sample_times[channel as usize]
+    }
+
+    /// Returns true if `channel` is converted in differential mode
+    pub fn is_differential(&self, channel: u8) -> bool {
+        self.differential & (1 << channel) != 0
+    }
+}
+
+/// Internal reference voltage, ADC1 channel 0
+pub struct VRefInt;
+/// Internal temperature sensor, ADC1 channel 17
+pub struct Temperature;
+/// VBAT/3, ADC1 channel 18
+pub struct VBat;
+
+macro_rules! impl_channels {
+    ($ADC:ident: [$($PIN:ty: $chan:expr,)+]) => {
+        $(
+            impl Channel<$ADC> for $PIN {
+                type ID = u8;
+
+                fn channel() -> u8 {
+                    $chan
+                }
+            }
+        )+
+    };
+    (differential $ADC:ident: [$($PIN:ty: $NEG:ty,)+]) => {
+        $(
+            impl sealed::Sealed for $PIN {}
+
+            impl Differential for $PIN {
+                type Negative = $NEG;
+            }
+        )+
+    };
+}
+
+impl_channels!(ADC1: [
+    VRefInt: 0,
+    PC0<Analog>: 1,
+    PC1<Analog>: 2,
+    PC2<Analog>: 3,
+    PC3<Analog>: 4,
+    PA0<Analog>: 5,
+    PA1<Analog>: 6,
+    PA2<Analog>: 7,
+    PA3<Analog>: 8,
+    PA4<Analog>: 9,
+    PA5<Analog>: 10,
+    PA6<Analog>: 11,
+    PA7<Analog>: 12,
+    PC4<Analog>: 13,
+    PC5<Analog>: 14,
+    PB0<Analog>: 15,
+    PB1<Analog>: 16,
+    Temperature: 17,
+    VBat: 18,
+]);
+
+impl_channels!(differential ADC1: [
+    PC0<Analog>: PC1<Analog>,
+    PC1<Analog>: PC2<Analog>,
+    PC2<Analog>: PC3<Analog>,
+    PC3<Analog>: PA0<Analog>,
+    PA0<Analog>: PA1<Analog>,
+    PA1<Analog>: PA2<Analog>,
+    PA2<Analog>: PA3<Analog>,
+    PA3<Analog>: PA4<Analog>,
+    PA4<Analog>: PA5<Analog>,
+    PA5<Analog>: PA6<Analog>,
+    PA6<Analog>: PA7<Analog>,
+    PA7<Analog>: PC4<Analog>,
+    PC4<Analog>: PC5<Analog>,
+    PC5<Analog>: PB0<Analog>,
+    PB0<Analog>: PB1<Analog>,
+]);
+
+/// Analog to Digital Converter
+pub struct Adc<ADC> {
+    adc: ADC,
+    config: Config,
+    discard_first: bool,
+    vdda_mv: u16,
+}
+
+impl Adc<ADC1> {
+    /// Powers up, calibrates and enables ADC1.
+    ///
+    /// ADC is clocked synchronously by HCLK, divided by 2 unless AHB prescaler is 1.
+    pub fn adc1(adc: ADC1, clocks: &Clocks, ahb: &mut AHB) -> Self {
+        ahb.enr2().modify(|_, w| w.adcen().set_bit());
+        ahb.rstr2().modify(|_, w| w.adcrst().set_bit());
+        ahb.rstr2().modify(|_, w| w.adcrst().clear_bit());
+
+        // HCLK/1 is allowed only when AHB prescaler is 1
+        let ckmode = match clocks.hclk.0 == clocks.sysclk.0 {
+            true => 0b01,
+            false => 0b10,
+        };
+        unsafe { (*ADC123_COMMON::ptr()).ccr.modify(|_, w| w.ckmode().bits(ckmode)) };
+
+        adc.cr.modify(|_, w| w.deeppwd().clear_bit());
+        adc.cr.modify(|_, w| w.advregen().set_bit());
+        asm::delay(clocks.sysclk.0 / 1_000_000 * ADC_VREG_STARTUP_US);
+
+        let mut adc = Self {
+            adc,
+            config: Config::default(),
+            discard_first: false,
+            vdda_mv: VDDA_CAL_MV as u16,
+        };
+        adc.calibrate();
+        adc.enable();
+        adc.write_sample_times();
+
+        adc
+    }
+
+    /// Runs single-ended calibration, and differential one if any channel is differential.
+    ///
+    /// ADC is disabled for duration of calibration.
+    pub fn calibrate(&mut self) {
+        let is_enabled = self.adc.cr.read().aden().bit_is_set();
+        self.disable();
+
+        self.run_calibration(false);
+        if self.config.differential != 0 {
+            self.run_calibration(true);
+        }
+
+        if is_enabled {
+            self.enable();
+        }
+    }
+
+    fn run_calibration(&mut self, differential: bool) {
+        self.adc.cr.modify(|_, w| w.adcaldif().bit(differential));
+        self.adc.cr.modify(|_, w| w.adcal().set_bit());
+        while self.adc.cr.read().adcal().bit_is_set() {}
+    }
+
+    fn enable(&mut self) {
+        // ADRDY is cleared by writing 1
+        self.adc.isr.write(|w| w.adrdy().set_bit());
+        self.adc.cr.modify(|_, w| w.aden().set_bit());
+        while self.adc.isr.read().adrdy().bit_is_clear() {}
+    }
+
+    fn disable(&mut self) {
+        if self.adc.cr.read().aden().bit_is_clear() {
+            return;
+        }
+
+        self.stop_conversion();
+        self.adc.cr.modify(|_, w| w.addis().set_bit());
+        while self.adc.cr.read().aden().bit_is_set() {}
+    }
+
+    pub(crate) fn stop_conversion(&mut self) {
+        if self.adc.cr.read().adstart().bit_is_set() {
+            self.adc.cr.modify(|_, w| w.adstp().set_bit());
+            while self.adc.cr.read().adstp().bit_is_set() {}
+        }
+    }
+
+    /// Applies per-channel configuration.
+    ///
+    /// Changing input mode of any channel requires ADC to be disabled and recalibrated.
+    pub fn configure(&mut self, config: Config) {
+        self.stop_conversion();
+
+        let difsel_changed = config.differential != self.config.differential;
+        self.config = config;
+        if difsel_changed {
+            self.disable();
+            self.adc.difsel.write(|w| unsafe { w.bits(config.differential) });
+            self.calibrate();
+            self.enable();
+        }
+
+        self.write_sample_times();
+    }
+
+    /// Returns current per-channel configuration
+    pub fn config(&self) -> &Config {
+        &self.config
+    }
+
+    /// Sets sampling time of all channels
+    pub fn set_sample_time(&mut self, sample_time: SampleTime) {
+        let config = self.config.sample_time(sample_time);
+        self.configure(config);
+    }
+
+    /// Sets resolution of conversion
+    pub fn set_resolution(&mut self, resolution: Resolution) {
+        self.adc.cfgr.modify(|_, w| unsafe { w.res().bits(resolution as u8) });
+    }
+
+    /// Turns on/off internal reference voltage channel
+    pub fn enable_vref(&mut self, is_on: bool) {
+        unsafe { (*ADC123_COMMON::ptr()).ccr.modify(|_, w| w.vrefen().bit(is_on)) };
+    }
+
+    /// Turns on/off temperature sensor channel
+    pub fn enable_temperature(&mut self, is_on: bool) {
+        unsafe { (*ADC123_COMMON::ptr()).ccr.modify(|_, w| w.tsen().bit(is_on)) };
+    }
+
+    /// Turns on/off VBAT channel
+    pub fn enable_vbat(&mut self, is_on: bool) {
+        unsafe { (*ADC123_COMMON::ptr()).ccr.modify(|_, w| w.vbaten().bit(is_on)) };
+    }
+
+    /// Writes sampling times of all channels from configuration
+    fn write_sample_times(&mut self) {
+        write_sample_times([&self.adc.smpr1, &self.adc.smpr2], &self.config.sample_times);
+    }
+
+    /// Sets sampling time of `channel`
+    fn set_channel_sample_time(&mut self, channel: u8, sample_time: SampleTime) {
+        write_channel_sample_time([&self.adc.smpr1, &self.adc.smpr2], channel, sample_time);
+    }
+
+    /// Configures regular sequence.
+    ///
+    /// # Pancis:
+    ///
+    /// In debug mode the function checks that sequence length is within `1..=MAX_SEQUENCE_LEN`.
+    pub(crate) fn set_sequence(&mut self, channels: &[u8]) {
+        debug_assert!(!channels.is_empty() && channels.len() <= MAX_SEQUENCE_LEN);
+
+        write_sequence([&self.adc.sqr1, &self.adc.sqr2, &self.adc.sqr3, &self.adc.sqr4], channels);
+    }
+
+    /// Returns address of data register, for use with DMA
+    pub(crate) fn data_address(&self) -> u32 {
+        &self.adc.dr as *const _ as u32
+    }
+
+    /// Returns raw ADC1 register block
+    pub(crate) fn inner(&self) -> &ADC1 {
+        &self.adc
+    }
+
+    /// Performs blocking conversion of `channel`.
+    ///
+    /// Result of differential channel is offset by half of full scale.
+    pub fn convert(&mut self, channel: u8) -> u16 {
+        self.set_sequence(&[channel]);
+
+        self.adc.isr.write(|w| w.eoc().set_bit().eos().set_bit().ovr().set_bit());
+        self.adc.cr.modify(|_, w| w.adstart().set_bit());
+        while self.adc.isr.read().eoc().bit_is_clear() {}
+
+        // Reading data register clears EOC
+        self.adc.dr.read().regular_data().bits()
+    }
+
+    /// Converts `channels` in one pass of regular sequence, storing results in `results`.
+    ///
+    /// Sequences longer than `MAX_SEQUENCE_LEN` (half of it when first sample is discarded)
+    /// are split, so sequence registers are written once per chunk rather than per conversion.
+    ///
+    /// # Pancis:
+    ///
+    /// In debug mode the function checks that `results` is as long as `channels`.
+    pub fn read_sequence(&mut self, channels: &[u8], results: &mut [u16]) {
+        debug_assert_eq!(channels.len(), results.len());
+
+        let repeat = if self.discard_first { 2 } else { 1 };
+        let chunk_len = MAX_SEQUENCE_LEN / repeat;
+        self.stop_conversion();
+
+        for (channels, results) in channels.chunks(chunk_len).zip(results.chunks_mut(chunk_len)) {
+            let mut sequence = [0u8; MAX_SEQUENCE_LEN];
+            for (idx, &channel) in channels.iter().enumerate() {
+                for slot in &mut sequence[idx * repeat..(idx + 1) * repeat] {
+                    *slot = channel;
+                }
+            }
+            self.set_sequence(&sequence[..channels.len() * repeat]);
+
+            self.adc.isr.write(|w| w.eoc().set_bit().eos().set_bit().ovr().set_bit());
+            self.adc.cr.modify(|_, w| w.adstart().set_bit());
+            for result in results.iter_mut() {
+                for _ in 0..repeat {
+                    while self.adc.isr.read().eoc().bit_is_clear() {}
+                    *result = self.adc.dr.read().regular_data().bits();
+                }
+            }
+        }
+    }
+
+    /// Discards first sample after each channel switch in `read_sequence`.
+    ///
+    /// Every channel is converted twice, giving sampling capacitor time to settle
+    /// when sources have high impedance.
+    pub fn set_discard_first(&mut self, discard: bool) {
+        self.discard_first = discard;
+    }
+
+    /// Measures actual VDDA in mV with internal reference and its factory calibration.
+    ///
+    /// Measured value is used by subsequent `to_millivolts` conversions.
+    /// Internal reference needs about 12 us to start up after it's enabled.
+    pub fn measure_vdda_mv(&mut self) -> u16 {
+        self.enable_vref(true);
+
+        self.set_channel_sample_time(VRefInt::channel(), SampleTime::Cycles640_5);
+        let vrefint = self.convert(VRefInt::channel()) as u32;
+        self.write_sample_times();
+
+        // VREFINT_CAL is 12 bit value, scale raw reading accordingly
+        let vrefint = vrefint << (2 * self.adc.cfgr.read().res().bits() as u32);
+        let vrefint_cal = unsafe { ptr::read(VREFINT_CAL) } as u32;
+        self.vdda_mv = (VDDA_CAL_MV * vrefint_cal / vrefint.max(1)) as u16;
+
+        self.vdda_mv
+    }
+
+    /// Returns VDDA in mV, either measured last time or 3000 mV by default
+    pub fn vdda_mv(&self) -> u16 {
+        self.vdda_mv
+    }
+
+    /// Converts raw single-ended sample to mV, at current resolution and VDDA
+    pub fn to_millivolts(&self, sample: u16) -> u16 {
+        let bits = 12 - 2 * self.adc.cfgr.read().res().bits() as u32;
+        ((sample as u32 * self.vdda_mv as u32) / ((1 << bits) - 1)) as u16
+    }
+
+    /// Converts raw differential sample to signed mV of input difference, at current resolution and VDDA
+    pub fn to_differential_millivolts(&self, sample: u16) -> i16 {
+        let bits = 12 - 2 * self.adc.cfgr.read().res().bits() as u32;
+        differential_millivolts(sample, bits, self.vdda_mv)
+    }
+
+    /// Performs blocking conversion of differential `pair`, returning raw sample offset by half of full scale.
+    ///
+    /// Channel is switched to differential mode first if needed, which recalibrates ADC,
+    /// so it's better configured in advance with [Config::differential](struct.Config.html#method.differential).
+    pub fn read_differential<P: Differential>(&mut self, pair: &mut DifferentialPair<P>) -> u16 {
+        if !self.config.is_differential(pair.channel()) {
+            let config = self.config.differential::<P>();
+            self.configure(config);
+        }
+
+        self.convert(pair.channel())
+    }
+
+    /// Measures temperature in °C with internal sensor, compensating VDDA with VREFINT.
+    ///
+    /// Temperature sensor and internal reference are enabled if needed, but they need
+    /// about 120 us to start up, so enable them in advance for accurate first reading.
+    /// Conversion uses maximal sampling time as required by sensor.
+    /// Resolution must be 12 bits.
+    pub fn read_temperature(&mut self) -> f32 {
+        self.enable_vref(true);
+        self.enable_temperature(true);
+
+        self.set_channel_sample_time(VRefInt::channel(), SampleTime::Cycles640_5);
+        self.set_channel_sample_time(Temperature::channel(), SampleTime::Cycles640_5);
+        let vrefint = self.convert(VRefInt::channel()) as f32;
+        let raw = self.convert(Temperature::channel()) as f32;
+        self.write_sample_times();
+
+        let (ts_cal1, ts_cal2, vrefint_cal) = unsafe {
+            (ptr::read(TS_CAL1) as f32, ptr::read(TS_CAL2) as f32, ptr::read(VREFINT_CAL) as f32)
+        };
+        // Scale to VDDA = 3.0 V, at which calibration values are taken
+        let raw = raw * vrefint_cal / vrefint;
+
+        (TS_CAL2_TEMP - TS_CAL1_TEMP) / (ts_cal2 - ts_cal1) * (raw - ts_cal1) + TS_CAL1_TEMP
+    }
+
+    /// Disables ADC, its voltage regulator and releases it
+    pub fn free(mut self) -> ADC1 {
+        self.disable();
+        self.adc.cr.modify(|_, w| w.advregen().clear_bit());
+        self.adc
+    }
+}
+
+impl<PIN: Channel<ADC1, ID = u8>> OneShot<ADC1, u16, PIN> for Adc<ADC1> {
+    type Error = ();
+
+    fn read(&mut self, _: &mut PIN) -> nb::Result<u16, ()> {
+        Ok(self.convert(PIN::channel()))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{differential_millivolts, write_channel_sample_time, write_sample_times, write_sequence, SampleTime, CHANNELS};
+    use crate::common::{MockRegister, RawRegister};
+
+    #[test]
+    fn sample_times_layout() {
+        let (smpr1, smpr2) = (MockRegister::default(), MockRegister::default());
+        let mut sample_times = [SampleTime::Cycles2_5; CHANNELS];
+        sample_times[1] = SampleTime::Cycles6_5;
+        sample_times[9] = SampleTime::Cycles640_5;
+        sample_times[10] = SampleTime::Cycles47_5;
+        sample_times[18] = SampleTime::Cycles247_5;
+
+        write_sample_times([&smpr1, &smpr2], &sample_times);
+        assert_eq!(smpr1.read_bits(), 0b001 << 3 | 0b111 << 27);
+        assert_eq!(smpr2.read_bits(), 0b100 | 0b110 << 24);
+
+        write_channel_sample_time([&smpr1, &smpr2], 9, SampleTime::Cycles12_5);
+        write_channel_sample_time([&smpr1, &smpr2], 11, SampleTime::Cycles92_5);
+        assert_eq!(smpr1.read_bits(), 0b001 << 3 | 0b010 << 27);
+        assert_eq!(smpr2.read_bits(), 0b100 | 0b101 << 3 | 0b110 << 24);
+    }
+
+    #[test]
+    fn sequence_layout() {
+        let sqr = [MockRegister::new(0xFFFF_FFFF), MockRegister::default(), MockRegister::default(), MockRegister::default()];
+
+        write_sequence([&sqr[0], &sqr[1], &sqr[2], &sqr[3]], &[5]);
+        assert_eq!(sqr[0].read_bits(), 5 << 6);
+
+        let channels = [1, 2, 3, 4, 5, 6, 7, 8, 9, 10, 11, 12, 13, 14, 15, 16];
+        write_sequence([&sqr[0], &sqr[1], &sqr[2], &sqr[3]], &channels);
+        assert_eq!(sqr[0].read_bits(), 15 | 1 << 6 | 2 << 12 | 3 << 18 | 4 << 24);
+        assert_eq!(sqr[1].read_bits(), 5 | 6 << 6 | 7 << 12 | 8 << 18 | 9 << 24);
+        assert_eq!(sqr[2].read_bits(), 10 | 11 << 6 | 12 << 12 | 13 << 18 | 14 << 24);
+        assert_eq!(sqr[3].read_bits(), 15 | 16 << 6);
+    }
+
+    #[test]
+    fn differential_coding() {
+        assert_eq!(differential_millivolts(2048, 12, 3000), 0);
+        assert_eq!(differential_millivolts(0, 12, 3000), -3000);
+        assert_eq!(differential_millivolts(4095, 12, 3000), 2998);
+        assert_eq!(differential_millivolts(3072, 12, 3300), 1650);
+        assert_eq!(differential_millivolts(64, 8, 3000), -1500);
+    }
+}
@@ -0,0 +1,113 @@
+//! Timer triggered scan of regular sequence into DMA circular buffer
+//!
+//! ```rust, ignore
+//! let buffer = dma::buffer!([[u16; 2 * 3]; 2] = [[0; 2 * 3]; 2]).unwrap();
+//!
+//! timer.trigger_on_update();
+//! let mut scan = ScanDma::new(adc, &[5, 6, 17], Trigger::Tim2Trgo, dma1.C1, buffer);
+//!
+//! // DMA1 channel 1 interrupt
+//! scan.peek(|half, _| process(half))?;
+//! ```
+
+use stm32l4::stm32l4x5::ADC1;
+
+use crate::dma::{dma1, CircBuffer, DmaChannel, Error, Half};
+
+use super::Adc;
+
+/// DMA request of ADC1 on DMA1 channel 1
+const ADC1_DMA_REQUEST: u8 = 0b0000;
+
+/// Trigger of regular conversions (EXTSEL)
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum Trigger {
+    /// TIM1 TRGO
+    Tim1Trgo = 0b1001,
+    /// TIM2 TRGO
+    Tim2Trgo = 0b1011,
+    /// TIM3 TRGO
+    Tim3Trgo = 0b0100,
+    /// TIM4 TRGO
+    Tim4Trgo = 0b1100,
+    /// TIM6 TRGO
+    Tim6Trgo = 0b1101,
+    /// TIM8 TRGO
+    Tim8Trgo = 0b0111,
+    /// TIM15 TRGO
+    Tim15Trgo = 0b1110,
+}
+
+/// Regular sequence scanned on every trigger into circular double buffer.
+///
+/// Each half of buffer, `N` samples, is filled with whole number of sequences,
+/// samples follow order of channels.
+pub struct ScanDma<const N: usize> {
+    adc: Adc<ADC1>,
+    buffer: CircBuffer<u16, N, dma1::C1>,
+}
+
+impl<const N: usize> ScanDma<N> {
+    /// Configures sequence of `channels` and starts waiting for `trigger`.
+    ///
+    /// # Pancis:
+    ///
+    /// In debug mode the function checks that sequence length is within `1..=MAX_SEQUENCE_LEN`.
+    pub fn new(mut adc: Adc<ADC1>, channels: &[u8], trigger: Trigger, mut channel: dma1::C1, buffer: &'static mut [[u16; N]; 2]) -> Self {
+        adc.stop_conversion();
+        adc.set_sequence(channels);
+
+        channel.set_request(ADC1_DMA_REQUEST);
+        let buffer = CircBuffer::new(channel, adc.data_address(), buffer);
+
+        let regs = adc.inner();
+        regs.cfgr.modify(|_, w| unsafe {
+            w.cont().clear_bit()
+             .dmaen().set_bit()
+             // Circular DMA mode
+             .dmacfg().set_bit()
+             .ovrmod().set_bit()
+             .extsel().bits(trigger as u8)
+             // Rising edge
+             .exten().bits(0b01)
+        });
+        regs.isr.write(|w| w.eoc().set_bit().eos().set_bit().ovr().set_bit());
+        regs.cr.modify(|_, w| w.adstart().set_bit());
+
+        Self {
+            adc,
+            buffer,
+        }
+    }
+
+    /// Enables half and full transfer complete interrupts of DMA channel
+    pub fn subscribe(&mut self) {
+        self.buffer.subscribe();
+    }
+
+    /// Disables half and full transfer complete interrupts of DMA channel
+    pub fn unsubscribe(&mut self) {
+        self.buffer.unsubscribe();
+    }
+
+    /// Returns half of buffer, which is ready to be read.
+    pub fn readable_half(&mut self) -> Result<Half, Error> {
+        self.buffer.readable_half()
+    }
+
+    /// Gives access to readable half of buffer.
+    ///
+    /// Returns `Error::Overrun` if DMA has written to the half while it was read.
+    pub fn peek<R, F: FnOnce(&[u16; N], Half) -> R>(&mut self, f: F) -> Result<R, Error> {
+        self.buffer.peek(f)
+    }
+
+    /// Stops conversions and releases resources
+    pub fn stop(mut self) -> (Adc<ADC1>, &'static mut [[u16; N]; 2], dma1::C1) {
+        self.adc.stop_conversion();
+        self.adc.inner().cfgr.modify(|_, w| unsafe { w.exten().bits(0b00).dmaen().clear_bit() });
+        let (buffer, channel) = self.buffer.stop();
+
+        (self.adc, buffer, channel)
+    }
+}
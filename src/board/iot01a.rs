@@ -0,0 +1,181 @@
+//! B-L475E-IOT01A Discovery kit
+//!
+//! To use these definitions, enable the "board-iot01a" feature, and take the board like so:
+//!
+//! ```rust, ignore
+//! use stm32l4x5_hal::board::iot01a::Board;
+//!
+//! let mut board = Board::take().unwrap();
+//! board.leds.1.on();
+//! ```
+//!
+//! Pin assignment follows the user manual UM2153.
+
+use stm32l4::stm32l4x5::{self as device, Peripherals, SPI3};
+
+use embedded_hal::spi::MODE_0;
+
+use crate::common::Constrain;
+use crate::gpio::stm32l475vg::gpio::{self, PE0, PE1, PE7, PE8, PE9};
+use crate::gpio::{Input, Output, Floating, PushPull, Led, PinState, AF4, AF6};
+use crate::gpio::{PA5, PB10, PB11, PB13, PB14, PC10, PC11, PC12, PC13};
+use crate::rcc::{Clocks, AHB, APB1, APB2};
+use crate::spi::Spi;
+use crate::time::Hertz;
+
+/// SPI clock used to talk to the Wi-Fi module.
+pub const WIFI_SPI_FREQ: u32 = 1_000_000;
+
+define_led!(
+    /// User LED1 (green)
+    Led1,
+    PA5<Output<PushPull>>
+);
+
+define_led!(
+    /// User LED2 (green)
+    Led2,
+    PB14<Output<PushPull>>
+);
+
+/// User button B2.
+///
+/// The line is pulled up on the board and reads low while pressed.
+pub type Button = PC13<Input<Floating>>;
+
+/// SPI3 bus connected to the ISM43362 Wi-Fi module.
+pub type WifiSpi = Spi<SPI3, PC10<AF6>, PC11<AF6>, PC12<AF6>>;
+
+/// ISM43362 Wi-Fi module
+pub struct Wifi {
+    /// SPI bus
+    pub spi: WifiSpi,
+    /// Chip select, active low
+    pub nss: PE0<Output<PushPull>>,
+    /// Command/data ready line
+    pub data_ready: PE1<Input<Floating>>,
+    /// Module reset, active low
+    pub reset: PE8<Output<PushPull>>,
+    /// Module wakeup
+    pub wakeup: PB13<Output<PushPull>>,
+}
+
+/// Internal I2C2 bus shared by LIS3MDL, LSM6DSL, HTS221, LPS22HB and VL53L0X.
+pub struct SensorBus {
+    /// I2C2 SCL
+    pub scl: PB10<AF4>,
+    /// I2C2 SDA
+    pub sda: PB11<AF4>,
+}
+
+/// MP34DT01 PDM microphones wired to DFSDM1.
+pub struct Microphone {
+    /// DFSDM1_CKOUT
+    pub clk: PE9<AF6>,
+    /// DFSDM1_DATIN2
+    pub data: PE7<AF6>,
+}
+
+define_remaining!(
+    /// Device peripherals, which are not used by the board
+    Device: device [RCC, FLASH, SPI3, GPIOA, GPIOB, GPIOC, GPIOE] {
+        DAC1, DMA1, DMA2, CRC, LCD, TSC, IWDG, WWDG, COMP, FIREWALL, I2C1, I2C2, I2C3, PWR, SYSCFG, RNG, AES,
+        ADC1, ADC2, ADC3, GPIOD, GPIOH, GPIOF, GPIOG, SAI1, SAI2, TIM2, TIM5, TIM4, TIM3, TIM15, TIM16, TIM17,
+        TIM1, TIM6, TIM7, LPTIM1, LPTIM2, USART1, USART2, USART3, UART5, UART4, LPUART1, SPI1, SPI2, SDMMC,
+        EXTI, VREFBUF, CAN1, RTC, SWPMI1, OPAMP, CRS, USB, QUADSPI, FMC, DFSDM, TIM8, ADC123_COMMON, DBGMCU,
+        FPU, STK, NVIC_STIR, FPU_CPACR, SCB_ACTRL,
+    }
+);
+
+/// Named resources of the B-L475E-IOT01A.
+pub struct Board {
+    /// User LEDs
+    pub leds: (Led1, Led2),
+    /// User button
+    pub button: Button,
+    /// Wi-Fi module
+    pub wifi: Wifi,
+    /// Internal sensor bus
+    pub sensors: SensorBus,
+    /// PDM microphones
+    pub mic: Microphone,
+    /// Frozen clock configuration
+    pub clocks: Clocks,
+    /// AHB register access
+    pub ahb: AHB,
+    /// APB1 register access
+    pub apb1: APB1,
+    /// APB2 register access
+    pub apb2: APB2,
+    /// Remaining device peripherals, e.g. I2C2 for the sensor bus
+    pub device: Device,
+}
+
+impl Board {
+    /// Takes device peripherals and configures board resources.
+    ///
+    /// Clocks are frozen with default configuration (MSI at 4 MHz).
+    ///
+    /// Returns `None` if device peripherals have been already taken.
+    pub fn take() -> Option<Self> {
+        Peripherals::take().map(Self::new)
+    }
+
+    /// Configures board resources out of device peripherals.
+    pub fn new(device: Peripherals) -> Self {
+        // GPIO ports are accessed through HAL parts
+        let ((rcc, flash, spi3, _, _, _, _), device) = Device::split(device);
+        let rcc = rcc.constrain();
+        let mut flash = flash.constrain();
+        let clocks = rcc.cfgr.freeze(&mut flash.acr);
+
+        let mut ahb = rcc.ahb;
+        let mut apb1 = rcc.apb1;
+
+        let mut gpio_a = gpio::A::new(&mut ahb);
+        let mut gpio_b = gpio::B::new(&mut ahb);
+        let mut gpio_c = gpio::C::new(&mut ahb);
+        let mut gpio_e = gpio::E::new(&mut ahb);
+
+        let led1 = gpio_a.PA5.into_output::<PushPull>(&mut gpio_a.moder, &mut gpio_a.otyper);
+        let led2 = gpio_b.PB14.into_output::<PushPull>(&mut gpio_b.moder, &mut gpio_b.otyper);
+
+        let button = gpio_c.PC13.into_input::<Floating>(&mut gpio_c.moder, &mut gpio_c.pupdr);
+
+        let sck = gpio_c.PC10.into_alt_fun::<AF6>(&mut gpio_c.moder, &mut gpio_c.afrh);
+        let miso = gpio_c.PC11.into_alt_fun::<AF6>(&mut gpio_c.moder, &mut gpio_c.afrh);
+        let mosi = gpio_c.PC12.into_alt_fun::<AF6>(&mut gpio_c.moder, &mut gpio_c.afrh);
+        let spi = Spi::new(spi3, (sck, miso, mosi), Hertz(WIFI_SPI_FREQ), MODE_0, &clocks, &mut apb1).unwrap();
+
+        let wifi = Wifi {
+            spi,
+            nss: gpio_e.PE0.into_push_pull_output_with_state(&mut gpio_e.moder, &mut gpio_e.otyper, PinState::High),
+            data_ready: gpio_e.PE1.into_input::<Floating>(&mut gpio_e.moder, &mut gpio_e.pupdr),
+            reset: gpio_e.PE8.into_push_pull_output_with_state(&mut gpio_e.moder, &mut gpio_e.otyper, PinState::High),
+            wakeup: gpio_b.PB13.into_output::<PushPull>(&mut gpio_b.moder, &mut gpio_b.otyper),
+        };
+
+        let sensors = SensorBus {
+            scl: gpio_b.PB10.into_open_drain_alt_fun::<AF4>(&mut gpio_b.moder, &mut gpio_b.otyper, &mut gpio_b.afrh),
+            sda: gpio_b.PB11.into_open_drain_alt_fun::<AF4>(&mut gpio_b.moder, &mut gpio_b.otyper, &mut gpio_b.afrh),
+        };
+
+        let mic = Microphone {
+            clk: gpio_e.PE9.into_alt_fun::<AF6>(&mut gpio_e.moder, &mut gpio_e.afrh),
+            data: gpio_e.PE7.into_alt_fun::<AF6>(&mut gpio_e.moder, &mut gpio_e.afrl),
+        };
+
+        Self {
+            leds: (Led1::new(led1), Led2::new(led2)),
+            button,
+            wifi,
+            sensors,
+            mic,
+            clocks,
+            ahb,
+            apb1,
+            apb2: rcc.apb2,
+            device,
+        }
+    }
+}
@@ -0,0 +1,7 @@
+//! Board support
+//!
+//! Each board lives in its own feature-gated module and maps the named resources of the board
+//! to configured HAL types.
+
+#[cfg(feature = "board-iot01a")]
+pub mod iot01a;
@@ -1,7 +1,115 @@
 //! Common primitives for this HAL
 
+/// Defines struct of peripherals, which remain after `TAKEN` ones are moved out of `$module::Peripherals`.
+///
+/// Generated `split` returns tuple of taken peripherals along with the remaining ones.
+macro_rules! define_remaining {
+    ($(#[$attr:meta])* $Name:ident: $module:ident [$($TAKEN:ident),+] { $($PERIPH:ident,)+ }) => {
+        $(#[$attr])*
+        #[allow(non_snake_case)]
+        pub struct $Name {
+            $(
+                #[allow(missing_docs)]
+                pub $PERIPH: $module::$PERIPH,
+            )+
+        }
+
+        impl $Name {
+            fn split(peripherals: $module::Peripherals) -> (($($module::$TAKEN,)+), Self) {
+                (($(peripherals.$TAKEN,)+), Self { $($PERIPH: peripherals.$PERIPH,)+ })
+            }
+        }
+    };
+}
+
+use embedded_hal::timer::CountDown;
+
 /// Extension trait to constrain the peripheral.
 pub trait Constrain<T> {
     /// Constrains the peripheral to play nicely with the other abstractions
     fn constrain(self) -> T;
 }
+
+/// Peripheral configuration error
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum ConfigError {
+    /// Requested frequency (baud rate) is above what kernel clock allows
+    FrequencyTooHigh,
+    /// Requested frequency (baud rate) is below what kernel clock allows
+    FrequencyTooLow,
+}
+
+/// Plain access to 32-bit register.
+///
+/// Drivers route register arithmetic through it, so that it can be tested on host with `MockRegister`.
+pub(crate) trait RawRegister {
+    /// Reads register value
+    fn read_bits(&self) -> u32;
+
+    /// Writes register value
+    fn write_bits(&self, bits: u32);
+}
+
+/// Implements `RawRegister` for registers of device crate.
+macro_rules! impl_raw_register {
+    ($($REG:ty,)+) => {
+        $(
+            impl crate::common::RawRegister for $REG {
+                #[inline]
+                fn read_bits(&self) -> u32 {
+                    self.read().bits()
+                }
+
+                #[inline]
+                fn write_bits(&self, bits: u32) {
+                    self.write(|w| unsafe { w.bits(bits) });
+                }
+            }
+        )+
+    }
+}
+
+/// Register in memory, which stands for device register in host tests.
+#[cfg(test)]
+#[derive(Default)]
+pub(crate) struct MockRegister(core::cell::Cell<u32>);
+
+#[cfg(test)]
+impl MockRegister {
+    /// Creates register with `bits` as initial value
+    pub(crate) fn new(bits: u32) -> Self {
+        MockRegister(core::cell::Cell::new(bits))
+    }
+}
+
+#[cfg(test)]
+impl RawRegister for MockRegister {
+    fn read_bits(&self) -> u32 {
+        self.0.get()
+    }
+
+    fn write_bits(&self, bits: u32) {
+        self.0.set(bits);
+    }
+}
+
+/// Expiry of transfer timeout, converted into `Timeout` error of driver
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub struct TimedOut;
+
+/// Polls non-blocking `op` until it completes, or `timer` expires.
+///
+/// `timer` must be started with timeout beforehand, it can be shared by several calls
+/// to limit duration of whole transfer.
+pub fn block_timeout<T, E, C, F>(timer: &mut C, mut op: F) -> Result<T, E>
+    where E: From<TimedOut>, C: CountDown, F: FnMut() -> nb::Result<T, E> {
+    loop {
+        match op() {
+            Ok(value) => return Ok(value),
+            Err(nb::Error::Other(error)) => return Err(error),
+            Err(nb::Error::WouldBlock) => if timer.wait().is_ok() {
+                return Err(TimedOut.into());
+            },
+        }
+    }
+}
@@ -0,0 +1,165 @@
+//! Comparators (COMP1, COMP2)
+//!
+//! See Reference manual Ch. 22
+
+use stm32l4::stm32l4x5::COMP;
+
+use crate::rcc::APB2;
+
+/// Non-inverting (plus) input
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum NonInverting {
+    /// COMP1: PC5, COMP2: PB4
+    Io1 = 0,
+    /// COMP1: PB2, COMP2: PB6
+    Io2 = 1,
+}
+
+/// Inverting (minus) input
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum Inverting {
+    /// 1/4 of VREFINT
+    QuarterVref = 0b000,
+    /// 1/2 of VREFINT
+    HalfVref = 0b001,
+    /// 3/4 of VREFINT
+    ThreeQuarterVref = 0b010,
+    /// VREFINT
+    Vref = 0b011,
+    /// DAC channel 1
+    Dac1 = 0b100,
+    /// DAC channel 2
+    Dac2 = 0b101,
+    /// COMP1: PB1, COMP2: PB3
+    Io1 = 0b110,
+    /// COMP1: PC4, COMP2: PB7
+    Io2 = 0b111,
+}
+
+/// Hysteresis
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum Hysteresis {
+    /// No hysteresis
+    None = 0b00,
+    /// Low hysteresis
+    Low = 0b01,
+    /// Medium hysteresis
+    Medium = 0b10,
+    /// High hysteresis
+    High = 0b11,
+}
+
+/// Power mode, trading speed for consumption
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum PowerMode {
+    /// High speed
+    HighSpeed = 0b00,
+    /// Medium speed
+    MediumSpeed = 0b01,
+    /// Ultra low power
+    UltraLowPower = 0b11,
+}
+
+/// Comparator configuration
+#[derive(Clone, Copy)]
+pub struct Config {
+    /// Non-inverting input
+    pub plus: NonInverting,
+    /// Inverting input
+    pub minus: Inverting,
+    /// Hysteresis
+    pub hysteresis: Hysteresis,
+    /// Power mode
+    pub power: PowerMode,
+    /// Inverts output
+    pub inverted: bool,
+}
+
+impl Default for Config {
+    fn default() -> Self {
+        Config {
+            plus: NonInverting::Io1,
+            minus: Inverting::HalfVref,
+            hysteresis: Hysteresis::None,
+            power: PowerMode::HighSpeed,
+            inverted: false,
+        }
+    }
+}
+
+/// Extension trait to split COMP into comparators
+pub trait CompExt {
+    /// Enables comparators clock and splits them
+    fn split(self, apb: &mut APB2) -> Parts;
+}
+
+/// Comparators
+pub struct Parts {
+    /// COMP1
+    pub comp1: Comp1,
+    /// COMP2
+    pub comp2: Comp2,
+}
+
+impl CompExt for COMP {
+    fn split(self, apb: &mut APB2) -> Parts {
+        // Comparators share clock with SYSCFG
+        apb.enr().modify(|_, w| w.syscfgen().set_bit());
+
+        Parts {
+            comp1: Comp1(()),
+            comp2: Comp2(()),
+        }
+    }
+}
+
+macro_rules! impl_comp {
+    ($($COMPx:ident: [$csr:ident; $en:ident, $pwrmode:ident, $inmsel:ident, $inpsel:ident, $polarity:ident, $hyst:ident, $brgen:ident, $scalen:ident, $lock:ident, $value:ident],)+) => {
+        $(
+            /// Comparator
+            pub struct $COMPx(());
+
+            impl $COMPx {
+                /// Configures and enables comparator.
+                pub fn configure(&mut self, config: Config) {
+                    // Scaler of VREFINT is required for all its fractions and bridge for all but VREFINT itself
+                    let scalen = (config.minus as u8) <= (Inverting::Vref as u8);
+                    let brgen = (config.minus as u8) < (Inverting::Vref as u8);
+
+                    unsafe {
+                        (*COMP::ptr()).$csr.write(|w| {
+                            w.$pwrmode().bits(config.power as u8)
+                             .$inmsel().bits(config.minus as u8)
+                             .$inpsel().bit(config.plus == NonInverting::Io2)
+                             .$polarity().bit(config.inverted)
+                             .$hyst().bits(config.hysteresis as u8)
+                             .$brgen().bit(brgen)
+                             .$scalen().bit(scalen)
+                             .$en().set_bit()
+                        });
+                    }
+                }
+
+                /// Turns comparator on/off
+                pub fn enable(&mut self, is_on: bool) {
+                    unsafe { (*COMP::ptr()).$csr.modify(|_, w| w.$en().bit(is_on)) }
+                }
+
+                /// Returns output level
+                pub fn output(&self) -> bool {
+                    unsafe { (*COMP::ptr()).$csr.read().$value().bit_is_set() }
+                }
+
+                /// Locks configuration until system reset
+                pub fn lock(&mut self) {
+                    unsafe { (*COMP::ptr()).$csr.modify(|_, w| w.$lock().set_bit()) }
+                }
+            }
+        )+
+    }
+}
+
+impl_comp!(
+    Comp1: [comp1_csr; comp1_en, comp1_pwrmode, comp1_inmsel, comp1_inpsel, comp1_polarity, comp1_hyst, comp1_brgen, comp1_scalen, comp1_lock, comp1_value],
+    Comp2: [comp2_csr; comp2_en, comp2_pwrmode, comp2_inmsel, comp2_inpsel, comp2_polarity, comp2_hyst, comp2_brgen, comp2_scalen, comp2_lock, comp2_value],
+);
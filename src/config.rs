@@ -10,7 +10,7 @@ use crate::rcc::Clocks;
 ///
 /// SysTick is 24-bit timer.
 /// Reference: http://infocenter.arm.com/help/topic/com.arm.doc.dui0553a/Babieigh.html
-pub const SYST_MAX_RVR: u32 = (1 << 24);
+pub const SYST_MAX_RVR: u32 = 1 << 24;
 
 /// Extension to configure SYST
 pub trait SysClockConfig {
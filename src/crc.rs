@@ -1,17 +1,85 @@
 //!CRC module
 
-use stm32l4::stm32l4x5::CRC as Inner;
+use stm32l4::stm32l4x5::{CRC as Inner, RCC};
+use crate::dma::{DmaChannel, Direction, Event, WordSize};
 use crate::rcc::AHB;
 
+use core::mem;
 use core::ptr;
 use core::ops;
 use core::hash::Hasher;
+use core::sync::atomic::{self, AtomicBool, Ordering};
 
 ///Initial value of polynomial.
 pub const DEFAULT_POLY: u32 = 0x04C11DB7;
 ///Initial value for CRC's INIT
 pub const DEFAULT_INIT: u32 = 0xFFFF_FFFF;
 
+///Whether CRC unit is taken, either by `CRC` instance or by ongoing `checksum`
+static IS_TAKEN: AtomicBool = AtomicBool::new(false);
+
+///CRC-32 (Ethernet) of every nibble, with reflected polynomial 0xEDB88320
+const NIBBLE_TABLE: [u32; 16] = [
+    0x00000000, 0x1DB71064, 0x3B6E20C8, 0x26D930AC, 0x76DC4190, 0x6B6B51F4, 0x4DB26158, 0x5005713C,
+    0xEDB88320, 0xF00F9344, 0xD6D6A3E8, 0xCB61B38C, 0x9B64C2B0, 0x86D3D2D4, 0xA00AE278, 0xBDBDF21C,
+];
+
+///Calculates CRC-32 (Ethernet, zlib) of `data` in software, nibble by nibble.
+///
+///Fallback for when CRC unit is owned elsewhere, it needs no peripheral and 64 bytes of table.
+pub fn software_checksum(data: &[u8]) -> u32 {
+    !data.iter().fold(DEFAULT_INIT, |crc, &byte| {
+        let crc = crc ^ byte as u32;
+        let crc = (crc >> 4) ^ NIBBLE_TABLE[crc as usize & 0xF];
+        (crc >> 4) ^ NIBBLE_TABLE[crc as usize & 0xF]
+    })
+}
+
+///Calculates CRC-32 (Ethernet, zlib) of `data` with CRC unit, borrowed for the call.
+///
+///Returns `None` if CRC unit is not enabled (see [enable](struct.CRC.html#method.enable)),
+///is owned by `CRC` instance or is busy with `checksum` of interrupted context.
+///Afterwards CRC unit is left in its reset configuration.
+pub fn hardware_checksum(data: &[u8]) -> Option<u32> {
+    let is_enabled = unsafe { (*RCC::ptr()).ahb1enr.read().crcen().bit_is_set() };
+    if !is_enabled || IS_TAKEN.swap(true, Ordering::Acquire) {
+        return None;
+    }
+
+    let inner = unsafe { &*Inner::ptr() };
+    // Written through raw pointer, as data is fed in bytes as well as words
+    let dr = unsafe { ptr::addr_of!((*Inner::ptr()).dr) } as *mut u32;
+    inner.pol.write(|w| unsafe { w.polynomialcoefficients().bits(DEFAULT_POLY) });
+    inner.init.write(|w| unsafe { w.crc_init().bits(DEFAULT_INIT) });
+    inner.cr.write(|w| unsafe { w.rev_in().bits(ReverseInput::Byte as u8) }.rev_out().set_bit().reset().set_bit());
+
+    // Words are fed most significant byte first, so they match byte order of data
+    let mut words = data.chunks_exact(4);
+    for word in &mut words {
+        unsafe { ptr::write_volatile(dr, u32::from_be_bytes([word[0], word[1], word[2], word[3]])) };
+    }
+    for &byte in words.remainder() {
+        unsafe { ptr::write_volatile(dr as *mut u8, byte) };
+    }
+    let result = unsafe { ptr::read_volatile(dr) };
+
+    inner.cr.reset();
+    IS_TAKEN.store(false, Ordering::Release);
+    Some(!result)
+}
+
+///Calculates CRC-32 (Ethernet, zlib) of `data`, e.g. for small frames or configuration blocks.
+///
+///CRC unit is used when it is free, otherwise calculation falls back to
+///[software_checksum](fn.software_checksum.html). Either function can be called directly to
+///select implementation at call site.
+pub fn checksum(data: &[u8]) -> u32 {
+    match hardware_checksum(data) {
+        Some(result) => result,
+        None => software_checksum(data),
+    }
+}
+
 #[derive(Copy, Clone)]
 #[repr(u8)]
 ///Describes possible polynomial sizes
@@ -68,10 +136,17 @@ impl CRC {
 
     ///Creates new instance of CRC calculator.
     ///
-    ///Takes ownership over device CRC
-    pub fn new(inner: Inner) -> Self {
-        Self {
-            inner,
+    ///Takes ownership over device CRC, so [checksum](fn.checksum.html) falls back to software
+    ///until it is released.
+    ///
+    ///Returns device CRC back, if it is busy with [hardware_checksum](fn.hardware_checksum.html),
+    ///e.g. when called from interrupt, which preempted the checksum.
+    pub fn new(inner: Inner) -> Result<Self, Inner> {
+        match IS_TAKEN.compare_exchange(false, true, Ordering::Acquire, Ordering::Relaxed) {
+            Ok(_) => Ok(Self {
+                inner,
+            }),
+            Err(_) => Err(inner),
         }
     }
 
@@ -117,17 +192,25 @@ impl CRC {
     ///Retrieves current result
     pub fn result(&self) -> u32 {
         unsafe {
-            ptr::read_volatile(self.dr() as *mut u32)
+            ptr::read_volatile(self.dr() as *const u32)
         }
     }
 
     ///Consumes self and returns device's CRC
     pub fn into_raw(self) -> Inner {
-        self.inner
+        let inner = unsafe { ptr::read(&self.inner) };
+        mem::drop(self);
+        inner
+    }
+
+    fn dr(&self) -> *mut u8 {
+        unsafe { ptr::addr_of!((*Inner::ptr()).dr) as *mut u8 }
     }
+}
 
-    fn dr(&self) -> *const u8 {
-        &self.inner.dr as *const _ as *const u8
+impl Drop for CRC {
+    fn drop(&mut self) {
+        IS_TAKEN.store(false, Ordering::Release);
     }
 }
 
@@ -150,7 +233,7 @@ impl ops::AddAssign<u16> for CRC {
 impl ops::AddAssign<u8> for CRC {
     fn add_assign(&mut self, value: u8) {
         unsafe {
-            ptr::write_volatile(self.dr() as *mut u8, value)
+            ptr::write_volatile(self.dr(), value)
         }
     }
 }
@@ -207,3 +290,149 @@ impl Hasher for CRC {
         *self += value;
     }
 }
+
+///Frame reception error
+#[derive(Copy, Clone, PartialEq, Eq, Debug)]
+pub enum FrameError {
+    ///DMA bus error
+    Transfer,
+    ///CRC of frame doesn't match its trailer
+    Crc,
+}
+
+#[derive(Copy, Clone, PartialEq, Eq)]
+enum FrameState {
+    Idle,
+    Receiving(usize),
+    Checking(usize),
+}
+
+///Receiver of frames with CRC32 trailer, which are validated by CRC unit without CPU copying.
+///
+///Frame is received by peripheral's DMA channel, which must be already configured with
+///request and peripheral address (e.g. `Serial::rx_dma(channel).free()`). Then second channel
+///feeds frame into CRC unit in memory-to-memory mode.
+///
+///Trailer is 32-bit little endian value, compared with CRC result XOR `xor_out`.
+///CRC unit must be configured for the protocol beforehand.
+pub struct FrameReceiver<RX, M2M> {
+    rx: RX,
+    m2m: M2M,
+    crc: CRC,
+    buffer: &'static mut [u8],
+    xor_out: u32,
+    state: FrameState,
+}
+
+impl<RX: DmaChannel, M2M: DmaChannel> FrameReceiver<RX, M2M> {
+    ///Creates receiver, using `buffer` for frames.
+    pub fn new(rx: RX, m2m: M2M, crc: CRC, buffer: &'static mut [u8]) -> Self {
+        Self {
+            rx,
+            m2m,
+            crc,
+            buffer,
+            xor_out: 0,
+            state: FrameState::Idle,
+        }
+    }
+
+    ///Sets value XORed with CRC result, e.g. `0xFFFF_FFFF` for CRC-32 (Ethernet)
+    pub fn with_xor_out(mut self, xor_out: u32) -> Self {
+        self.xor_out = xor_out;
+        self
+    }
+
+    ///Starts reception of frame with `len` bytes, including 4 bytes of trailer.
+    ///
+    ///# Pancis:
+    ///
+    ///In debug mode the function checks that frame fits into buffer and has trailer.
+    pub fn start(&mut self, len: usize) {
+        debug_assert!(len > 4 && len <= self.buffer.len() && len <= u16::max_value() as usize);
+
+        self.rx.stop();
+        // NOTE(unsafe) buffer is owned by receiver, DMA is stopped before it's read
+        unsafe { self.rx.set_memory_address(self.buffer.as_ptr() as u32, true) };
+        self.rx.set_transfer_length(len as u16);
+        self.rx.configure(Direction::PeripheralToMemory, false, WordSize::Byte);
+        self.rx.start();
+
+        self.state = FrameState::Receiving(len);
+    }
+
+    ///Returns payload of received frame once its CRC is validated.
+    ///
+    ///Next frame is not received until [start](#method.start) is called again.
+    pub fn recv_checked(&mut self) -> nb::Result<&[u8], FrameError> {
+        match self.state {
+            FrameState::Idle => Err(nb::Error::WouldBlock),
+            FrameState::Receiving(len) => {
+                if self.rx.is_event(Event::TransferError) {
+                    self.rx.stop();
+                    self.state = FrameState::Idle;
+                    return Err(nb::Error::Other(FrameError::Transfer));
+                } else if !self.rx.is_event(Event::TransferComplete) {
+                    return Err(nb::Error::WouldBlock);
+                }
+                self.rx.stop();
+
+                self.crc.reset();
+                self.m2m.stop();
+                // NOTE(unsafe) buffer is owned by receiver and only read, DR of owned CRC is fed
+                unsafe {
+                    self.m2m.set_memory_address(self.buffer.as_ptr() as u32, true);
+                    self.m2m.set_peripheral_address(self.crc.dr() as u32, false);
+                }
+                self.m2m.set_transfer_length((len - 4) as u16);
+                self.m2m.configure(Direction::MemoryToMemory, false, WordSize::Byte);
+                self.m2m.start();
+
+                self.state = FrameState::Checking(len);
+                Err(nb::Error::WouldBlock)
+            },
+            FrameState::Checking(len) => {
+                if self.m2m.is_event(Event::TransferError) {
+                    self.m2m.stop();
+                    self.state = FrameState::Idle;
+                    return Err(nb::Error::Other(FrameError::Transfer));
+                } else if !self.m2m.is_event(Event::TransferComplete) {
+                    return Err(nb::Error::WouldBlock);
+                }
+                self.m2m.stop();
+                self.state = FrameState::Idle;
+
+                atomic::compiler_fence(Ordering::SeqCst);
+
+                let payload_len = len - 4;
+                let trailer = &self.buffer[payload_len..len];
+                let expected = u32::from(trailer[0]) | u32::from(trailer[1]) << 8 | u32::from(trailer[2]) << 16 | u32::from(trailer[3]) << 24;
+
+                match self.crc.result() ^ self.xor_out == expected {
+                    true => Ok(&self.buffer[..payload_len]),
+                    false => Err(nb::Error::Other(FrameError::Crc)),
+                }
+            },
+        }
+    }
+
+    ///Stops reception and releases its parts
+    pub fn free(mut self) -> (RX, M2M, CRC, &'static mut [u8]) {
+        self.rx.stop();
+        self.m2m.stop();
+
+        (self.rx, self.m2m, self.crc, self.buffer)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::software_checksum;
+
+    #[test]
+    fn software_crc32() {
+        assert_eq!(software_checksum(b""), 0);
+        assert_eq!(software_checksum(b"123456789"), 0xCBF43926);
+        assert_eq!(software_checksum(b"The quick brown fox jumps over the lazy dog"), 0x414FA339);
+    }
+}
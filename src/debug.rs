@@ -0,0 +1,199 @@
+//! Debug support (DBGMCU)
+//!
+//! Watchdogs and timers keep running while core is halted on breakpoint, so IWDG resets MCU
+//! in the middle of debugging session. DBGMCU freezes their counters for the time core is halted:
+//!
+//! ```rust, ignore
+//! let mut debug = DebugMcu::new(device.DBGMCU);
+//! debug.freeze_if_attached();
+//! ```
+//!
+//! Debugger loses connection, once clocks are stopped in Stop and Standby modes.
+//! Low power debug keeps them running during development, and is cleared for production:
+//!
+//! ```rust, ignore
+//! match cfg!(debug_assertions) {
+//!     true => debug.set_low_power_debug(LowPowerDebug::all()),
+//!     false => debug.disable_low_power_debug(),
+//! }
+//! ```
+//!
+//! Settings are kept across system reset, and cleared only by power-on reset.
+//!
+//! See Reference manual, section Debug support (DBG)
+
+use cortex_m::peripheral::DCB;
+use stm32l4::stm32l4x5::DBGMCU;
+
+/// Peripheral, which counter can be frozen while core is halted
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum Freeze {
+    /// Independent watchdog
+    Iwdg,
+    /// Window watchdog
+    Wwdg,
+    /// RTC
+    Rtc,
+    /// Timer TIM1
+    Tim1,
+    /// Timer TIM2
+    Tim2,
+    /// Timer TIM3
+    Tim3,
+    /// Timer TIM4
+    Tim4,
+    /// Timer TIM5
+    Tim5,
+    /// Timer TIM6
+    Tim6,
+    /// Timer TIM7
+    Tim7,
+    /// Timer TIM8
+    Tim8,
+    /// Timer TIM15
+    Tim15,
+    /// Timer TIM16
+    Tim16,
+    /// Timer TIM17
+    Tim17,
+    /// Low power timer LPTIM1
+    Lptim1,
+    /// Low power timer LPTIM2
+    Lptim2,
+    /// SMBus timeout of I2C1
+    I2c1,
+    /// SMBus timeout of I2C2
+    I2c2,
+    /// SMBus timeout of I2C3
+    I2c3,
+    /// CAN1
+    Can1,
+}
+
+/// Low power modes, in which debugger stays connected
+#[derive(Clone, Copy, PartialEq, Eq, Debug, Default)]
+pub struct LowPowerDebug {
+    /// Keeps core clock running in Sleep mode (DBG_SLEEP)
+    pub sleep: bool,
+    /// Keeps core and system clocks running in Stop modes (DBG_STOP)
+    pub stop: bool,
+    /// Keeps digital part powered in Standby mode (DBG_STANDBY)
+    pub standby: bool,
+}
+
+impl LowPowerDebug {
+    /// Debugger stays connected in all low power modes
+    pub const fn all() -> Self {
+        Self {
+            sleep: true,
+            stop: true,
+            standby: true,
+        }
+    }
+}
+
+/// All timers, including low power ones
+const TIMERS: [Freeze; 13] = [
+    Freeze::Tim1, Freeze::Tim2, Freeze::Tim3, Freeze::Tim4, Freeze::Tim5, Freeze::Tim6, Freeze::Tim7,
+    Freeze::Tim8, Freeze::Tim15, Freeze::Tim16, Freeze::Tim17, Freeze::Lptim1, Freeze::Lptim2,
+];
+
+/// Returns whether debugger is attached, i.e. halting debug is enabled (C_DEBUGEN of DHCSR).
+pub fn is_debugger_attached() -> bool {
+    unsafe { (*DCB::PTR).dhcsr.read() & 1 != 0 }
+}
+
+/// MCU debug component
+pub struct DebugMcu {
+    dbgmcu: DBGMCU,
+}
+
+impl DebugMcu {
+    /// Wraps DBGMCU peripheral
+    pub fn new(dbgmcu: DBGMCU) -> Self {
+        Self {
+            dbgmcu,
+        }
+    }
+
+    /// Sets whether counter of `peripheral` is stopped while core is halted.
+    pub fn freeze(&mut self, peripheral: Freeze, is_frozen: bool) {
+        let fzr1 = &self.dbgmcu.apb1_fzr1;
+        let fzr2 = &self.dbgmcu.apb2_fzr;
+        match peripheral {
+            Freeze::Iwdg => fzr1.modify(|_, w| w.dbg_iwdg_stop().bit(is_frozen)),
+            Freeze::Wwdg => fzr1.modify(|_, w| w.dbg_wwdg_stop().bit(is_frozen)),
+            Freeze::Rtc => fzr1.modify(|_, w| w.dbg_rtc_stop().bit(is_frozen)),
+            Freeze::Tim2 => fzr1.modify(|_, w| w.dbg_timer2_stop().bit(is_frozen)),
+            Freeze::Tim3 => fzr1.modify(|_, w| w.dbg_tim3_stop().bit(is_frozen)),
+            Freeze::Tim4 => fzr1.modify(|_, w| w.dbg_tim4_stop().bit(is_frozen)),
+            Freeze::Tim5 => fzr1.modify(|_, w| w.dbg_tim5_stop().bit(is_frozen)),
+            Freeze::Tim6 => fzr1.modify(|_, w| w.dbg_timer6_stop().bit(is_frozen)),
+            Freeze::Tim7 => fzr1.modify(|_, w| w.dbg_tim7_stop().bit(is_frozen)),
+            Freeze::Lptim1 => fzr1.modify(|_, w| w.dbg_lptimer_stop().bit(is_frozen)),
+            Freeze::I2c1 => fzr1.modify(|_, w| w.dbg_i2c1_stop().bit(is_frozen)),
+            Freeze::I2c2 => fzr1.modify(|_, w| w.dbg_i2c2_stop().bit(is_frozen)),
+            Freeze::I2c3 => fzr1.modify(|_, w| w.dbg_i2c3_stop().bit(is_frozen)),
+            Freeze::Can1 => fzr1.modify(|_, w| w.dbg_can_stop().bit(is_frozen)),
+            Freeze::Lptim2 => self.dbgmcu.apb1_fzr2.modify(|_, w| w.dbg_lptim2_stop().bit(is_frozen)),
+            Freeze::Tim1 => fzr2.modify(|_, w| w.dbg_tim1_stop().bit(is_frozen)),
+            Freeze::Tim8 => fzr2.modify(|_, w| w.dbg_tim8_stop().bit(is_frozen)),
+            Freeze::Tim15 => fzr2.modify(|_, w| w.dbg_tim15_stop().bit(is_frozen)),
+            Freeze::Tim16 => fzr2.modify(|_, w| w.dbg_tim16_stop().bit(is_frozen)),
+            Freeze::Tim17 => fzr2.modify(|_, w| w.dbg_tim17_stop().bit(is_frozen)),
+        }
+    }
+
+    /// Sets whether IWDG and WWDG are stopped while core is halted.
+    pub fn freeze_watchdogs(&mut self, is_frozen: bool) {
+        self.freeze(Freeze::Iwdg, is_frozen);
+        self.freeze(Freeze::Wwdg, is_frozen);
+    }
+
+    /// Sets whether all timers, including low power ones, are stopped while core is halted.
+    pub fn freeze_timers(&mut self, is_frozen: bool) {
+        for &timer in TIMERS.iter() {
+            self.freeze(timer, is_frozen);
+        }
+    }
+
+    /// Freezes watchdogs and timers, if debugger is attached.
+    ///
+    /// Returns whether debugger is attached, so production units run unaffected.
+    pub fn freeze_if_attached(&mut self) -> bool {
+        let is_attached = is_debugger_attached();
+        if is_attached {
+            self.freeze_watchdogs(true);
+            self.freeze_timers(true);
+        }
+        is_attached
+    }
+
+    /// Sets low power modes, in which debugger stays connected.
+    ///
+    /// Clocks are kept running in low power modes, increasing consumption.
+    pub fn set_low_power_debug(&mut self, modes: LowPowerDebug) {
+        self.dbgmcu.cr.modify(|_, w| w.dbg_sleep().bit(modes.sleep).dbg_stop().bit(modes.stop).dbg_standby().bit(modes.standby));
+    }
+
+    /// Returns low power modes, in which debugger stays connected
+    pub fn low_power_debug(&self) -> LowPowerDebug {
+        let cr = self.dbgmcu.cr.read();
+        LowPowerDebug {
+            sleep: cr.dbg_sleep().bit_is_set(),
+            stop: cr.dbg_stop().bit_is_set(),
+            standby: cr.dbg_standby().bit_is_set(),
+        }
+    }
+
+    /// Clears low power debug, so consumption in low power modes isn't affected by
+    /// settings left by debugger or previous firmware.
+    pub fn disable_low_power_debug(&mut self) {
+        self.set_low_power_debug(LowPowerDebug::default());
+    }
+
+    /// Releases DBGMCU peripheral
+    pub fn free(self) -> DBGMCU {
+        self.dbgmcu
+    }
+}
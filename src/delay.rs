@@ -2,13 +2,17 @@
 
 use cast::u32;
 use cortex_m::peripheral::syst::SystClkSource;
-use cortex_m::peripheral::SYST;
+use cortex_m::peripheral::{NVIC, SYST};
 use embedded_hal::blocking::delay::{DelayMs, DelayUs};
+use embedded_hal::watchdog::Watchdog;
+use stm32l4::stm32l4x5::{Interrupt, LPTIM1};
 
 use core::cmp;
 
 use crate::config::SYST_MAX_RVR;
-use crate::rcc::Clocks;
+use crate::power;
+use crate::pwm::lptim::ClockSource;
+use crate::rcc::{APB1, CCIPR, Clocks};
 
 /// System timer (SysTick) as a delay provider
 pub struct Delay {
@@ -24,6 +28,15 @@ impl Delay {
         Delay { syst, clocks }
     }
 
+    /// Delays for `ms` milliseconds, feeding `watchdog` every millisecond.
+    pub fn delay_ms_feeding<W: Watchdog>(&mut self, watchdog: &mut W, ms: u32) {
+        for _ in 0..ms {
+            watchdog.feed();
+            self.delay_us(1_000u32);
+        }
+        watchdog.feed();
+    }
+
     /// Releases the system timer (SysTick) resource
     pub fn free(self) -> SYST {
         self.syst
@@ -80,3 +93,93 @@ impl DelayUs<u8> for Delay {
         self.delay_us(u32(us))
     }
 }
+
+/// Low power timer LPTIM1 as a delay provider.
+///
+/// Core sleeps with `WFE` until delay elapses, and LPTIM1 clocked from LSI or LSE keeps counting
+/// when sleep is deepened into Stop mode, which stops SysTick.
+/// Auto-reload match must generate event, while LPTIM1 interrupt stays disabled in NVIC:
+///
+/// ```rust, ignore
+/// power::set_sev_on_pend(&mut core.SCB, true);
+/// let mut delay = LpDelay::lptim1(device.LPTIM1, ClockSource::Lse, &clocks, &mut rcc.apb1, &mut rcc.ccipr);
+/// delay.delay_ms(500u32);
+/// ```
+pub struct LpDelay {
+    tim: LPTIM1,
+    frequency: u32,
+}
+
+impl LpDelay {
+    /// Configures LPTIM1 clocked by `clock` as a delay provider.
+    ///
+    /// # Pancis:
+    ///
+    /// In debug mode the function checks that clock is either LSI or LSE.
+    pub fn lptim1(tim: LPTIM1, clock: ClockSource, clocks: &Clocks, apb: &mut APB1, ccipr: &mut CCIPR) -> Self {
+        debug_assert!(clock == ClockSource::Lsi || clock == ClockSource::Lse);
+
+        ccipr.inner().modify(|_, w| unsafe { w.lptim1sel().bits(clock as u8) });
+        apb.enr1().modify(|_, w| w.lptim1en().set_bit());
+        apb.rstr1().modify(|_, w| w.lptim1rst().set_bit());
+        apb.rstr1().modify(|_, w| w.lptim1rst().clear_bit());
+
+        // CFGR and IER are writable only while disabled
+        tim.cfgr.reset();
+        tim.ier.write(|w| w.arrmie().set_bit());
+        tim.cr.write(|w| w.enable().set_bit());
+
+        Self {
+            tim,
+            frequency: clocks.lptim1_clk().0,
+        }
+    }
+
+    /// Counts `ticks` of kernel clock once, sleeping until auto-reload match.
+    fn wait_ticks(&mut self, ticks: u16) {
+        self.tim.arr.write(|w| unsafe { w.arr().bits(ticks) });
+        while self.tim.isr.read().arrok().bit_is_clear() {}
+        self.tim.icr.write(|w| w.arrokcf().set_bit());
+        self.tim.cr.modify(|_, w| w.sngstrt().set_bit());
+
+        let isr = &self.tim.isr;
+        let _ = power::wait_for_event(|| match isr.read().arrm().bit_is_set() {
+            true => Ok(()),
+            false => Err(nb::Error::<()>::WouldBlock),
+        });
+        self.tim.icr.write(|w| w.arrmcf().set_bit());
+        // Pending interrupt generates event only on its transition to pending
+        NVIC::unpend(Interrupt::LPTIM1);
+    }
+
+    /// Releases LPTIM1, which is disabled
+    pub fn free(self) -> LPTIM1 {
+        self.tim.cr.write(|w| w.enable().clear_bit());
+        self.tim
+    }
+}
+
+impl DelayMs<u32> for LpDelay {
+    fn delay_ms(&mut self, ms: u32) {
+        // Rounded up, so delay is never shorter than requested
+        let mut ticks = (ms as u64 * self.frequency as u64 + 999) / 1_000;
+
+        while ticks != 0 {
+            let current = cmp::min(ticks, 0xFFFF);
+            self.wait_ticks(current as u16);
+            ticks -= current;
+        }
+    }
+}
+
+impl DelayMs<u16> for LpDelay {
+    fn delay_ms(&mut self, ms: u16) {
+        self.delay_ms(u32(ms));
+    }
+}
+
+impl DelayMs<u8> for LpDelay {
+    fn delay_ms(&mut self, ms: u8) {
+        self.delay_ms(u32(ms));
+    }
+}
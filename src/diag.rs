@@ -0,0 +1,266 @@
+//! Reset and panic diagnostics
+//!
+//! Reset cause is decoded from flags in RCC CSR register, see Reference manual Ch. 6.4.29
+//!
+//! Panic message can be preserved across system reset in RTC backup registers:
+//!
+//! ```rust, ignore
+//! #[panic_handler]
+//! fn panic(info: &core::panic::PanicInfo) -> ! {
+//!     stm32l4x5_hal::diag::store_panic(info);
+//!     cortex_m::peripheral::SCB::sys_reset();
+//! }
+//! ```
+//!
+//! And retrieved after reboot with [last_panic](fn.last_panic.html).
+//!
+//! Last registers hold [EventLog](struct.EventLog.html) of application defined event codes,
+//! which survives Standby mode and resets, as long as backup domain is powered, e.g. by VBAT.
+
+use core::cmp;
+use core::fmt::{self, Write};
+use core::panic::PanicInfo;
+use core::ptr;
+
+use stm32l4::stm32l4x5::{PWR, RCC, RTC};
+
+use crate::rcc::CSR;
+
+/// Number of RTC backup registers
+const BKP_REGS: usize = 32;
+/// Marks valid message in first backup register, lower half holds length.
+const PANIC_MAGIC: u32 = 0xDEAD_0000;
+const PANIC_MAGIC_MASK: u32 = 0xFFFF_0000;
+
+/// Number of events kept in event log
+pub const EVENT_LOG_LEN: usize = 8;
+/// First backup register of event log, which holds its header
+const EVENT_LOG_REG: usize = BKP_REGS - 1 - EVENT_LOG_LEN;
+/// Marks valid event log in its header, lower half holds count and index of next slot.
+const EVENT_MAGIC: u32 = 0xE7E7_0000;
+/// Mask of timestamp stored with event
+const EVENT_TIMESTAMP_MASK: u32 = 0x00FF_FFFF;
+
+/// Maximal length of stored panic message in bytes.
+pub const PANIC_MSG_MAX: usize = (EVENT_LOG_REG - 1) * 4;
+
+/// Cause of the last reset
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum ResetCause {
+    /// Illegal Stop, Standby or Shutdown mode entry
+    LowPower,
+    /// Window watchdog
+    WindowWatchdog,
+    /// Independent watchdog
+    IndependentWatchdog,
+    /// Software reset by `SYSRESETREQ`
+    Software,
+    /// Brown out reset, including power-on
+    BrownOut,
+    /// Firewall access violation
+    Firewall,
+    /// Option bytes loading
+    OptionByteLoader,
+    /// External NRST pin
+    Pin,
+    /// No reset flag is set, e.g. flags have been cleared
+    Unknown,
+}
+
+/// Returns cause of the last reset.
+///
+/// As NRST pin is driven low by every internal reset source,
+/// pin reset is reported only when no other flag is set.
+pub fn reset_cause(csr: &mut CSR) -> ResetCause {
+    let csr = csr.inner().read();
+
+    if csr.lpwrstf().bit_is_set() {
+        ResetCause::LowPower
+    } else if csr.wwdgrstf().bit_is_set() {
+        ResetCause::WindowWatchdog
+    } else if csr.iwdgrstf().bit_is_set() {
+        ResetCause::IndependentWatchdog
+    } else if csr.sftrstf().bit_is_set() {
+        ResetCause::Software
+    } else if csr.borrstf().bit_is_set() {
+        ResetCause::BrownOut
+    } else if csr.firewallrstf().bit_is_set() {
+        ResetCause::Firewall
+    } else if csr.oblrstf().bit_is_set() {
+        ResetCause::OptionByteLoader
+    } else if csr.pinrstf().bit_is_set() {
+        ResetCause::Pin
+    } else {
+        ResetCause::Unknown
+    }
+}
+
+/// Clears all reset flags, so next reset cause can be distinguished.
+pub fn clear_reset_flags(csr: &mut CSR) {
+    csr.inner().modify(|_, w| w.rmvf().set_bit());
+}
+
+/// Enables access to RTC backup registers
+fn unlock_backup() {
+    unsafe {
+        (*RCC::ptr()).apb1enr1.modify(|_, w| w.pwren().set_bit().rtcapben().set_bit());
+        (*PWR::ptr()).cr1.modify(|_, w| w.dbp().set_bit());
+    }
+}
+
+fn read_backup(idx: usize) -> u32 {
+    unsafe { ptr::read_volatile((&(*RTC::ptr()).bkp0r as *const _ as *const u32).add(idx)) }
+}
+
+fn write_backup(idx: usize, value: u32) {
+    unsafe { ptr::write_volatile((&(*RTC::ptr()).bkp0r as *const _ as *mut u32).add(idx), value) }
+}
+
+/// Writer that packs bytes into backup registers, truncating the rest.
+struct BackupWriter {
+    len: usize,
+}
+
+impl Write for BackupWriter {
+    fn write_str(&mut self, text: &str) -> fmt::Result {
+        for byte in text.bytes() {
+            if self.len == PANIC_MSG_MAX {
+                break;
+            }
+
+            let idx = 1 + self.len / 4;
+            let shift = (self.len % 4) * 8;
+            let word = read_backup(idx) & !(0xFF << shift);
+            write_backup(idx, word | (byte as u32) << shift);
+            self.len += 1;
+        }
+        Ok(())
+    }
+}
+
+/// Stores panic message in RTC backup registers.
+///
+/// Intended to be called from panic handler, hence it accesses registers directly.
+/// Message is truncated to `PANIC_MSG_MAX` bytes.
+pub fn store_panic(info: &PanicInfo) {
+    unlock_backup();
+
+    let mut writer = BackupWriter { len: 0 };
+    let _ = write!(writer, "{}", info);
+    write_backup(0, PANIC_MAGIC | writer.len as u32);
+}
+
+/// Retrieves panic message stored by [store_panic](fn.store_panic.html) and clears it.
+///
+/// Returns number of bytes written into `buffer`, or `None` if there is no message.
+pub fn last_panic(buffer: &mut [u8]) -> Option<usize> {
+    unlock_backup();
+
+    let header = read_backup(0);
+    if header & PANIC_MAGIC_MASK != PANIC_MAGIC {
+        return None;
+    }
+
+    let len = cmp::min((header & !PANIC_MAGIC_MASK) as usize, PANIC_MSG_MAX);
+    let len = cmp::min(len, buffer.len());
+    for (idx, byte) in buffer[..len].iter_mut().enumerate() {
+        *byte = (read_backup(1 + idx / 4) >> ((idx % 4) * 8)) as u8;
+    }
+
+    write_backup(0, 0);
+    Some(len)
+}
+
+/// Event stored in [EventLog](struct.EventLog.html)
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct Event {
+    /// Application defined code
+    pub code: u8,
+    /// Time of event, lower 24 bits only
+    pub timestamp: u32,
+}
+
+/// Round robin log of events in RTC backup registers, which keeps last `EVENT_LOG_LEN` events.
+///
+/// Timestamp is defined by application, e.g. seconds of [Rtc::epoch](../rtc/struct.Rtc.html#method.epoch),
+/// which wrap around after 194 days in 24 bits:
+///
+/// ```rust, ignore
+/// let mut log = EventLog::new();
+/// for event in log.iter() {
+///     writeln!(console, "{:02x} at {}", event.code, event.timestamp)?;
+/// }
+/// log.record(EVENT_BOOT, rtc.epoch() as u32);
+/// ```
+pub struct EventLog(());
+
+impl EventLog {
+    /// Enables access to backup registers, log is kept as is.
+    pub fn new() -> Self {
+        unlock_backup();
+        EventLog(())
+    }
+
+    /// Returns number of events and index of slot for next event
+    fn header(&self) -> (usize, usize) {
+        let header = read_backup(EVENT_LOG_REG);
+        match header & PANIC_MAGIC_MASK == EVENT_MAGIC {
+            true => {
+                let count = cmp::min((header >> 8 & 0xFF) as usize, EVENT_LOG_LEN);
+                (count, (header & 0xFF) as usize % EVENT_LOG_LEN)
+            },
+            false => (0, 0),
+        }
+    }
+
+    /// Stores event with `code` and `timestamp`, overwriting the oldest one when log is full.
+    pub fn record(&mut self, code: u8, timestamp: u32) {
+        let (count, next) = self.header();
+
+        write_backup(EVENT_LOG_REG + 1 + next, (code as u32) << 24 | timestamp & EVENT_TIMESTAMP_MASK);
+        let count = cmp::min(count + 1, EVENT_LOG_LEN);
+        let next = (next + 1) % EVENT_LOG_LEN;
+        write_backup(EVENT_LOG_REG, EVENT_MAGIC | (count as u32) << 8 | next as u32);
+    }
+
+    /// Returns number of stored events
+    pub fn len(&self) -> usize {
+        self.header().0
+    }
+
+    /// Returns whether log is empty, e.g. after backup domain reset
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    /// Returns event at `index`, counted from the oldest one
+    pub fn get(&self, index: usize) -> Option<Event> {
+        let (count, next) = self.header();
+        if index >= count {
+            return None;
+        }
+
+        let slot = (next + EVENT_LOG_LEN - count + index) % EVENT_LOG_LEN;
+        let value = read_backup(EVENT_LOG_REG + 1 + slot);
+        Some(Event {
+            code: (value >> 24) as u8,
+            timestamp: value & EVENT_TIMESTAMP_MASK,
+        })
+    }
+
+    /// Iterates over events from the oldest one
+    pub fn iter(&self) -> impl Iterator<Item = Event> + '_ {
+        (0..self.len()).filter_map(move |index| self.get(index))
+    }
+
+    /// Removes all events
+    pub fn clear(&mut self) {
+        write_backup(EVENT_LOG_REG, 0);
+    }
+}
+
+impl Default for EventLog {
+    fn default() -> Self {
+        Self::new()
+    }
+}
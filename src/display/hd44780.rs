@@ -0,0 +1,299 @@
+//! HD44780 character LCD in 4-bit mode
+//!
+//! Display is driven by RS, E and D4..D7 pins, completion of instructions is awaited
+//! either with their worst case execution time, or by polling busy flag with RW pin:
+//!
+//! ```rust, ignore
+//! let mut lcd = Hd44780::new(rs, en, (d4, d5, d6, d7), delay);
+//! lcd.set_cursor(0, 1);
+//! write!(lcd, "T = {} C", temperature)?;
+//! ```
+//!
+//! Busy flag polling requires data pins to be open drain outputs, as display drives them
+//! during read. Most displays run at 5 V, so pins must be 5 V tolerant and pulled up externally.
+//!
+//! See Hitachi HD44780U datasheet
+
+use core::fmt;
+
+use embedded_hal::blocking::delay::DelayUs;
+use embedded_hal::digital::{InputPin, OutputPin};
+
+/// Instruction: Clear display
+const CLEAR_DISPLAY: u8 = 0x01;
+/// Instruction: Return home
+const RETURN_HOME: u8 = 0x02;
+/// Instruction: Entry mode set, cursor moves right
+const ENTRY_MODE_INCREMENT: u8 = 0x06;
+/// Instruction: Display on/off control
+const DISPLAY_CONTROL: u8 = 0x08;
+/// Instruction: Function set, 4-bit interface, 2 lines, 5x8 font
+const FUNCTION_SET_4BIT_2LINES: u8 = 0x28;
+/// Instruction: Set CGRAM address
+const SET_CGRAM_ADDRESS: u8 = 0x40;
+/// Instruction: Set DDRAM address
+const SET_DDRAM_ADDRESS: u8 = 0x80;
+
+/// Execution time of clear and home instructions in microseconds
+const LONG_EXECUTION_US: u16 = 1_600;
+/// Execution time of other instructions and data writes in microseconds
+const SHORT_EXECUTION_US: u16 = 50;
+/// Maximal number of busy flag reads, before display is considered not responding
+const MAX_BUSY_POLLS: u32 = 1_000;
+
+/// DDRAM addresses of rows start, for 20 columns displays
+const ROW_OFFSETS: [u8; 4] = [0x00, 0x40, 0x14, 0x54];
+
+/// Data pins D4..D7 of 4-bit interface
+pub trait DataPins {
+    /// Outputs lower 4 bits of `nibble`
+    fn write_nibble(&mut self, nibble: u8);
+}
+
+/// Data pins, which can be read back, i.e. open drain outputs.
+pub trait ReadableDataPins: DataPins {
+    /// Reads level of D7, which carries busy flag
+    fn is_d7_high(&self) -> bool;
+}
+
+impl<D4: OutputPin, D5: OutputPin, D6: OutputPin, D7: OutputPin> DataPins for (D4, D5, D6, D7) {
+    fn write_nibble(&mut self, nibble: u8) {
+        fn set<P: OutputPin>(pin: &mut P, is_high: bool) {
+            match is_high {
+                true => pin.set_high(),
+                false => pin.set_low(),
+            }
+        }
+
+        set(&mut self.0, nibble & 0b0001 != 0);
+        set(&mut self.1, nibble & 0b0010 != 0);
+        set(&mut self.2, nibble & 0b0100 != 0);
+        set(&mut self.3, nibble & 0b1000 != 0);
+    }
+}
+
+impl<D4, D5, D6, D7> ReadableDataPins for (D4, D5, D6, D7)
+where D4: OutputPin + InputPin, D5: OutputPin + InputPin, D6: OutputPin + InputPin, D7: OutputPin + InputPin {
+    fn is_d7_high(&self) -> bool {
+        self.3.is_high()
+    }
+}
+
+/// Completion of instructions is awaited with their execution time
+pub struct Delays;
+
+/// Completion of instructions is awaited by polling busy flag, read with `RW` pin
+pub struct BusyFlag<RW>(RW);
+
+impl<RW> BusyFlag<RW> {
+    /// Releases RW pin
+    pub fn free(self) -> RW {
+        self.0
+    }
+}
+
+mod sealed {
+    pub trait Wait {
+        /// Waits until instruction, which takes at most `us`, completes
+        fn wait(&mut self, us: u16);
+    }
+}
+
+/// Character LCD
+pub struct Hd44780<RS, EN, DATA, DELAY, MODE> {
+    rs: RS,
+    en: EN,
+    data: DATA,
+    delay: DELAY,
+    mode: MODE,
+}
+
+impl<RS: OutputPin, EN: OutputPin, DATA: DataPins, DELAY: DelayUs<u16>> Hd44780<RS, EN, DATA, DELAY, Delays> {
+    /// Initializes display with RW pin tied low.
+    pub fn new(rs: RS, en: EN, data: DATA, delay: DELAY) -> Self {
+        let mut lcd = Self {
+            rs,
+            en,
+            data,
+            delay,
+            mode: Delays,
+        };
+        lcd.init();
+        lcd
+    }
+}
+
+impl<RS, EN, DATA, DELAY, RW> Hd44780<RS, EN, DATA, DELAY, BusyFlag<RW>>
+where RS: OutputPin, EN: OutputPin, DATA: ReadableDataPins, DELAY: DelayUs<u16>, RW: OutputPin {
+    /// Initializes display, which is polled for busy flag with `rw` pin.
+    pub fn with_busy_flag(rs: RS, rw: RW, en: EN, data: DATA, delay: DELAY) -> Self {
+        let mut lcd = Self {
+            rs,
+            en,
+            data,
+            delay,
+            mode: BusyFlag(rw),
+        };
+        lcd.mode.0.set_low();
+        lcd.init();
+        lcd
+    }
+
+    /// Reads busy flag, discarding address counter
+    fn is_busy(&mut self) -> bool {
+        self.rs.set_low();
+        self.mode.0.set_high();
+
+        self.en.set_high();
+        self.delay.delay_us(1);
+        let is_busy = self.data.is_d7_high();
+        self.en.set_low();
+        self.delay.delay_us(1);
+        // Lower nibble of address counter
+        self.en.set_high();
+        self.delay.delay_us(1);
+        self.en.set_low();
+
+        self.mode.0.set_low();
+        is_busy
+    }
+}
+
+impl<RS, EN, DATA: DataPins, DELAY: DelayUs<u16>> sealed::Wait for Hd44780<RS, EN, DATA, DELAY, Delays> {
+    fn wait(&mut self, us: u16) {
+        self.delay.delay_us(us);
+    }
+}
+
+impl<RS, EN, DATA, DELAY, RW> sealed::Wait for Hd44780<RS, EN, DATA, DELAY, BusyFlag<RW>>
+where RS: OutputPin, EN: OutputPin, DATA: ReadableDataPins, DELAY: DelayUs<u16>, RW: OutputPin {
+    fn wait(&mut self, _: u16) {
+        // Release data lines, so display can drive them
+        self.data.write_nibble(0xF);
+        for _ in 0..MAX_BUSY_POLLS {
+            if !self.is_busy() {
+                break;
+            }
+        }
+    }
+}
+
+impl<RS, EN, DATA, DELAY, MODE> Hd44780<RS, EN, DATA, DELAY, MODE>
+where RS: OutputPin, EN: OutputPin, DATA: DataPins, DELAY: DelayUs<u16>, Self: sealed::Wait {
+    /// Latches nibble with enable pulse
+    fn pulse_nibble(&mut self, nibble: u8) {
+        self.data.write_nibble(nibble);
+        self.en.set_high();
+        self.delay.delay_us(1);
+        self.en.set_low();
+        self.delay.delay_us(1);
+    }
+
+    fn write(&mut self, is_data: bool, byte: u8, execution_us: u16) {
+        match is_data {
+            true => self.rs.set_high(),
+            false => self.rs.set_low(),
+        }
+        self.pulse_nibble(byte >> 4);
+        self.pulse_nibble(byte & 0xF);
+        sealed::Wait::wait(self, execution_us);
+    }
+
+    /// Switches display into 4-bit mode, whatever its state is after power on.
+    ///
+    /// Busy flag cannot be checked until interface width is set.
+    fn init(&mut self) {
+        self.rs.set_low();
+        self.en.set_low();
+        // Power on delay
+        self.delay.delay_us(50_000);
+
+        self.pulse_nibble(0x3);
+        self.delay.delay_us(4_500);
+        self.pulse_nibble(0x3);
+        self.delay.delay_us(150);
+        self.pulse_nibble(0x3);
+        self.delay.delay_us(150);
+        self.pulse_nibble(0x2);
+        self.delay.delay_us(150);
+
+        self.command(FUNCTION_SET_4BIT_2LINES);
+        self.set_display(false, false, false);
+        self.clear();
+        self.command(ENTRY_MODE_INCREMENT);
+        self.set_display(true, false, false);
+    }
+
+    /// Sends instruction `byte`
+    pub fn command(&mut self, byte: u8) {
+        self.write(false, byte, SHORT_EXECUTION_US);
+    }
+
+    /// Writes character code `byte` at cursor position
+    pub fn write_byte(&mut self, byte: u8) {
+        self.write(true, byte, SHORT_EXECUTION_US);
+    }
+
+    /// Clears display and returns cursor to top left corner
+    pub fn clear(&mut self) {
+        self.write(false, CLEAR_DISPLAY, LONG_EXECUTION_US);
+    }
+
+    /// Returns cursor to top left corner and undoes display shift
+    pub fn home(&mut self) {
+        self.write(false, RETURN_HOME, LONG_EXECUTION_US);
+    }
+
+    /// Turns display, cursor and its blinking on or off
+    pub fn set_display(&mut self, is_on: bool, has_cursor: bool, is_blinking: bool) {
+        self.command(DISPLAY_CONTROL | (is_on as u8) << 2 | (has_cursor as u8) << 1 | is_blinking as u8);
+    }
+
+    /// Moves cursor to `col` of `row`, rows are laid out as on 20 columns displays.
+    ///
+    /// # Pancis:
+    ///
+    /// In debug mode the function checks that row is less than 4.
+    pub fn set_cursor(&mut self, col: u8, row: u8) {
+        debug_assert!(row < 4);
+
+        self.command(SET_DDRAM_ADDRESS | (ROW_OFFSETS[row as usize & 0b11] + col));
+    }
+
+    /// Defines custom character with code `index`, which is shown by writing that code.
+    ///
+    /// Each byte of `bitmap` is one row of 5 pixels, top row first. Cursor must be placed
+    /// with [set_cursor](#method.set_cursor) afterwards.
+    ///
+    /// # Pancis:
+    ///
+    /// In debug mode the function checks that index is less than 8.
+    pub fn define_char(&mut self, index: u8, bitmap: &[u8; 8]) {
+        debug_assert!(index < 8);
+
+        self.command(SET_CGRAM_ADDRESS | (index & 0b111) << 3);
+        for row in bitmap {
+            self.write_byte(row & 0x1F);
+        }
+    }
+
+    /// Writes `text` at cursor position, ASCII characters map to display character codes.
+    pub fn write_str(&mut self, text: &str) {
+        for byte in text.bytes() {
+            self.write_byte(byte);
+        }
+    }
+
+    /// Releases pins and delay provider
+    pub fn free(self) -> (RS, EN, DATA, DELAY, MODE) {
+        (self.rs, self.en, self.data, self.delay, self.mode)
+    }
+}
+
+impl<RS, EN, DATA, DELAY, MODE> fmt::Write for Hd44780<RS, EN, DATA, DELAY, MODE>
+where RS: OutputPin, EN: OutputPin, DATA: DataPins, DELAY: DelayUs<u16>, Self: sealed::Wait {
+    fn write_str(&mut self, text: &str) -> fmt::Result {
+        Hd44780::write_str(self, text);
+        Ok(())
+    }
+}
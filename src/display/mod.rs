@@ -0,0 +1,6 @@
+//! Drivers of external displays
+//!
+//! Segment LCD controller of the MCU is driven by [lcd](../lcd/index.html) module.
+
+pub mod hd44780;
+pub use self::hd44780::Hd44780;
@@ -0,0 +1,637 @@
+//! Direct Memory Access (DMA)
+//!
+//! Each controller is split into independent channels, which are used by peripheral drivers.
+//!
+//! ```rust, ignore
+//! let channels = device.DMA1.split(&mut rcc.ahb);
+//! ```
+//!
+//! One-shot [Transfer](struct.Transfer.html) accepts any buffer implementing
+//! `ReadBuffer` or `WriteBuffer` of `embedded-dma`, which are re-exported here,
+//! e.g. `&'static mut [u8; N]` or pool boxes, whose memory stays in place while DMA owns it:
+//!
+//! ```rust, ignore
+//! let buffer = dma::buffer!([u8; 64] = [0; 64]).unwrap();
+//! channel.set_request(REQUEST);
+//! let transfer = Transfer::memory_to_peripheral(channel, address, buffer);
+//! let (result, buffer, channel) = transfer.wait();
+//! result?;
+//! ```
+//!
+//! Transfer is stopped when dropped, so DMA never accesses buffer after it's released.
+//!
+//! See Reference manual Ch. 11
+
+use core::mem;
+use core::ptr;
+use core::sync::atomic::{self, Ordering};
+
+pub use embedded_dma::{ReadBuffer, ReadTarget, WriteBuffer, WriteTarget};
+
+use crate::rcc::AHB;
+
+/// DMA error
+#[derive(Copy, Clone, PartialEq, Eq, Debug)]
+pub enum Error {
+    /// Data has been overwritten before it was read
+    Overrun,
+    /// Bus error during transfer, channel is disabled by hardware
+    Transfer,
+}
+
+/// Channel event
+#[derive(Copy, Clone, PartialEq, Eq, Debug)]
+pub enum Event {
+    /// Half of data has been transferred
+    HalfTransfer,
+    /// All data has been transferred
+    TransferComplete,
+    /// Bus error during transfer
+    TransferError,
+}
+
+/// Half of double buffer
+#[derive(Copy, Clone, PartialEq, Eq, Debug)]
+pub enum Half {
+    /// First half
+    First,
+    /// Second half
+    Second,
+}
+
+/// Direction of transfer
+#[derive(Copy, Clone, PartialEq, Eq, Debug)]
+pub enum Direction {
+    /// Read from peripheral into memory
+    PeripheralToMemory,
+    /// Read from memory into peripheral
+    MemoryToPeripheral,
+    /// Read from memory into peripheral address without waiting for requests (MEM2MEM),
+    /// e.g. to feed data into CRC unit
+    MemoryToMemory,
+}
+
+/// Size of single data item
+#[derive(Copy, Clone, PartialEq, Eq, Debug)]
+#[repr(u8)]
+pub enum WordSize {
+    /// 8 bits
+    Byte = 0b00,
+    /// 16 bits
+    HalfWord = 0b01,
+    /// 32 bits
+    Word = 0b10,
+}
+
+impl WordSize {
+    /// Returns number of bytes in data item
+    pub fn bytes(&self) -> usize {
+        1 << (*self as u8)
+    }
+}
+
+mod sealed {
+    /// Implemented by HAL only
+    pub trait Sealed {}
+}
+
+/// Data item, which DMA can transfer.
+///
+/// Note: it is sealed and cannot be implemented outside of HAL.
+///
+/// # Safety
+///
+/// `SIZE` must match size of implementing type, as DMA accesses memory with it.
+pub unsafe trait Word: embedded_dma::Word + sealed::Sealed {
+    /// Size of item
+    const SIZE: WordSize;
+}
+
+macro_rules! impl_word {
+    ($($T:ty: $SIZE:ident,)+) => {
+        $(
+            impl sealed::Sealed for $T {}
+            unsafe impl Word for $T {
+                const SIZE: WordSize = WordSize::$SIZE;
+            }
+        )+
+    }
+}
+
+impl_word!(
+    u8: Byte,
+    i8: Byte,
+    u16: HalfWord,
+    i16: HalfWord,
+    u32: Word,
+    i32: Word,
+);
+
+/// Word aligned storage of [buffer](macro.buffer.html)
+#[doc(hidden)]
+#[repr(C, align(4))]
+pub struct Aligned<T>(pub T);
+
+/// Declares word aligned static buffer and returns it as `&'static mut` exactly once.
+///
+/// Expands to `Option<&'static mut T>`, which is `None` once buffer has been taken,
+/// e.g. when enclosing code runs again. Returned reference is
+/// [WriteBuffer](dma/trait.WriteBuffer.html) and [ReadBuffer](dma/trait.ReadBuffer.html):
+///
+/// ```rust, ignore
+/// let samples = dma::buffer!([[u16; 6]; 2] = [[0; 6]; 2]).unwrap();
+/// let scan = ScanDma::new(adc, &[5, 6, 17], Trigger::Tim2Trgo, dma1.C1, samples);
+/// ```
+#[doc(hidden)]
+#[macro_export]
+macro_rules! __dma_buffer {
+    ($ty:ty = $init:expr) => {
+        $crate::cortex_m::interrupt::free(|_| {
+            static mut TAKEN: bool = false;
+            static mut BUFFER: $crate::dma::Aligned<$ty> = $crate::dma::Aligned($init);
+
+            unsafe {
+                match TAKEN {
+                    true => None,
+                    false => {
+                        TAKEN = true;
+                        let buffer: &'static mut $ty = &mut (*::core::ptr::addr_of_mut!(BUFFER)).0;
+                        Some(buffer)
+                    },
+                }
+            }
+        })
+    };
+}
+
+#[doc(inline)]
+pub use crate::__dma_buffer as buffer;
+
+/// Extension trait to split DMA controller into channels
+pub trait DmaExt {
+    /// Channels of controller
+    type Channels;
+
+    /// Enables and resets controller, then splits it into channels
+    fn split(self, ahb: &mut AHB) -> Self::Channels;
+}
+
+/// DMA channel.
+///
+/// Note: it is sealed and cannot be implemented outside of HAL.
+pub trait DmaChannel: sealed::Sealed {
+    /// Selects peripheral request that is routed to channel (CSELR value).
+    fn set_request(&mut self, request: u8);
+
+    /// Sets address of peripheral register
+    ///
+    /// # Safety
+    ///
+    /// Once started, DMA accesses `address` without any checks, so it must be
+    /// a register, which channel is allowed to access for as long as it runs.
+    unsafe fn set_peripheral_address(&mut self, address: u32, inc: bool);
+
+    /// Sets address of memory
+    ///
+    /// # Safety
+    ///
+    /// Once started, DMA accesses memory at `address` without any checks, so it must be
+    /// valid for the whole transfer and not be accessed otherwise, until channel is stopped.
+    unsafe fn set_memory_address(&mut self, address: u32, inc: bool);
+
+    /// Sets number of data items to transfer
+    fn set_transfer_length(&mut self, len: u16);
+
+    /// Returns number of data items remaining to transfer
+    fn get_ndtr(&self) -> u16;
+
+    /// Configures direction, circular mode and size of data items.
+    ///
+    /// Channel must be stopped.
+    fn configure(&mut self, direction: Direction, circular: bool, size: WordSize);
+
+    /// Starts transfer.
+    fn start(&mut self);
+
+    /// Stops transfer and clears all flags.
+    fn stop(&mut self);
+
+    /// Returns whether channel is enabled.
+    fn is_enabled(&self) -> bool;
+
+    /// Starts listening for an `event`
+    fn subscribe(&mut self, event: Event);
+
+    /// Stops listening for an `event`
+    fn unsubscribe(&mut self, event: Event);
+
+    /// Returns whether `event` has occurred.
+    fn is_event(&self, event: Event) -> bool;
+
+    /// Clears flag of `event`.
+    ///
+    /// Unless cleared, Interrupt handler is going to be continiously called.
+    fn clear_event(&mut self, event: Event);
+
+    /// Returns whether transfer is still ongoing.
+    fn in_progress(&self) -> bool {
+        !self.is_event(Event::TransferComplete)
+    }
+}
+
+/// Double buffer that is continiously filled by DMA channel in circular mode.
+///
+/// While DMA writes one half, the other one can be read. Half-transfer and transfer-complete
+/// events are raised whenever the first and the second halves become readable.
+/// Each half holds `N` items of `W`, whose size is used as transfer width.
+pub struct CircBuffer<W: Word + 'static, const N: usize, C> {
+    buffer: &'static mut [[W; N]; 2],
+    channel: C,
+    readable_half: Half,
+}
+
+impl<W: Word, const N: usize, C: DmaChannel> CircBuffer<W, N, C> {
+    /// Starts circular transfer from peripheral register at `address` into `buffer`.
+    ///
+    /// Channel request must be selected beforehand.
+    ///
+    /// # Pancis:
+    ///
+    /// In debug mode the function checks that buffer has at most 65535 items.
+    pub fn new(mut channel: C, address: u32, buffer: &'static mut [[W; N]; 2]) -> Self {
+        let len = 2 * N;
+        debug_assert!(len <= u16::max_value() as usize);
+
+        channel.stop();
+        // NOTE(unsafe) buffer is owned until stopped, while address is register of peripheral
+        unsafe {
+            channel.set_peripheral_address(address, false);
+            channel.set_memory_address(buffer.as_ptr() as u32, true);
+        }
+        channel.set_transfer_length(len as u16);
+        channel.configure(Direction::PeripheralToMemory, true, W::SIZE);
+
+        atomic::compiler_fence(Ordering::SeqCst);
+
+        channel.start();
+
+        Self {
+            buffer,
+            channel,
+            // First readable half will be the first one after DMA fills it.
+            readable_half: Half::Second,
+        }
+    }
+
+    /// Starts listening for half-transfer and transfer-complete events,
+    /// which are raised whenever one of halves becomes readable.
+    pub fn subscribe(&mut self) {
+        self.channel.subscribe(Event::HalfTransfer);
+        self.channel.subscribe(Event::TransferComplete);
+    }
+
+    /// Stops listening for half-transfer and transfer-complete events.
+    pub fn unsubscribe(&mut self) {
+        self.channel.unsubscribe(Event::HalfTransfer);
+        self.channel.unsubscribe(Event::TransferComplete);
+    }
+
+    /// Returns the half that can be read.
+    ///
+    /// Clears corresponding event, so it is suitable to be called from Interrupt handler.
+    pub fn readable_half(&mut self) -> Result<Half, Error> {
+        if self.channel.is_event(Event::TransferError) {
+            return Err(Error::Transfer);
+        }
+
+        let first_half_is_done = self.channel.is_event(Event::HalfTransfer);
+        let second_half_is_done = self.channel.is_event(Event::TransferComplete);
+
+        if first_half_is_done && second_half_is_done {
+            return Err(Error::Overrun);
+        }
+
+        let last_read_half = self.readable_half;
+
+        Ok(match last_read_half {
+            Half::First => if second_half_is_done {
+                self.channel.clear_event(Event::TransferComplete);
+                self.readable_half = Half::Second;
+                Half::Second
+            } else {
+                last_read_half
+            },
+            Half::Second => if first_half_is_done {
+                self.channel.clear_event(Event::HalfTransfer);
+                self.readable_half = Half::First;
+                Half::First
+            } else {
+                last_read_half
+            },
+        })
+    }
+
+    /// Passes the readable half to `f` without stopping the stream.
+    ///
+    /// Returns `Error::Overrun` if DMA started writing the half while `f` was reading it.
+    pub fn peek<R, F: FnOnce(&[W; N], Half) -> R>(&mut self, f: F) -> Result<R, Error> {
+        let half_being_read = self.readable_half()?;
+
+        atomic::compiler_fence(Ordering::SeqCst);
+
+        let buf = match half_being_read {
+            Half::First => &self.buffer[0],
+            Half::Second => &self.buffer[1],
+        };
+        let result = f(buf, half_being_read);
+
+        atomic::compiler_fence(Ordering::SeqCst);
+
+        let first_half_is_done = self.channel.is_event(Event::HalfTransfer);
+        let second_half_is_done = self.channel.is_event(Event::TransferComplete);
+
+        if (half_being_read == Half::First && second_half_is_done) || (half_being_read == Half::Second && first_half_is_done) {
+            Err(Error::Overrun)
+        } else {
+            Ok(result)
+        }
+    }
+
+    /// Stops the stream and returns buffer with channel.
+    pub fn stop(mut self) -> (&'static mut [[W; N]; 2], C) {
+        self.channel.stop();
+
+        atomic::compiler_fence(Ordering::SeqCst);
+
+        (self.buffer, self.channel)
+    }
+}
+
+/// One-shot transfer between peripheral register and buffer, which DMA owns until stopped.
+///
+/// Dropping transfer stops channel, as buffer is released along with it.
+pub struct Transfer<BUF, C: DmaChannel> {
+    buffer: BUF,
+    channel: C,
+}
+
+impl<BUF, C: DmaChannel> Transfer<BUF, C> {
+    fn start(mut channel: C, address: u32, ptr: u32, len: usize, direction: Direction, size: WordSize, buffer: BUF) -> Self {
+        debug_assert!(len > 0 && len <= u16::max_value() as usize);
+
+        channel.stop();
+        // NOTE(unsafe) buffer is owned until stopped, while address is register of peripheral
+        unsafe {
+            channel.set_peripheral_address(address, false);
+            channel.set_memory_address(ptr, true);
+        }
+        channel.set_transfer_length(len as u16);
+        channel.configure(direction, false, size);
+
+        atomic::compiler_fence(Ordering::SeqCst);
+
+        channel.start();
+
+        Self {
+            buffer,
+            channel,
+        }
+    }
+
+    /// Returns whether all data has been transferred.
+    pub fn is_done(&self) -> Result<bool, Error> {
+        match self.channel.is_event(Event::TransferError) {
+            true => Err(Error::Transfer),
+            false => Ok(!self.channel.in_progress()),
+        }
+    }
+
+    /// Blocks until all data has been transferred or transfer error, returning buffer with channel.
+    pub fn wait(self) -> (Result<(), Error>, BUF, C) {
+        let result = loop {
+            match self.is_done() {
+                Ok(false) => (),
+                Ok(true) => break Ok(()),
+                Err(error) => break Err(error),
+            }
+        };
+
+        let (buffer, channel) = self.stop();
+        (result, buffer, channel)
+    }
+
+    /// Stops transfer, returning buffer with channel.
+    pub fn stop(mut self) -> (BUF, C) {
+        self.channel.stop();
+
+        atomic::compiler_fence(Ordering::SeqCst);
+
+        // Fields are moved out, so Drop must not run
+        let parts = unsafe { (ptr::read(&self.buffer), ptr::read(&self.channel)) };
+        mem::forget(self);
+        parts
+    }
+}
+
+impl<BUF, C: DmaChannel> Drop for Transfer<BUF, C> {
+    fn drop(&mut self) {
+        self.channel.stop();
+
+        atomic::compiler_fence(Ordering::SeqCst);
+    }
+}
+
+impl<BUF: WriteBuffer<Word = W>, W: Word, C: DmaChannel> Transfer<BUF, C> {
+    /// Starts reading peripheral register at `address` into `buffer`.
+    ///
+    /// Channel request must be selected beforehand.
+    ///
+    /// # Pancis:
+    ///
+    /// In debug mode the function checks that buffer has between 1 and 65535 items.
+    pub fn peripheral_to_memory(channel: C, address: u32, mut buffer: BUF) -> Self {
+        let (ptr, len) = unsafe { buffer.write_buffer() };
+        Self::start(channel, address, ptr as u32, len, Direction::PeripheralToMemory, W::SIZE, buffer)
+    }
+}
+
+impl<BUF: ReadBuffer<Word = W>, W: Word, C: DmaChannel> Transfer<BUF, C> {
+    /// Starts writing `buffer` into peripheral register at `address`.
+    ///
+    /// Channel request must be selected beforehand.
+    ///
+    /// # Pancis:
+    ///
+    /// In debug mode the function checks that buffer has between 1 and 65535 items.
+    pub fn memory_to_peripheral(channel: C, address: u32, buffer: BUF) -> Self {
+        let (ptr, len) = unsafe { buffer.read_buffer() };
+        Self::start(channel, address, ptr as u32, len, Direction::MemoryToPeripheral, W::SIZE, buffer)
+    }
+}
+
+macro_rules! impl_dma {
+    ($($DMAX:ident: ($dmax:ident, $dmaxen:ident, $dmaxrst:ident, {
+        $($CX:ident: (
+            $ccrX:ident, $cndtrX:ident, $cparX:ident, $cmarX:ident, $cXs:ident,
+            $htifX:ident, $tcifX:ident, $teifX:ident,
+            $chtifX:ident, $ctcifX:ident, $cteifX:ident, $cgifX:ident
+        ),)+
+    }),)+) => {
+        $(
+            /// Channels of DMA controller
+            pub mod $dmax {
+                use stm32l4::stm32l4x5::$DMAX;
+
+                use crate::rcc::AHB;
+                use super::{sealed, DmaExt, DmaChannel, Direction, Event, WordSize};
+
+                #[allow(non_snake_case)]
+                /// All channels of controller
+                pub struct Channels {
+                    $(
+                        /// Channel
+                        pub $CX: $CX,
+                    )+
+                }
+
+                $(
+                    /// DMA channel
+                    pub struct $CX(());
+
+                    impl sealed::Sealed for $CX {}
+
+                    impl DmaChannel for $CX {
+                        fn set_request(&mut self, request: u8) {
+                            unsafe { (*$DMAX::ptr()).cselr.modify(|_, w| w.$cXs().bits(request)) }
+                        }
+
+                        unsafe fn set_peripheral_address(&mut self, address: u32, inc: bool) {
+                            (*$DMAX::ptr()).$cparX.write(|w| w.pa().bits(address));
+                            (*$DMAX::ptr()).$ccrX.modify(|_, w| w.pinc().bit(inc));
+                        }
+
+                        unsafe fn set_memory_address(&mut self, address: u32, inc: bool) {
+                            (*$DMAX::ptr()).$cmarX.write(|w| w.ma().bits(address));
+                            (*$DMAX::ptr()).$ccrX.modify(|_, w| w.minc().bit(inc));
+                        }
+
+                        fn set_transfer_length(&mut self, len: u16) {
+                            unsafe { (*$DMAX::ptr()).$cndtrX.write(|w| w.ndt().bits(len)) }
+                        }
+
+                        fn get_ndtr(&self) -> u16 {
+                            // NOTE(unsafe) atomic read with no side effects
+                            unsafe { (*$DMAX::ptr()).$cndtrX.read().ndt().bits() }
+                        }
+
+                        fn configure(&mut self, direction: Direction, circular: bool, size: WordSize) {
+                            unsafe {
+                                (*$DMAX::ptr()).$ccrX.modify(|_, w| {
+                                    w.mem2mem().bit(direction == Direction::MemoryToMemory)
+                                     .pl().medium()
+                                     .msize().bits(size as u8)
+                                     .psize().bits(size as u8)
+                                     .circ().bit(circular)
+                                     .dir().bit(direction != Direction::PeripheralToMemory)
+                                })
+                            }
+                        }
+
+                        fn start(&mut self) {
+                            unsafe { (*$DMAX::ptr()).$ccrX.modify(|_, w| w.en().set_bit()) }
+                        }
+
+                        fn stop(&mut self) {
+                            unsafe {
+                                (*$DMAX::ptr()).$ccrX.modify(|_, w| w.en().clear_bit());
+                                (*$DMAX::ptr()).ifcr.write(|w| w.$cgifX().set_bit());
+                            }
+                        }
+
+                        fn is_enabled(&self) -> bool {
+                            unsafe { (*$DMAX::ptr()).$ccrX.read().en().bit_is_set() }
+                        }
+
+                        fn subscribe(&mut self, event: Event) {
+                            unsafe {
+                                (*$DMAX::ptr()).$ccrX.modify(|_, w| match event {
+                                    Event::HalfTransfer => w.htie().set_bit(),
+                                    Event::TransferComplete => w.tcie().set_bit(),
+                                    Event::TransferError => w.teie().set_bit(),
+                                })
+                            }
+                        }
+
+                        fn unsubscribe(&mut self, event: Event) {
+                            unsafe {
+                                (*$DMAX::ptr()).$ccrX.modify(|_, w| match event {
+                                    Event::HalfTransfer => w.htie().clear_bit(),
+                                    Event::TransferComplete => w.tcie().clear_bit(),
+                                    Event::TransferError => w.teie().clear_bit(),
+                                })
+                            }
+                        }
+
+                        fn is_event(&self, event: Event) -> bool {
+                            // NOTE(unsafe) atomic read with no side effects
+                            let isr = unsafe { (*$DMAX::ptr()).isr.read() };
+                            match event {
+                                Event::HalfTransfer => isr.$htifX().bit_is_set(),
+                                Event::TransferComplete => isr.$tcifX().bit_is_set(),
+                                Event::TransferError => isr.$teifX().bit_is_set(),
+                            }
+                        }
+
+                        fn clear_event(&mut self, event: Event) {
+                            // NOTE(unsafe) atomic write to a stateless register
+                            unsafe {
+                                (*$DMAX::ptr()).ifcr.write(|w| match event {
+                                    Event::HalfTransfer => w.$chtifX().set_bit(),
+                                    Event::TransferComplete => w.$ctcifX().set_bit(),
+                                    Event::TransferError => w.$cteifX().set_bit(),
+                                })
+                            }
+                        }
+                    }
+                )+
+
+                impl DmaExt for $DMAX {
+                    type Channels = Channels;
+
+                    fn split(self, ahb: &mut AHB) -> Channels {
+                        ahb.enr1().modify(|_, w| w.$dmaxen().set_bit());
+                        ahb.rstr1().modify(|_, w| w.$dmaxrst().set_bit());
+                        ahb.rstr1().modify(|_, w| w.$dmaxrst().clear_bit());
+
+                        Channels {
+                            $(
+                                $CX: $CX(()),
+                            )+
+                        }
+                    }
+                }
+            }
+        )+
+    }
+}
+
+impl_dma!(
+    DMA1: (dma1, dma1en, dma1rst, {
+        C1: (ccr1, cndtr1, cpar1, cmar1, c1s, htif1, tcif1, teif1, chtif1, ctcif1, cteif1, cgif1),
+        C2: (ccr2, cndtr2, cpar2, cmar2, c2s, htif2, tcif2, teif2, chtif2, ctcif2, cteif2, cgif2),
+        C3: (ccr3, cndtr3, cpar3, cmar3, c3s, htif3, tcif3, teif3, chtif3, ctcif3, cteif3, cgif3),
+        C4: (ccr4, cndtr4, cpar4, cmar4, c4s, htif4, tcif4, teif4, chtif4, ctcif4, cteif4, cgif4),
+        C5: (ccr5, cndtr5, cpar5, cmar5, c5s, htif5, tcif5, teif5, chtif5, ctcif5, cteif5, cgif5),
+        C6: (ccr6, cndtr6, cpar6, cmar6, c6s, htif6, tcif6, teif6, chtif6, ctcif6, cteif6, cgif6),
+        C7: (ccr7, cndtr7, cpar7, cmar7, c7s, htif7, tcif7, teif7, chtif7, ctcif7, cteif7, cgif7),
+    }),
+    DMA2: (dma2, dma2en, dma2rst, {
+        C1: (ccr1, cndtr1, cpar1, cmar1, c1s, htif1, tcif1, teif1, chtif1, ctcif1, cteif1, cgif1),
+        C2: (ccr2, cndtr2, cpar2, cmar2, c2s, htif2, tcif2, teif2, chtif2, ctcif2, cteif2, cgif2),
+        C3: (ccr3, cndtr3, cpar3, cmar3, c3s, htif3, tcif3, teif3, chtif3, ctcif3, cteif3, cgif3),
+        C4: (ccr4, cndtr4, cpar4, cmar4, c4s, htif4, tcif4, teif4, chtif4, ctcif4, cteif4, cgif4),
+        C5: (ccr5, cndtr5, cpar5, cmar5, c5s, htif5, tcif5, teif5, chtif5, ctcif5, cteif5, cgif5),
+        C6: (ccr6, cndtr6, cpar6, cmar6, c6s, htif6, tcif6, teif6, chtif6, ctcif6, cteif6, cgif6),
+        C7: (ccr7, cndtr7, cpar7, cmar7, c7s, htif7, tcif7, teif7, chtif7, ctcif7, cteif7, cgif7),
+    }),
+);
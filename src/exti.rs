@@ -0,0 +1,187 @@
+//! Extended interrupts and events controller (EXTI)
+//!
+//! GPIO lines 5..=9 and 10..=15 share interrupts, so [Dispatcher](struct.Dispatcher.html)
+//! resolves pending lines and calls handler registered for each of them:
+//!
+//! ```rust, ignore
+//! fn on_button(line: u8) {
+//!     ...
+//! }
+//!
+//! button.make_interrupt_source(&mut syscfg);
+//! let mut exti = Dispatcher::new(device.EXTI);
+//! exti.register(13, Edge::Falling, on_button).unwrap();
+//! exti.listen(7, Edge::Both);
+//!
+//! #[interrupt]
+//! fn EXTI15_10() {
+//!     exti::dispatch(exti::LINES_15_10);
+//! }
+//!
+//! #[interrupt]
+//! fn EXTI9_5() {
+//!     exti::dispatch(exti::LINES_9_5);
+//! }
+//!
+//! // Line 7 has no handler, so it is only flagged
+//! if exti::take_pending(7) {
+//!     ...
+//! }
+//! ```
+//!
+//! See Reference manual Ch. 14
+
+use core::cell::Cell;
+
+use cortex_m::interrupt::{self, Mutex};
+use stm32l4::stm32l4x5::EXTI;
+
+/// Number of GPIO lines
+pub const GPIO_LINES: u8 = 16;
+/// GPIO lines 5..=9, sharing `EXTI9_5` interrupt
+pub const LINES_9_5: u16 = 0x03E0;
+/// GPIO lines 10..=15, sharing `EXTI15_10` interrupt
+pub const LINES_15_10: u16 = 0xFC00;
+
+/// Active edge of line
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum Edge {
+    /// Rising edge
+    Rising,
+    /// Falling edge
+    Falling,
+    /// Both edges
+    Both,
+}
+
+/// Handler of line, called with its number
+pub type Handler = fn(u8);
+
+static HANDLERS: Mutex<Cell<[Option<Handler>; GPIO_LINES as usize]>> = Mutex::new(Cell::new([None; GPIO_LINES as usize]));
+/// Lines, which have been triggered without handler
+static PENDING: Mutex<Cell<u16>> = Mutex::new(Cell::new(0));
+
+/// Owner of GPIO lines of EXTI
+pub struct Dispatcher {
+    exti: EXTI,
+}
+
+impl Dispatcher {
+    /// Takes EXTI, no line is listened to.
+    pub fn new(exti: EXTI) -> Self {
+        Self {
+            exti,
+        }
+    }
+
+    /// Unmasks interrupt of `line` triggered on `edge`, without handler.
+    ///
+    /// Triggers are recorded by [dispatch](fn.dispatch.html) and checked with [take_pending](fn.take_pending.html).
+    ///
+    /// # Pancis:
+    ///
+    /// In debug mode the function checks that `line` is GPIO line.
+    pub fn listen(&mut self, line: u8, edge: Edge) {
+        debug_assert!(line < GPIO_LINES);
+
+        let bit = 1 << line;
+        let (rising, falling) = match edge {
+            Edge::Rising => (bit, 0),
+            Edge::Falling => (0, bit),
+            Edge::Both => (bit, bit),
+        };
+        self.exti.rtsr1.modify(|r, w| unsafe { w.bits(r.bits() & !bit | rising) });
+        self.exti.ftsr1.modify(|r, w| unsafe { w.bits(r.bits() & !bit | falling) });
+        self.exti.pr1.write(|w| unsafe { w.bits(bit) });
+        self.exti.imr1.modify(|r, w| unsafe { w.bits(r.bits() | bit) });
+    }
+
+    /// Masks interrupt of `line` and drops its handler.
+    pub fn unlisten(&mut self, line: u8) {
+        debug_assert!(line < GPIO_LINES);
+
+        self.exti.imr1.modify(|r, w| unsafe { w.bits(r.bits() & !(1 << line)) });
+        set_handler(line, None);
+    }
+
+    /// Listens to `line` triggered on `edge`, calling `handler` from [dispatch](fn.dispatch.html).
+    ///
+    /// Returns `handler` back if another one is registered for `line`.
+    pub fn register(&mut self, line: u8, edge: Edge, handler: Handler) -> Result<(), Handler> {
+        debug_assert!(line < GPIO_LINES);
+
+        let is_free = interrupt::free(|cs| {
+            let cell = HANDLERS.borrow(cs);
+            let mut handlers = cell.get();
+            match handlers[line as usize] {
+                Some(_) => false,
+                None => {
+                    handlers[line as usize] = Some(handler);
+                    cell.set(handlers);
+                    true
+                },
+            }
+        });
+
+        match is_free {
+            true => {
+                self.listen(line, edge);
+                Ok(())
+            },
+            false => Err(handler),
+        }
+    }
+
+    /// Masks all GPIO lines and releases EXTI
+    pub fn free(self) -> EXTI {
+        self.exti.imr1.modify(|r, w| unsafe { w.bits(r.bits() & !0xFFFF) });
+        interrupt::free(|cs| HANDLERS.borrow(cs).set([None; GPIO_LINES as usize]));
+        self.exti
+    }
+}
+
+fn set_handler(line: u8, handler: Option<Handler>) {
+    interrupt::free(|cs| {
+        let cell = HANDLERS.borrow(cs);
+        let mut handlers = cell.get();
+        handlers[line as usize] = handler;
+        cell.set(handlers);
+    })
+}
+
+/// Clears pending lines within `lines` mask and calls their handlers, in order of line number.
+///
+/// To be called from EXTI interrupt, with mask of lines sharing it, e.g. `LINES_9_5` or `1 << 0`.
+/// Lines without handler are recorded for [take_pending](fn.take_pending.html).
+pub fn dispatch(lines: u16) {
+    let exti = unsafe { &*EXTI::ptr() };
+    let pending = exti.pr1.read().bits() & exti.imr1.read().bits() & lines as u32;
+    // Clear before handling, so edges occurring meanwhile are not lost
+    exti.pr1.write(|w| unsafe { w.bits(pending) });
+
+    let handlers = interrupt::free(|cs| HANDLERS.borrow(cs).get());
+    let mut unhandled = 0;
+    for line in (0..GPIO_LINES).filter(|line| pending & (1 << line) != 0) {
+        match handlers[line as usize] {
+            Some(handler) => handler(line),
+            None => unhandled |= 1 << line,
+        }
+    }
+
+    if unhandled != 0 {
+        interrupt::free(|cs| {
+            let cell = PENDING.borrow(cs);
+            cell.set(cell.get() | unhandled);
+        });
+    }
+}
+
+/// Returns whether `line` without handler has been triggered since last call, clearing the flag.
+pub fn take_pending(line: u8) -> bool {
+    interrupt::free(|cs| {
+        let cell = PENDING.borrow(cs);
+        let pending = cell.get();
+        cell.set(pending & !(1 << line));
+        pending & (1 << line) != 0
+    })
+}
@@ -0,0 +1,337 @@
+//! Production test harness
+//!
+//! Runs board level checks in factory test mode: GPIO walking-bit test over given pins,
+//! start of crystals, and reads device signature. Results are streamed as text over UART:
+//!
+//! ```rust, ignore
+//! let pins = [TestPin::of(&gpio_a.PA0), TestPin::of(&gpio_a.PA1), TestPin::of(&gpio_b.PB6)];
+//! let report = factory::run(&pins, Some(HighSpeedExternalOSC::new(8_000_000)), &mut rcc.bdcr);
+//! factory::stream(&mut serial, &report)?;
+//! ```
+//!
+//! Which prints:
+//!
+//! ```text
+//! UID: 00200029 4D4B5008 20373352
+//! FLASH: 1024 KiB
+//! GPIO: OK
+//! LSE: OK
+//! HSE: FAIL
+//! ```
+
+use core::fmt::{self, Write};
+
+use cortex_m::asm;
+use embedded_hal::serial;
+use stm32l4::stm32l4x5::RCC;
+
+use crate::gpio::{self, PinId};
+use crate::rcc::BDCR;
+use crate::rcc::clocking::HighSpeedExternalOSC;
+use crate::signature;
+
+/// Cycles to wait for pin level to settle through pull resistor
+const SETTLE_CYCLES: u32 = 200;
+/// Number of ports, which can be tested, GPIOA to GPIOH
+const PORTS: usize = 8;
+
+/// Pin under walking-bit test
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub struct TestPin {
+    /// Port letter, e.g. `b'A'`
+    pub port: u8,
+    /// Pin number within port
+    pub num: u8,
+}
+
+impl TestPin {
+    /// Describes `pin`, which must not be used by application while test runs.
+    pub fn of<PIN: PinId>(_pin: &PIN) -> Self {
+        Self {
+            port: PIN::PORT,
+            num: PIN::NUM,
+        }
+    }
+
+    /// Returns index of pin's port, or `None` if pin doesn't exist.
+    fn port_index(self) -> Option<usize> {
+        match self.port.checked_sub(b'A') {
+            Some(index) if (index as usize) < PORTS && self.num < 16 => Some(index as usize),
+            _ => None,
+        }
+    }
+
+    fn set_input(self, pull_up: bool) {
+        let port = gpio::port_registers(self.port);
+        let offset = 2 * self.num as u32;
+
+        port.moder.modify(|r, w| unsafe { w.bits(r.bits() & !(0b11 << offset)) });
+        port.pupdr.modify(|r, w| unsafe {
+            let pull = match pull_up {
+                true => 0b01,
+                false => 0b10,
+            };
+            w.bits(r.bits() & !(0b11 << offset) | pull << offset)
+        });
+    }
+
+    fn drive(self, is_high: bool) {
+        let port = gpio::port_registers(self.port);
+        let offset = 2 * self.num as u32;
+
+        let bit = match is_high {
+            true => 1 << self.num,
+            false => 1 << (self.num + 16),
+        };
+        port.bsrr.write(|w| unsafe { w.bits(bit) });
+        port.otyper.modify(|r, w| unsafe { w.bits(r.bits() & !(1 << self.num)) });
+        port.moder.modify(|r, w| unsafe { w.bits(r.bits() & !(0b11 << offset) | 0b01 << offset) });
+    }
+
+    fn is_high(self) -> bool {
+        gpio::port_registers(self.port).idr.read().bits() & (1 << self.num) != 0
+    }
+}
+
+impl fmt::Display for TestPin {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "P{}{}", self.port as char, self.num)
+    }
+}
+
+/// Fault found by walking-bit test
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum GpioFault {
+    /// Pin doesn't exist, nothing has been tested
+    Invalid(TestPin),
+    /// Pin doesn't follow its own output, e.g. shorted to supply or ground
+    Stuck(TestPin),
+    /// Driving `driven` pin changes level of `affected` one
+    Short {
+        /// Pin driven by test
+        driven: TestPin,
+        /// Pin following driven one
+        affected: TestPin,
+    },
+}
+
+impl fmt::Display for GpioFault {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            GpioFault::Invalid(pin) => write!(f, "INVALID {}", pin),
+            GpioFault::Stuck(pin) => write!(f, "STUCK {}", pin),
+            GpioFault::Short { driven, affected } => write!(f, "SHORT {} {}", driven, affected),
+        }
+    }
+}
+
+/// Saved configuration of port, restored after test
+struct PortState {
+    port: u8,
+    moder: u32,
+    otyper: u32,
+    pupdr: u32,
+    odr: u32,
+}
+
+impl PortState {
+    fn save(port: u8) -> Self {
+        let registers = gpio::port_registers(port);
+        Self {
+            port,
+            moder: registers.moder.read().bits(),
+            otyper: registers.otyper.read().bits(),
+            pupdr: registers.pupdr.read().bits(),
+            odr: registers.odr.read().bits(),
+        }
+    }
+
+    fn restore(&self) {
+        let registers = gpio::port_registers(self.port);
+        registers.odr.write(|w| unsafe { w.bits(self.odr) });
+        registers.otyper.write(|w| unsafe { w.bits(self.otyper) });
+        registers.pupdr.write(|w| unsafe { w.bits(self.pupdr) });
+        registers.moder.write(|w| unsafe { w.bits(self.moder) });
+    }
+}
+
+/// Runs walking-bit test over `pins`, which must be unconnected on tested board.
+///
+/// Each pin is driven high, then low, while the rest are pulled the opposite way:
+/// the driven pin must follow its output, while others must keep their pulled level.
+/// Ports of pins must be enabled, their configuration is restored after test.
+///
+/// Returns `GpioFault::Invalid` before driving any pin, if some of `pins` doesn't exist.
+pub fn walk_pins(pins: &[TestPin]) -> Result<(), GpioFault> {
+    let mut ports: [Option<PortState>; PORTS] = [None, None, None, None, None, None, None, None];
+    for &pin in pins {
+        let state = match pin.port_index() {
+            Some(index) => &mut ports[index],
+            None => return Err(GpioFault::Invalid(pin)),
+        };
+        if state.is_none() {
+            *state = Some(PortState::save(pin.port));
+        }
+    }
+
+    let result = [true, false].iter().try_for_each(|&level| walk_level(pins, level));
+
+    for state in ports.iter().flatten() {
+        state.restore();
+    }
+    result
+}
+
+/// Walks `level` across `pins`, pulling the rest to opposite one.
+fn walk_level(pins: &[TestPin], level: bool) -> Result<(), GpioFault> {
+    for pin in pins {
+        pin.set_input(!level);
+    }
+
+    for &driven in pins {
+        driven.drive(level);
+        asm::delay(SETTLE_CYCLES);
+
+        let result = match driven.is_high() == level {
+            true => match pins.iter().find(|&&pin| pin != driven && pin.is_high() == level) {
+                Some(&affected) => Err(GpioFault::Short { driven, affected }),
+                None => Ok(()),
+            },
+            false => Err(GpioFault::Stuck(driven)),
+        };
+
+        driven.set_input(!level);
+        result?;
+    }
+    Ok(())
+}
+
+/// Checks that LSE starts within `clocking::LSE_READY_TIMEOUT`.
+///
+/// Backup domain must be writable. LSE is left in its original state.
+pub fn check_lse(bdcr: &mut BDCR) -> bool {
+    if bdcr.inner().read().lserdy().bit_is_set() {
+        return true;
+    }
+
+    let is_started = bdcr.try_lse_enable(true).is_ok();
+    let _ = bdcr.try_lse_enable(false);
+    is_started
+}
+
+/// Checks that `hse` starts within `clocking::READY_TIMEOUT`.
+///
+/// HSE, which is already running, is left untouched, otherwise it is turned off after check.
+pub fn check_hse(hse: HighSpeedExternalOSC) -> bool {
+    let rcc = unsafe { &*RCC::ptr() };
+    if rcc.cr.read().hserdy().bit_is_set() {
+        return true;
+    }
+
+    let is_started = hse.try_configure(rcc).is_ok();
+    rcc.cr.modify(|_, w| w.hseon().clear_bit().csson().clear_bit());
+    is_started
+}
+
+/// Results of production test
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub struct Report {
+    /// Unique device identifier
+    pub uid: [u32; 3],
+    /// Size of Flash in KiB
+    pub flash_size: u16,
+    /// Result of walking-bit test
+    pub gpio: Result<(), GpioFault>,
+    /// Whether LSE has started
+    pub lse: bool,
+    /// Whether HSE has started, `None` if it isn't mounted
+    pub hse: Option<bool>,
+}
+
+impl Report {
+    /// Returns whether all checks have passed
+    pub fn is_passed(&self) -> bool {
+        self.gpio.is_ok() && self.lse && self.hse != Some(false)
+    }
+}
+
+fn verdict(is_ok: bool) -> &'static str {
+    match is_ok {
+        true => "OK",
+        false => "FAIL",
+    }
+}
+
+impl fmt::Display for Report {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "UID: {:08X} {:08X} {:08X}\r\n", self.uid[0], self.uid[1], self.uid[2])?;
+        write!(f, "FLASH: {} KiB\r\n", self.flash_size)?;
+        match self.gpio {
+            Ok(()) => write!(f, "GPIO: OK\r\n")?,
+            Err(fault) => write!(f, "GPIO: {}\r\n", fault)?,
+        }
+        write!(f, "LSE: {}\r\n", verdict(self.lse))?;
+        if let Some(hse) = self.hse {
+            write!(f, "HSE: {}\r\n", verdict(hse))?;
+        }
+        Ok(())
+    }
+}
+
+/// Runs all checks, HSE is checked only when `hse` is given.
+pub fn run(pins: &[TestPin], hse: Option<HighSpeedExternalOSC>, bdcr: &mut BDCR) -> Report {
+    Report {
+        uid: signature::uid(),
+        flash_size: signature::flash_size(),
+        gpio: walk_pins(pins),
+        lse: check_lse(bdcr),
+        hse: hse.map(check_hse),
+    }
+}
+
+/// Writer that blocks on serial, keeping its error
+struct SerialWriter<'a, W: serial::Write<u8>> {
+    serial: &'a mut W,
+    error: Option<W::Error>,
+}
+
+impl<'a, W: serial::Write<u8>> Write for SerialWriter<'a, W> {
+    fn write_str(&mut self, text: &str) -> fmt::Result {
+        for byte in text.bytes() {
+            if let Err(error) = nb::block!(self.serial.write(byte)) {
+                self.error = Some(error);
+                return Err(fmt::Error);
+            }
+        }
+        Ok(())
+    }
+}
+
+/// Streams `report` as text over `serial`, waiting until it is transmitted.
+pub fn stream<W: serial::Write<u8>>(serial: &mut W, report: &Report) -> Result<(), W::Error> {
+    let mut writer = SerialWriter {
+        serial,
+        error: None,
+    };
+
+    if write!(writer, "{}", report).is_err() {
+        if let Some(error) = writer.error.take() {
+            return Err(error);
+        }
+    }
+    nb::block!(writer.serial.flush())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::TestPin;
+
+    #[test]
+    fn port_index() {
+        assert_eq!(TestPin { port: b'A', num: 0 }.port_index(), Some(0));
+        assert_eq!(TestPin { port: b'H', num: 15 }.port_index(), Some(7));
+        assert_eq!(TestPin { port: b'0', num: 0 }.port_index(), None);
+        assert_eq!(TestPin { port: b'I', num: 0 }.port_index(), None);
+        assert_eq!(TestPin { port: b'B', num: 16 }.port_index(), None);
+    }
+}
@@ -1,12 +1,46 @@
 //! Flash memory
+//!
+//! STM32L4x5 has no program/erase suspend, so [FlashWriter](struct.FlashWriter.html)
+//! offers non-blocking erase instead. CPU stalls only on access to bank under erase,
+//! therefore interrupts keep being served while their handlers and vector table are
+//! located in the other bank or in RAM.
+//!
+//! ```rust, ignore
+//! let mut flash = device.FLASH.constrain();
+//! loop {
+//!     match flash.writer.erase_page_resumable(511) {
+//!         Err(nb::Error::WouldBlock) => do_other_work(),
+//!         result => break result,
+//!     }
+//! }
+//! ```
+//!
+//! Code which must keep running while its own bank is erased, e.g. updater itself,
+//! can be placed into RAM with [ramfunc](../macro.ramfunc.html):
+//!
+//! ```rust, ignore
+//! ramfunc! {
+//!     fn apply_update(writer: &mut FlashWriter) {
+//!         // ...
+//!     }
+//! }
+//! ```
+//!
+//! See Reference manual Ch. 3.3
 
-use stm32l4::stm32l4x5::{flash, FLASH};
+use core::ptr;
+
+use stm32l4::stm32l4x5::{flash, FLASH, SYSCFG};
 
 use crate::common::Constrain;
+use crate::signature;
 
 impl Constrain<Parts> for FLASH {
     fn constrain(self) -> Parts {
-        Parts { acr: ACR(()) }
+        Parts {
+            acr: ACR(()),
+            writer: FlashWriter { pending: None },
+        }
     }
 }
 
@@ -14,6 +48,8 @@ impl Constrain<Parts> for FLASH {
 pub struct Parts {
     /// Opaque ACR register
     pub acr: ACR,
+    /// Programming and erase interface
+    pub writer: FlashWriter,
 }
 
 /// Opaque ACR register
@@ -23,3 +59,310 @@ impl ACR {
         unsafe { &(*FLASH::ptr()).acr }
     }
 }
+
+/// Start of main Flash memory
+pub const FLASH_START: u32 = 0x0800_0000;
+/// Size of Flash page in bytes
+pub const PAGE_SIZE: u32 = 2048;
+
+/// Returns size of each of two banks in bytes, half of Flash size reported by device signature.
+pub fn bank_size() -> u32 {
+    signature::flash_size() as u32 * 1024 / 2
+}
+
+/// Returns number of pages in each of two banks, e.g. 256 on 1 MiB parts.
+pub fn bank_pages() -> u16 {
+    (bank_size() / PAGE_SIZE) as u16
+}
+
+const KEY1: u32 = 0x4567_0123;
+const KEY2: u32 = 0xCDEF_89AB;
+const OPTKEY1: u32 = 0x0819_2A3B;
+const OPTKEY2: u32 = 0x4C5D_6E7F;
+
+/// Flash programming errors
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum Error {
+    /// Page is write protected
+    WriteProtection,
+    /// Programming sequence, size or alignment error
+    Programming,
+    /// Operation on other page is pending
+    Busy,
+    /// Page is outside of Flash memory
+    InvalidPage,
+    /// Page belongs to bank, from which code is executed
+    ActiveBank,
+}
+
+/// Physical Flash bank
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum Bank {
+    /// Bank 1, pages `0..bank_pages()`
+    Bank1,
+    /// Bank 2, pages `bank_pages()..2 * bank_pages()`
+    Bank2,
+}
+
+impl Bank {
+    /// Returns bank of `page`
+    pub fn of_page(page: u16) -> Self {
+        match page < bank_pages() {
+            true => Bank::Bank1,
+            false => Bank::Bank2,
+        }
+    }
+
+    /// Returns physical bank at `address`, taking bank swap (FB_MODE) into account.
+    ///
+    /// Returns `None` if address is outside of main Flash memory.
+    pub fn of_address(address: u32) -> Option<Self> {
+        let bank_size = bank_size();
+        if address < FLASH_START || address >= FLASH_START + 2 * bank_size {
+            return None;
+        }
+
+        let is_swapped = unsafe { (*SYSCFG::ptr()).memrmp.read().fb_mode().bit_is_set() };
+        let is_first = address < FLASH_START + bank_size;
+        match is_first != is_swapped {
+            true => Some(Bank::Bank1),
+            false => Some(Bank::Bank2),
+        }
+    }
+
+    /// Returns bank, from which code of this crate is executed, or `None` when run from RAM.
+    #[inline(never)]
+    pub fn executing() -> Option<Self> {
+        Self::of_address(Self::executing as fn() -> Option<Self> as usize as u32)
+    }
+}
+
+/// Defines functions placed in RAM, which keep running while Flash is erased or programmed.
+///
+/// Functions are put into `.data` section, copied to RAM by runtime on startup.
+/// Everything they call must be in RAM too, or inlined.
+#[macro_export]
+macro_rules! ramfunc {
+    ($($(#[$attr:meta])* $vis:vis fn $name:ident($($arg:tt)*) $(-> $ret:ty)? $body:block)+) => {
+        $(
+            $(#[$attr])*
+            #[inline(never)]
+            #[link_section = ".data.ramfunc"]
+            $vis fn $name($($arg)*) $(-> $ret)? $body
+        )+
+    }
+}
+
+/// Copies position independent machine code into RAM `buffer`.
+///
+/// Returns address to call, with Thumb bit set.
+///
+/// # Safety
+///
+/// `code` must be position independent Thumb code, its calls must not target Flash under erase.
+pub unsafe fn copy_to_ram(code: &[u8], buffer: &'static mut [u8]) -> usize {
+    debug_assert!(code.len() <= buffer.len());
+
+    buffer[..code.len()].copy_from_slice(code);
+    // Make sure code is written before it is fetched
+    cortex_m::asm::dsb();
+    cortex_m::asm::isb();
+
+    buffer.as_ptr() as usize | 1
+}
+
+/// Flash programming and erase interface
+pub struct FlashWriter {
+    pending: Option<u16>,
+}
+
+impl FlashWriter {
+    fn flash(&self) -> &flash::RegisterBlock {
+        unsafe { &*FLASH::ptr() }
+    }
+
+    fn unlock(&mut self) {
+        let flash = self.flash();
+        if flash.cr.read().lock().bit_is_set() {
+            flash.keyr.write(|w| unsafe { w.keyr().bits(KEY1) });
+            flash.keyr.write(|w| unsafe { w.keyr().bits(KEY2) });
+        }
+    }
+
+    fn unlock_options(&mut self) {
+        self.unlock();
+        let flash = self.flash();
+        if flash.cr.read().optlock().bit_is_set() {
+            flash.optkeyr.write(|w| unsafe { w.optkeyr().bits(OPTKEY1) });
+            flash.optkeyr.write(|w| unsafe { w.optkeyr().bits(OPTKEY2) });
+        }
+    }
+
+    fn lock(&mut self) {
+        self.flash().cr.modify(|_, w| w.lock().set_bit());
+    }
+
+    /// Clears error flags, returning first found error
+    fn check_errors(&mut self) -> Result<(), Error> {
+        let sr = self.flash().sr.read();
+        let result = if sr.wrperr().bit_is_set() {
+            Err(Error::WriteProtection)
+        } else if sr.progerr().bit_is_set() || sr.pgaerr().bit_is_set() || sr.sizerr().bit_is_set()
+               || sr.pgserr().bit_is_set() || sr.miserr().bit_is_set() || sr.fasterr().bit_is_set() {
+            Err(Error::Programming)
+        } else {
+            Ok(())
+        };
+
+        // Flags are cleared by writing 1
+        self.flash().sr.write(|w| {
+            w.eop().set_bit().operr().set_bit().progerr().set_bit().wrperr().set_bit()
+             .pgaerr().set_bit().sizerr().set_bit().pgserr().set_bit().miserr().set_bit()
+             .fasterr().set_bit().optverr().set_bit()
+        });
+        result
+    }
+
+    /// Starts erase of `page`, counted from start of bank 1 through bank 2.
+    fn start_erase(&mut self, page: u16) -> Result<(), Error> {
+        let bank_pages = bank_pages();
+        if page >= 2 * bank_pages {
+            return Err(Error::InvalidPage);
+        }
+
+        while self.flash().sr.read().bsy().bit_is_set() {}
+        self.check_errors()?;
+        self.unlock();
+
+        let bank2 = page >= bank_pages;
+        let pnb = (page % bank_pages) as u8;
+        self.flash().cr.modify(|_, w| unsafe { w.per().set_bit().bker().bit(bank2).pnb().bits(pnb) });
+        self.flash().cr.modify(|_, w| w.start().set_bit());
+        Ok(())
+    }
+
+    /// Completes erase once Flash isn't busy.
+    fn finish_erase(&mut self) -> nb::Result<(), Error> {
+        if self.flash().sr.read().bsy().bit_is_set() {
+            return Err(nb::Error::WouldBlock);
+        }
+
+        self.pending = None;
+        self.flash().cr.modify(|_, w| w.per().clear_bit());
+        self.lock();
+        self.check_errors().map_err(nb::Error::Other)
+    }
+
+    /// Erases `page` without blocking.
+    ///
+    /// First call starts erase, subsequent calls poll for its completion, which takes
+    /// up to ~25 ms. Calls for other page return `Busy` while erase is pending.
+    ///
+    /// Erase of bank, from which code is executed, would stall CPU anyway,
+    /// so it is rejected with `ActiveBank`, use [erase_page](#method.erase_page) for it.
+    /// Pages outside of `0..2 * bank_pages()` are rejected with `InvalidPage`.
+    pub fn erase_page_resumable(&mut self, page: u16) -> nb::Result<(), Error> {
+        match self.pending {
+            None => {
+                if Bank::executing() == Some(Bank::of_page(page)) {
+                    return Err(nb::Error::Other(Error::ActiveBank));
+                }
+                self.start_erase(page).map_err(nb::Error::Other)?;
+                self.pending = Some(page);
+                self.finish_erase()
+            },
+            Some(pending) if pending == page => self.finish_erase(),
+            Some(_) => Err(nb::Error::Other(Error::Busy)),
+        }
+    }
+
+    /// Erases `page`, blocking until completion.
+    ///
+    /// CPU stalls on fetch from the bank under erase, so erasing page with running code
+    /// leads to hard fault, unless it is executed from RAM.
+    /// Pages outside of `0..2 * bank_pages()` are rejected with `InvalidPage`.
+    pub fn erase_page(&mut self, page: u16) -> Result<(), Error> {
+        if self.pending.is_some() {
+            return Err(Error::Busy);
+        }
+
+        self.start_erase(page)?;
+        self.pending = Some(page);
+        nb::block!(self.finish_erase())
+    }
+
+    /// Selects bank to boot from (BFB2 option), effective after [reload_options](#method.reload_options).
+    ///
+    /// When booting from bank 2, banks are swapped, so it is mapped at `FLASH_START`.
+    pub fn set_boot_bank(&mut self, bank: Bank) -> Result<(), Error> {
+        if self.pending.is_some() {
+            return Err(Error::Busy);
+        }
+
+        while self.flash().sr.read().bsy().bit_is_set() {}
+        self.check_errors()?;
+        self.unlock_options();
+
+        self.flash().optr.modify(|_, w| w.bfb2().bit(bank == Bank::Bank2));
+        self.flash().cr.modify(|_, w| w.optstrt().set_bit());
+        while self.flash().sr.read().bsy().bit_is_set() {}
+
+        self.lock();
+        self.check_errors()
+    }
+
+    /// Returns bank selected to boot from
+    pub fn boot_bank(&self) -> Bank {
+        match self.flash().optr.read().bfb2().bit_is_set() {
+            true => Bank::Bank2,
+            false => Bank::Bank1,
+        }
+    }
+
+    /// Loads programmed option bytes, which resets MCU.
+    pub fn reload_options(&mut self) -> ! {
+        self.unlock_options();
+        self.flash().cr.modify(|_, w| w.obl_launch().set_bit());
+
+        loop {
+            cortex_m::asm::nop();
+        }
+    }
+
+    /// Programs `data` at `address`, which must be 8 bytes aligned and erased.
+    ///
+    /// # Pancis:
+    ///
+    /// In debug mode the function checks that `address` is aligned.
+    pub fn write(&mut self, address: u32, data: &[u64]) -> Result<(), Error> {
+        debug_assert_eq!(address % 8, 0);
+        if self.pending.is_some() {
+            return Err(Error::Busy);
+        }
+
+        while self.flash().sr.read().bsy().bit_is_set() {}
+        self.check_errors()?;
+        self.unlock();
+        self.flash().cr.modify(|_, w| w.pg().set_bit());
+
+        let mut result = Ok(());
+        for (idx, &double_word) in data.iter().enumerate() {
+            let target = (address as usize + idx * 8) as *mut u32;
+            // Double word is written as two consecutive words
+            unsafe {
+                ptr::write_volatile(target, double_word as u32);
+                ptr::write_volatile(target.add(1), (double_word >> 32) as u32);
+            }
+            while self.flash().sr.read().bsy().bit_is_set() {}
+
+            result = self.check_errors();
+            if result.is_err() {
+                break;
+            }
+        }
+
+        self.flash().cr.modify(|_, w| w.pg().clear_bit());
+        self.lock();
+        result
+    }
+}
@@ -0,0 +1,163 @@
+//! In-application firmware update
+//!
+//! New image is written into inactive bank, which is mapped right after the active one,
+//! while CRC unit verifies what ends up in Flash. Once image is complete, boot bank option
+//! is switched to it, so it is started after option bytes reload.
+//!
+//! ```rust, ignore
+//! let mut update = Update::new(&mut flash.writer, &mut crc, image_len)?;
+//! while let Some(chunk) = receive_chunk() {
+//!     update.write(chunk)?;
+//! }
+//! update.finish(expected_crc)?;
+//! // Resets MCU into new firmware
+//! flash.writer.reload_options();
+//! ```
+//!
+//! Image must be linked to run from `FLASH_START`, as banks are swapped on boot from bank 2.
+
+use crate::crc::CRC;
+use crate::flash::{self, Bank, FlashWriter, FLASH_START, PAGE_SIZE};
+
+/// Programming granularity of Flash
+const DOUBLE_WORD: usize = 8;
+/// Value of erased Flash
+const ERASED: u8 = 0xFF;
+
+/// Firmware update errors
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum Error {
+    /// Flash erase or programming failed
+    Flash(flash::Error),
+    /// CRC of written image doesn't match expected one
+    Crc,
+    /// Image doesn't fit into bank
+    Overflow,
+}
+
+impl From<flash::Error> for Error {
+    fn from(error: flash::Error) -> Self {
+        Error::Flash(error)
+    }
+}
+
+/// Firmware image being written into inactive bank.
+///
+/// CRC is calculated over image bytes, as they are read back from Flash, with current
+/// configuration of CRC unit.
+pub struct Update<'a> {
+    writer: &'a mut FlashWriter,
+    crc: &'a mut CRC,
+    first_page: u16,
+    // Size of inactive bank, according to Flash size of device
+    bank_size: u32,
+    // Number of programmed bytes
+    len: u32,
+    pending: [u8; DOUBLE_WORD],
+    pending_len: usize,
+}
+
+impl<'a> Update<'a> {
+    /// Starts update of inactive bank with image of `image_len` bytes, resetting CRC unit.
+    ///
+    /// Returns `Overflow` if image doesn't fit into bank.
+    pub fn new(writer: &'a mut FlashWriter, crc: &'a mut CRC, image_len: u32) -> Result<Self, Error> {
+        let bank_size = flash::bank_size();
+        if image_len > bank_size {
+            return Err(Error::Overflow);
+        }
+
+        crc.reset();
+        let first_page = match Bank::of_address(FLASH_START + bank_size) {
+            Some(Bank::Bank2) => flash::bank_pages(),
+            _ => 0,
+        };
+
+        Ok(Self {
+            writer,
+            crc,
+            first_page,
+            bank_size,
+            len: 0,
+            pending: [ERASED; DOUBLE_WORD],
+            pending_len: 0,
+        })
+    }
+
+    /// Returns address, at which inactive bank is mapped
+    fn base(&self) -> u32 {
+        FLASH_START + self.bank_size
+    }
+
+    /// Returns physical bank, into which image is written
+    pub fn target_bank(&self) -> Bank {
+        Bank::of_page(self.first_page)
+    }
+
+    /// Returns number of image bytes written so far
+    pub fn len(&self) -> u32 {
+        self.len + self.pending_len as u32
+    }
+
+    /// Returns true if nothing has been written yet
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    /// Appends `data` to image, erasing pages as they are reached.
+    pub fn write(&mut self, data: &[u8]) -> Result<(), Error> {
+        if self.len() + data.len() as u32 > self.bank_size {
+            return Err(Error::Overflow);
+        }
+
+        for &byte in data {
+            self.pending[self.pending_len] = byte;
+            self.pending_len += 1;
+
+            if self.pending_len == DOUBLE_WORD {
+                self.program()?;
+            }
+        }
+
+        Ok(())
+    }
+
+    /// Programs pending double word, padded with erased value, and feeds it back into CRC.
+    fn program(&mut self) -> Result<(), Error> {
+        let address = self.base() + self.len;
+        if self.len % PAGE_SIZE == 0 {
+            self.writer.erase_page(self.first_page + (self.len / PAGE_SIZE) as u16)?;
+        }
+
+        let mut double_word = [0; DOUBLE_WORD];
+        double_word.copy_from_slice(&self.pending);
+        self.writer.write(address, &[u64::from_le_bytes(double_word)])?;
+
+        for offset in 0..self.pending_len {
+            let byte = unsafe { *((address as usize + offset) as *const u8) };
+            *self.crc += byte;
+        }
+
+        self.len += DOUBLE_WORD as u32;
+        self.pending = [ERASED; DOUBLE_WORD];
+        self.pending_len = 0;
+        Ok(())
+    }
+
+    /// Writes remaining data, verifies image against `expected_crc` and selects its bank to boot from.
+    ///
+    /// New image is started on next option bytes reload, see `FlashWriter::reload_options`.
+    pub fn finish(mut self, expected_crc: u32) -> Result<(), Error> {
+        if self.pending_len > 0 {
+            self.program()?;
+        }
+
+        if self.crc.result() != expected_crc {
+            return Err(Error::Crc);
+        }
+
+        let bank = self.target_bank();
+        self.writer.set_boot_bank(bank)?;
+        Ok(())
+    }
+}
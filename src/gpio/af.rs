@@ -0,0 +1,162 @@
+//! Alternate function mapping
+//!
+//! Table of peripheral signals available on each pin of ports A, B and C,
+//! which are present in all Stm32l4x5 packages. Peripheral constructors check
+//! their pins against it in debug mode.
+//!
+//! ```rust, ignore
+//! let tx = gpioa.PA9.into_alt_fun::<AF7>(&mut gpioa.moder, &mut gpioa.afrh);
+//! assert!(af::has_af(&tx, Signal::UsartTx(1)));
+//! ```
+//!
+//! See Datasheet Ch. 4, Alternate function tables
+
+use super::AltFunPin;
+
+/// Peripheral signal, parametrized by peripheral index
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum Signal {
+    /// USART transmit
+    UsartTx(u8),
+    /// USART receive
+    UsartRx(u8),
+    /// USART clock
+    UsartCk(u8),
+    /// USART driver enable
+    UsartDe(u8),
+    /// SPI clock
+    SpiSck(u8),
+    /// SPI master input
+    SpiMiso(u8),
+    /// SPI master output
+    SpiMosi(u8),
+    /// SPI slave select
+    SpiNss(u8),
+    /// I2C clock
+    I2cScl(u8),
+    /// I2C data
+    I2cSda(u8),
+    /// Timer capture/compare channel, as (timer, channel)
+    TimCh(u8, u8),
+}
+
+impl Signal {
+    const fn code(self) -> u16 {
+        match self {
+            Signal::UsartTx(idx) => 0x0100 | idx as u16,
+            Signal::UsartRx(idx) => 0x0200 | idx as u16,
+            Signal::UsartCk(idx) => 0x0300 | idx as u16,
+            Signal::UsartDe(idx) => 0x0400 | idx as u16,
+            Signal::SpiSck(idx) => 0x0500 | idx as u16,
+            Signal::SpiMiso(idx) => 0x0600 | idx as u16,
+            Signal::SpiMosi(idx) => 0x0700 | idx as u16,
+            Signal::SpiNss(idx) => 0x0800 | idx as u16,
+            Signal::I2cScl(idx) => 0x0900 | idx as u16,
+            Signal::I2cSda(idx) => 0x0A00 | idx as u16,
+            Signal::TimCh(idx, channel) => 0x0B00 | (idx as u16) << 4 | channel as u16,
+        }
+    }
+}
+
+/// Alternate function of pin
+#[derive(Clone, Copy, Debug)]
+pub struct Mapping {
+    /// Port letter, e.g. `b'A'`
+    pub port: u8,
+    /// Pin number within port
+    pub num: u8,
+    /// Alternate function number
+    pub af: u8,
+    /// Peripheral signal
+    pub signal: Signal,
+}
+
+macro_rules! af_table {
+    ($($port:expr, $num:literal: [$($af:literal => $signal:expr,)+];)+) => {
+        &[$($(Mapping { port: $port, num: $num, af: $af, signal: $signal },)+)+]
+    }
+}
+
+use self::Signal::*;
+
+/// Alternate functions of ports A, B and C
+pub const AF_TABLE: &[Mapping] = af_table!(
+    b'A', 0: [1 => TimCh(2, 1), 2 => TimCh(5, 1),];
+    b'A', 1: [1 => TimCh(2, 2), 2 => TimCh(5, 2), 5 => SpiSck(1), 7 => UsartDe(2),];
+    b'A', 2: [1 => TimCh(2, 3), 2 => TimCh(5, 3), 7 => UsartTx(2),];
+    b'A', 3: [1 => TimCh(2, 4), 2 => TimCh(5, 4), 7 => UsartRx(2),];
+    b'A', 4: [5 => SpiNss(1), 6 => SpiNss(3), 7 => UsartCk(2),];
+    b'A', 5: [1 => TimCh(2, 1), 5 => SpiSck(1),];
+    b'A', 6: [2 => TimCh(3, 1), 5 => SpiMiso(1),];
+    b'A', 7: [2 => TimCh(3, 2), 5 => SpiMosi(1),];
+    b'A', 8: [1 => TimCh(1, 1), 7 => UsartCk(1),];
+    b'A', 9: [1 => TimCh(1, 2), 7 => UsartTx(1),];
+    b'A', 10: [1 => TimCh(1, 3), 7 => UsartRx(1),];
+    b'A', 11: [1 => TimCh(1, 4), 5 => SpiMiso(1),];
+    b'A', 12: [5 => SpiMosi(1), 7 => UsartDe(1),];
+    b'A', 15: [1 => TimCh(2, 1), 3 => UsartRx(2), 5 => SpiNss(1), 6 => SpiNss(3),];
+    b'B', 0: [2 => TimCh(3, 3), 5 => SpiNss(1), 7 => UsartCk(3),];
+    b'B', 1: [2 => TimCh(3, 4), 7 => UsartDe(3),];
+    b'B', 3: [1 => TimCh(2, 2), 5 => SpiSck(1), 6 => SpiSck(3), 7 => UsartDe(1),];
+    b'B', 4: [2 => TimCh(3, 1), 5 => SpiMiso(1), 6 => SpiMiso(3),];
+    b'B', 5: [2 => TimCh(3, 2), 5 => SpiMosi(1), 6 => SpiMosi(3), 7 => UsartCk(1),];
+    b'B', 6: [2 => TimCh(4, 1), 4 => I2cScl(1), 7 => UsartTx(1),];
+    b'B', 7: [2 => TimCh(4, 2), 4 => I2cSda(1), 7 => UsartRx(1),];
+    b'B', 8: [2 => TimCh(4, 3), 4 => I2cScl(1),];
+    b'B', 9: [2 => TimCh(4, 4), 4 => I2cSda(1), 5 => SpiNss(2),];
+    b'B', 10: [1 => TimCh(2, 3), 4 => I2cScl(2), 5 => SpiSck(2), 7 => UsartTx(3),];
+    b'B', 11: [1 => TimCh(2, 4), 4 => I2cSda(2), 7 => UsartRx(3),];
+    b'B', 12: [5 => SpiNss(2), 7 => UsartCk(3),];
+    b'B', 13: [4 => I2cScl(2), 5 => SpiSck(2),];
+    b'B', 14: [4 => I2cSda(2), 5 => SpiMiso(2), 7 => UsartDe(3),];
+    b'B', 15: [5 => SpiMosi(2),];
+    b'C', 0: [4 => I2cScl(3),];
+    b'C', 1: [4 => I2cSda(3),];
+    b'C', 2: [5 => SpiMiso(2),];
+    b'C', 3: [5 => SpiMosi(2),];
+    b'C', 4: [7 => UsartTx(3),];
+    b'C', 5: [7 => UsartRx(3),];
+    b'C', 6: [2 => TimCh(3, 1), 3 => TimCh(8, 1),];
+    b'C', 7: [2 => TimCh(3, 2), 3 => TimCh(8, 2),];
+    b'C', 8: [2 => TimCh(3, 3), 3 => TimCh(8, 3),];
+    b'C', 9: [2 => TimCh(3, 4), 3 => TimCh(8, 4),];
+    b'C', 10: [6 => SpiSck(3), 7 => UsartTx(3),];
+    b'C', 11: [6 => SpiMiso(3), 7 => UsartRx(3),];
+    b'C', 12: [6 => SpiMosi(3), 7 => UsartCk(3),];
+);
+
+/// Returns true if `signal` is available on pin `num` of `port` as alternate function `af`.
+pub const fn lookup(port: u8, num: u8, af: u8, signal: Signal) -> bool {
+    let code = signal.code();
+    let mut idx = 0;
+    while idx < AF_TABLE.len() {
+        let mapping = &AF_TABLE[idx];
+        if mapping.port == port && mapping.num == num && mapping.af == af && mapping.signal.code() == code {
+            return true;
+        }
+        idx += 1;
+    }
+
+    false
+}
+
+/// Returns true if `pin` carries `signal` with its current alternate function.
+pub fn has_af<PIN: AltFunPin>(_pin: &PIN, signal: Signal) -> bool {
+    lookup(PIN::PORT, PIN::NUM, PIN::AF, signal)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{lookup, Signal};
+
+    #[test]
+    fn af_lookup() {
+        assert!(lookup(b'A', 9, 7, Signal::UsartTx(1)));
+        assert!(lookup(b'B', 9, 4, Signal::I2cSda(1)));
+        assert!(lookup(b'C', 6, 3, Signal::TimCh(8, 1)));
+
+        assert!(!lookup(b'A', 9, 7, Signal::UsartTx(2)));
+        assert!(!lookup(b'A', 9, 4, Signal::UsartTx(1)));
+        assert!(!lookup(b'B', 9, 4, Signal::I2cScl(1)));
+    }
+}
@@ -0,0 +1,113 @@
+//! Debouncing of mechanical switches
+//!
+//! [Debounced](struct.Debounced.html) is polled with fixed period, usually from timer interrupt,
+//! and reports new state only when input has been stable for configured time.
+//!
+//! ```rust,ignore
+//! let button = gpio_c.PC13.into_input::<Floating>(&mut gpio_c.moder, &mut gpio_c.pupdr);
+//! let mut button = Debounced::new(button, Active::Low, MilliSeconds(20), MilliSeconds(1));
+//!
+//! // In 1 ms timer interrupt
+//! if let Some(Edge::Pressed) = button.poll() {
+//!     led.toggle();
+//! }
+//! ```
+
+use embedded_hal::digital::InputPin;
+
+use crate::time::MilliSeconds;
+
+/// Input level, at which switch is pressed
+#[derive(Copy, Clone, PartialEq, Eq, Debug)]
+pub enum Active {
+    /// Pressed switch pulls input low
+    Low,
+    /// Pressed switch pulls input high
+    High,
+}
+
+/// Change of debounced state
+#[derive(Copy, Clone, PartialEq, Eq, Debug)]
+pub enum Edge {
+    /// Switch has been pressed
+    Pressed,
+    /// Switch has been released
+    Released,
+}
+
+/// Debounced input pin
+pub struct Debounced<PIN> {
+    pin: PIN,
+    active: Active,
+    stable_polls: u32,
+    counter: u32,
+    is_pressed: bool,
+}
+
+impl<PIN: InputPin> Debounced<PIN> {
+    /// Creates debouncer, which is polled every `period` and requires input to be stable for `stable` time.
+    ///
+    /// Initial state is taken from the pin without debouncing.
+    ///
+    /// # Pancis:
+    ///
+    /// In debug mode the function checks that `period` is not zero.
+    pub fn new(pin: PIN, active: Active, stable: MilliSeconds, period: MilliSeconds) -> Self {
+        debug_assert!(period.0 > 0);
+
+        let stable_polls = (stable.0 + period.0 - 1) / period.0;
+        Self::with_polls(pin, active, stable_polls)
+    }
+
+    /// Creates debouncer, which requires input to be stable for `stable_polls` consecutive polls.
+    pub fn with_polls(pin: PIN, active: Active, stable_polls: u32) -> Self {
+        let mut debounced = Self {
+            pin,
+            active,
+            stable_polls,
+            counter: 0,
+            is_pressed: false,
+        };
+        debounced.is_pressed = debounced.is_pressed_raw();
+
+        debounced
+    }
+
+    fn is_pressed_raw(&self) -> bool {
+        match self.active {
+            Active::Low => self.pin.is_low(),
+            Active::High => self.pin.is_high(),
+        }
+    }
+
+    /// Samples input, returning edge once new state has been stable long enough.
+    pub fn poll(&mut self) -> Option<Edge> {
+        if self.is_pressed_raw() == self.is_pressed {
+            self.counter = 0;
+            return None;
+        }
+
+        self.counter += 1;
+        if self.counter < self.stable_polls {
+            return None;
+        }
+
+        self.counter = 0;
+        self.is_pressed = !self.is_pressed;
+        match self.is_pressed {
+            true => Some(Edge::Pressed),
+            false => Some(Edge::Released),
+        }
+    }
+
+    /// Returns debounced state.
+    #[inline]
+    pub fn is_pressed(&self) -> bool {
+        self.is_pressed
+    }
+
+    /// Releases pin
+    pub fn free(self) -> PIN {
+        self.pin
+    }
+}
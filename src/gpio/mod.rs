@@ -7,10 +7,20 @@
 
 use core::marker::PhantomData;
 use core::ops::Deref;
+use core::ptr;
 
+use cortex_m::interrupt;
 use embedded_hal::digital::{toggleable, OutputPin, StatefulOutputPin, InputPin};
+pub use embedded_hal::digital::v2::PinState;
 
+use crate::power::{self, Power, StandbyPull};
 use crate::rcc::AHB;
+use crate::syscfg::{ExtiPort, SYSCFG};
+use stm32l4::stm32l4x5::gpioa;
+
+pub mod af;
+pub mod debounce;
+pub use self::debounce::Debounced;
 
 /// Input Mode Trait
 /// Implemented only for corresponding structs.
@@ -49,6 +59,14 @@ pub struct Input<MODE> {
     _mode: PhantomData<MODE>,
 }
 
+/// Analog mode (type state)
+pub struct Analog;
+
+/// Offset of GPIOx_ASCR register, which is missing in device crate.
+///
+/// See Reference manual Ch. 8.5.12
+const ASCR_OFFSET: usize = 0x2C;
+
 /// Output Mode Trait
 /// Implemented only for corresponding structs.
 ///
@@ -78,6 +96,21 @@ pub struct Output<MODE> {
     _mode: PhantomData<MODE>,
 }
 
+/// Output speed, i.e. slew rate of output driver
+///
+/// See datasheet for maximal frequencies, which depend on VDD and load capacitance.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Speed {
+    /// Low speed, reset state for most pins
+    Low = 0b00,
+    /// Medium speed
+    Medium = 0b01,
+    /// High speed
+    High = 0b10,
+    /// Very high speed
+    VeryHigh = 0b11,
+}
+
 /// Alternate Function Trait
 /// Implemented only for corresponding structs.
 ///
@@ -86,6 +119,20 @@ pub trait AltFun {
     const NUM: u32;
 }
 
+/// Location of pin, used to look up its alternate functions.
+pub trait PinId {
+    /// Port letter, e.g. `b'A'`
+    const PORT: u8;
+    /// Pin number within port
+    const NUM: u8;
+}
+
+/// Pin configured as alternate function.
+pub trait AltFunPin: PinId {
+    /// Alternate function number
+    const AF: u8;
+}
+
 /// Alternate function 0 (type state)
 pub struct AF0;
 impl AltFun for AF0 {
@@ -192,6 +239,11 @@ macro_rules! impl_parts {
                     unsafe { &(*$GPIOX::ptr()).otyper }
                 }
             }
+            impl OSPEEDR<$GPIOX> {
+                pub(crate) fn ospeedr(&mut self) -> &stm32l4::stm32l4x5::$gpiox::OSPEEDR {
+                    unsafe { &(*$GPIOX::ptr()).ospeedr }
+                }
+            }
             impl PUPDR<$GPIOX> {
                 pub(crate) fn pupdr(&mut self) -> &stm32l4::stm32l4x5::$gpiox::PUPDR {
                     unsafe { &(*$GPIOX::ptr()).pupdr }
@@ -223,6 +275,8 @@ macro_rules! impl_gpio {
             pub moder: MODER<$GPIOX>,
             /// Opaque OTYPER register
             pub otyper: OTYPER<$GPIOX>,
+            /// Opaque OSPEEDR register
+            pub ospeedr: OSPEEDR<$GPIOX>,
             /// Opaque PUPDR register
             pub pupdr: PUPDR<$GPIOX>,
             $(
@@ -238,15 +292,14 @@ macro_rules! impl_gpio {
         impl $name {
             ///Creates new instance of GPIO by enabling it on AHB register
             pub fn new(ahb: &mut AHB) -> Self {
-                ahb.enr2().modify(|_, w| w.$gpioen().set_bit());
-                ahb.rstr2().modify(|_, w| w.$gpiorst().set_bit());
-                ahb.rstr2().modify(|_, w| w.$gpiorst().clear_bit());
+                Self::enable(ahb);
 
                 Self {
                     afrh: AFRH(PhantomData),
                     afrl: AFRL(PhantomData),
                     moder: MODER(PhantomData),
                     otyper: OTYPER(PhantomData),
+                    ospeedr: OSPEEDR(PhantomData),
                     pupdr: PUPDR(PhantomData),
                     $(
                         $PXiL: $PXiL(PhantomData),
@@ -256,6 +309,27 @@ macro_rules! impl_gpio {
                     )*
                 }
             }
+
+            ///Creates GPIO, which hands out its pins one by one with [take](struct.Partial.html#method.take)
+            pub fn new_partial(ahb: &mut AHB) -> Partial<$GPIOX> {
+                Self::enable(ahb);
+
+                Partial {
+                    afrh: AFRH(PhantomData),
+                    afrl: AFRL(PhantomData),
+                    moder: MODER(PhantomData),
+                    otyper: OTYPER(PhantomData),
+                    ospeedr: OSPEEDR(PhantomData),
+                    pupdr: PUPDR(PhantomData),
+                    taken: 0,
+                }
+            }
+
+            fn enable(ahb: &mut AHB) {
+                ahb.enr2().modify(|_, w| w.$gpioen().set_bit());
+                ahb.rstr2().modify(|_, w| w.$gpiorst().set_bit());
+                ahb.rstr2().modify(|_, w| w.$gpiorst().clear_bit());
+            }
         }
 
     }
@@ -266,6 +340,23 @@ macro_rules! impl_pin {
         /// Specific Pin
         pub struct $PXi<MODE>(PhantomData<MODE>);
 
+        impl<MODE> PinId for $PXi<MODE> {
+            // GPIOx
+            const PORT: u8 = stringify!($GPIOX).as_bytes()[4];
+            const NUM: u8 = $i;
+        }
+
+        impl sealed::Unclaimed for $PXi<Input<Floating>> {
+            fn new() -> Self {
+                $PXi(PhantomData)
+            }
+        }
+        impl Take<$GPIOX> for $PXi<Input<Floating>> {}
+
+        impl<AF: AltFun> AltFunPin for $PXi<AF> {
+            const AF: u8 = AF::NUM as u8;
+        }
+
         impl<MODE> $PXi<MODE> {
             const OFFSET: u32 = 2 * $i;
 
@@ -279,14 +370,43 @@ macro_rules! impl_pin {
 
             /// Configures the PIN to operate as Output Pin according to Mode.
             pub fn into_output<Mode: OutputMode>(self, moder: &mut MODER<$GPIOX>, otyper: &mut OTYPER<$GPIOX>) -> $PXi<Output<Mode>> {
+                // Output type goes first, so open drain pin never drives push pull
+                otyper.otyper().modify(|r, w| unsafe { w.bits(Mode::modify_otyper_bits(r.bits(), $i)) });
                 moder
                     .moder()
                     .modify(|r, w| unsafe { w.bits((r.bits() & !(0b11 << Self::OFFSET)) | (0b01 << Self::OFFSET)) });
-                otyper.otyper().modify(|r, w| unsafe { w.bits(Mode::modify_otyper_bits(r.bits(), $i)) });
 
                 $PXi(PhantomData)
             }
 
+            /// Configures the PIN to operate as Output Pin according to Mode, driving `state` from the start.
+            ///
+            /// Output data register and output type are written before mode is switched, so the line doesn't glitch.
+            pub fn into_output_with_state<Mode: OutputMode>(self, moder: &mut MODER<$GPIOX>, otyper: &mut OTYPER<$GPIOX>,
+                                                            state: PinState) -> $PXi<Output<Mode>> {
+                Self::write_state(state);
+                self.into_output(moder, otyper)
+            }
+
+            /// Configures the PIN to operate as Push Pull Output Pin, driving `state` from the start.
+            pub fn into_push_pull_output_with_state(self, moder: &mut MODER<$GPIOX>, otyper: &mut OTYPER<$GPIOX>,
+                                                    state: PinState) -> $PXi<Output<PushPull>> {
+                self.into_output_with_state(moder, otyper, state)
+            }
+
+            /// Configures the PIN to operate as Open Drain Output Pin, driving `state` from the start.
+            pub fn into_open_drain_output_with_state(self, moder: &mut MODER<$GPIOX>, otyper: &mut OTYPER<$GPIOX>,
+                                                     state: PinState) -> $PXi<Output<OpenDrain>> {
+                self.into_output_with_state(moder, otyper, state)
+            }
+
+            /// Configures the PIN to operate as Output Pin according to Mode with given speed.
+            pub fn into_output_with_speed<Mode: OutputMode>(self, moder: &mut MODER<$GPIOX>, otyper: &mut OTYPER<$GPIOX>,
+                                                            ospeedr: &mut OSPEEDR<$GPIOX>, speed: Speed) -> $PXi<Output<Mode>> {
+                Self::write_speed(ospeedr, speed);
+                self.into_output(moder, otyper)
+            }
+
             /// Configures the PIN to operate as Alternate Function.
             pub fn into_alt_fun<AF: AltFun>(self, moder: &mut MODER<$GPIOX>, afr: &mut $AFR<$GPIOX>) -> $PXi<AF> {
                 // AFRx pin fields are 4 bits wide, and each 8-pin bank has its own reg (L or H); e.g. pin 8's offset is _0_, within AFRH.
@@ -298,6 +418,116 @@ macro_rules! impl_pin {
 
                 $PXi(PhantomData)
             }
+
+            /// Configures the PIN to operate as open drain Alternate Function, e.g. for I2C.
+            pub fn into_open_drain_alt_fun<AF: AltFun>(self, moder: &mut MODER<$GPIOX>, otyper: &mut OTYPER<$GPIOX>,
+                                                       afr: &mut $AFR<$GPIOX>) -> $PXi<AF> {
+                otyper.otyper().modify(|r, w| unsafe { w.bits(OpenDrain::modify_otyper_bits(r.bits(), $i)) });
+                self.into_alt_fun(moder, afr)
+            }
+
+            /// Configures the PIN to operate as Alternate Function with given speed.
+            pub fn into_alt_fun_with_speed<AF: AltFun>(self, moder: &mut MODER<$GPIOX>, afr: &mut $AFR<$GPIOX>,
+                                                       ospeedr: &mut OSPEEDR<$GPIOX>, speed: Speed) -> $PXi<AF> {
+                Self::write_speed(ospeedr, speed);
+                self.into_alt_fun(moder, afr)
+            }
+
+            /// Configures the PIN to operate in Analog mode, connecting it to ADC.
+            ///
+            /// Analog switch degrades ADC readings when VDDA is below `BOOSTER_VDDA_MV`, so its
+            /// booster is turned on/off according to `vdda_mv`, e.g. measured by `Adc::measure_vdda_mv`.
+            /// Booster is shared by all pins.
+            pub fn into_analog(self, moder: &mut MODER<$GPIOX>, pupdr: &mut PUPDR<$GPIOX>, syscfg: &mut SYSCFG, vdda_mv: u16) -> $PXi<Analog> {
+                syscfg.analog_booster_for(vdda_mv);
+
+                let mut pin = self.into_analog_disconnected(moder, pupdr);
+                pin.set_analog_switch(true);
+                pin
+            }
+
+            /// Configures the PIN to operate in Analog mode, keeping it disconnected from ADC,
+            /// when pad is used only by DAC, comparator or OPAMP.
+            pub fn into_analog_disconnected(self, moder: &mut MODER<$GPIOX>, pupdr: &mut PUPDR<$GPIOX>) -> $PXi<Analog> {
+                moder.moder().modify(|r, w| unsafe { w.bits(r.bits() | (0b11 << Self::OFFSET)) });
+                pupdr.pupdr().modify(|r, w| unsafe { w.bits(r.bits() & !(0b11 << Self::OFFSET)) });
+
+                let mut pin = $PXi(PhantomData);
+                pin.set_analog_switch(false);
+                pin
+            }
+
+            /// Configures the PIN as EVENTOUT (AF15), which pulses on every `SEV` instruction.
+            pub fn into_event_out(self, moder: &mut MODER<$GPIOX>, afr: &mut $AFR<$GPIOX>) -> $PXi<AF15> {
+                self.into_alt_fun::<AF15>(moder, afr)
+            }
+
+            /// Pulls the PIN up in Standby and Shutdown modes, keeping its level defined.
+            ///
+            /// Takes effect once enabled with `Power::set_standby_pulls`.
+            pub fn retain_pull_up_in_standby(&mut self, power: &mut Power) {
+                power::set_standby_pull(power, <Self as PinId>::PORT, $i, StandbyPull::Up);
+            }
+
+            /// Pulls the PIN down in Standby and Shutdown modes, keeping its level defined.
+            ///
+            /// Takes effect once enabled with `Power::set_standby_pulls`.
+            pub fn retain_pull_down_in_standby(&mut self, power: &mut Power) {
+                power::set_standby_pull(power, <Self as PinId>::PORT, $i, StandbyPull::Down);
+            }
+
+            /// Leaves the PIN floating in Standby and Shutdown modes.
+            pub fn release_pull_in_standby(&mut self, power: &mut Power) {
+                power::set_standby_pull(power, <Self as PinId>::PORT, $i, StandbyPull::Floating);
+            }
+
+            fn write_state(state: PinState) {
+                let bits = match state {
+                    PinState::High => 1 << $i,
+                    PinState::Low => 1 << (16 + $i),
+                };
+                // NOTE(unsafe) atomic write to a stateless register
+                unsafe { (*$GPIOX::ptr()).bsrr.write(|w| w.bits(bits)) }
+            }
+
+            fn write_speed(ospeedr: &mut OSPEEDR<$GPIOX>, speed: Speed) {
+                ospeedr.ospeedr().modify(|r, w| unsafe { w.bits((r.bits() & !(0b11 << Self::OFFSET)) | ((speed as u32) << Self::OFFSET)) });
+            }
+
+            /// Makes the PIN source of its EXTI line.
+            pub fn make_interrupt_source(&mut self, syscfg: &mut SYSCFG) {
+                syscfg.set_exti_source($i, <$GPIOX as ExtiPort>::PORT);
+            }
+        }
+
+        impl<MODE> $PXi<Output<MODE>> {
+            /// Sets output speed of the PIN.
+            pub fn set_speed(&mut self, ospeedr: &mut OSPEEDR<$GPIOX>, speed: Speed) {
+                Self::write_speed(ospeedr, speed);
+            }
+        }
+
+        impl $PXi<Analog> {
+            /// Connects the PIN to ADC through analog switch, or disconnects it, when pad is
+            /// used only by DAC, comparator or OPAMP.
+            ///
+            /// Booster is not changed, it is set up by [into_analog](#method.into_analog)
+            /// or `SYSCFG::analog_booster_for`.
+            pub fn set_analog_switch(&mut self, is_closed: bool) {
+                // ASCR is shared by pins of port and has no register token
+                interrupt::free(|_| unsafe {
+                    let ascr = ($GPIOX::ptr() as *const u8).add(ASCR_OFFSET) as *mut u32;
+                    let bits = ptr::read_volatile(ascr) & !(1 << $i);
+                    ptr::write_volatile(ascr, bits | (is_closed as u32) << $i);
+                })
+            }
+        }
+
+        impl<AF: AltFun> $PXi<AF> {
+            /// Sets output speed of the PIN.
+            pub fn set_speed(&mut self, ospeedr: &mut OSPEEDR<$GPIOX>, speed: Speed) {
+                Self::write_speed(ospeedr, speed);
+            }
         }
 
         impl<MODE> InputPin for $PXi<Input<MODE>> {
@@ -327,6 +557,19 @@ macro_rules! impl_pin {
             }
         }
 
+        impl InputPin for $PXi<Output<OpenDrain>> {
+            /// Returns whether line is reading low.
+            fn is_low(&self) -> bool {
+                // NOTE(unsafe) atomic read with no side effects
+                unsafe { (*$GPIOX::ptr()).idr.read().bits() & (1 << $i) == 0 }
+            }
+
+            /// Returns whether line is reading high.
+            fn is_high(&self) -> bool {
+                !self.is_low()
+            }
+        }
+
         impl<MODE> StatefulOutputPin for $PXi<Output<MODE>> {
             /// Returns whether high bit is set.
             fn is_set_high(&self) -> bool {
@@ -351,7 +594,7 @@ macro_rules! impl_pins {
 }
 
 /// Generic LED
-pub struct Led<PIN>(PIN);
+pub struct Led<PIN>(pub(crate) PIN);
 impl<PIN: OutputPin + StatefulOutputPin> Led<PIN> {
     #[inline]
     /// Turns LED off.
@@ -425,6 +668,165 @@ macro_rules! define_led {
     }
 }
 
+mod sealed {
+    /// Pin in its reset state, which can be created only by GPIO
+    pub trait Unclaimed {
+        fn new() -> Self;
+    }
+}
+
+/// Pin in reset state, which can be taken from `Partial<GPIO>`.
+///
+/// Note: MUST not be implemented by user.
+pub trait Take<GPIO>: PinId + sealed::Unclaimed {}
+
+/// GPIO, which hands out its pins on demand.
+///
+/// Board support crate can take only pins it drives and pass the rest to application:
+///
+/// ```rust, ignore
+/// let mut gpio_a = gpio::A::new_partial(&mut ahb);
+/// let led = gpio_a.take::<PA5<_>>().unwrap().into_output::<PushPull>(&mut gpio_a.moder, &mut gpio_a.otyper);
+///
+/// // Application, PA5 is gone
+/// let button: Option<PA0<_>> = gpio_a.take();
+/// ```
+pub struct Partial<GPIO> {
+    /// Opaque AFRH register
+    pub afrh: AFRH<GPIO>,
+    /// Opaque AFRL register
+    pub afrl: AFRL<GPIO>,
+    /// Opaque MODER register
+    pub moder: MODER<GPIO>,
+    /// Opaque OTYPER register
+    pub otyper: OTYPER<GPIO>,
+    /// Opaque OSPEEDR register
+    pub ospeedr: OSPEEDR<GPIO>,
+    /// Opaque PUPDR register
+    pub pupdr: PUPDR<GPIO>,
+    taken: u16,
+}
+
+impl<GPIO> Partial<GPIO> {
+    /// Takes `PIN` in its reset state, returns `None` if it has been taken already.
+    pub fn take<PIN: Take<GPIO>>(&mut self) -> Option<PIN> {
+        let mask = 1 << PIN::NUM;
+        match self.taken & mask {
+            0 => {
+                self.taken |= mask;
+                Some(PIN::new())
+            },
+            _ => None,
+        }
+    }
+
+    /// Checks whether `PIN` has been taken
+    pub fn is_taken<PIN: Take<GPIO>>(&self) -> bool {
+        self.taken & (1 << PIN::NUM) != 0
+    }
+}
+
+/// Returns registers of port with letter `port`, e.g. `b'A'`.
+///
+/// Register layout is the same for all ports, so they are described by GPIOA's block.
+pub(crate) fn port_registers(port: u8) -> &'static gpioa::RegisterBlock {
+    // Ports are laid out 0x400 bytes apart, starting with GPIOA
+    unsafe { &*((GPIOA::ptr() as usize + (port - b'A') as usize * 0x400) as *const gpioa::RegisterBlock) }
+}
+
+/// Reads input level of `PIN`, which is sampled in any mode but analog.
+pub(crate) fn input_level<PIN: PinId>(_pin: &PIN) -> bool {
+    port_registers(PIN::PORT).idr.read().bits() & (1 << PIN::NUM) != 0
+}
+
+/// Defines struct of board pins, taken from `Partial` ports and configured in one call.
+///
+/// Each pin is described by port argument, pin and mode, which is one of
+/// `input(PullMode)`, `output(OutputMode)`, `alt_fun(AFx, afrl|afrh)` and `analog`.
+/// Analog pins are left disconnected from ADC, until `set_analog_switch` is called:
+///
+/// ```rust, ignore
+/// board_pins! {
+///     /// Pins of Nucleo board
+///     pub struct Pins(gpio_a: GPIOA, gpio_c: GPIOC) {
+///         /// User LED
+///         led: gpio_a.PA5 => output(PushPull),
+///         button: gpio_c.PC13 => input(PullUp),
+///         uart_tx: gpio_a.PA2 => alt_fun(AF7, afrl),
+///         uart_rx: gpio_a.PA3 => alt_fun(AF7, afrl),
+///         vbat: gpio_c.PC1 => analog,
+///     }
+/// }
+///
+/// let mut gpio_a = gpio::A::new_partial(&mut rcc.ahb);
+/// let mut gpio_c = gpio::C::new_partial(&mut rcc.ahb);
+/// let pins = Pins::new(&mut gpio_a, &mut gpio_c).unwrap();
+/// ```
+///
+/// Pin types and modes are resolved at call site, so they must be in scope.
+#[macro_export]
+macro_rules! board_pins {
+    (@type $pin:ident input($mode:ty)) => { $pin<$crate::gpio::Input<$mode>> };
+    (@type $pin:ident output($mode:ty)) => { $pin<$crate::gpio::Output<$mode>> };
+    (@type $pin:ident alt_fun($af:ty, $afr:ident)) => { $pin<$af> };
+    (@type $pin:ident analog) => { $pin<$crate::gpio::Analog> };
+
+    (@configure $port:ident, $pin:expr, input($mode:ty)) => {
+        $pin.into_input::<$mode>(&mut $port.moder, &mut $port.pupdr)
+    };
+    (@configure $port:ident, $pin:expr, output($mode:ty)) => {
+        $pin.into_output::<$mode>(&mut $port.moder, &mut $port.otyper)
+    };
+    (@configure $port:ident, $pin:expr, alt_fun($af:ty, $afr:ident)) => {
+        $pin.into_alt_fun::<$af>(&mut $port.moder, &mut $port.$afr)
+    };
+    (@configure $port:ident, $pin:expr, analog) => {
+        $pin.into_analog_disconnected(&mut $port.moder, &mut $port.pupdr)
+    };
+
+    ($(#[$attr:meta])* $vis:vis struct $name:ident($($port:ident: $GPIO:ty),+ $(,)?) {
+        $($(#[$field_attr:meta])* $field:ident: $pin_port:ident.$pin:ident => $mode:ident $(($($arg:tt)*))?,)+
+    }) => {
+        $(#[$attr])*
+        $vis struct $name {
+            $(
+                $(#[$field_attr])*
+                pub $field: $crate::board_pins!(@type $pin $mode $(($($arg)*))?),
+            )+
+        }
+
+        impl $name {
+            /// Takes and configures all pins.
+            ///
+            /// Returns `None` and leaves ports untouched, if any of pins has been taken already
+            /// or is listed twice.
+            $vis fn new($($port: &mut $crate::gpio::Partial<$GPIO>),+) -> Option<Self> {
+                // Pins listed so far, per port, all of them are validated before any is configured
+                let mut listed = [0u16; 16];
+                $(
+                    {
+                        type Reset = $pin<$crate::gpio::Input<$crate::gpio::Floating>>;
+                        let port = usize::from(<Reset as $crate::gpio::PinId>::PORT - b'A');
+                        let mask = 1 << <Reset as $crate::gpio::PinId>::NUM;
+                        if listed[port] & mask != 0 || $pin_port.is_taken::<Reset>() {
+                            return None;
+                        }
+                        listed[port] |= mask;
+                    }
+                )+
+
+                Some(Self {
+                    $(
+                        $field: $crate::board_pins!(@configure $pin_port,
+                                                    $pin_port.take::<$pin<$crate::gpio::Input<$crate::gpio::Floating>>>()?,
+                                                    $mode $(($($arg)*))?),
+                    )+
+                })
+            }
+        }
+    };
+}
+
 /// Opaque AFRL register
 pub struct AFRL<GPIO>(PhantomData<GPIO>);
 /// Opaque AFRH register
@@ -433,6 +835,8 @@ pub struct AFRH<GPIO>(PhantomData<GPIO>);
 pub struct MODER<GPIO>(PhantomData<GPIO>);
 /// Opaque OTYPER register
 pub struct OTYPER<GPIO>(PhantomData<GPIO>);
+/// Opaque OSPEEDR register
+pub struct OSPEEDR<GPIO>(PhantomData<GPIO>);
 /// Opaque PUPDR register
 pub struct PUPDR<GPIO>(PhantomData<GPIO>);
 
@@ -461,5 +865,49 @@ impl_gpio!(C, GPIOC, gpiocen, gpiocrst,
            AFRH: [PC8, 8; PC9, 9; PC10, 10; PC11, 11; PC12, 12; PC13, 13; PC14, 14; PC15, 15; ]
           );
 
-#[cfg(feature = "STM32L476VG")]
+#[cfg(feature = "STM32L475VG")]
 pub mod stm32l475vg;
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn partial<GPIO>(taken: u16) -> Partial<GPIO> {
+        Partial {
+            afrh: AFRH(PhantomData),
+            afrl: AFRL(PhantomData),
+            moder: MODER(PhantomData),
+            otyper: OTYPER(PhantomData),
+            ospeedr: OSPEEDR(PhantomData),
+            pupdr: PUPDR(PhantomData),
+            taken,
+        }
+    }
+
+    board_pins! {
+        struct Duplicate(gpio_a: GPIOA) {
+            led: gpio_a.PA5 => output(PushPull),
+            button: gpio_a.PA0 => input(PullUp),
+            again: gpio_a.PA5 => analog,
+        }
+    }
+
+    board_pins! {
+        struct Taken(gpio_a: GPIOA, gpio_c: GPIOC) {
+            led: gpio_a.PA5 => output(PushPull),
+            button: gpio_c.PC13 => input(PullUp),
+        }
+    }
+
+    #[test]
+    fn board_pins_validated_before_configured() {
+        let mut gpio_a = partial::<GPIOA>(0);
+        assert!(Duplicate::new(&mut gpio_a).is_none());
+        assert_eq!(gpio_a.taken, 0);
+
+        let mut gpio_a = partial::<GPIOA>(0);
+        let mut gpio_c = partial::<GPIOC>(1 << 13);
+        assert!(Taken::new(&mut gpio_a, &mut gpio_c).is_none());
+        assert_eq!(gpio_a.taken, 0);
+    }
+}
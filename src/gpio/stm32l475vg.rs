@@ -6,8 +6,6 @@
 //! use stm32l4::stm32l4x5_hal::gpio::stm32l476vg::gpio;
 //! ```
 
-use stm32l4::stm32l4x5;
-
 use super::*;
 
 impl_parts!(
@@ -0,0 +1,143 @@
+//! Master transfers over DMA
+//!
+//! DMA moves all data of transfer at once, while NBYTES is reloaded for every chunk
+//! of 255 bytes, so pages of EEPROM or frames of display are written in single transfer.
+//! Write followed by read keeps the bus with repeated START, e.g. for random read of EEPROM:
+//!
+//! ```rust, ignore
+//! let mut i2c = i2c.with_dma(channels.C6, channels.C7);
+//! i2c.write(EEPROM, &page)?;
+//! i2c.write_read(EEPROM, &[0x01, 0x00], &mut buffer)?;
+//! ```
+//!
+//! Calls block until transfer completes, as reload is served by CPU.
+
+use core::sync::atomic::{self, Ordering};
+
+use embedded_hal::blocking::i2c::{Read, Write, WriteRead};
+
+use crate::dma::{DmaChannel, Direction, Event, WordSize};
+
+use super::{Address, Error, I2c, InnerI2c, MAX_NBYTES, SCL, SDA};
+
+/// I2C master served by DMA.
+///
+/// Created by `I2c::with_dma`.
+pub struct I2cDma<I2C: InnerI2c, SCL, SDA> {
+    i2c: I2c<I2C, SCL, SDA>,
+    tx: I2C::TxChannel,
+    rx: I2C::RxChannel,
+}
+
+impl<I2C: InnerI2c, SC: SCL, SD: SDA> I2cDma<I2C, SC, SD> {
+    pub(crate) fn new(i2c: I2c<I2C, SC, SD>, tx: I2C::TxChannel, rx: I2C::RxChannel) -> Self {
+        Self {
+            i2c,
+            tx,
+            rx,
+        }
+    }
+
+    /// Disables DMA requests, releasing I2C with DMA channels
+    pub fn free(self) -> (I2c<I2C, SC, SD>, (I2C::TxChannel, I2C::RxChannel)) {
+        self.i2c.i2c.cr1.modify(|_, w| w.txdmaen().clear_bit().rxdmaen().clear_bit());
+        (self.i2c, (self.tx, self.rx))
+    }
+}
+
+/// Waits for completion of started transfer with `len` bytes, serving reloads.
+///
+/// DMA error stops I2C as timeout would.
+fn serve<I2C, SC, SD, C>(i2c: &I2c<I2C, SC, SD>, channel: &C, len: usize, autoend: bool) -> Result<(), Error>
+    where I2C: InnerI2c, SC: SCL, SD: SDA, C: DmaChannel {
+    let mut is_expired = || channel.is_event(Event::TransferError);
+
+    let mut remaining = len;
+    while remaining > MAX_NBYTES {
+        remaining -= MAX_NBYTES;
+        i2c.reload(remaining, &mut is_expired)?;
+    }
+
+    match autoend {
+        true => i2c.wait_stop(&mut is_expired)?,
+        false => i2c.wait(|isr| isr.tc().bit_is_set(), &mut is_expired)?,
+    }
+
+    // Last received byte may be still moved by DMA
+    while len > 0 && channel.in_progress() && !is_expired() {}
+
+    Ok(())
+}
+
+/// Transfers `len` bytes at `ptr` with `channel`.
+///
+/// With `autoend` transfer ends with STOP, otherwise bus is kept for repeated START.
+fn transfer<I2C, SC, SD, C>(i2c: &I2c<I2C, SC, SD>, channel: &mut C, address: Address, ptr: u32, len: usize, read: bool, autoend: bool) -> Result<(), Error>
+    where I2C: InnerI2c, SC: SCL, SD: SDA, C: DmaChannel {
+    if len > u16::max_value() as usize {
+        return Err(Error::TooLong);
+    }
+
+    let direction = match read {
+        true => Direction::PeripheralToMemory,
+        false => Direction::MemoryToPeripheral,
+    };
+    channel.stop();
+    if len > 0 {
+        // NOTE(unsafe) caller keeps buffer at `ptr` borrowed until transfer ends
+        unsafe { channel.set_memory_address(ptr, true) };
+        channel.set_transfer_length(len as u16);
+        channel.configure(direction, false, WordSize::Byte);
+
+        atomic::compiler_fence(Ordering::SeqCst);
+
+        channel.start();
+    }
+
+    i2c.start(address, len, read, autoend, false);
+    let result = serve(i2c, channel, len, autoend);
+    let is_dma_error = channel.is_event(Event::TransferError);
+    channel.stop();
+
+    atomic::compiler_fence(Ordering::SeqCst);
+
+    match is_dma_error {
+        true => Err(Error::Dma),
+        false => result,
+    }
+}
+
+impl<I2C: InnerI2c, SC: SCL, SD: SDA> Write for I2cDma<I2C, SC, SD> {
+    type Error = Error;
+
+    /// Writes up to 65535 bytes to slave, longer `bytes` are rejected with `TooLong` error.
+    fn write(&mut self, address: u8, bytes: &[u8]) -> Result<(), Error> {
+        transfer(&self.i2c, &mut self.tx, Address::SevenBit(address), bytes.as_ptr() as u32, bytes.len(), false, true)
+    }
+}
+
+impl<I2C: InnerI2c, SC: SCL, SD: SDA> Read for I2cDma<I2C, SC, SD> {
+    type Error = Error;
+
+    /// Reads up to 65535 bytes from slave, longer `buffer` is rejected with `TooLong` error.
+    fn read(&mut self, address: u8, buffer: &mut [u8]) -> Result<(), Error> {
+        transfer(&self.i2c, &mut self.rx, Address::SevenBit(address), buffer.as_mut_ptr() as u32, buffer.len(), true, true)
+    }
+}
+
+impl<I2C: InnerI2c, SC: SCL, SD: SDA> WriteRead for I2cDma<I2C, SC, SD> {
+    type Error = Error;
+
+    /// Writes and reads up to 65535 bytes each with repeated START in between.
+    ///
+    /// Both lengths are checked before write starts, so the bus isn't left held.
+    fn write_read(&mut self, address: u8, bytes: &[u8], buffer: &mut [u8]) -> Result<(), Error> {
+        if buffer.len() > u16::max_value() as usize {
+            return Err(Error::TooLong);
+        }
+
+        let address = Address::SevenBit(address);
+        transfer(&self.i2c, &mut self.tx, address, bytes.as_ptr() as u32, bytes.len(), false, false)?;
+        transfer(&self.i2c, &mut self.rx, address, buffer.as_mut_ptr() as u32, buffer.len(), true, true)
+    }
+}
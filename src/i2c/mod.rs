@@ -0,0 +1,823 @@
+//! Inter-Integrated Circuit (I2C) interface
+//!
+//! Blocking master mode with 7-bit and 10-bit addressing and general call,
+//! and slave mode with wakeup from Stop on own address match.
+//! Master transfers longer than 255 bytes are split with NBYTES reload, and can be served by [DMA](dma/index.html).
+//!
+//! See Reference manual Ch. 39
+
+use core::ops::Deref;
+
+use embedded_hal::blocking::i2c::{Read, Write, WriteRead};
+use embedded_hal::timer::CountDown;
+use stm32l4::stm32l4x5::{i2c1, I2C1, I2C2, I2C3};
+
+use crate::common::{ConfigError, RawRegister};
+use crate::dma::{dma1, DmaChannel};
+use crate::gpio::af::{self, Signal};
+use crate::gpio::{self, AltFunPin, PinId, AF4, PB10, PB11, PB13, PB14, PB6, PB7, PB8, PB9, PC0, PC1};
+use crate::rcc::{Clocks, APB1, CCIPR};
+use crate::rcc::clocking::HSI16_FREQ;
+use crate::selftest;
+use crate::time::Hertz;
+
+pub mod dma;
+pub use self::dma::I2cDma;
+
+/// Maximal number of bytes in single transfer without reload
+const MAX_NBYTES: usize = 255;
+
+/// Returns NBYTES of next chunk of transfer with `remaining` bytes, and whether
+/// NBYTES must be reloaded after it.
+fn next_chunk(remaining: usize) -> (u8, bool) {
+    match remaining > MAX_NBYTES {
+        true => (MAX_NBYTES as u8, true),
+        false => (remaining as u8, false),
+    }
+}
+
+///Describes SCL Pin
+pub trait SCL {
+    ///I2C index
+    const I2C_IDX: u8;
+}
+
+///Describes SDA Pin
+pub trait SDA {
+    ///I2C index
+    const I2C_IDX: u8;
+}
+
+macro_rules! impl_pins_trait {
+    ($IDX:expr => {
+        TRAIT: $TRAIT:ident,
+        AF: $AFx:ident,
+        PINS: [$($PIN:ident,)+]
+    }) => {
+        $(
+            impl $TRAIT for $PIN<$AFx> {
+                const I2C_IDX: u8 = $IDX;
+            }
+        )+
+    }
+}
+
+impl_pins_trait!(1 => {
+    TRAIT: SCL,
+    AF: AF4,
+    PINS: [PB6, PB8,]
+});
+impl_pins_trait!(1 => {
+    TRAIT: SDA,
+    AF: AF4,
+    PINS: [PB7, PB9,]
+});
+impl_pins_trait!(2 => {
+    TRAIT: SCL,
+    AF: AF4,
+    PINS: [PB10, PB13,]
+});
+impl_pins_trait!(2 => {
+    TRAIT: SDA,
+    AF: AF4,
+    PINS: [PB11, PB14,]
+});
+impl_pins_trait!(3 => {
+    TRAIT: SCL,
+    AF: AF4,
+    PINS: [PC0,]
+});
+impl_pins_trait!(3 => {
+    TRAIT: SDA,
+    AF: AF4,
+    PINS: [PC1,]
+});
+
+#[derive(Copy, Clone, PartialEq, Eq, Debug)]
+///Kernel clock of I2C
+pub enum ClockSource {
+    ///APB1 clock, stopped in Stop mode
+    Pclk,
+    ///System clock, stopped in Stop mode
+    Sysclk,
+    ///HSI16 oscillator, required for wakeup from Stop mode
+    Hsi16,
+}
+
+impl ClockSource {
+    ///Returns frequency of kernel clock
+    pub fn freq(self, clocks: &Clocks) -> Hertz {
+        match self {
+            ClockSource::Pclk => clocks.pclk1(),
+            ClockSource::Sysclk => clocks.sysclk(),
+            ClockSource::Hsi16 => Hertz(HSI16_FREQ),
+        }
+    }
+
+    fn bits(self) -> u8 {
+        match self {
+            ClockSource::Pclk => 0b00,
+            ClockSource::Sysclk => 0b01,
+            ClockSource::Hsi16 => 0b10,
+        }
+    }
+}
+
+/// I2C errors.
+#[derive(Copy, Clone, PartialEq, Eq, Debug)]
+pub enum Error {
+    /// Misplaced START or STOP condition
+    Bus,
+    /// Arbitration lost
+    Arbitration,
+    /// Address or data byte is not acknowledged
+    Nack,
+    /// Overrun or underrun in slave mode
+    Overrun,
+    /// Transfer hasn't completed within timeout
+    Timeout,
+    /// DMA bus error
+    Dma,
+    /// Transfer over DMA is longer than 65535 bytes
+    TooLong,
+}
+
+/// Slave address
+#[derive(Copy, Clone, PartialEq, Eq, Debug)]
+pub enum Address {
+    /// 7-bit address
+    SevenBit(u8),
+    /// 10-bit address
+    TenBit(u16),
+}
+
+/// Address of general call, broadcast to all slaves
+pub const GENERAL_CALL: u8 = 0x00;
+
+/// I2C events.
+pub enum Event {
+    /// Own address matched, wakes up from Stop when wakeup is enabled.
+    AddressMatch,
+    /// Byte received
+    Rx,
+    /// Transmit register is empty
+    Tx,
+    /// STOP condition detected
+    Stop,
+    /// NACK received
+    Nack,
+    /// Bus, arbitration or overrun error
+    Error,
+}
+
+/// Direction of transfer requested by master, seen from slave side.
+#[derive(Copy, Clone, PartialEq, Eq, Debug)]
+pub enum Direction {
+    /// Master writes, slave receives
+    Write,
+    /// Master reads, slave transmits
+    Read,
+}
+
+///Returns maximal SCL/SDA rise and fall times (ns) allowed by specification in mode of `freq`.
+pub const fn max_slopes(freq: u32) -> (u32, u32) {
+    match freq {
+        0..=100_000 => (1000, 300),
+        100_001..=400_000 => (300, 300),
+        _ => (120, 120),
+    }
+}
+
+///Calculates TIMINGR value to derive SCL `freq` from I2C kernel `clock`.
+///
+///Standard mode is used up to 100 kHz, Fast mode up to 400 kHz and Fast mode Plus up to 1 MHz.
+///Bus `rise_ns` and `fall_ns` times extend data setup/hold delays and are subtracted from SCL
+///period, as SCL edges are delayed by them. Frequency is rounded down.
+pub const fn calc_timingr(clock: u32, freq: u32, rise_ns: u32, fall_ns: u32) -> Result<u32, ConfigError> {
+    // Data setup time (ns) and minimal SCL low/high periods (ns) in mode
+    let (setup_ns, low_ns, high_ns) = match freq {
+        0 => return Err(ConfigError::FrequencyTooLow),
+        1..=100_000 => (250u64, 4700u64, 4000u64),
+        100_001..=400_000 => (100, 1300, 600),
+        400_001..=1_000_000 => (50, 500, 260),
+        _ => return Err(ConfigError::FrequencyTooHigh),
+    };
+    // Products of delays and clock don't fit into u32 for slow buses
+    let clock_khz = (clock / 1000) as u64;
+    let scldel_ns = setup_ns + rise_ns as u64;
+    // Minimal analog filter delay
+    let sdadel_ns = fall_ns.saturating_sub(50) as u64;
+    let slopes = ((rise_ns as u64 + fall_ns as u64) * clock_khz + 999_999) / 1_000_000;
+
+    let period = (clock / freq) as u64;
+    if period < 8 + slopes {
+        return Err(ConfigError::FrequencyTooHigh);
+    }
+
+    let mut presc = 0;
+    while presc < 16 {
+        let cycles = (period - slopes) / (presc + 1);
+        let prescaled_khz = clock_khz / (presc + 1);
+        let scldel = (scldel_ns * prescaled_khz + 999_999) / 1_000_000;
+        let sdadel = (sdadel_ns * prescaled_khz + 999_999) / 1_000_000;
+        let scll = (cycles * low_ns + low_ns + high_ns - 1) / (low_ns + high_ns);
+        let sclh = cycles - scll;
+
+        // SCLL and SCLH hold periods minus one, so both must be within 1..=256
+        if scll > 256 || sclh > 256 || scldel > 16 || sdadel > 15 {
+            presc += 1;
+            continue;
+        }
+        if scll == 0 || sclh == 0 {
+            return Err(ConfigError::FrequencyTooHigh);
+        }
+        let bits = presc << 28 | scldel.saturating_sub(1) << 20 | sdadel << 16 | (sclh - 1) << 8 | (scll - 1);
+        return Ok(bits as u32);
+    }
+
+    Err(ConfigError::FrequencyTooLow)
+}
+
+impl_raw_register!(i2c1::CR1, i2c1::TIMINGR,);
+
+///Peripheral enable bit of CR1
+const CR1_PE: u32 = 1;
+
+///Writes `value` into TIMINGR, which is writable only while peripheral is disabled.
+fn write_timingr(cr1: &dyn RawRegister, timingr: &dyn RawRegister, value: u32) {
+    cr1.write_bits(cr1.read_bits() & !CR1_PE);
+    timingr.write_bits(value);
+    cr1.write_bits(cr1.read_bits() | CR1_PE);
+}
+
+///Calculates TIMINGR at compile time for presets, assuming maximal rise and fall times.
+const fn preset(clock: u32, freq: u32) -> u32 {
+    let (rise_ns, fall_ns) = max_slopes(freq);
+    match calc_timingr(clock, freq, rise_ns, fall_ns) {
+        Ok(timingr) => timingr,
+        Err(_) => panic!("I2C timing preset is out of range"),
+    }
+}
+
+///TIMINGR presets for common kernel clocks, with maximal rise and fall times of each mode.
+pub mod presets {
+    use super::preset;
+
+    ///8 MHz kernel clock, 100 kHz SCL
+    pub const CLK8MHZ_100KHZ: u32 = preset(8_000_000, 100_000);
+    ///8 MHz kernel clock, 400 kHz SCL
+    pub const CLK8MHZ_400KHZ: u32 = preset(8_000_000, 400_000);
+    ///16 MHz kernel clock, 100 kHz SCL
+    pub const CLK16MHZ_100KHZ: u32 = preset(16_000_000, 100_000);
+    ///16 MHz kernel clock, 400 kHz SCL
+    pub const CLK16MHZ_400KHZ: u32 = preset(16_000_000, 400_000);
+    ///16 MHz kernel clock, 1 MHz SCL
+    pub const CLK16MHZ_1MHZ: u32 = preset(16_000_000, 1_000_000);
+    ///48 MHz kernel clock, 100 kHz SCL
+    pub const CLK48MHZ_100KHZ: u32 = preset(48_000_000, 100_000);
+    ///48 MHz kernel clock, 400 kHz SCL
+    pub const CLK48MHZ_400KHZ: u32 = preset(48_000_000, 400_000);
+    ///48 MHz kernel clock, 1 MHz SCL
+    pub const CLK48MHZ_1MHZ: u32 = preset(48_000_000, 1_000_000);
+    ///80 MHz kernel clock, 100 kHz SCL
+    pub const CLK80MHZ_100KHZ: u32 = preset(80_000_000, 100_000);
+    ///80 MHz kernel clock, 400 kHz SCL
+    pub const CLK80MHZ_400KHZ: u32 = preset(80_000_000, 400_000);
+    ///80 MHz kernel clock, 1 MHz SCL
+    pub const CLK80MHZ_1MHZ: u32 = preset(80_000_000, 1_000_000);
+}
+
+///Describes raw I2C from device crate
+pub trait InnerI2c: Deref<Target = i2c1::RegisterBlock> {
+    ///Index of I2C, used at runtime to verify that correct PIN is used.
+    const IDX: u8;
+
+    ///DMA channel serving transmitter
+    type TxChannel: DmaChannel;
+
+    ///DMA channel serving receiver
+    type RxChannel: DmaChannel;
+
+    ///Request of I2C on its DMA channels (CSELR value)
+    const DMA_REQUEST: u8;
+
+    ///Resets and enables I2C
+    fn enable(apb: &mut APB1);
+
+    ///Selects kernel clock of I2C
+    fn select_clock(ccipr: &mut CCIPR, clock: ClockSource);
+}
+
+macro_rules! impl_inner_i2c {
+    ($($I2CX:ident: ($idx:expr, $i2cxen:ident, $i2cxrst:ident, $i2cxsel:ident, $TX:ident, $RX:ident),)+) => {
+        $(
+            impl InnerI2c for $I2CX {
+                const IDX: u8 = $idx;
+                type TxChannel = dma1::$TX;
+                type RxChannel = dma1::$RX;
+                const DMA_REQUEST: u8 = 0b0011;
+
+                fn enable(apb: &mut APB1) {
+                    apb.enr1().modify(|_, w| w.$i2cxen().set_bit());
+                    apb.rstr1().modify(|_, w| w.$i2cxrst().set_bit());
+                    apb.rstr1().modify(|_, w| w.$i2cxrst().clear_bit());
+                }
+
+                fn select_clock(ccipr: &mut CCIPR, clock: ClockSource) {
+                    if clock == ClockSource::Hsi16 {
+                        ccipr.hsi16_enable();
+                    }
+                    ccipr.inner().modify(|_, w| unsafe { w.$i2cxsel().bits(clock.bits()) });
+                }
+            }
+        )+
+    }
+}
+
+impl_inner_i2c!(
+    I2C1: (1, i2c1en, i2c1rst, i2c1sel, C6, C7),
+    I2C2: (2, i2c2en, i2c2rst, i2c2sel, C4, C5),
+    I2C3: (3, i2c3en, i2c3rst, i2c3sel, C2, C3),
+);
+
+/// I2C
+pub struct I2c<I2C, SCL, SDA> {
+    i2c: I2C,
+    pins: (SCL, SDA),
+    freq: Hertz,
+}
+
+impl<I2C: InnerI2c, SC: SCL, SD: SDA> I2c<I2C, SC, SD> {
+    /// Creates new instance of I2C running SCL at `freq`, using `clock` as kernel clock.
+    ///
+    /// Timing assumes maximal rise and fall times of the mode, use `set_timingr` to override it.
+    /// Returns error if `freq` cannot be derived from kernel clock.
+    ///
+    /// # Pancis:
+    ///
+    /// In debug mode the function checks if index of each PIN corresponds to I2C's index,
+    /// and that PINs carry I2C signals according to [AF table](../gpio/af/index.html).
+    pub fn new(i2c: I2C, pins: (SC, SD), freq: Hertz, clock: ClockSource, clocks: &Clocks, apb: &mut APB1, ccipr: &mut CCIPR) -> Result<Self, ConfigError>
+        where SC: AltFunPin, SD: AltFunPin {
+        debug_assert_eq!(I2C::IDX, SC::I2C_IDX);
+        debug_assert_eq!(I2C::IDX, SD::I2C_IDX);
+        debug_assert!(af::has_af(&pins.0, Signal::I2cScl(I2C::IDX)));
+        debug_assert!(af::has_af(&pins.1, Signal::I2cSda(I2C::IDX)));
+
+        let (rise_ns, fall_ns) = max_slopes(freq.0);
+        let timingr = calc_timingr(clock.freq(clocks).0, freq.0, rise_ns, fall_ns)?;
+
+        I2C::enable(apb);
+        I2C::select_clock(ccipr, clock);
+
+        i2c.cr1.write(|w| w.pe().clear_bit());
+        write_timingr(&i2c.cr1, &i2c.timingr, timingr);
+
+        Ok(Self {
+            i2c,
+            pins,
+            freq,
+        })
+    }
+
+    /// Overrides timing with raw TIMINGR value, e.g. from [presets](presets/index.html)
+    /// or `calc_timingr` with measured rise and fall times.
+    pub fn set_timingr(&mut self, timingr: u32) {
+        write_timingr(&self.i2c.cr1, &self.i2c.timingr, timingr);
+    }
+
+    /// Sets 7-bit own address, to which I2C responds as slave.
+    pub fn set_own_address(&mut self, address: u8) {
+        self.i2c.oar1.write(|w| w.oa1en().clear_bit());
+        self.i2c.oar1.write(|w| w.oa1().bits((address as u16) << 1).oa1mode().clear_bit().oa1en().set_bit());
+    }
+
+    /// Enables wakeup from Stop mode on own address match.
+    ///
+    /// Kernel clock is switched to HSI16 and timing is recalculated for it, as other
+    /// clocks are stopped in Stop mode. Digital filter is disabled, as required for wakeup.
+    /// [AddressMatch](enum.Event.html) event must be subscribed to wake up the MCU.
+    pub fn enable_wakeup(&mut self, ccipr: &mut CCIPR) -> Result<(), ConfigError> {
+        let (rise_ns, fall_ns) = max_slopes(self.freq.0);
+        let timingr = calc_timingr(HSI16_FREQ, self.freq.0, rise_ns, fall_ns)?;
+
+        self.i2c.cr1.modify(|_, w| w.pe().clear_bit());
+        I2C::select_clock(ccipr, ClockSource::Hsi16);
+        self.i2c.timingr.write(|w| unsafe { w.bits(timingr) });
+        self.i2c.cr1.modify(|_, w| w.dnf().bits(0).wupen().set_bit().pe().set_bit());
+
+        Ok(())
+    }
+
+    /// Disables wakeup from Stop mode, kernel clock is left as HSI16.
+    pub fn disable_wakeup(&mut self) {
+        self.i2c.cr1.modify(|_, w| w.wupen().clear_bit());
+    }
+
+    /// Starts listening for event.
+    pub fn subscribe(&mut self, event: Event) {
+        self.i2c.cr1.modify(|_, w| match event {
+            Event::AddressMatch => w.addrie().set_bit(),
+            Event::Rx => w.rxie().set_bit(),
+            Event::Tx => w.txie().set_bit(),
+            Event::Stop => w.stopie().set_bit(),
+            Event::Nack => w.nackie().set_bit(),
+            Event::Error => w.errie().set_bit(),
+        });
+    }
+
+    /// Stops listening for event.
+    pub fn unsubscribe(&mut self, event: Event) {
+        self.i2c.cr1.modify(|_, w| match event {
+            Event::AddressMatch => w.addrie().clear_bit(),
+            Event::Rx => w.rxie().clear_bit(),
+            Event::Tx => w.txie().clear_bit(),
+            Event::Stop => w.stopie().clear_bit(),
+            Event::Nack => w.nackie().clear_bit(),
+            Event::Error => w.errie().clear_bit(),
+        });
+    }
+
+    /// Returns direction of transfer, if I2C has been addressed as slave.
+    ///
+    /// Address match flag is cleared, which releases SCL.
+    pub fn addressed(&mut self) -> Option<Direction> {
+        let isr = self.i2c.isr.read();
+        if isr.addr().bit_is_clear() {
+            return None;
+        }
+
+        let direction = match isr.dir().bit_is_set() {
+            true => Direction::Read,
+            false => Direction::Write,
+        };
+        self.i2c.icr.write(|w| w.addrcf().set_bit());
+
+        Some(direction)
+    }
+
+    /// Returns whether STOP condition has been detected, clearing the flag.
+    pub fn is_stopped(&mut self) -> bool {
+        let is_stopped = self.i2c.isr.read().stopf().bit_is_set();
+        if is_stopped {
+            self.i2c.icr.write(|w| w.stopcf().set_bit());
+        }
+        is_stopped
+    }
+
+    /// Runs bus [self-test](../selftest/index.html), checking that SCL and SDA are released.
+    ///
+    /// Line held low, e.g. by solder bridge or missing pull-up, is reported as `BusStuck`.
+    pub fn selftest(&mut self) -> Result<(), selftest::Fault> where SC: PinId, SD: PinId {
+        let is_released = gpio::input_level(&self.pins.0) && gpio::input_level(&self.pins.1);
+
+        match is_released && self.i2c.isr.read().busy().bit_is_clear() {
+            true => Ok(()),
+            false => Err(selftest::Fault::BusStuck),
+        }
+    }
+
+    /// Reads byte received in slave mode.
+    pub fn read_byte(&mut self) -> nb::Result<u8, Error> {
+        let isr = self.i2c.isr.read();
+        self.check_errors()?;
+
+        match isr.rxne().bit_is_set() {
+            true => Ok(self.i2c.rxdr.read().rxdata().bits()),
+            false => Err(nb::Error::WouldBlock),
+        }
+    }
+
+    /// Writes byte to be transmitted in slave mode.
+    pub fn write_byte(&mut self, byte: u8) -> nb::Result<(), Error> {
+        let isr = self.i2c.isr.read();
+        self.check_errors()?;
+
+        match isr.txis().bit_is_set() {
+            true => {
+                self.i2c.txdr.write(|w| w.txdata().bits(byte));
+                Ok(())
+            }
+            false => Err(nb::Error::WouldBlock),
+        }
+    }
+
+    /// Checks error flags, clearing the one found.
+    fn check_errors(&self) -> Result<(), Error> {
+        let isr = self.i2c.isr.read();
+
+        if isr.berr().bit_is_set() {
+            self.i2c.icr.write(|w| w.berrcf().set_bit());
+            Err(Error::Bus)
+        } else if isr.arlo().bit_is_set() {
+            self.i2c.icr.write(|w| w.arlocf().set_bit());
+            Err(Error::Arbitration)
+        } else if isr.ovr().bit_is_set() {
+            self.i2c.icr.write(|w| w.ovrcf().set_bit());
+            Err(Error::Overrun)
+        } else if isr.nackf().bit_is_set() {
+            self.i2c.icr.write(|w| w.nackcf().set_bit());
+            // Flush transmit register, it holds byte that hasn't been sent
+            self.i2c.isr.write(|w| w.txe().set_bit());
+            Err(Error::Nack)
+        } else {
+            Ok(())
+        }
+    }
+
+    /// Waits until flag is set by `is_set`, returning errors.
+    ///
+    /// Once `is_expired`, STOP is requested to release the bus and `Timeout` is returned.
+    fn wait<F, X>(&self, is_set: F, is_expired: &mut X) -> Result<(), Error>
+        where F: Fn(&i2c1::isr::R) -> bool, X: FnMut() -> bool {
+        loop {
+            self.check_errors()?;
+            if is_set(&self.i2c.isr.read()) {
+                return Ok(());
+            }
+            if is_expired() {
+                self.i2c.cr2.modify(|_, w| w.stop().set_bit());
+                return Err(Error::Timeout);
+            }
+        }
+    }
+
+    /// Starts master transfer of `len` bytes.
+    ///
+    /// Transfer longer than 255 bytes starts with first chunk, followed by reload.
+    /// With `head10r` only header of 10-bit address is sent for read, following write
+    /// to the same slave.
+    fn start(&self, address: Address, len: usize, read: bool, autoend: bool, head10r: bool) {
+        let (nbytes, reload) = next_chunk(len);
+
+        let (sadd, add10) = match address {
+            Address::SevenBit(address) => ((address as u16) << 1, false),
+            Address::TenBit(address) => {
+                debug_assert!(address < 0x400);
+                (address, true)
+            },
+        };
+
+        // STOP following NACK of previous transfer is not waited for
+        self.i2c.icr.write(|w| w.stopcf().set_bit());
+
+        self.i2c.cr2.write(|w| {
+            w.sadd().bits(sadd)
+             .add10().bit(add10)
+             .head10r().bit(head10r)
+             .rd_wrn().bit(read)
+             .nbytes().bits(nbytes)
+             .reload().bit(reload)
+             .autoend().bit(autoend)
+             .start().set_bit()
+        });
+    }
+
+    /// Waits for end of chunk (TCR), then loads next one out of `remaining` bytes.
+    fn reload<X: FnMut() -> bool>(&self, remaining: usize, is_expired: &mut X) -> Result<(), Error> {
+        self.wait(|isr| isr.tcr().bit_is_set(), is_expired)?;
+        let (nbytes, reload) = next_chunk(remaining);
+        // Writing NBYTES clears TCR
+        self.i2c.cr2.modify(|_, w| w.nbytes().bits(nbytes).reload().bit(reload));
+        Ok(())
+    }
+
+    /// Waits for STOP generated by AUTOEND
+    fn wait_stop<X: FnMut() -> bool>(&self, is_expired: &mut X) -> Result<(), Error> {
+        self.wait(|isr| isr.stopf().bit_is_set(), is_expired)?;
+        self.i2c.icr.write(|w| w.stopcf().set_bit());
+        Ok(())
+    }
+
+    fn write_bytes<X: FnMut() -> bool>(&self, bytes: &[u8], is_expired: &mut X) -> Result<(), Error> {
+        for (idx, chunk) in bytes.chunks(MAX_NBYTES).enumerate() {
+            if idx > 0 {
+                self.reload(bytes.len() - idx * MAX_NBYTES, is_expired)?;
+            }
+            for byte in chunk {
+                self.wait(|isr| isr.txis().bit_is_set(), is_expired)?;
+                self.i2c.txdr.write(|w| w.txdata().bits(*byte));
+            }
+        }
+        Ok(())
+    }
+
+    fn read_bytes<X: FnMut() -> bool>(&self, buffer: &mut [u8], is_expired: &mut X) -> Result<(), Error> {
+        let len = buffer.len();
+        for (idx, chunk) in buffer.chunks_mut(MAX_NBYTES).enumerate() {
+            if idx > 0 {
+                self.reload(len - idx * MAX_NBYTES, is_expired)?;
+            }
+            for byte in chunk {
+                self.wait(|isr| isr.rxne().bit_is_set(), is_expired)?;
+                *byte = self.i2c.rxdr.read().rxdata().bits();
+            }
+        }
+        Ok(())
+    }
+
+    fn write_to<X: FnMut() -> bool>(&mut self, address: Address, bytes: &[u8], is_expired: &mut X) -> Result<(), Error> {
+        self.start(address, bytes.len(), false, true, false);
+        self.write_bytes(bytes, is_expired)?;
+        self.wait_stop(is_expired)
+    }
+
+    fn read_from<X: FnMut() -> bool>(&mut self, address: Address, buffer: &mut [u8], is_expired: &mut X) -> Result<(), Error> {
+        self.start(address, buffer.len(), true, true, false);
+        self.read_bytes(buffer, is_expired)?;
+        self.wait_stop(is_expired)
+    }
+
+    fn write_read_with<X: FnMut() -> bool>(&mut self, address: Address, bytes: &[u8], buffer: &mut [u8], is_expired: &mut X) -> Result<(), Error> {
+        self.start(address, bytes.len(), false, false, false);
+        self.write_bytes(bytes, is_expired)?;
+        self.wait(|isr| isr.tc().bit_is_set(), is_expired)?;
+
+        self.start(address, buffer.len(), true, true, true);
+        self.read_bytes(buffer, is_expired)?;
+        self.wait_stop(is_expired)
+    }
+
+    /// Writes to slave, or returns `Timeout` once started `timer` expires.
+    ///
+    /// Timeout protects against absent device holding clock stretched, or wedged bus.
+    pub fn write_with_timeout<C: CountDown>(&mut self, address: u8, bytes: &[u8], timer: &mut C) -> Result<(), Error> {
+        self.write_to(Address::SevenBit(address), bytes, &mut || timer.wait().is_ok())
+    }
+
+    /// Reads from slave, or returns `Timeout` once started `timer` expires.
+    pub fn read_with_timeout<C: CountDown>(&mut self, address: u8, buffer: &mut [u8], timer: &mut C) -> Result<(), Error> {
+        self.read_from(Address::SevenBit(address), buffer, &mut || timer.wait().is_ok())
+    }
+
+    /// Writes and reads bytes, or returns `Timeout` once started `timer` expires.
+    pub fn write_read_with_timeout<C: CountDown>(&mut self, address: u8, bytes: &[u8], buffer: &mut [u8], timer: &mut C) -> Result<(), Error> {
+        self.write_read_with(Address::SevenBit(address), bytes, buffer, &mut || timer.wait().is_ok())
+    }
+
+    /// Writes to slave with 10-bit `address`.
+    pub fn write_10bit(&mut self, address: u16, bytes: &[u8]) -> Result<(), Error> {
+        self.write_to(Address::TenBit(address), bytes, &mut || false)
+    }
+
+    /// Reads from slave with 10-bit `address`.
+    ///
+    /// Full 10-bit address is sent, followed by repeated START with address header for read.
+    pub fn read_10bit(&mut self, address: u16, buffer: &mut [u8]) -> Result<(), Error> {
+        self.read_from(Address::TenBit(address), buffer, &mut || false)
+    }
+
+    /// Writes and reads bytes from slave with 10-bit `address`.
+    ///
+    /// Read follows with repeated START and address header only, as slave is already addressed.
+    pub fn write_read_10bit(&mut self, address: u16, bytes: &[u8], buffer: &mut [u8]) -> Result<(), Error> {
+        self.write_read_with(Address::TenBit(address), bytes, buffer, &mut || false)
+    }
+
+    /// Broadcasts to all slaves with general call.
+    ///
+    /// First byte defines meaning of the call, e.g. `0x06` requests reset and write of
+    /// programmable part of slave address.
+    pub fn general_call(&mut self, bytes: &[u8]) -> Result<(), Error> {
+        self.write_to(Address::SevenBit(GENERAL_CALL), bytes, &mut || false)
+    }
+
+    /// Enables acknowledge of general call in slave mode
+    pub fn enable_general_call(&mut self, is_on: bool) {
+        self.i2c.cr1.modify(|_, w| w.gcen().bit(is_on));
+    }
+
+    /// Makes master transfers to be served by DMA `tx` and `rx` channels.
+    pub fn with_dma(self, mut tx: I2C::TxChannel, mut rx: I2C::RxChannel) -> I2cDma<I2C, SC, SD> {
+        tx.stop();
+        tx.set_request(I2C::DMA_REQUEST);
+        // NOTE(unsafe) data registers of owned I2C
+        unsafe { tx.set_peripheral_address(&self.i2c.txdr as *const _ as u32, false) };
+        rx.stop();
+        rx.set_request(I2C::DMA_REQUEST);
+        unsafe { rx.set_peripheral_address(&self.i2c.rxdr as *const _ as u32, false) };
+
+        self.i2c.cr1.modify(|_, w| w.txdmaen().set_bit().rxdmaen().set_bit());
+
+        I2cDma::new(self, tx, rx)
+    }
+
+    /// Consumes self and returns I2C and PINS
+    pub fn into_raw(self) -> (I2C, (SC, SD)) {
+        (self.i2c, self.pins)
+    }
+}
+
+impl<I2C: InnerI2c, SC: SCL, SD: SDA> Write for I2c<I2C, SC, SD> {
+    type Error = Error;
+
+    /// Writes to slave.
+    fn write(&mut self, address: u8, bytes: &[u8]) -> Result<(), Error> {
+        self.write_to(Address::SevenBit(address), bytes, &mut || false)
+    }
+}
+
+impl<I2C: InnerI2c, SC: SCL, SD: SDA> Read for I2c<I2C, SC, SD> {
+    type Error = Error;
+
+    /// Reads from slave.
+    fn read(&mut self, address: u8, buffer: &mut [u8]) -> Result<(), Error> {
+        self.read_from(Address::SevenBit(address), buffer, &mut || false)
+    }
+}
+
+impl<I2C: InnerI2c, SC: SCL, SD: SDA> WriteRead for I2c<I2C, SC, SD> {
+    type Error = Error;
+
+    /// Writes and reads bytes with repeated START in between.
+    fn write_read(&mut self, address: u8, bytes: &[u8], buffer: &mut [u8]) -> Result<(), Error> {
+        self.write_read_with(Address::SevenBit(address), bytes, buffer, &mut || false)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{calc_timingr, max_slopes, next_chunk, presets, write_timingr, CR1_PE};
+    use crate::common::{ConfigError, MockRegister, RawRegister};
+
+    fn fields(timingr: u32) -> (u32, u32, u32, u32, u32) {
+        (timingr >> 28, (timingr >> 20) & 0xF, (timingr >> 16) & 0xF, (timingr >> 8) & 0xFF, timingr & 0xFF)
+    }
+
+    #[test]
+    fn timingr_standard_mode() {
+        let (presc, scldel, sdadel, sclh, scll) = fields(calc_timingr(16_000_000, 100_000, 1000, 300).unwrap());
+        let tick = (presc + 1) * 1_000_000_000 / 16_000_000;
+
+        assert!((scll + 1) * tick + 300 >= 4_700);
+        assert!((sclh + 1) * tick + 1000 >= 4_000);
+        assert!((scldel + 1) * tick >= 1_250);
+        assert!(sdadel * tick >= 250);
+        assert!((scll + sclh + 2) * tick + 1300 <= 10_000);
+    }
+
+    #[test]
+    fn timingr_fast_mode() {
+        let (presc, _, _, sclh, scll) = fields(calc_timingr(80_000_000, 400_000, 300, 300).unwrap());
+        let tick = (presc + 1) * 1_000_000_000 / 80_000_000;
+
+        assert!((scll + 1) * tick + 300 >= 1_300);
+        assert!((sclh + 1) * tick + 300 >= 600);
+        assert!((scll + sclh + 2) * tick + 600 <= 2_500);
+    }
+
+    #[test]
+    fn timingr_limits() {
+        assert_eq!(calc_timingr(4_000_000, 1_000_000, 0, 0), Err(ConfigError::FrequencyTooHigh));
+        assert_eq!(calc_timingr(80_000_000, 2_000_000, 0, 0), Err(ConfigError::FrequencyTooHigh));
+        assert_eq!(calc_timingr(80_000_000, 1_000, 0, 0), Err(ConfigError::FrequencyTooLow));
+        // PRESC can't stretch SCL period enough
+        assert_eq!(calc_timingr(80_000_000, 1, 0, 0), Err(ConfigError::FrequencyTooLow));
+        // Delays, which don't fit into SCLDEL/SDADEL, and slopes longer than period
+        assert_eq!(calc_timingr(80_000_000, 100_000, 100_000, 0), Err(ConfigError::FrequencyTooHigh));
+        assert_eq!(calc_timingr(80_000_000, 100_000, u32::MAX, u32::MAX), Err(ConfigError::FrequencyTooHigh));
+    }
+
+    #[test]
+    fn timingr_rounds_frequency_down() {
+        for &clock in &[8_000_000, 16_000_000, 48_000_000, 80_000_000] {
+            for &freq in &[10_000, 100_000, 250_000, 400_000, 1_000_000] {
+                let (rise_ns, fall_ns) = max_slopes(freq);
+                let (presc, _, _, sclh, scll) = match calc_timingr(clock, freq, rise_ns, fall_ns) {
+                    Ok(timingr) => fields(timingr),
+                    Err(_) => continue,
+                };
+
+                // SCL period in ns, including slopes and minimal synchronization delays,
+                // i.e. 50 ns of analog filter and 2 kernel clock cycles on each edge
+                let sync = 100 + 4 * 1_000_000_000 / clock as u64;
+                let period = (scll + sclh + 2) as u64 * (presc + 1) as u64 * 1_000_000_000 / clock as u64 + (rise_ns + fall_ns) as u64 + sync;
+                assert!(period * freq as u64 >= 1_000_000_000, "{} Hz from {} Hz", freq, clock);
+            }
+        }
+    }
+
+    #[test]
+    fn timingr_write_sequence() {
+        let cr1 = MockRegister::new(0x0000_00F1);
+        let timingr = MockRegister::default();
+
+        write_timingr(&cr1, &timingr, presets::CLK16MHZ_400KHZ);
+        assert_eq!(timingr.read_bits(), presets::CLK16MHZ_400KHZ);
+        assert_eq!(cr1.read_bits(), 0x0000_00F0 | CR1_PE);
+    }
+
+    #[test]
+    fn timingr_presets() {
+        assert_eq!(presets::CLK16MHZ_100KHZ, calc_timingr(16_000_000, 100_000, 1000, 300).unwrap());
+        assert_eq!(presets::CLK80MHZ_400KHZ, calc_timingr(80_000_000, 400_000, 300, 300).unwrap());
+    }
+
+    #[test]
+    fn reload_chunks() {
+        assert_eq!(next_chunk(0), (0, false));
+        assert_eq!(next_chunk(255), (255, false));
+        assert_eq!(next_chunk(256), (255, true));
+        assert_eq!(next_chunk(1000 - 3 * 255), (235, false));
+    }
+}
@@ -0,0 +1,187 @@
+//! Batteries-included initialization
+//!
+//! Performs constrain/freeze/split of commonly used peripherals in one call:
+//!
+//! ```rust, ignore
+//! use stm32l4x5_hal as hal;
+//!
+//! let dp = hal::stm32l4::stm32l4x5::Peripherals::take().unwrap();
+//! let cp = hal::cortex_m::Peripherals::take().unwrap();
+//!
+//! let mut hal = hal::init(dp, cp, hal::init::Config::default()).unwrap();
+//! hal.led.on();
+//! ```
+//!
+//! Resources are fixed to the ones found on ST development boards:
+//!
+//! - Console is USART1 on PB6 (TX) and PB7 (RX), the ST-LINK virtual COM port of B-L475E-IOT01A.
+//! - LED is PA5, LD1 on B-L475E-IOT01A and LD2 on Nucleo-64 boards.
+//!
+//! The rest of GPIOA and GPIOB is not available, use manual initialization if you need it.
+//! Other peripherals are returned untouched in [Hal::device](struct.Hal.html#structfield.device)
+//! and [Hal::core](struct.Hal.html#structfield.core).
+
+use cortex_m::peripheral::{self, NVIC, SYST};
+use stm32l4::stm32l4x5::{self as device, Peripherals, USART1};
+
+use crate::common::{ConfigError, Constrain};
+use crate::delay::Delay;
+use crate::flash::ACR;
+use crate::gpio::{self, Led, Output, PushPull, AF7, PA5, PB6, PB7};
+use crate::rcc::clocking::{MediumSpeedInternalRC, MsiRange, SysClkSource};
+use crate::rcc::{self, Clocks, AHB, APB1, APB2, BDCR, CCIPR, CSR};
+use crate::serial::config::DefaultCfg;
+use crate::serial::{self, DummyPin, Serial};
+use crate::time::Hertz;
+
+/// Console serial interface
+pub type Console = Serial<USART1, PB6<AF7>, PB7<AF7>, DummyPin>;
+
+/// Declarative configuration of `init`
+pub struct Config<B> {
+    /// System clock source
+    pub sysclk: SysClkSource,
+    /// AHB frequency, `None` to run at SYSCLK
+    pub hclk: Option<Hertz>,
+    /// APB1 frequency, `None` to run at HCLK
+    pub pclk1: Option<Hertz>,
+    /// APB2 frequency, `None` to run at HCLK
+    pub pclk2: Option<Hertz>,
+    /// Console serial configuration
+    pub console: B,
+}
+
+impl Default for Config<DefaultCfg> {
+    /// MSI at 4 MHz and console with default serial configuration.
+    fn default() -> Self {
+        Config {
+            sysclk: SysClkSource::MSI(MediumSpeedInternalRC::new(MsiRange::R4M, false)),
+            hclk: None,
+            pclk1: None,
+            pclk2: None,
+            console: DefaultCfg,
+        }
+    }
+}
+
+define_remaining!(
+    /// Device peripherals, which are not used by `init`
+    Device: device [RCC, FLASH, USART1, GPIOA, GPIOB, GPIOC] {
+        DAC1, DMA1, DMA2, CRC, LCD, TSC, IWDG, WWDG, COMP, FIREWALL, I2C1, I2C2, I2C3, PWR, SYSCFG, RNG, AES,
+        ADC1, ADC2, ADC3, GPIOD, GPIOE, GPIOH, GPIOF, GPIOG, SAI1, SAI2, TIM2, TIM5, TIM4, TIM3, TIM15, TIM16,
+        TIM17, TIM1, TIM6, TIM7, LPTIM1, LPTIM2, USART2, USART3, UART5, UART4, LPUART1, SPI1, SPI2, SPI3, SDMMC,
+        EXTI, VREFBUF, CAN1, RTC, SWPMI1, OPAMP, CRS, USB, QUADSPI, FMC, DFSDM, TIM8, ADC123_COMMON, DBGMCU,
+        FPU, STK, NVIC_STIR, FPU_CPACR, SCB_ACTRL,
+    }
+);
+
+define_remaining!(
+    /// Core peripherals, which are not used by `init`
+    Core: peripheral [SYST, NVIC] {
+        CBP, CPUID, DCB, DWT, FPB, FPU, ITM, MPU, SCB, TPIU,
+    }
+);
+
+/// Ready to use drivers
+pub struct Hal {
+    /// Frozen clock configuration
+    pub clocks: Clocks,
+    /// SysTick based delay
+    pub delay: Delay,
+    /// Nested Vectored Interrupt Controller
+    pub nvic: NVIC,
+    /// Console serial interface
+    pub console: Console,
+    /// User LED
+    pub led: Led<PA5<Output<PushPull>>>,
+    /// GPIOC pins
+    pub gpio_c: gpio::C,
+    /// AHB register access
+    pub ahb: AHB,
+    /// APB1 register access
+    pub apb1: APB1,
+    /// APB2 register access
+    pub apb2: APB2,
+    /// Backup domain registers
+    pub bdcr: BDCR,
+    /// Control/status register
+    pub csr: CSR,
+    /// Peripherals kernel clock selection
+    pub ccipr: CCIPR,
+    /// Flash access control register
+    pub acr: ACR,
+    /// Remaining device peripherals
+    pub device: Device,
+    /// Remaining core peripherals
+    pub core: Core,
+}
+
+impl Hal {
+    /// Resets all peripherals and restores default clock configuration.
+    ///
+    /// Intended for bootloader-like applications before jumping to
+    /// another image. Returns core peripherals, which are not affected.
+    pub fn deinit(self) -> (SYST, NVIC) {
+        let Hal { delay, nvic, mut ahb, mut apb1, mut apb2, mut acr, .. } = self;
+
+        rcc::reset_all_peripherals(&mut ahb, &mut apb1, &mut apb2);
+        rcc::reset_clocks(&mut acr);
+
+        (delay.free(), nvic)
+    }
+}
+
+/// Configures clocks, console, LED and delay according to `config`.
+///
+/// Returns error if console baud rate cannot be derived from APB2 clock.
+pub fn init<B: serial::Config>(dp: Peripherals, cp: cortex_m::Peripherals, config: Config<B>) -> Result<Hal, ConfigError> {
+    // GPIOA, GPIOB and GPIOC are accessed through HAL parts
+    let ((rcc, flash, usart1, _, _, _), device) = Device::split(dp);
+    let ((syst, nvic), core) = Core::split(cp);
+
+    let rcc = rcc.constrain();
+    let mut flash = flash.constrain();
+
+    let mut cfgr = rcc.cfgr.sysclk(config.sysclk);
+    if let Some(hclk) = config.hclk {
+        cfgr = cfgr.hclk(hclk);
+    }
+    if let Some(pclk1) = config.pclk1 {
+        cfgr = cfgr.pclk1(pclk1);
+    }
+    if let Some(pclk2) = config.pclk2 {
+        cfgr = cfgr.pclk2(pclk2);
+    }
+    let clocks = cfgr.freeze(&mut flash.acr);
+
+    let mut ahb = rcc.ahb;
+    let mut apb2 = rcc.apb2;
+
+    let mut gpio_a = gpio::A::new(&mut ahb);
+    let mut gpio_b = gpio::B::new(&mut ahb);
+    let gpio_c = gpio::C::new(&mut ahb);
+
+    let led = gpio_a.PA5.into_output::<PushPull>(&mut gpio_a.moder, &mut gpio_a.otyper);
+
+    let tx = gpio_b.PB6.into_alt_fun::<AF7>(&mut gpio_b.moder, &mut gpio_b.afrl);
+    let rx = gpio_b.PB7.into_alt_fun::<AF7>(&mut gpio_b.moder, &mut gpio_b.afrl);
+    let console = Serial::with_dummy(usart1, (tx, rx), config.console, &clocks, &mut apb2)?;
+
+    Ok(Hal {
+        clocks,
+        delay: Delay::new(syst, clocks),
+        nvic,
+        console,
+        led: Led(led),
+        gpio_c,
+        ahb,
+        apb1: rcc.apb1,
+        apb2,
+        bdcr: rcc.bdcr,
+        csr: rcc.csr,
+        ccipr: rcc.ccipr,
+        acr: flash.acr,
+        device,
+        core,
+    })
+}
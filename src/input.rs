@@ -0,0 +1,105 @@
+//! Human input helpers
+//!
+//! [RotaryEncoder](struct.RotaryEncoder.html) decodes mechanical quadrature encoder from two GPIO inputs.
+//! It is meant to be updated from EXTI interrupts of both pins (see `make_interrupt_source`),
+//! or polled frequently enough to see every transition.
+
+use embedded_hal::digital::InputPin;
+
+/// Quadrature transitions table, indexed by `previous << 2 | current` state of `(A, B)`.
+///
+/// Invalid transitions (both inputs changed, i.e. glitch or missed edge) and bounces back
+/// to the previous state are cancelled out.
+const TRANSITIONS: [i8; 16] = [0, -1, 1, 0, 1, 0, 0, -1, -1, 0, 0, 1, 0, 1, -1, 0];
+
+/// Mechanical rotary encoder on two GPIO inputs.
+pub struct RotaryEncoder<A, B> {
+    pins: (A, B),
+    state: u8,
+    transitions: i8,
+    transitions_per_detent: i8,
+    position: i32,
+}
+
+impl<A: InputPin, B: InputPin> RotaryEncoder<A, B> {
+    /// Creates encoder, which has `transitions_per_detent` transitions between its detents.
+    ///
+    /// Most of encoders have full quadrature cycle (4 transitions) per detent,
+    /// while some have half of it (2 transitions).
+    ///
+    /// # Pancis:
+    ///
+    /// In debug mode the function checks that `transitions_per_detent` is 1, 2 or 4.
+    pub fn new(pins: (A, B), transitions_per_detent: u8) -> Self {
+        debug_assert!(transitions_per_detent == 1 || transitions_per_detent == 2 || transitions_per_detent == 4);
+
+        let mut encoder = Self {
+            pins,
+            state: 0,
+            transitions: 0,
+            transitions_per_detent: transitions_per_detent as i8,
+            position: 0,
+        };
+        encoder.state = encoder.read_state();
+
+        encoder
+    }
+
+    fn read_state(&self) -> u8 {
+        (self.pins.0.is_high() as u8) << 1 | self.pins.1.is_high() as u8
+    }
+
+    /// Samples inputs, returning number of detents passed since last update.
+    ///
+    /// Positive value means clockwise rotation (A leads B).
+    pub fn update(&mut self) -> i8 {
+        let state = self.read_state();
+        self.transitions += TRANSITIONS[(self.state << 2 | state) as usize];
+        self.state = state;
+
+        let steps = self.transitions / self.transitions_per_detent;
+        self.transitions %= self.transitions_per_detent;
+        self.position = self.position.wrapping_add(steps as i32);
+
+        steps
+    }
+
+    /// Returns position in detents accumulated since creation or last reset.
+    #[inline]
+    pub fn position(&self) -> i32 {
+        self.position
+    }
+
+    /// Returns position and resets it to zero.
+    pub fn take_position(&mut self) -> i32 {
+        let position = self.position;
+        self.position = 0;
+        position
+    }
+
+    /// Releases pins
+    pub fn free(self) -> (A, B) {
+        self.pins
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::TRANSITIONS;
+
+    fn walk(states: &[u8]) -> i32 {
+        states.windows(2).map(|pair| TRANSITIONS[(pair[0] << 2 | pair[1]) as usize] as i32).sum()
+    }
+
+    #[test]
+    fn full_cycle() {
+        assert_eq!(walk(&[0b00, 0b10, 0b11, 0b01, 0b00]), 4);
+        assert_eq!(walk(&[0b00, 0b01, 0b11, 0b10, 0b00]), -4);
+    }
+
+    #[test]
+    fn bounce_cancels_out() {
+        assert_eq!(walk(&[0b00, 0b10, 0b00, 0b10, 0b00]), 0);
+        assert_eq!(walk(&[0b00, 0b11, 0b00]), 0);
+    }
+}
@@ -162,6 +162,16 @@ pub enum VoltageSource {
     External = 1,
 }
 
+impl VoltageSource {
+    /// Returns VSEL bit, which is cleared for step-up converter and set for VLCD pin
+    pub(crate) fn vsel(&self) -> bool {
+        match self {
+            VoltageSource::Internal => false,
+            VoltageSource::External => true,
+        }
+    }
+}
+
 #[repr(u8)]
 /// Pulse duration
 ///
@@ -206,6 +216,16 @@ pub enum HighDrive {
     On = 1,
 }
 
+#[repr(u8)]
+/// Voltage output buffer.
+///
+/// Buffers intermediate voltage levels on VLCDx pins, improving contrast of big glasses at cost of
+/// additional consumption. Has effect only with [Internal](enum.VoltageSource.html) voltage source.
+pub enum VoltageBuffer {
+    Off = 0,
+    On = 1,
+}
+
 #[repr(u8)]
 /// SEG pin remapping.
 ///
@@ -232,5 +252,6 @@ pub struct Config {
     pub pulse_duration: Option<PulseDuration>,
     pub contrast: Option<Contrast>,
     pub high_drive: Option<HighDrive>,
+    pub voltage_buffer: Option<VoltageBuffer>,
     pub mux_segment: Option<MuxSegment>,
 }
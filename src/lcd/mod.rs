@@ -11,6 +11,7 @@ use core::mem;
 
 pub mod config;
 pub mod ram;
+pub use self::ram::Ram;
 
 pub enum ValidationResult {
     /// Valid Frame Rate
@@ -35,14 +36,14 @@ pub struct LCD {
 #[inline]
 fn calculate_frame_rate(clock_frequency: u32, ps: u32, div: u32, duty: u8) -> u32 {
     // Take duty * 1000, then divide by 1000 to drop floating point part
-    (clock_frequency / ((2u32.pow(ps)) * (16 + div)) * match duty {
+    clock_frequency / (2u32.pow(ps) * (16 + div)) * match duty {
         0 => 1000,
         1 => 500,
         2 => 333,
         3 => 250,
         4 => 125,
         _ => unreachable!(),
-    } / 1000)
+    } / 1000
 }
 
 impl LCD {
@@ -245,6 +246,7 @@ impl LCD {
             duty,
             mux_segment,
             voltage_source,
+            voltage_buffer,
         } = config;
 
         self.inner.fcr.modify(|_, w| {
@@ -304,9 +306,12 @@ impl LCD {
                     w.duty().bits(duty as u8);
                 }
             }
-            match voltage_source {
-                Some(config::VoltageSource::Internal) => w.vsel().set_bit(),
-                Some(config::VoltageSource::External) => w.vsel().clear_bit(),
+            if let Some(voltage_source) = voltage_source {
+                w.vsel().bit(voltage_source.vsel());
+            }
+            match voltage_buffer {
+                Some(config::VoltageBuffer::On) => w.bufen().set_bit(),
+                Some(config::VoltageBuffer::Off) => w.bufen().clear_bit(),
                 _ => w,
             };
             match mux_segment {
@@ -317,6 +322,25 @@ impl LCD {
         });
     }
 
+    /// Changes contrast while LCD is running.
+    ///
+    /// Has effect only with [Internal](config/enum.VoltageSource.html) voltage source.
+    pub fn set_contrast(&mut self, contrast: config::Contrast) {
+        self.inner.fcr.modify(|_, w| unsafe { w.cc().bits(contrast as u8) });
+        while self.inner.sr.read().fcrsf().bit_is_clear() {}
+    }
+
+    /// Toggles high drive mode while LCD is running.
+    ///
+    /// When enabling, pulse duration is set to `One` as required.
+    pub fn set_high_drive(&mut self, high_drive: config::HighDrive) {
+        self.inner.fcr.modify(|_, w| match high_drive {
+            config::HighDrive::On => unsafe { w.hd().set_bit().pon().bits(config::PulseDuration::One as u8) },
+            config::HighDrive::Off => w.hd().clear_bit(),
+        });
+        while self.inner.sr.read().fcrsf().bit_is_clear() {}
+    }
+
     #[inline]
     /// Resets LCD's RAM.
     ///
@@ -395,6 +419,15 @@ impl Drop for LCD {
 
 #[cfg(test)]
 mod tests {
+    #[test]
+    pub fn voltage_source_encoding() {
+        use super::config::VoltageSource;
+
+        // Reference manual Ch. 25.6.1, VSEL = 0 selects internal step-up converter
+        assert!(!VoltageSource::Internal.vsel());
+        assert!(VoltageSource::External.vsel());
+    }
+
     #[test]
     pub fn calculate_frame_rate() {
         use super::config;
@@ -1,5 +1,86 @@
+//! Segment RAM of LCD
+//!
+//! Each COM line has its own RAM word pair, where bit N drives segment SEGN while the line
+//! is active. Glass mappings of boards are written against [Ram](trait.Ram.html) trait,
+//! which HAL's [LCD](../struct.LCD.html) implements:
+//!
+//! ```rust, ignore
+//! fn show_colon<R: Ram>(ram: &mut R) {
+//!     let line = ram.read_com(1);
+//!     ram.write_com(1, line | 1 << 17);
+//!     ram.request_update();
+//! }
+//! ```
+
+use core::ptr;
+
 use super::LCD;
 
+/// Number of COM lines
+pub const COMS: u8 = 8;
+/// Number of segments per COM line
+pub const SEGMENTS: u8 = 44;
+
+/// Update display request bit of SR
+const SR_UDR: u32 = 1 << 2;
+
+/// Access to segment RAM with frame synchronization.
+pub trait Ram {
+    /// Writes segments of COM line `com`, bit N of `segments` drives SEGN.
+    ///
+    /// Blocks until previously requested update completes, as RAM is write protected until then.
+    fn write_com(&mut self, com: u8, segments: u64);
+
+    /// Reads segments of COM line `com`, as written since last update.
+    fn read_com(&self, com: u8) -> u64;
+
+    /// Requests transfer of RAM to display at the start of next frame.
+    fn request_update(&mut self);
+
+    /// Returns whether requested update has not yet been transferred to display.
+    fn is_update_pending(&self) -> bool;
+
+    /// Blocks until requested update is transferred to display.
+    fn wait_for_sync(&mut self) {
+        while self.is_update_pending() {}
+    }
+}
+
+/// Returns pointer to the lower word of COM line `com`, upper word follows it.
+///
+/// Upper words, which hold SEG32 to SEG43, are missing in device crate.
+fn com_ptr(lcd: &LCD, com: u8) -> *mut u32 {
+    debug_assert!(com < COMS);
+
+    unsafe { (&lcd.inner.ram_com0 as *const _ as *mut u32).add(com as usize * 2) }
+}
+
+impl Ram for LCD {
+    fn write_com(&mut self, com: u8, segments: u64) {
+        self.wait_for_sync();
+
+        let ptr = com_ptr(self, com);
+        unsafe {
+            ptr::write_volatile(ptr, segments as u32);
+            ptr::write_volatile(ptr.add(1), (segments >> 32) as u32 & ((1 << (SEGMENTS - 32)) - 1));
+        }
+    }
+
+    fn read_com(&self, com: u8) -> u64 {
+        let ptr = com_ptr(self, com);
+        unsafe { ptr::read_volatile(ptr) as u64 | (ptr::read_volatile(ptr.add(1)) as u64) << 32 }
+    }
+
+    fn request_update(&mut self) {
+        self.update_request();
+    }
+
+    fn is_update_pending(&self) -> bool {
+        // UDR reads back as set until update is done, but it is write-only in device crate
+        self.inner.sr.read().bits() & SR_UDR != 0
+    }
+}
+
 pub trait Index {
     type RamType;
 
@@ -20,16 +20,47 @@ pub extern crate nb;
 pub extern crate embedded_hal;
 pub extern crate stm32l4;
 
+#[macro_use]
 pub mod common;
 pub mod config;
 pub mod delay;
+pub mod dma;
+pub mod exti;
 pub mod flash;
+#[macro_use]
 pub mod gpio;
 pub mod lcd;
 pub mod power;
 pub mod rcc;
 pub mod time;
 pub mod timer;
+pub mod tick;
+#[cfg(feature = "embassy-time-driver")]
+pub mod time_driver;
+pub mod logger;
 pub mod spi;
 pub mod crc;
 pub mod serial;
+pub mod board;
+pub mod vrefbuf;
+pub mod syscfg;
+pub mod signature;
+pub mod init;
+pub mod diag;
+pub mod debug;
+pub mod watchdog;
+pub mod comp;
+pub mod adc;
+pub mod rtc;
+pub mod i2c;
+pub mod input;
+pub mod onewire;
+pub mod parallel_bus;
+pub mod display;
+pub mod softpwm;
+pub mod pwm;
+pub mod fwupdate;
+pub mod selftest;
+pub mod factory;
+
+pub use crate::init::{init, Hal};
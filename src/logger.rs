@@ -0,0 +1,385 @@
+//! Buffered global logger
+//!
+//! Log records are written into interrupt-safe ring buffer, which is drained into sink,
+//! either transmitter of Serial or ITM stimulus port, outside of logging call.
+//! Record, which doesn't fit into buffer, is dropped as a whole and counted, so output stays
+//! readable under load.
+//!
+//! With `log` feature, [init_log](fn.init_log.html) installs logger of `log` crate:
+//!
+//! ```rust, ignore
+//! let (tx, rx) = serial.split();
+//! logger::install_tx(singleton!(: Tx<USART2, PA2<AF7>, DummyPin> = tx).unwrap());
+//! logger::init_log(log::LevelFilter::Info);
+//!
+//! log::info!("started at {} Hz", clocks.sysclk().0);
+//!
+//! loop {
+//!     logger::drain();
+//! }
+//! ```
+//!
+//! With `defmt` feature, logger is registered as global logger of `defmt`,
+//! writing encoded frames into the same buffer.
+
+use core::cell::{Cell, RefCell};
+#[cfg(feature = "log")]
+use core::fmt;
+
+use cortex_m::interrupt::{self, Mutex};
+use cortex_m::peripheral::ITM;
+
+use crate::serial::{self, Instance, Tx};
+
+/// Capacity of buffer in bytes
+pub const BUFFER_SIZE: usize = 1024;
+
+/// Sink accepting byte without blocking, returns whether byte has been accepted
+pub type Sink = fn(u8) -> bool;
+
+/// Ring buffer of records
+struct Ring {
+    buffer: [u8; BUFFER_SIZE],
+    start: usize,
+    len: usize,
+    /// Length of buffer before current record, and whether record overflowed
+    record: Option<(usize, bool)>,
+    dropped: u32,
+}
+
+impl Ring {
+    const fn new() -> Self {
+        Self {
+            buffer: [0; BUFFER_SIZE],
+            start: 0,
+            len: 0,
+            record: None,
+            dropped: 0,
+        }
+    }
+
+    /// Starts record, which is kept only if it fits as a whole
+    fn begin_record(&mut self) {
+        self.record = Some((self.len, false));
+    }
+
+    fn push(&mut self, bytes: &[u8]) {
+        for &byte in bytes {
+            if self.len == BUFFER_SIZE {
+                if let Some((mark, _)) = self.record {
+                    self.record = Some((mark, true));
+                }
+                return;
+            }
+            self.buffer[(self.start + self.len) % BUFFER_SIZE] = byte;
+            self.len += 1;
+        }
+    }
+
+    /// Ends record, discarding it if it has overflowed
+    fn end_record(&mut self) {
+        if let Some((mark, true)) = self.record.take() {
+            self.len = mark;
+            self.dropped = self.dropped.wrapping_add(1);
+        }
+    }
+
+    /// Returns length of finished records, as unfinished one may be rolled back
+    fn committed(&self) -> usize {
+        self.record.map(|(mark, _)| mark).unwrap_or(self.len)
+    }
+
+    fn front(&self) -> Option<u8> {
+        match self.committed() {
+            0 => None,
+            _ => Some(self.buffer[self.start]),
+        }
+    }
+
+    fn pop(&mut self) {
+        if self.committed() == 0 {
+            return;
+        }
+
+        self.start = (self.start + 1) % BUFFER_SIZE;
+        self.len -= 1;
+        if let Some((mark, is_overflow)) = self.record {
+            self.record = Some((mark - 1, is_overflow));
+        }
+    }
+}
+
+/// Transmitter, which is owned by logger
+trait WriteByte: Send {
+    fn write_byte(&mut self, byte: u8) -> bool;
+}
+
+impl<UART: Instance, T: serial::TX + Send, C: serial::CK + Send> WriteByte for Tx<UART, T, C> {
+    fn write_byte(&mut self, byte: u8) -> bool {
+        serial::write_byte(unsafe { &*UART::register_block() }, byte).is_ok()
+    }
+}
+
+/// Destination of drained bytes
+enum Output {
+    Sink(Sink),
+    Tx(&'static mut dyn WriteByte),
+}
+
+impl Output {
+    fn write(&mut self, byte: u8) -> bool {
+        match self {
+            Output::Sink(sink) => sink(byte),
+            Output::Tx(tx) => tx.write_byte(byte),
+        }
+    }
+}
+
+static RING: Mutex<RefCell<Ring>> = Mutex::new(RefCell::new(Ring::new()));
+static OUTPUT: Mutex<RefCell<Option<Output>>> = Mutex::new(RefCell::new(None));
+static ITM_PORT: Mutex<Cell<usize>> = Mutex::new(Cell::new(0));
+
+/// Number of stimulus ports of ITM
+pub const ITM_PORTS: u8 = 32;
+
+fn itm_write(byte: u8) -> bool {
+    let port = interrupt::free(|cs| ITM_PORT.borrow(cs).get());
+    let itm = unsafe { &mut *(ITM::ptr() as *mut cortex_m::peripheral::itm::RegisterBlock) };
+
+    // Without debugger ITM or port stays disabled and FIFO never gets ready, so byte is discarded
+    let is_enabled = itm.tcr.read() & 1 != 0 && itm.ter[0].read() & (1 << port) != 0;
+    if !is_enabled {
+        return true;
+    }
+
+    let stim = &mut itm.stim[port];
+    match stim.is_fifo_ready() {
+        true => {
+            stim.write_u8(byte);
+            true
+        },
+        false => false,
+    }
+}
+
+/// Sets `sink`, into which buffer is drained.
+pub fn install(sink: Sink) {
+    interrupt::free(|cs| *OUTPUT.borrow(cs).borrow_mut() = Some(Output::Sink(sink)));
+}
+
+/// Drains buffer into stimulus `port` of ITM.
+///
+/// Bytes are discarded while ITM or `port` is not enabled by debugger.
+///
+/// Panics if `port` is not below `ITM_PORTS`.
+pub fn install_itm(port: u8) {
+    assert!(port < ITM_PORTS, "ITM has only 32 stimulus ports");
+
+    interrupt::free(|cs| ITM_PORT.borrow(cs).set(port as usize));
+    install(itm_write);
+}
+
+/// Drains buffer into transmitter of Serial, which is owned by logger from now on.
+///
+/// `tx` is kept in static memory, e.g. created by `cortex_m::singleton!`.
+pub fn install_tx<UART: Instance + 'static, T: serial::TX + Send + 'static, C: serial::CK + Send + 'static>(tx: &'static mut Tx<UART, T, C>) {
+    interrupt::free(move |cs| *OUTPUT.borrow(cs).borrow_mut() = Some(Output::Tx(tx)));
+}
+
+/// Writes `bytes` as single record, e.g. for output without `log` or `defmt`.
+pub fn write(bytes: &[u8]) {
+    interrupt::free(|cs| {
+        let mut ring = RING.borrow(cs).borrow_mut();
+        ring.begin_record();
+        ring.push(bytes);
+        ring.end_record();
+    });
+}
+
+/// Moves buffered bytes into sink until it stops accepting them, e.g. from idle loop
+/// or periodic interrupt.
+pub fn drain() {
+    interrupt::free(|cs| {
+        let mut output = OUTPUT.borrow(cs).borrow_mut();
+        let output = match output.as_mut() {
+            Some(output) => output,
+            None => return,
+        };
+        let mut ring = RING.borrow(cs).borrow_mut();
+        while let Some(byte) = ring.front() {
+            if !output.write(byte) {
+                break;
+            }
+            ring.pop();
+        }
+    });
+}
+
+/// Blocks until buffer is drained into sink, e.g. before reset.
+pub fn flush() {
+    while interrupt::free(|cs| RING.borrow(cs).borrow().committed() != 0 && OUTPUT.borrow(cs).borrow().is_some()) {
+        drain();
+    }
+}
+
+/// Returns number of records dropped, because buffer was full.
+pub fn dropped() -> u32 {
+    interrupt::free(|cs| RING.borrow(cs).borrow().dropped)
+}
+
+/// Formats record into ring
+#[cfg(feature = "log")]
+struct RingWriter<'a>(&'a mut Ring);
+
+#[cfg(feature = "log")]
+impl<'a> fmt::Write for RingWriter<'a> {
+    fn write_str(&mut self, text: &str) -> fmt::Result {
+        self.0.push(text.as_bytes());
+        Ok(())
+    }
+}
+
+#[cfg(feature = "log")]
+struct Logger;
+
+#[cfg(feature = "log")]
+impl log::Log for Logger {
+    fn enabled(&self, _: &log::Metadata) -> bool {
+        true
+    }
+
+    fn log(&self, record: &log::Record) {
+        use core::fmt::Write;
+
+        interrupt::free(|cs| {
+            let mut ring = RING.borrow(cs).borrow_mut();
+            ring.begin_record();
+            let _ = write!(RingWriter(&mut ring), "[{}] {}: {}\r\n", record.level(), record.target(), record.args());
+            ring.end_record();
+        });
+    }
+
+    fn flush(&self) {
+        flush();
+    }
+}
+
+#[cfg(feature = "log")]
+static LOGGER: Logger = Logger;
+
+/// Installs logger of `log` crate with maximal `level`.
+///
+/// Records are formatted within critical section, so long messages delay interrupts.
+#[cfg(feature = "log")]
+pub fn init_log(level: log::LevelFilter) -> Result<(), log::SetLoggerError> {
+    log::set_logger(&LOGGER)?;
+    log::set_max_level(level);
+    Ok(())
+}
+
+#[cfg(feature = "defmt")]
+mod defmt_logger {
+    use cortex_m::interrupt::{self, CriticalSection};
+    use cortex_m::register::primask;
+
+    use super::RING;
+
+    static mut ENCODER: defmt::Encoder = defmt::Encoder::new();
+    /// Whether interrupts were enabled before frame was acquired
+    static mut WERE_ENABLED: bool = false;
+    /// Whether frame is being written, e.g. when fault handler logs during interrupted frame
+    static mut TAKEN: bool = false;
+
+    fn push(bytes: &[u8]) {
+        // Interrupts are disabled between acquire and release
+        let cs = unsafe { CriticalSection::new() };
+        RING.borrow(&cs).borrow_mut().push(bytes);
+    }
+
+    #[defmt::global_logger]
+    struct Logger;
+
+    unsafe impl defmt::Logger for Logger {
+        fn acquire() {
+            let were_enabled = primask::read().is_inactive();
+            interrupt::disable();
+
+            unsafe {
+                if TAKEN {
+                    panic!("defmt logger taken reentrantly");
+                }
+                TAKEN = true;
+                WERE_ENABLED = were_enabled;
+                RING.borrow(&CriticalSection::new()).borrow_mut().begin_record();
+                (*core::ptr::addr_of_mut!(ENCODER)).start_frame(push);
+            }
+        }
+
+        unsafe fn flush() {
+            // Sink is drained outside of critical section
+        }
+
+        unsafe fn release() {
+            (*core::ptr::addr_of_mut!(ENCODER)).end_frame(push);
+            RING.borrow(&CriticalSection::new()).borrow_mut().end_record();
+
+            TAKEN = false;
+            if WERE_ENABLED {
+                interrupt::enable();
+            }
+        }
+
+        unsafe fn write(bytes: &[u8]) {
+            (*core::ptr::addr_of_mut!(ENCODER)).write(bytes, push);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{Ring, BUFFER_SIZE};
+
+    fn drain(ring: &mut Ring) -> usize {
+        let mut len = 0;
+        while ring.front().is_some() {
+            ring.pop();
+            len += 1;
+        }
+        len
+    }
+
+    #[test]
+    fn records_are_kept_or_dropped_whole() {
+        let mut ring = Ring::new();
+        let record = [0x55; 100];
+
+        for _ in 0..11 {
+            ring.begin_record();
+            ring.push(&record);
+            ring.end_record();
+        }
+        assert_eq!(ring.len, 1000);
+        assert_eq!(ring.dropped, 1);
+
+        assert_eq!(drain(&mut ring), 1000);
+
+        ring.begin_record();
+        ring.push(&[0xAA; BUFFER_SIZE]);
+        ring.end_record();
+        assert_eq!(ring.front(), Some(0xAA));
+        assert_eq!(drain(&mut ring), BUFFER_SIZE);
+        assert_eq!(ring.dropped, 1);
+    }
+
+    #[test]
+    fn unfinished_record_is_not_drained() {
+        let mut ring = Ring::new();
+        ring.push(b"done");
+        ring.begin_record();
+        ring.push(b"partial");
+
+        assert_eq!(drain(&mut ring), 4);
+        ring.end_record();
+        assert_eq!(drain(&mut ring), 7);
+    }
+}
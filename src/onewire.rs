@@ -0,0 +1,270 @@
+//! 1-Wire bus master over GPIO
+//!
+//! Bus is driven by open drain pin with external pull-up, while time slots are measured
+//! with DWT cycle counter, so they don't depend on compiler optimizations.
+//! Interrupts are disabled for the duration of each time slot.
+//!
+//! ```rust,ignore
+//! let pin = gpio_a.PA0.into_output::<OpenDrain>(&mut gpio_a.moder, &mut gpio_a.otyper);
+//! let mut bus = OneWire::new(pin, &mut cp.DCB, &mut cp.DWT, &clocks);
+//!
+//! let mut search = Search::new();
+//! while let Some(rom) = bus.search(&mut search)? {
+//!     // DS18B20 has family code 0x28
+//! }
+//! ```
+
+use cortex_m::interrupt;
+use cortex_m::peripheral::{DCB, DWT};
+use embedded_hal::digital::{InputPin, OutputPin};
+
+use crate::rcc::Clocks;
+
+/// ROM command: Search ROM
+const SEARCH_ROM: u8 = 0xF0;
+/// ROM command: Match ROM
+const MATCH_ROM: u8 = 0x55;
+/// ROM command: Skip ROM
+const SKIP_ROM: u8 = 0xCC;
+
+/// 64-bit ROM code of device: family code, serial number and CRC
+pub type Rom = [u8; 8];
+
+/// 1-Wire errors
+#[derive(Copy, Clone, PartialEq, Eq, Debug)]
+pub enum Error {
+    /// No device responded with presence pulse
+    NoPresence,
+    /// CRC of received data doesn't match
+    Crc,
+    /// Device responded with invalid bit combination during search
+    Search,
+}
+
+/// State of ROM search, which is carried between calls to [search](struct.OneWire.html#method.search)
+pub struct Search {
+    rom: Rom,
+    last_discrepancy: u8,
+    is_done: bool,
+}
+
+impl Search {
+    /// Creates new search, starting from the first device
+    pub fn new() -> Self {
+        Self {
+            rom: [0; 8],
+            last_discrepancy: 0,
+            is_done: false,
+        }
+    }
+}
+
+impl Default for Search {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+/// Calculates Maxim/Dallas CRC8 (polynomial x^8 + x^5 + x^4 + 1).
+///
+/// Data followed by its CRC yields 0.
+pub fn crc8(data: &[u8]) -> u8 {
+    data.iter().fold(0, |crc, byte| {
+        let mut crc = crc ^ byte;
+        for _ in 0..8 {
+            crc = match crc & 1 {
+                1 => (crc >> 1) ^ 0x8C,
+                _ => crc >> 1,
+            };
+        }
+        crc
+    })
+}
+
+/// 1-Wire bus master
+pub struct OneWire<PIN> {
+    pin: PIN,
+    cycles_per_us: u32,
+}
+
+impl<PIN: OutputPin + InputPin> OneWire<PIN> {
+    /// Creates bus master on open drain `pin`, enabling DWT cycle counter.
+    pub fn new(mut pin: PIN, dcb: &mut DCB, dwt: &mut DWT, clocks: &Clocks) -> Self {
+        dcb.enable_trace();
+        dwt.enable_cycle_counter();
+
+        pin.set_high();
+
+        Self {
+            pin,
+            cycles_per_us: clocks.hclk().0 / 1_000_000,
+        }
+    }
+
+    fn delay_us(&self, us: u32) {
+        let start = DWT::get_cycle_count();
+        let cycles = us * self.cycles_per_us;
+        while DWT::get_cycle_count().wrapping_sub(start) < cycles {}
+    }
+
+    /// Sends reset pulse, returning whether any device responded with presence pulse.
+    pub fn reset(&mut self) -> bool {
+        self.pin.set_low();
+        self.delay_us(480);
+
+        let is_present = interrupt::free(|_| {
+            self.pin.set_high();
+            self.delay_us(70);
+            self.pin.is_low()
+        });
+        self.delay_us(410);
+
+        is_present
+    }
+
+    /// Writes single bit
+    pub fn write_bit(&mut self, bit: bool) {
+        interrupt::free(|_| {
+            self.pin.set_low();
+            match bit {
+                true => {
+                    self.delay_us(6);
+                    self.pin.set_high();
+                    self.delay_us(64);
+                },
+                false => {
+                    self.delay_us(60);
+                    self.pin.set_high();
+                    self.delay_us(10);
+                },
+            }
+        })
+    }
+
+    /// Reads single bit
+    pub fn read_bit(&mut self) -> bool {
+        interrupt::free(|_| {
+            self.pin.set_low();
+            self.delay_us(6);
+            self.pin.set_high();
+            self.delay_us(9);
+            let bit = self.pin.is_high();
+            self.delay_us(55);
+            bit
+        })
+    }
+
+    /// Writes byte, LSB first
+    pub fn write_byte(&mut self, byte: u8) {
+        for idx in 0..8 {
+            self.write_bit(byte & (1 << idx) != 0);
+        }
+    }
+
+    /// Reads byte, LSB first
+    pub fn read_byte(&mut self) -> u8 {
+        (0..8).fold(0, |byte, idx| byte | (self.read_bit() as u8) << idx)
+    }
+
+    /// Writes all `bytes`
+    pub fn write_bytes(&mut self, bytes: &[u8]) {
+        for byte in bytes {
+            self.write_byte(*byte);
+        }
+    }
+
+    /// Reads bytes into `buffer`
+    pub fn read_bytes(&mut self, buffer: &mut [u8]) {
+        for byte in buffer {
+            *byte = self.read_byte();
+        }
+    }
+
+    /// Resets bus and addresses device with `rom`.
+    pub fn select(&mut self, rom: &Rom) -> Result<(), Error> {
+        if !self.reset() {
+            return Err(Error::NoPresence);
+        }
+        self.write_byte(MATCH_ROM);
+        self.write_bytes(rom);
+        Ok(())
+    }
+
+    /// Resets bus and addresses all devices, e.g. to start conversion on each of them.
+    pub fn skip_rom(&mut self) -> Result<(), Error> {
+        if !self.reset() {
+            return Err(Error::NoPresence);
+        }
+        self.write_byte(SKIP_ROM);
+        Ok(())
+    }
+
+    /// Finds ROM of the next device on bus, returning `None` once all of them are found.
+    ///
+    /// See Maxim Application Note 187.
+    pub fn search(&mut self, search: &mut Search) -> Result<Option<Rom>, Error> {
+        if search.is_done {
+            return Ok(None);
+        }
+        if !self.reset() {
+            return Err(Error::NoPresence);
+        }
+
+        self.write_byte(SEARCH_ROM);
+
+        let mut last_zero = 0;
+        for bit_number in 1..=64u8 {
+            let (byte, mask) = (((bit_number - 1) / 8) as usize, 1 << ((bit_number - 1) % 8));
+
+            let bit = self.read_bit();
+            let complement = self.read_bit();
+
+            let direction = match (bit, complement) {
+                (true, true) => return Err(Error::Search),
+                (bit, complement) if bit != complement => bit,
+                _ => {
+                    let direction = match bit_number < search.last_discrepancy {
+                        true => search.rom[byte] & mask != 0,
+                        false => bit_number == search.last_discrepancy,
+                    };
+                    if !direction {
+                        last_zero = bit_number;
+                    }
+                    direction
+                }
+            };
+
+            match direction {
+                true => search.rom[byte] |= mask,
+                false => search.rom[byte] &= !mask,
+            }
+            self.write_bit(direction);
+        }
+
+        search.last_discrepancy = last_zero;
+        search.is_done = last_zero == 0;
+
+        match crc8(&search.rom) {
+            0 => Ok(Some(search.rom)),
+            _ => Err(Error::Crc),
+        }
+    }
+
+    /// Releases pin
+    pub fn free(self) -> PIN {
+        self.pin
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::crc8;
+
+    #[test]
+    fn crc8_of_rom() {
+        // ROM code from Maxim Application Note 27
+        let rom = [0x02, 0x1C, 0xB8, 0x01, 0x00, 0x00, 0x00, 0xA2];
+        assert_eq!(crc8(&rom[..7]), 0xA2);
+        assert_eq!(crc8(&rom), 0);
+    }
+}
@@ -0,0 +1,265 @@
+//! Parallel bus over GPIO
+//!
+//! Drives 8 or 16 data pins of one port at once through BSRR, with write (WR),
+//! read (RD) and chip select (CS) strobes timed by DWT cycle counter.
+//! It serves character LCDs (HD44780) and displays with 8080 interface on packages without FMC.
+//!
+//! ```rust,ignore
+//! let data = (pd0, pd1, pd2, pd3, pd4, pd5, pd6, pd7);
+//! let mut bus = ParallelBus::new(data, wr, rd, cs, Timing::I8080, &mut cp.DCB, &mut cp.DWT, &clocks);
+//!
+//! dc.set_low();
+//! bus.write(0x2C);
+//! dc.set_high();
+//! bus.write_all(&pixels);
+//! ```
+//!
+//! HD44780 latches data on falling edge of its enable pin, so it is used as active high WR,
+//! while RW is tied low and `NoPin` is passed for RD and CS.
+
+use cortex_m::interrupt;
+use cortex_m::peripheral::{DCB, DWT};
+use embedded_hal::digital::OutputPin;
+
+use crate::gpio::{port_registers, PinId};
+use crate::rcc::Clocks;
+
+/// Placeholder for strobe, which is not connected
+pub struct NoPin;
+
+impl OutputPin for NoPin {
+    fn set_high(&mut self) {}
+
+    fn set_low(&mut self) {}
+}
+
+/// Active level of WR and RD strobes
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum Polarity {
+    /// Strobe is active low, data is latched on rising edge (8080)
+    ActiveLow,
+    /// Strobe is active high, data is latched on falling edge (HD44780 enable)
+    ActiveHigh,
+}
+
+/// Timing of bus cycle in nanoseconds
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub struct Timing {
+    /// Data setup time before strobe is asserted
+    pub setup_ns: u32,
+    /// Strobe pulse width
+    pub pulse_ns: u32,
+    /// Data hold time after strobe is released
+    pub hold_ns: u32,
+    /// Active level of strobes
+    pub polarity: Polarity,
+}
+
+impl Timing {
+    /// HD44780 compatible character LCD at 5 V
+    pub const HD44780: Timing = Timing {
+        setup_ns: 60,
+        pulse_ns: 450,
+        hold_ns: 20,
+        polarity: Polarity::ActiveHigh,
+    };
+
+    /// Display with 8080 interface, e.g. ILI9341
+    pub const I8080: Timing = Timing {
+        setup_ns: 10,
+        pulse_ns: 30,
+        hold_ns: 30,
+        polarity: Polarity::ActiveLow,
+    };
+}
+
+/// Data pins of bus, which must be outputs of one port.
+///
+/// Implemented for tuples of 8 and 16 pins, first pin carries the least significant bit.
+pub trait DataPins {
+    /// Port letter of pins
+    const PORT: u8;
+    /// Whether all pins belong to `PORT`
+    const IS_ONE_PORT: bool;
+    /// Number of pins
+    const WIDTH: usize;
+    /// Pin numbers in order of data bits
+    const POSITIONS: [u8; 16];
+}
+
+macro_rules! impl_data_pins {
+    ($width:expr, [$($pad:expr),*], $P0:ident $(, $P:ident)*) => {
+        impl<$P0: OutputPin + PinId $(, $P: OutputPin + PinId)*> DataPins for ($P0, $($P,)*) {
+            const PORT: u8 = $P0::PORT;
+            const IS_ONE_PORT: bool = true $(&& $P::PORT == $P0::PORT)*;
+            const WIDTH: usize = $width;
+            const POSITIONS: [u8; 16] = [$P0::NUM $(, $P::NUM)* $(, $pad)*];
+        }
+    }
+}
+
+impl_data_pins!(8, [0, 0, 0, 0, 0, 0, 0, 0], P0, P1, P2, P3, P4, P5, P6, P7);
+impl_data_pins!(16, [], P0, P1, P2, P3, P4, P5, P6, P7, P8, P9, P10, P11, P12, P13, P14, P15);
+
+/// Calculates BSRR value, which outputs `value` on pins at `positions`.
+pub fn bsrr(value: u16, positions: &[u8]) -> u32 {
+    positions.iter().enumerate().fold(0, |bsrr, (bit, &pos)| match value & (1 << bit) {
+        0 => bsrr | 1 << (pos + 16),
+        _ => bsrr | 1 << pos,
+    })
+}
+
+/// Gathers value from `idr` of pins at `positions`.
+pub fn gather(idr: u32, positions: &[u8]) -> u16 {
+    positions.iter().enumerate().fold(0, |value, (bit, &pos)| value | ((idr >> pos) as u16 & 1) << bit)
+}
+
+/// Parallel bus master
+pub struct ParallelBus<DATA, WR, RD, CS> {
+    data: DATA,
+    wr: WR,
+    rd: RD,
+    cs: CS,
+    polarity: Polarity,
+    setup: u32,
+    pulse: u32,
+    hold: u32,
+}
+
+impl<DATA: DataPins, WR: OutputPin, RD: OutputPin, CS: OutputPin> ParallelBus<DATA, WR, RD, CS> {
+    /// Fails to compile, when used with data pins of different ports
+    const ONE_PORT: () = assert!(DATA::IS_ONE_PORT, "data pins must belong to one port");
+
+    /// Creates bus master with `timing`, enabling DWT cycle counter.
+    ///
+    /// All data pins must belong to one port, otherwise the call doesn't compile.
+    pub fn new(data: DATA, wr: WR, rd: RD, cs: CS, timing: Timing, dcb: &mut DCB, dwt: &mut DWT, clocks: &Clocks) -> Self {
+        let () = Self::ONE_PORT;
+
+        dcb.enable_trace();
+        dwt.enable_cycle_counter();
+
+        // Round up, so no delay is shorter than requested
+        let cycles = |ns: u32| ((ns as u64 * clocks.hclk().0 as u64 + 999_999_999) / 1_000_000_000) as u32;
+        let mut bus = Self {
+            data,
+            wr,
+            rd,
+            cs,
+            polarity: timing.polarity,
+            setup: cycles(timing.setup_ns),
+            pulse: cycles(timing.pulse_ns),
+            hold: cycles(timing.hold_ns),
+        };
+        bus.strobe_wr(false);
+        bus.strobe_rd(false);
+        bus.cs.set_high();
+
+        bus
+    }
+
+    fn delay(cycles: u32) {
+        let start = DWT::get_cycle_count();
+        while DWT::get_cycle_count().wrapping_sub(start) < cycles {}
+    }
+
+    fn level(&self, is_active: bool) -> bool {
+        is_active == (self.polarity == Polarity::ActiveHigh)
+    }
+
+    fn strobe_wr(&mut self, is_active: bool) {
+        match self.level(is_active) {
+            true => self.wr.set_high(),
+            false => self.wr.set_low(),
+        }
+    }
+
+    fn strobe_rd(&mut self, is_active: bool) {
+        match self.level(is_active) {
+            true => self.rd.set_high(),
+            false => self.rd.set_low(),
+        }
+    }
+
+    fn positions() -> &'static [u8] {
+        &DATA::POSITIONS[..DATA::WIDTH]
+    }
+
+    /// Writes single word within already selected chip
+    fn write_cycle(&mut self, value: u16) {
+        port_registers(DATA::PORT).bsrr.write(|w| unsafe { w.bits(bsrr(value, Self::positions())) });
+        Self::delay(self.setup);
+        self.strobe_wr(true);
+        Self::delay(self.pulse);
+        self.strobe_wr(false);
+        Self::delay(self.hold);
+    }
+
+    /// Writes `value`, only lower 8 bits are used on 8-bit bus.
+    pub fn write(&mut self, value: u16) {
+        self.cs.set_low();
+        self.write_cycle(value);
+        self.cs.set_high();
+    }
+
+    /// Writes all `values`, keeping chip selected in between.
+    pub fn write_all(&mut self, values: &[u16]) {
+        self.cs.set_low();
+        for &value in values {
+            self.write_cycle(value);
+        }
+        self.cs.set_high();
+    }
+
+    /// Writes all `bytes`, keeping chip selected in between.
+    pub fn write_bytes(&mut self, bytes: &[u8]) {
+        self.cs.set_low();
+        for &byte in bytes {
+            self.write_cycle(byte as u16);
+        }
+        self.cs.set_high();
+    }
+
+    /// Reads word, with data pins switched to input for the duration of RD strobe.
+    pub fn read(&mut self) -> u16 {
+        let registers = port_registers(DATA::PORT);
+        let mask = Self::positions().iter().fold(0, |mask, &pos| mask | 0b11 << (pos * 2));
+
+        // MODER is shared with other pins of the port
+        interrupt::free(|_| registers.moder.modify(|r, w| unsafe { w.bits(r.bits() & !mask) }));
+        self.cs.set_low();
+        self.strobe_rd(true);
+        Self::delay(self.pulse);
+        let value = gather(registers.idr.read().bits(), Self::positions());
+        self.strobe_rd(false);
+        self.cs.set_high();
+        Self::delay(self.hold);
+        // Output mode is 0b01
+        interrupt::free(|_| registers.moder.modify(|r, w| unsafe { w.bits(r.bits() & !mask | mask & 0x5555_5555) }));
+
+        value
+    }
+
+    /// Releases data pins and strobes
+    pub fn free(self) -> (DATA, WR, RD, CS) {
+        (self.data, self.wr, self.rd, self.cs)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{bsrr, gather};
+
+    #[test]
+    fn spread_and_gather() {
+        let contiguous = [0, 1, 2, 3, 4, 5, 6, 7];
+        assert_eq!(bsrr(0xA5, &contiguous), 0x005A_00A5);
+
+        let shifted = [8, 9, 10, 11, 12, 13, 14, 15];
+        assert_eq!(bsrr(0x0F, &shifted), 0xF000_0F00);
+
+        let scattered = [3, 0, 7, 1, 2, 4, 5, 6];
+        assert_eq!(gather(bsrr(0x81, &scattered) & 0xFFFF, &scattered), 0x81);
+        assert_eq!(gather(0x0000_0008, &scattered), 0x01);
+    }
+}
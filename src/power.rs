@@ -1,8 +1,25 @@
 //! Power control
 
+use cortex_m::asm;
+use cortex_m::peripheral::SCB;
+use embedded_hal::timer::CountDown;
 use stm32l4::stm32l4x5::{pwr, PWR};
 
-use crate::common::Constrain;
+use crate::common::{Constrain, TimedOut};
+use crate::flash::ACR;
+use crate::rcc::{self, clocking, Clocks, BDCR, CFGR};
+
+#[cfg(target_arch = "arm")]
+use cortex_m::{
+    interrupt,
+    peripheral::{NVIC, SYST},
+};
+
+#[cfg(target_arch = "arm")]
+use crate::{
+    rcc::{AHB, APB1, APB2},
+    syscfg::{MemoryMapping, SYSCFG},
+};
 
 impl Constrain<Power> for PWR {
     fn constrain(self) -> Power {
@@ -41,6 +58,13 @@ impl Power {
         unsafe { &(*PWR::ptr()).scr }
     }
 
+    /// Turns on/off pull configuration of GPIOs in Standby and Shutdown modes (APC).
+    ///
+    /// Pulls are set per pin, e.g. with `retain_pull_up_in_standby`.
+    pub fn set_standby_pulls(&mut self, is_on: bool) {
+        self.cr3().modify(|_, w| w.apc().bit(is_on));
+    }
+
     /// Removes write protection from Backup Domain Control register.
     pub fn remove_bdp(&mut self) {
         let cr1 = self.cr1();
@@ -53,3 +77,397 @@ impl Power {
         }
     }
 }
+
+/// Pull applied to pin in Standby and Shutdown modes
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum StandbyPull {
+    /// No pull
+    Floating,
+    /// Pull-up
+    Up,
+    /// Pull-down
+    Down,
+}
+
+macro_rules! standby_pull {
+    ($($port:expr => ($pucr:ident, $pdcr:ident),)+) => {
+        /// Sets pull of pin `num` of `port` in Standby, which is applied only when enabled with APC.
+        pub(crate) fn set_standby_pull(_: &mut Power, port: u8, num: u8, pull: StandbyPull) {
+            let (up, down) = match pull {
+                StandbyPull::Floating => (false, false),
+                StandbyPull::Up => (true, false),
+                StandbyPull::Down => (false, true),
+            };
+            let bits = |original: u32, is_set: bool| (original & !(1 << num)) | ((is_set as u32) << num);
+
+            let pwr = unsafe { &*PWR::ptr() };
+            match port {
+                $(
+                    $port => {
+                        pwr.$pucr.modify(|r, w| unsafe { w.bits(bits(r.bits(), up)) });
+                        pwr.$pdcr.modify(|r, w| unsafe { w.bits(bits(r.bits(), down)) });
+                    },
+                )+
+                _ => unreachable!(),
+            }
+        }
+    }
+}
+
+standby_pull!(
+    b'A' => (pucra, pdcra),
+    b'B' => (pucrb, pdcrb),
+    b'C' => (pucrc, pdcrc),
+    b'D' => (pucrd, pdcrd),
+    b'E' => (pucre, pdcre),
+    b'F' => (pucrf, pdcrf),
+    b'G' => (pucrg, pdcrg),
+    b'H' => (pucrh, pdcrh),
+);
+
+/// Wakeup pin, which can bring MCU out of Standby and Shutdown modes
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum WakeupPin {
+    /// WKUP1 on PA0
+    Wkup1 = 0,
+    /// WKUP2 on PC13
+    Wkup2 = 1,
+    /// WKUP3 on PE6
+    Wkup3 = 2,
+    /// WKUP4 on PA2
+    Wkup4 = 3,
+    /// WKUP5 on PC5
+    Wkup5 = 4,
+}
+
+impl WakeupPin {
+    /// Returns port and number of GPIO pin
+    fn location(self) -> (u8, u8) {
+        match self {
+            WakeupPin::Wkup1 => (b'A', 0),
+            WakeupPin::Wkup2 => (b'C', 13),
+            WakeupPin::Wkup3 => (b'E', 6),
+            WakeupPin::Wkup4 => (b'A', 2),
+            WakeupPin::Wkup5 => (b'C', 5),
+        }
+    }
+}
+
+/// Active edge of wakeup pin
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum WakeupEdge {
+    /// Wakes up on rising edge
+    Rising,
+    /// Wakes up on falling edge
+    Falling,
+}
+
+/// SBF bit of SR1 and CSBF of SCR
+const STANDBY_FLAG: u32 = 1 << 8;
+/// Mask of WUFx bits of SR1 and CWUFx of SCR
+const WAKEUP_FLAGS: u32 = 0b1_1111;
+/// Standby low power mode selection
+const LPMS_STANDBY: u8 = 0b011;
+/// Stop 2 low power mode selection
+const LPMS_STOP2: u8 = 0b010;
+
+/// Wakeup pins configuration for Standby and Shutdown modes.
+///
+/// ```rust, ignore
+/// let mut wakeup = WakeupPins::new(&mut power);
+/// if wakeup.is_standby_wakeup() {
+///     // Woken up from Standby, RAM content is lost
+/// }
+/// wakeup.configure(&[(WakeupPin::Wkup1, WakeupEdge::Rising, StandbyPull::Down)]);
+/// enter_standby(&mut power, &mut core.SCB);
+/// ```
+pub struct WakeupPins<'a> {
+    power: &'a mut Power,
+}
+
+impl<'a> WakeupPins<'a> {
+    /// Wraps power control to configure wakeup pins
+    pub fn new(power: &'a mut Power) -> Self {
+        Self {
+            power,
+        }
+    }
+
+    /// Enables `pin` to wake up on `edge`, with `pull` applied to it in Standby.
+    ///
+    /// Pull configuration in Standby is enabled for all pins (APC).
+    pub fn enable(&mut self, pin: WakeupPin, edge: WakeupEdge, pull: StandbyPull) {
+        let mask = 1 << pin as u32;
+        let (port, num) = pin.location();
+
+        set_standby_pull(self.power, port, num, pull);
+        self.power.set_standby_pulls(true);
+        self.power.cr4().modify(|r, w| unsafe {
+            w.bits(match edge {
+                WakeupEdge::Rising => r.bits() & !mask,
+                WakeupEdge::Falling => r.bits() | mask,
+            })
+        });
+        // Polarity change may set flag
+        self.clear_flag(pin);
+        self.power.cr3().modify(|r, w| unsafe { w.bits(r.bits() | mask) });
+    }
+
+    /// Disables wakeup by `pin`
+    pub fn disable(&mut self, pin: WakeupPin) {
+        self.power.cr3().modify(|r, w| unsafe { w.bits(r.bits() & !(1 << pin as u32)) });
+    }
+
+    /// Enables all `pins` at once, disabling others, and clears wakeup flags.
+    pub fn configure(&mut self, pins: &[(WakeupPin, WakeupEdge, StandbyPull)]) {
+        self.power.cr3().modify(|r, w| unsafe { w.bits(r.bits() & !WAKEUP_FLAGS) });
+        for &(pin, edge, pull) in pins {
+            self.enable(pin, edge, pull);
+        }
+        self.clear_flags();
+    }
+
+    /// Returns whether wakeup event was detected on `pin`
+    pub fn is_woken(&mut self, pin: WakeupPin) -> bool {
+        self.power.sr1().read().bits() & (1 << pin as u32) != 0
+    }
+
+    /// Clears wakeup flag of `pin`
+    pub fn clear_flag(&mut self, pin: WakeupPin) {
+        self.power.scr().write(|w| unsafe { w.bits(1 << pin as u32) });
+    }
+
+    /// Clears wakeup flags of all pins, required before entering Standby
+    pub fn clear_flags(&mut self) {
+        self.power.scr().write(|w| unsafe { w.bits(WAKEUP_FLAGS) });
+    }
+
+    /// Returns whether MCU has been woken up from Standby
+    pub fn is_standby_wakeup(&mut self) -> bool {
+        self.power.sr1().read().bits() & STANDBY_FLAG != 0
+    }
+
+    /// Clears Standby flag
+    pub fn clear_standby_flag(&mut self) {
+        self.power.scr().write(|w| unsafe { w.bits(STANDBY_FLAG) });
+    }
+}
+
+/// Enters Standby mode, from which MCU wakes up through reset.
+///
+/// Wakeup flags are cleared, so enable wakeup sources beforehand.
+pub fn enter_standby(power: &mut Power, scb: &mut SCB) -> ! {
+    power.scr().write(|w| unsafe { w.bits(WAKEUP_FLAGS) });
+    power.cr1().modify(|_, w| unsafe { w.lpms().bits(LPMS_STANDBY) });
+    scb.set_sleepdeep();
+
+    loop {
+        asm::wfi();
+    }
+}
+
+/// Start of System memory, which contains ROM bootloader.
+///
+/// See Reference manual Ch. 3.3.1
+pub const SYSTEM_MEMORY: u32 = 0x1FFF_0000;
+
+/// Jumps to the ROM bootloader, which allows to flash firmware over USART, USB DFU and etc.
+///
+/// Before jump, interrupts are disabled and cleared in NVIC, SysTick is stopped,
+/// clock configuration is reset to MSI at 4 MHz and peripherals are reset, with their clocks disabled,
+/// see [reset_all_peripherals](../rcc/fn.reset_all_peripherals.html).
+/// Then System memory is mapped at address zero and vector table is moved to it.
+///
+/// See AN2606 for bootloader description.
+///
+/// Available only on ARM targets.
+#[cfg(target_arch = "arm")]
+pub fn enter_system_bootloader(syscfg: &mut SYSCFG, syst: &mut SYST, acr: &mut ACR, ahb: &mut AHB, apb1: &mut APB1, apb2: &mut APB2) -> ! {
+    interrupt::disable();
+
+    syst.disable_counter();
+    syst.disable_interrupt();
+
+    unsafe {
+        let nvic = &*NVIC::ptr();
+        for idx in 0..nvic.icer.len() {
+            nvic.icer[idx].write(0xFFFF_FFFF);
+            nvic.icpr[idx].write(0xFFFF_FFFF);
+        }
+    }
+
+    rcc::reset_clocks(acr);
+    rcc::reset_all_peripherals(ahb, apb1, apb2);
+
+    // SYSCFG is not reset, but its clock has been disabled with the rest of peripherals
+    apb2.enr().modify(|_, w| w.syscfgen().set_bit());
+    syscfg.set_memory_mapping(MemoryMapping::SystemFlash);
+
+    unsafe {
+        (*SCB::ptr()).vtor.write(SYSTEM_MEMORY);
+
+        let stack = *(SYSTEM_MEMORY as *const u32);
+        let reset = *((SYSTEM_MEMORY + 4) as *const u32);
+        jump(stack, reset)
+    }
+}
+
+/// Loads `stack` into MSP and branches to `reset` handler, without touching stack in between.
+///
+/// Bootloader expects interrupts to be enabled, all sources are disabled in NVIC already.
+#[cfg(target_arch = "arm")]
+unsafe fn jump(stack: u32, reset: u32) -> ! {
+    core::arch::asm!(
+        "msr MSP, {stack}",
+        "cpsie i",
+        "bx {reset}",
+        stack = in(reg) stack,
+        reset = in(reg) reset,
+        options(noreturn),
+    )
+}
+
+/// SEVONPEND bit of System Control Register
+const SCR_SEVONPEND: u32 = 1 << 4;
+
+/// Sets whether pending interrupts, including disabled in NVIC, generate event waking up `WFE`.
+///
+/// With it interrupt source can be used to wake up core from `WFE`,
+/// without running interrupt handler.
+pub fn set_sev_on_pend(scb: &mut SCB, is_on: bool) {
+    unsafe {
+        scb.scr.modify(|scr| match is_on {
+            true => scr | SCR_SEVONPEND,
+            false => scr & !SCR_SEVONPEND,
+        });
+    }
+}
+
+/// Signals event to the core and EVENTOUT pins.
+#[inline]
+pub fn send_event() {
+    asm::sev();
+}
+
+/// Polls `op` until it completes, sleeping with `WFE` in between.
+///
+/// Low power replacement of `nb::block!`, which requires source of `op` readiness to
+/// generate event, e.g. its interrupt to be enabled (see [set_sev_on_pend](fn.set_sev_on_pend.html)).
+pub fn wait_for_event<R, E, F: FnMut() -> nb::Result<R, E>>(mut op: F) -> Result<R, E> {
+    loop {
+        match op() {
+            Ok(result) => return Ok(result),
+            Err(nb::Error::Other(error)) => return Err(error),
+            Err(nb::Error::WouldBlock) => asm::wfe(),
+        }
+    }
+}
+
+/// Polls `op` until it completes or `timer` expires, sleeping with `WFE` in between.
+///
+/// Timer's timeout event must be subscribed to wake up the core.
+///
+/// Returns `Timeout` error of driver, converted from [TimedOut](../common/struct.TimedOut.html), on timeout,
+/// as [block_timeout](../common/fn.block_timeout.html) does.
+pub fn wait_for_event_with_timeout<T, R, E, F>(timer: &mut T, mut op: F) -> Result<R, E>
+    where E: From<TimedOut>, T: CountDown, F: FnMut() -> nb::Result<R, E> {
+    loop {
+        match op() {
+            Ok(result) => return Ok(result),
+            Err(nb::Error::Other(error)) => return Err(error),
+            Err(nb::Error::WouldBlock) => (),
+        }
+
+        if timer.wait().is_ok() {
+            return Err(TimedOut.into());
+        }
+
+        asm::wfe();
+    }
+}
+
+/// Voltage scaling Range 1, up to 80 MHz
+const VOS_RANGE1: u8 = 0b01;
+/// Voltage scaling Range 2, up to 26 MHz
+const VOS_RANGE2: u8 = 0b10;
+
+/// Reference low power states of current consumption tables in datasheet (DS10969 Ch. 6.3.5).
+///
+/// Each state is configured in one call, so consumption of board can be compared with
+/// typical values. Datasheet measures with all peripherals disabled and GPIOs in analog mode,
+/// which is left to application, as only it knows what is connected:
+///
+/// ```rust, ignore
+/// let clocks = Profile::low_power_run(&mut power, &mut flash.acr);
+/// // measure IDD here
+/// Profile::run(&mut power);
+/// let clocks = rcc::reconfigure(cfgr, &mut flash.acr);
+/// ```
+pub struct Profile;
+
+impl Profile {
+    /// Sets voltage scaling range, waiting for regulator to settle.
+    fn set_voltage_range(power: &mut Power, vos: u8) {
+        power.cr1().modify(|_, w| unsafe { w.vos().bits(vos) });
+        while power.sr2().read().vosf().bit_is_set() {}
+    }
+
+    /// Runs from flash at 2 MHz MSI in voltage Range 2, with prefetch off.
+    ///
+    /// Clocks are switched with [reconfigure](../rcc/fn.reconfigure.html), so subscribed drivers follow them.
+    pub fn run_2mhz(power: &mut Power, acr: &mut ACR) -> Clocks {
+        Self::run(power);
+
+        let msi = clocking::MediumSpeedInternalRC::new(clocking::MsiRange::R2M, false);
+        let clocks = rcc::reconfigure(CFGR::default().sysclk(clocking::SysClkSource::MSI(msi)), acr);
+        acr.acr().modify(|_, w| w.prften().clear_bit());
+        Self::set_voltage_range(power, VOS_RANGE2);
+
+        clocks
+    }
+
+    /// Enters Low-power run at 2 MHz MSI, which is its maximal frequency.
+    pub fn low_power_run(power: &mut Power, acr: &mut ACR) -> Clocks {
+        let clocks = Self::run_2mhz(power, acr);
+        power.cr1().modify(|_, w| w.lpr().set_bit());
+        while power.sr2().read().reglpf().bit_is_clear() {}
+
+        clocks
+    }
+
+    /// Leaves Low-power run and restores voltage Range 1, so clocks can be raised up to 80 MHz.
+    pub fn run(power: &mut Power) {
+        power.cr1().modify(|_, w| w.lpr().clear_bit());
+        while power.sr2().read().reglpf().bit_is_set() {}
+        Self::set_voltage_range(power, VOS_RANGE1);
+    }
+
+    /// Enters Stop 2 with RTC clocked by LSE, returning once woken up.
+    ///
+    /// LSE and RTC are enabled, wakeup timer or alarm of RTC must be set beforehand to wake up.
+    /// MSI clocks system after wakeup, so clocks must be reconfigured by application.
+    pub fn stop2_with_rtc(power: &mut Power, bdcr: &mut BDCR, scb: &mut SCB) {
+        // Stop 2 cannot be entered from Low-power run
+        Self::run(power);
+
+        power.remove_bdp();
+        bdcr.lse_enable(true);
+        if let clocking::RtcClkSource::None = bdcr.rtc_clock() {
+            bdcr.set_rtc_clock(clocking::RtcClkSource::LSE);
+        }
+        bdcr.rtc_enable(true);
+
+        power.cr1().modify(|_, w| unsafe { w.lpms().bits(LPMS_STOP2) });
+        scb.set_sleepdeep();
+        asm::dsb();
+        asm::wfi();
+        scb.clear_sleepdeep();
+    }
+
+    /// Enters Standby without RTC, with SRAM2 content and GPIO pulls not retained.
+    pub fn standby(power: &mut Power, scb: &mut SCB) -> ! {
+        power.set_standby_pulls(false);
+        power.cr3().modify(|_, w| w.rrs().clear_bit());
+        enter_standby(power, scb)
+    }
+}
@@ -0,0 +1,248 @@
+//! Low power PWM on LPTIM output
+//!
+//! Clocked from LSE, LPTIM1 keeps its output running in Stop 0, 1 and 2 modes,
+//! while LPTIM2 does so down to Stop 1.
+//!
+//! ```rust, ignore
+//! rcc.bdcr.lse_enable(true);
+//! let pin = gpio_b.PB2.into_alt_fun::<AF1>(&mut gpio_b.moder, &mut gpio_b.afrl);
+//! let mut pwm = LpPwm::lptim1(device.LPTIM1, pin, Hertz(2), ClockSource::Lse, &clocks, &mut rcc.apb1, &mut rcc.ccipr)?;
+//! pwm.set_duty(pwm.get_max_duty() / 8);
+//! pwm.enable();
+//! ```
+//!
+//! See Reference manual Ch. 31
+
+use embedded_hal::PwmPin;
+use stm32l4::stm32l4x5::{LPTIM1, LPTIM2};
+
+use crate::common::ConfigError;
+use crate::gpio::{AF1, AF14, PA4, PA8, PB2, PC1};
+use crate::rcc::clocking::{HSI16_FREQ, LSE_FREQ, LSI_FREQ};
+use crate::rcc::{APB1, CCIPR, Clocks};
+use crate::time::Hertz;
+
+/// Maximal value of prescaler exponent, division by 128
+const PRESC_MAX: u8 = 7;
+
+/// Kernel clock of LPTIM
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum ClockSource {
+    /// APB1 clock, stopped in Stop modes
+    Pclk = 0b00,
+    /// LSI, must be enabled beforehand
+    Lsi = 0b01,
+    /// HSI16, stopped in Stop modes
+    Hsi16 = 0b10,
+    /// LSE, must be enabled beforehand
+    Lse = 0b11,
+}
+
+impl ClockSource {
+    fn freq(self, clocks: &Clocks) -> u32 {
+        match self {
+            ClockSource::Pclk => clocks.pclk1.0,
+            ClockSource::Lsi => clocks.lsi.map_or(LSI_FREQ, |freq| freq.0),
+            ClockSource::Hsi16 => HSI16_FREQ,
+            ClockSource::Lse => clocks.lse.map_or(LSE_FREQ, |freq| freq.0),
+        }
+    }
+}
+
+/// Describes PIN, which can be driven by LPTIM output
+pub trait Pin<LPTIM> {}
+
+impl Pin<LPTIM1> for PB2<AF1> {}
+impl Pin<LPTIM1> for PC1<AF1> {}
+impl Pin<LPTIM2> for PA4<AF14> {}
+impl Pin<LPTIM2> for PA8<AF14> {}
+
+/// Calculates prescaler exponent and auto-reload value to derive `freq` from `clock`.
+pub fn calc_presc_arr(clock: u32, freq: u32) -> Result<(u8, u16), ConfigError> {
+    if freq == 0 {
+        return Err(ConfigError::FrequencyTooLow);
+    }
+
+    let ticks = clock / freq;
+    // ARR must be greater than CMP, so at least 2 ticks are required
+    if ticks < 2 {
+        return Err(ConfigError::FrequencyTooHigh);
+    }
+
+    for presc in 0..=PRESC_MAX {
+        let arr = (ticks >> presc) - 1;
+        if arr <= u16::max_value() as u32 {
+            return Ok((presc, arr as u16));
+        }
+    }
+
+    Err(ConfigError::FrequencyTooLow)
+}
+
+/// PWM output of low power timer
+pub struct LpPwm<LPTIM, PIN> {
+    tim: LPTIM,
+    pin: PIN,
+    clock: u32,
+    // ARR and CMP are writable only while enabled, so they're restored by `enable`
+    arr: u16,
+    cmp: u16,
+}
+
+macro_rules! impl_lp_pwm {
+    ($($LPTIMx:ident: [constructor: $lptimx:ident; $enr:ident: $en:ident, $rstr:ident: $rst:ident, $sel:ident],)+) => {
+        $(
+            impl<PIN: Pin<$LPTIMx>> LpPwm<$LPTIMx, PIN> {
+                /// Configures LPTIM to generate `freq` on `pin`, output is disabled until `enable`.
+                ///
+                /// Returns error if `freq` cannot be derived from `clock`.
+                pub fn $lptimx(tim: $LPTIMx, pin: PIN, freq: Hertz, clock: ClockSource, clocks: &Clocks,
+                               apb: &mut APB1, ccipr: &mut CCIPR) -> Result<Self, ConfigError> {
+                    let clock_freq = clock.freq(clocks);
+                    let (presc, arr) = calc_presc_arr(clock_freq, freq.0)?;
+
+                    if clock == ClockSource::Hsi16 {
+                        ccipr.hsi16_enable();
+                    }
+                    ccipr.inner().modify(|_, w| unsafe { w.$sel().bits(clock as u8) });
+                    apb.$enr().modify(|_, w| w.$en().set_bit());
+                    apb.$rstr().modify(|_, w| w.$rst().set_bit());
+                    apb.$rstr().modify(|_, w| w.$rst().clear_bit());
+
+                    // CFGR is writable only while disabled
+                    tim.cfgr.write(|w| unsafe { w.presc().bits(presc).preload().set_bit() });
+                    tim.cr.write(|w| w.enable().set_bit());
+
+                    let mut pwm = Self {
+                        tim,
+                        pin,
+                        clock: clock_freq >> presc,
+                        arr,
+                        cmp: arr,
+                    };
+                    pwm.write_arr();
+                    pwm.set_duty(0);
+
+                    Ok(pwm)
+                }
+
+                /// Sets period in timer ticks, applied at the end of current period.
+                ///
+                /// # Pancis:
+                ///
+                /// In debug mode the function checks that period is at least 2 ticks.
+                pub fn set_period(&mut self, period: u16) {
+                    debug_assert!(period >= 2);
+
+                    self.arr = period - 1;
+                    if self.tim.cr.read().enable().bit_is_set() {
+                        self.write_arr();
+                    }
+                }
+
+                /// Returns period in timer ticks
+                pub fn period(&self) -> u16 {
+                    self.arr + 1
+                }
+
+                // ARR and CMP are written through to counter clock domain, next write is allowed
+                // only once ARROK (CMPOK) is set. Flag is cleared before write as well, so that
+                // one left by earlier write doesn't end the wait before this write completes.
+                fn write_arr(&mut self) {
+                    let arr = self.arr;
+                    self.tim.icr.write(|w| w.arrokcf().set_bit());
+                    self.tim.arr.write(|w| unsafe { w.arr().bits(arr) });
+                    while self.tim.isr.read().arrok().bit_is_clear() {}
+                    self.tim.icr.write(|w| w.arrokcf().set_bit());
+                }
+
+                fn write_cmp(&mut self) {
+                    let cmp = self.cmp;
+                    self.tim.icr.write(|w| w.cmpokcf().set_bit());
+                    self.tim.cmp.write(|w| unsafe { w.cmp().bits(cmp) });
+                    while self.tim.isr.read().cmpok().bit_is_clear() {}
+                    self.tim.icr.write(|w| w.cmpokcf().set_bit());
+                }
+
+                /// Sets period to match `freq`, within range of current prescaler.
+                pub fn set_frequency(&mut self, freq: Hertz) -> Result<(), ConfigError> {
+                    let ticks = self.clock / freq.0.max(1);
+                    if ticks < 2 {
+                        return Err(ConfigError::FrequencyTooHigh);
+                    } else if ticks > u16::max_value() as u32 {
+                        return Err(ConfigError::FrequencyTooLow);
+                    }
+
+                    let duty = self.get_duty() as u32 * ticks / self.get_max_duty().max(1) as u32;
+                    self.set_period(ticks as u16);
+                    self.set_duty(duty as u16);
+                    Ok(())
+                }
+
+                /// Returns frequency of PWM output
+                pub fn frequency(&self) -> Hertz {
+                    Hertz(self.clock / self.period() as u32)
+                }
+
+                /// Stops timer, releasing it and the pin
+                pub fn free(self) -> ($LPTIMx, PIN) {
+                    self.tim.cr.write(|w| w.enable().clear_bit());
+                    (self.tim, self.pin)
+                }
+            }
+
+            impl<PIN: Pin<$LPTIMx>> PwmPin for LpPwm<$LPTIMx, PIN> {
+                type Duty = u16;
+
+                /// Disables timer, which stops counter and drives output low
+                fn disable(&mut self) {
+                    self.tim.cr.write(|w| w.enable().clear_bit());
+                }
+
+                /// Enables timer, restores period and duty, then starts counter
+                fn enable(&mut self) {
+                    self.tim.cr.write(|w| w.enable().set_bit());
+                    self.write_arr();
+                    self.write_cmp();
+                    self.tim.cr.modify(|_, w| w.cntstrt().set_bit());
+                }
+
+                fn get_duty(&self) -> u16 {
+                    self.arr - self.cmp
+                }
+
+                fn get_max_duty(&self) -> u16 {
+                    self.arr
+                }
+
+                /// Output goes high once counter exceeds compare value, until end of period
+                fn set_duty(&mut self, duty: u16) {
+                    self.cmp = self.arr - duty.min(self.arr);
+                    if self.tim.cr.read().enable().bit_is_set() {
+                        self.write_cmp();
+                    }
+                }
+            }
+        )+
+    }
+}
+
+impl_lp_pwm!(
+    LPTIM1: [constructor: lptim1; enr1: lptim1en, rstr1: lptim1rst, lptim1sel],
+    LPTIM2: [constructor: lptim2; enr2: lptim2en, rstr2: lptim2rst, lptim2sel],
+);
+
+#[cfg(test)]
+mod tests {
+    use super::calc_presc_arr;
+    use crate::common::ConfigError;
+
+    #[test]
+    fn presc_arr() {
+        assert_eq!(calc_presc_arr(32_768, 1_024), Ok((0, 31)));
+        assert_eq!(calc_presc_arr(32_768, 1), Ok((0, 32_767)));
+        assert_eq!(calc_presc_arr(16_000_000, 100), Ok((2, 39_999)));
+        assert_eq!(calc_presc_arr(32_768, 20_000), Err(ConfigError::FrequencyTooHigh));
+        assert_eq!(calc_presc_arr(80_000_000, 1), Err(ConfigError::FrequencyTooLow));
+    }
+}
@@ -0,0 +1,361 @@
+//! Pulse Width Modulation on timer output channels
+//!
+//! [PwmTimer](struct.PwmTimer.html) sets period of the timer, while each
+//! [PwmChannel](struct.PwmChannel.html) drives its own pin with own duty cycle.
+//!
+//! ```rust, ignore
+//! let pin = gpio_a.PA0.into_alt_fun::<AF1>(&mut gpio_a.moder, &mut gpio_a.afrl);
+//! let mut timer = PwmTimer::tim2(tim2, Hertz(1_000), clocks, &mut rcc.apb1).unwrap();
+//! let mut channel = timer.channel(pin);
+//! channel.set_duty(channel.get_max_duty() / 2);
+//! channel.enable();
+//!
+//! // Duty ratio is kept across frequency change
+//! timer.set_frequency(Hertz(2_000)).unwrap();
+//! channel.set_duty_percent(12.5);
+//! ```
+
+use core::convert::TryFrom;
+use core::marker::PhantomData;
+
+use embedded_hal::PwmPin;
+use stm32l4::stm32l4x5::{TIM1, TIM2, TIM3, TIM4, TIM5, TIM8};
+
+use crate::common::ConfigError;
+use crate::gpio::{AF1, AF2, AF3};
+use crate::gpio::{PA0, PA1, PA2, PA3, PA5, PA6, PA7, PA8, PA9, PA10, PA11, PA15};
+use crate::gpio::{PB0, PB1, PB3, PB4, PB5, PB6, PB7, PB8, PB9, PB10, PB11, PB13, PB14, PB15};
+use crate::gpio::{PC6, PC7, PC8, PC9};
+use crate::rcc::{APB1, APB2, Clocks};
+use crate::time::Hertz;
+use crate::timer::{calc_psc_arr, timer_clock};
+
+pub mod lptim;
+pub mod servo;
+pub use self::lptim::LpPwm;
+pub use self::servo::Servo;
+
+/// Output channel of timer
+pub trait ChannelId {
+    /// Number of channel, 1 to 4
+    const NUMBER: u8;
+}
+
+macro_rules! impl_channels {
+    ($($C:ident: $number:expr,)+) => {
+        $(
+            /// Output channel of timer
+            pub struct $C;
+
+            impl ChannelId for $C {
+                const NUMBER: u8 = $number;
+            }
+        )+
+    }
+}
+
+impl_channels!(C1: 1, C2: 2, C3: 3, C4: 4,);
+
+/// Describes PIN, which can be driven by timer's output channel
+pub trait Pin<TIM> {
+    /// Channel driving the pin
+    type Channel: ChannelId;
+    /// Number of channel, 1 to 4
+    const CHANNEL: u8 = <Self::Channel as ChannelId>::NUMBER;
+    /// Whether complementary output of the channel is driven too
+    const COMPLEMENTARY: bool = false;
+}
+
+/// Describes PIN, which can be driven by complementary output of advanced timer's channel.
+///
+/// Pair of channel and complementary pins, `(PIN, NPIN)`, is used as [Pin](trait.Pin.html),
+/// with dead time inserted between them, see [Protection](../timer/protection/struct.Protection.html).
+/// Both pins must belong to the same channel, otherwise pair doesn't implement `Pin`.
+pub trait ComplementaryPin<TIM> {
+    /// Channel driving the pin, 1 to 3
+    type Channel: ChannelId;
+}
+
+impl<TIM, P: Pin<TIM>, N: ComplementaryPin<TIM, Channel = P::Channel>> Pin<TIM> for (P, N) {
+    type Channel = P::Channel;
+    const COMPLEMENTARY: bool = true;
+}
+
+macro_rules! impl_pins {
+    ($TIMx:ident, $AFx:ident: [$($PIN:ident: $C:ident,)+]) => {
+        $(
+            impl Pin<$TIMx> for $PIN<$AFx> {
+                type Channel = $C;
+            }
+        )+
+    }
+}
+
+impl_pins!(TIM1, AF1: [PA8: C1, PA9: C2, PA10: C3, PA11: C4,]);
+impl_pins!(TIM2, AF1: [PA0: C1, PA5: C1, PA15: C1, PA1: C2, PB3: C2, PA2: C3, PB10: C3, PA3: C4, PB11: C4,]);
+impl_pins!(TIM3, AF2: [PA6: C1, PB4: C1, PC6: C1, PA7: C2, PB5: C2, PC7: C2, PB0: C3, PC8: C3, PB1: C4, PC9: C4,]);
+impl_pins!(TIM4, AF2: [PB6: C1, PB7: C2, PB8: C3, PB9: C4,]);
+impl_pins!(TIM5, AF2: [PA0: C1, PA1: C2, PA2: C3, PA3: C4,]);
+impl_pins!(TIM8, AF3: [PC6: C1, PC7: C2, PC8: C3, PC9: C4,]);
+
+macro_rules! impl_complementary_pins {
+    ($TIMx:ident, $AFx:ident: [$($PIN:ident: $C:ident,)+]) => {
+        $(
+            impl ComplementaryPin<$TIMx> for $PIN<$AFx> {
+                type Channel = $C;
+            }
+        )+
+    }
+}
+
+impl_complementary_pins!(TIM1, AF1: [PA7: C1, PB13: C1, PB0: C2, PB14: C2, PB1: C3, PB15: C3,]);
+impl_complementary_pins!(TIM8, AF3: [PA5: C1, PA7: C1, PB0: C2, PB14: C2, PB1: C3, PB15: C3,]);
+
+/// Gate of all outputs, which is present only on advanced timers
+trait MainOutput {
+    /// Sets MOE bit, if timer has one
+    fn enable_main_output(&self) {}
+}
+
+impl MainOutput for TIM1 {
+    fn enable_main_output(&self) {
+        self.bdtr.modify(|_, w| w.moe().set_bit());
+    }
+}
+
+impl MainOutput for TIM8 {
+    fn enable_main_output(&self) {
+        self.bdtr.modify(|_, w| w.moe().set_bit());
+    }
+}
+
+impl MainOutput for TIM2 {}
+impl MainOutput for TIM3 {}
+impl MainOutput for TIM4 {}
+impl MainOutput for TIM5 {}
+
+/// Scales compare value `ccr` from period of `old_arr` to period of `new_arr`, keeping duty ratio.
+fn scale_ccr(ccr: u32, old_arr: u32, new_arr: u32) -> u32 {
+    (ccr as u64 * (new_arr as u64 + 1) / (old_arr as u64 + 1)) as u32
+}
+
+/// Calculates duty of `permille` of `max_duty`, rounded to the nearest.
+fn permille_duty(max_duty: u32, permille: u16) -> u32 {
+    let permille = permille.min(1_000) as u64;
+    ((max_duty as u64 * permille + 500) / 1_000) as u32
+}
+
+/// Duty cycle setters relative to maximal duty, available on every PWM output
+pub trait DutyRatio: PwmPin {
+    /// Sets duty cycle in per mille of maximal duty, limited by 1000
+    fn set_duty_permille(&mut self, permille: u16);
+
+    /// Sets duty cycle in percent of maximal duty, limited to 0..=100
+    fn set_duty_percent(&mut self, percent: f32);
+}
+
+impl<P: PwmPin> DutyRatio for P where P::Duty: Into<u32> + TryFrom<u32> {
+    fn set_duty_permille(&mut self, permille: u16) {
+        let duty = permille_duty(self.get_max_duty().into(), permille);
+        if let Ok(duty) = P::Duty::try_from(duty) {
+            self.set_duty(duty);
+        }
+    }
+
+    fn set_duty_percent(&mut self, percent: f32) {
+        let max_duty: u32 = self.get_max_duty().into();
+        let percent = percent.max(0.0).min(100.0);
+        let duty = (max_duty as f32 * percent / 100.0 + 0.5) as u32;
+        if let Ok(duty) = P::Duty::try_from(duty.min(max_duty)) {
+            self.set_duty(duty);
+        }
+    }
+}
+
+/// Returns CCER bits enabling outputs of channel driving `PIN`
+fn ccer_enable<TIM, PIN: Pin<TIM>>() -> u32 {
+    // CCxE, and CCxNE two bits above it
+    let bits = if PIN::COMPLEMENTARY { 0b101 } else { 0b001 };
+    bits << (4 * (PIN::CHANNEL as u32 - 1))
+}
+
+/// Timer running in PWM mode
+pub struct PwmTimer<TIM> {
+    tim: TIM,
+    clocks: Clocks,
+}
+
+/// PWM output channel of timer
+pub struct PwmChannel<TIM, PIN> {
+    pin: PIN,
+    _tim: PhantomData<TIM>,
+}
+
+impl<TIM, PIN> PwmChannel<TIM, PIN> {
+    /// Releases PIN, channel is left as it is.
+    pub fn free(self) -> PIN {
+        self.pin
+    }
+}
+
+macro_rules! impl_pwm {
+    ($($TIMx:ident: [constructor: $timx:ident; $APB:ident: {apb: $apb:ident; $enr:ident: $enr_bit:ident; $rstr:ident: $rstr_bit:ident; ppre: $ppre:ident}],)+) => {
+        $(
+            impl PwmTimer<$TIMx> {
+                /// Starts timer with PWM period of `freq`.
+                ///
+                /// Returns error if `freq` is zero or above timer clock.
+                pub fn $timx<T: Into<Hertz>>(tim: $TIMx, freq: T, clocks: Clocks, apb: &mut $APB) -> Result<Self, ConfigError> {
+                    let (psc, arr) = calc_psc_arr(timer_clock(clocks.$apb, clocks.$ppre), freq.into().0)?;
+
+                    // enable and reset peripheral to a clean slate state
+                    apb.$enr().modify(|_, w| w.$enr_bit().set_bit());
+                    apb.$rstr().modify(|_, w| w.$rstr_bit().set_bit());
+                    apb.$rstr().modify(|_, w| w.$rstr_bit().clear_bit());
+
+                    tim.psc.write(|w| unsafe { w.psc().bits(psc) });
+                    #[allow(unused_unsafe)]
+                    tim.arr.write(|w| unsafe { w.bits(arr as u32) });
+
+                    tim.enable_main_output();
+
+                    // Buffered auto-reload, load prescaler and start
+                    tim.cr1.modify(|_, w| w.arpe().set_bit());
+                    tim.egr.write(|w| w.ug().set_bit());
+                    tim.cr1.modify(|_, w| w.cen().set_bit());
+
+                    Ok(Self {
+                        tim,
+                        clocks,
+                    })
+                }
+
+                /// Configures channel driving `pin` in PWM mode 1 with zero duty cycle.
+                ///
+                /// Channel output is disabled until [enable](struct.PwmChannel.html#method.enable) is called.
+                pub fn channel<PIN: Pin<$TIMx>>(&mut self, pin: PIN) -> PwmChannel<$TIMx, PIN> {
+                    // OCxM = PWM mode 1, OCxPE = preload enable
+                    const MODE: u32 = 0b110 << 4 | 1 << 3;
+
+                    let offset = 8 * ((PIN::CHANNEL as u32 - 1) % 2);
+
+                    let mut channel = PwmChannel {
+                        pin,
+                        _tim: PhantomData,
+                    };
+                    channel.set_duty(0);
+
+                    match PIN::CHANNEL {
+                        1 | 2 => self.tim.ccmr1_output.modify(|r, w| unsafe { w.bits((r.bits() & !(0xFF << offset)) | MODE << offset) }),
+                        _ => self.tim.ccmr2_output.modify(|r, w| unsafe { w.bits((r.bits() & !(0xFF << offset)) | MODE << offset) }),
+                    }
+
+                    channel
+                }
+
+                /// Changes PWM period to `freq`, keeping duty ratio of all channels.
+                ///
+                /// Prescaler, auto-reload and compare values are buffered, so new period
+                /// starts at the end of the current one, without glitch on outputs.
+                /// Returns error, leaving the timer untouched, if `freq` is zero or above timer clock.
+                #[allow(unused_unsafe)]
+                pub fn set_frequency<T: Into<Hertz>>(&mut self, freq: T) -> Result<(), ConfigError> {
+                    let (psc, arr) = calc_psc_arr(timer_clock(self.clocks.$apb, self.clocks.$ppre), freq.into().0)?;
+                    let old_arr = self.tim.arr.read().bits();
+                    let arr = arr as u32;
+
+                    self.tim.ccr1.modify(|r, w| unsafe { w.bits(scale_ccr(r.bits(), old_arr, arr)) });
+                    self.tim.ccr2.modify(|r, w| unsafe { w.bits(scale_ccr(r.bits(), old_arr, arr)) });
+                    self.tim.ccr3.modify(|r, w| unsafe { w.bits(scale_ccr(r.bits(), old_arr, arr)) });
+                    self.tim.ccr4.modify(|r, w| unsafe { w.bits(scale_ccr(r.bits(), old_arr, arr)) });
+                    self.tim.psc.write(|w| unsafe { w.psc().bits(psc) });
+                    self.tim.arr.write(|w| unsafe { w.bits(arr) });
+                    Ok(())
+                }
+
+                /// Returns duty of channels, which corresponds to 100%
+                pub fn max_duty(&self) -> u32 {
+                    self.tim.arr.read().bits()
+                }
+
+                /// Returns PWM frequency
+                pub fn frequency(&self) -> Hertz {
+                    let clock = timer_clock(self.clocks.$apb, self.clocks.$ppre);
+                    let psc = self.tim.psc.read().psc().bits() as u32;
+                    let arr = self.tim.arr.read().bits();
+
+                    Hertz(clock / (psc + 1) / (arr + 1))
+                }
+
+                /// Stops timer and releases it
+                pub fn free(self) -> $TIMx {
+                    self.tim.cr1.modify(|_, w| w.cen().clear_bit());
+                    self.tim
+                }
+            }
+
+            impl<PIN: Pin<$TIMx>> PwmPin for PwmChannel<$TIMx, PIN> {
+                type Duty = u32;
+
+                fn disable(&mut self) {
+                    unsafe { (*$TIMx::ptr()).ccer.modify(|r, w| w.bits(r.bits() & !(ccer_enable::<$TIMx, PIN>()))) }
+                }
+
+                fn enable(&mut self) {
+                    unsafe { (*$TIMx::ptr()).ccer.modify(|r, w| w.bits(r.bits() | ccer_enable::<$TIMx, PIN>())) }
+                }
+
+                fn get_duty(&self) -> u32 {
+                    let tim = unsafe { &*$TIMx::ptr() };
+                    match PIN::CHANNEL {
+                        1 => tim.ccr1.read().bits(),
+                        2 => tim.ccr2.read().bits(),
+                        3 => tim.ccr3.read().bits(),
+                        _ => tim.ccr4.read().bits(),
+                    }
+                }
+
+                fn get_max_duty(&self) -> u32 {
+                    unsafe { (*$TIMx::ptr()).arr.read().bits() }
+                }
+
+                #[allow(unused_unsafe)]
+                fn set_duty(&mut self, duty: u32) {
+                    let tim = unsafe { &*$TIMx::ptr() };
+                    match PIN::CHANNEL {
+                        1 => tim.ccr1.write(|w| unsafe { w.bits(duty) }),
+                        2 => tim.ccr2.write(|w| unsafe { w.bits(duty) }),
+                        3 => tim.ccr3.write(|w| unsafe { w.bits(duty) }),
+                        _ => tim.ccr4.write(|w| unsafe { w.bits(duty) }),
+                    }
+                }
+            }
+        )+
+    }
+}
+
+impl_pwm!(
+    TIM1: [constructor: tim1; APB2: {apb: pclk2; enr: tim1en; rstr: tim1rst; ppre: ppre2}],
+    TIM8: [constructor: tim8; APB2: {apb: pclk2; enr: tim8en; rstr: tim8rst; ppre: ppre2}],
+    TIM2: [constructor: tim2; APB1: {apb: pclk1; enr1: tim2en; rstr1: tim2rst; ppre: ppre1}],
+    TIM3: [constructor: tim3; APB1: {apb: pclk1; enr1: tim3en; rstr1: tim3rst; ppre: ppre1}],
+    TIM4: [constructor: tim4; APB1: {apb: pclk1; enr1: tim4en; rstr1: tim4rst; ppre: ppre1}],
+    TIM5: [constructor: tim5; APB1: {apb: pclk1; enr1: tim5en; rstr1: tim5rst; ppre: ppre1}],
+);
+
+#[cfg(test)]
+mod tests {
+    use super::{permille_duty, scale_ccr};
+
+    #[test]
+    fn duty_ratio() {
+        assert_eq!(permille_duty(999, 500), 500);
+        assert_eq!(permille_duty(65_535, 1_000), 65_535);
+        assert_eq!(permille_duty(65_535, 2_000), 65_535);
+        assert_eq!(permille_duty(99, 5), 0);
+
+        // 25% of 1000 ticks period becomes 25% of 4000 ticks
+        assert_eq!(scale_ccr(250, 999, 3_999), 1_000);
+        assert_eq!(scale_ccr(1_000, 999, 99), 100);
+    }
+}
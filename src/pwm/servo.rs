@@ -0,0 +1,147 @@
+//! Hobby servo control
+//!
+//! Servo position is set by pulse width, usually 1 to 2 ms, repeated with 50 Hz frame rate.
+//!
+//! ```rust, ignore
+//! let mut timer = PwmTimer::tim3(tim3, Hertz(FRAME_RATE), clocks, &mut rcc.apb1).unwrap();
+//! let frame_rate = timer.frequency();
+//! let mut servo = Servo::new(timer.channel(pin), frame_rate, Calibration::default());
+//! servo.set_angle(45);
+//! ```
+
+use embedded_hal::PwmPin;
+
+use crate::time::Hertz;
+
+/// Usual frame rate of servos in Hz
+pub const FRAME_RATE: u32 = 50;
+
+/// Limits of servo pulse width
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub struct Calibration {
+    /// Pulse width at 0 degrees, in microseconds
+    pub min_pulse_us: u32,
+    /// Pulse width at `range` degrees, in microseconds
+    pub max_pulse_us: u32,
+    /// Angle of full travel, in degrees
+    pub range: u16,
+}
+
+impl Default for Calibration {
+    fn default() -> Self {
+        Self {
+            min_pulse_us: 1_000,
+            max_pulse_us: 2_000,
+            range: 180,
+        }
+    }
+}
+
+impl Calibration {
+    /// Returns pulse width in microseconds for `degrees`, which are limited by range.
+    ///
+    /// With zero range the servo has no travel, so minimal pulse is returned.
+    pub fn pulse_us(&self, degrees: u16) -> u32 {
+        if self.range == 0 {
+            return self.min_pulse_us;
+        }
+
+        let degrees = degrees.min(self.range) as u32;
+        self.min_pulse_us + (self.max_pulse_us - self.min_pulse_us) * degrees / self.range as u32
+    }
+}
+
+/// Device positioned by angle
+pub trait Actuator {
+    /// Moves to `degrees`
+    fn set_angle(&mut self, degrees: u16);
+
+    /// Returns last requested angle in degrees
+    fn angle(&self) -> u16;
+}
+
+/// Calculates duty cycle of `pulse_us` within frame of `frame_us`, where `max_duty` is full frame.
+fn calc_duty(pulse_us: u32, frame_us: u32, max_duty: u32) -> u32 {
+    (pulse_us as u64 * (max_duty as u64 + 1) / frame_us as u64) as u32
+}
+
+/// Servo on PWM channel
+pub struct Servo<CHANNEL> {
+    channel: CHANNEL,
+    frame_us: u32,
+    calibration: Calibration,
+    angle: u16,
+}
+
+impl<CHANNEL: PwmPin<Duty = u32>> Servo<CHANNEL> {
+    /// Creates servo on `channel`, whose timer runs at `frame_rate`, and moves it to the center.
+    ///
+    /// # Pancis:
+    ///
+    /// In debug mode the function checks that calibration limits are ordered and range is not zero.
+    pub fn new(mut channel: CHANNEL, frame_rate: Hertz, calibration: Calibration) -> Self {
+        debug_assert!(calibration.min_pulse_us <= calibration.max_pulse_us);
+        debug_assert!(calibration.range > 0);
+
+        channel.enable();
+
+        let mut servo = Self {
+            channel,
+            frame_us: 1_000_000 / frame_rate.0,
+            calibration,
+            angle: 0,
+        };
+        servo.set_angle(calibration.range / 2);
+
+        servo
+    }
+
+    /// Sets pulse width in microseconds, limited by calibration.
+    pub fn set_pulse_us(&mut self, pulse_us: u32) {
+        let pulse_us = pulse_us.max(self.calibration.min_pulse_us).min(self.calibration.max_pulse_us);
+        let duty = calc_duty(pulse_us, self.frame_us, self.channel.get_max_duty());
+
+        self.channel.set_duty(duty);
+    }
+
+    /// Releases channel, which is left enabled.
+    pub fn free(self) -> CHANNEL {
+        self.channel
+    }
+}
+
+impl<CHANNEL: PwmPin<Duty = u32>> Actuator for Servo<CHANNEL> {
+    fn set_angle(&mut self, degrees: u16) {
+        self.angle = degrees.min(self.calibration.range);
+        let pulse_us = self.calibration.pulse_us(self.angle);
+        self.set_pulse_us(pulse_us);
+    }
+
+    fn angle(&self) -> u16 {
+        self.angle
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn angle_to_pulse() {
+        let calibration = Calibration::default();
+        assert_eq!(calibration.pulse_us(0), 1_000);
+        assert_eq!(calibration.pulse_us(90), 1_500);
+        assert_eq!(calibration.pulse_us(270), 2_000);
+
+        let fixed = Calibration { range: 0, ..calibration };
+        assert_eq!(fixed.pulse_us(0), 1_000);
+        assert_eq!(fixed.pulse_us(90), 1_000);
+    }
+
+    #[test]
+    fn pulse_to_duty() {
+        // 20 ms frame with 20_000 ticks
+        assert_eq!(calc_duty(1_500, 20_000, 19_999), 1_500);
+        assert_eq!(calc_duty(1_000, 20_000, 1_999), 100);
+    }
+}
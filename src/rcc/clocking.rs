@@ -16,7 +16,7 @@
 //! use stm32l4::stm32l4x5_hal::rcc::clocking;
 //!
 //! let mut rcc = RCC.constrain();
-//! let msi_clk = clocking::MediumSpeedInternalRC::new(8_000_000, false);
+//! let msi_clk = clocking::MediumSpeedInternalRC::new(clocking::MsiRange::R8M, false);
 //! let sys_clk_src = clocking::SysClkSource::MSI(msi_clk);
 //! let cfgr = rcc.cfgr.sysclk(sys_clk_src);
 //! ```
@@ -24,8 +24,50 @@
 //! The PLL is a bit more complex because it _is_ a source (`PLLClkOutput`) and also _requires_
 //! a source (`PLLClkSource`), but you compose the types similarly.
 
+use super::plan::PllPlan;
 use super::rcc;
 
+/// Number of polls of ready flag, after which oscillator is considered to be failed.
+///
+/// It is roughly 100 ms with MSI at 4 MHz, the clock after reset.
+pub const READY_TIMEOUT: u32 = 50_000;
+
+/// Number of polls of LSE ready flag, LSE takes up to 2 s to start.
+pub const LSE_READY_TIMEOUT: u32 = 20 * READY_TIMEOUT;
+
+/// Frequency of HSI16 oscillator
+pub const HSI16_FREQ: u32 = 16_000_000;
+/// Nominal frequency of LSI oscillator, actual one is 29.5 - 34 kHz
+pub const LSI_FREQ: u32 = 32_000;
+/// Nominal frequency of LSE oscillator, i.e. of the usual 32.768 kHz crystal
+pub const LSE_FREQ: u32 = 32_768;
+
+/// Clock, which has not become ready within timeout
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum ReadyTimeout {
+    /// HSE oscillator, e.g. crystal is missing
+    Hse,
+    /// HSI16 oscillator
+    Hsi16,
+    /// MSI oscillator
+    Msi,
+    /// LSE oscillator, e.g. 32 kHz crystal is missing
+    Lse,
+    /// LSI oscillator
+    Lsi,
+    /// Main PLL has not locked or has not stopped
+    Pll,
+    /// PLLSAI1 has not locked or has not stopped
+    PllSai1,
+    /// PLLSAI2 has not locked or has not stopped
+    PllSai2,
+}
+
+/// Polls `is_ready` up to `timeout` times, returns whether it has become true.
+pub(crate) fn wait_ready<F: Fn() -> bool>(timeout: u32, is_ready: F) -> bool {
+    (0..timeout).any(|_| is_ready())
+}
+
 /// Clocks (OSCs or RCs) that can be used as inputs to peripherals
 ///
 /// This trait isn't actually specified anywhere, and is used only by convention.
@@ -45,53 +87,137 @@ pub struct HighSpeedInternal16RC {
 
 impl HighSpeedInternal16RC {
     /// Applies the selection options to the configuration registers and turns the clock on
+    ///
+    /// Panics if HSI16 doesn't become ready within `READY_TIMEOUT`.
     pub fn configure(&self, rcc: &rcc::RegisterBlock) -> (u32, u8) {
+        self.try_configure(rcc).expect("HSI16 is not ready")
+    }
+
+    /// Same as [configure](#method.configure), but returns error if HSI16 doesn't become ready.
+    ///
+    /// HSI16 is turned off on error.
+    pub fn try_configure(&self, rcc: &rcc::RegisterBlock) -> Result<(u32, u8), ReadyTimeout> {
         rcc.cr.modify(|_, w| w.hsion().set_bit().hsikeron().bit(self.always_on).hsiasfs().bit(self.auto_start));
-        while rcc.cr.read().hsirdy().bit_is_clear() {}
-        (16_000_000, 0b01)
+        if !wait_ready(READY_TIMEOUT, || rcc.cr.read().hsirdy().bit_is_set()) {
+            rcc.cr.modify(|_, w| w.hsion().clear_bit());
+            return Err(ReadyTimeout::Hsi16);
+        }
+        Ok((16_000_000, 0b01))
+    }
+}
+
+/// Frequency ranges of MSI, see Reference manual Ch. 6.2.3
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum MsiRange {
+    /// 100 kHz
+    R100k = 0b0000,
+    /// 200 kHz
+    R200k = 0b0001,
+    /// 400 kHz
+    R400k = 0b0010,
+    /// 800 kHz
+    R800k = 0b0011,
+    /// 1 MHz
+    R1M = 0b0100,
+    /// 2 MHz
+    R2M = 0b0101,
+    /// 4 MHz, range after reset
+    R4M = 0b0110,
+    /// 8 MHz
+    R8M = 0b0111,
+    /// 16 MHz
+    R16M = 0b1000,
+    /// 24 MHz
+    R24M = 0b1001,
+    /// 32 MHz
+    R32M = 0b1010,
+    /// 48 MHz, can drive CLK48
+    R48M = 0b1011,
+}
+
+impl MsiRange {
+    /// All ranges in ascending order
+    const ALL: [MsiRange; 12] = [
+        MsiRange::R100k, MsiRange::R200k, MsiRange::R400k, MsiRange::R800k,
+        MsiRange::R1M, MsiRange::R2M, MsiRange::R4M, MsiRange::R8M,
+        MsiRange::R16M, MsiRange::R24M, MsiRange::R32M, MsiRange::R48M,
+    ];
+
+    /// Returns frequency of range
+    pub const fn freq(self) -> u32 {
+        match self {
+            MsiRange::R100k => 100_000,
+            MsiRange::R200k => 200_000,
+            MsiRange::R400k => 400_000,
+            MsiRange::R800k => 800_000,
+            MsiRange::R1M => 1_000_000,
+            MsiRange::R2M => 2_000_000,
+            MsiRange::R4M => 4_000_000,
+            MsiRange::R8M => 8_000_000,
+            MsiRange::R16M => 16_000_000,
+            MsiRange::R24M => 24_000_000,
+            MsiRange::R32M => 32_000_000,
+            MsiRange::R48M => 48_000_000,
+        }
+    }
+
+    /// Returns range of exactly `freq`, or `None` if MSI cannot run at it
+    pub fn from_freq(freq: u32) -> Option<Self> {
+        Self::ALL.iter().cloned().find(|range| range.freq() == freq)
+    }
+
+    /// Returns range encoded as MSIRANGE `bits`
+    pub fn from_bits(bits: u8) -> Option<Self> {
+        Self::ALL.get(bits as usize).cloned()
     }
 }
 
 /// Medium-speed internal 100 kHz - 48 MHz RC
 #[derive(Clone, Copy)]
 pub struct MediumSpeedInternalRC {
-    freq: u32,
+    range: MsiRange,
     auto_cal: bool,
 }
 
 impl MediumSpeedInternalRC {
-    /// Create a new MSI RC
+    /// Create a new MSI RC running in `range`.
     ///
-    /// `freq` must be a valid MSI RC frequency range (see 6.2.3)
-    /// TODO make freq a repr(C) enum
-    pub fn new(freq: u32, auto_cal: bool) -> Self {
-        MediumSpeedInternalRC { freq, auto_cal }
+    /// With `auto_cal` MSI is trimmed by LSE (MSI PLL mode), which is required for
+    /// MSI at 48 MHz to be accurate enough for USB.
+    pub const fn new(range: MsiRange, auto_cal: bool) -> Self {
+        MediumSpeedInternalRC { range, auto_cal }
+    }
+
+    /// Create a new MSI RC running at `freq`, or `None` if it is not a valid MSI frequency (see 6.2.3)
+    pub fn from_freq(freq: u32, auto_cal: bool) -> Option<Self> {
+        MsiRange::from_freq(freq).map(|range| Self::new(range, auto_cal))
     }
 
-    /// Convert the freq range to MSIRANGE bits (6.4.1). Panics if `freq` is invalid.
+    /// Returns frequency range
+    pub fn range(&self) -> MsiRange {
+        self.range
+    }
+
+    /// Convert the freq range to MSIRANGE bits (6.4.1).
     pub fn bits(&self) -> u8 {
-        match self.freq {
-            100_000 => 0b0000,
-            200_000 => 0b0001,
-            400_000 => 0b0010,
-            800_000 => 0b0011,
-            1_000_000 => 0b0100,
-            2_000_000 => 0b0101,
-            4_000_000 => 0b0110,
-            8_000_000 => 0b0111,
-            16_000_000 => 0b1000,
-            24_000_000 => 0b1001,
-            32_000_000 => 0b1010,
-            48_000_000 => 0b1011,
-            _ => panic!("bad MSI speed value!"),
-        }
+        self.range as u8
     }
 
     /// Configures the MSI to the specified frequency, and enables hardware
     /// auto-calibration if requested by enabling (and waiting for) the LSE.
+    ///
+    /// Panics if MSI or LSE don't become ready within timeout.
     pub fn configure(&self, rcc: &rcc::RegisterBlock) -> (u32, u8) {
+        self.try_configure(rcc).expect("MSI is not ready")
+    }
+
+    /// Same as [configure](#method.configure), but returns error if MSI doesn't become ready
+    /// within `READY_TIMEOUT`, or LSE within `LSE_READY_TIMEOUT`.
+    pub fn try_configure(&self, rcc: &rcc::RegisterBlock) -> Result<(u32, u8), ReadyTimeout> {
         rcc.cr.modify(|_, w| unsafe { w.msirange().bits(self.bits()).msirgsel().set_bit() });
-        while rcc.cr.read().msirdy().bit_is_clear() {}
+        if !wait_ready(READY_TIMEOUT, || rcc.cr.read().msirdy().bit_is_set()) {
+            return Err(ReadyTimeout::Msi);
+        }
 
         if self.auto_cal {
             // FIXME This... may not work? I'm not sure if I've got a board problem or using
@@ -100,40 +226,127 @@ impl MediumSpeedInternalRC {
             rcc.apb1enr1.modify(|_, w| w.pwren().set_bit());
 
             rcc.bdcr.modify(|_, w| w.lseon().clear_bit());
-            while rcc.bdcr.read().lserdy().bit_is_set() {}
+            if !wait_ready(LSE_READY_TIMEOUT, || rcc.bdcr.read().lserdy().bit_is_clear()) {
+                return Err(ReadyTimeout::Lse);
+            }
             rcc.bdcr.modify(|_, w| unsafe { w.lsedrv().bits(0b11).lseon().set_bit() });
-            while rcc.bdcr.read().lserdy().bit_is_clear() {}
+            if !wait_ready(LSE_READY_TIMEOUT, || rcc.bdcr.read().lserdy().bit_is_set()) {
+                rcc.bdcr.modify(|_, w| w.lseon().clear_bit());
+                return Err(ReadyTimeout::Lse);
+            }
             rcc.cr.modify(|_, w| w.msipllen().set_bit());
         }
-        (self.freq(), 0b00)
+        Ok((self.freq(), 0b00))
     }
 }
 
 impl InputClock for MediumSpeedInternalRC {
     fn freq(&self) -> u32 {
-        self.freq
+        self.range.freq()
     }
 }
 
 /// High-speed external 4-48 MHz oscillator
 #[derive(Clone, Copy)]
-pub struct HighSpeedExternalOSC(pub u32);
+pub struct HighSpeedExternalOSC {
+    freq: u32,
+    bypass: bool,
+    css: bool,
+}
 
 impl InputClock for HighSpeedExternalOSC {
     fn freq(&self) -> u32 {
-        self.0
+        self.freq
     }
 }
 
 impl HighSpeedExternalOSC {
+    /// Crystal or ceramic resonator of `freq` connected to OSC_IN and OSC_OUT
+    pub const fn new(freq: u32) -> Self {
+        HighSpeedExternalOSC { freq, bypass: false, css: false }
+    }
+
+    /// External clock of `freq` fed into OSC_IN, oscillator is bypassed
+    pub const fn bypass(freq: u32) -> Self {
+        HighSpeedExternalOSC { freq, bypass: true, css: false }
+    }
+
+    /// Enables Clock Security System once HSE is ready.
+    ///
+    /// On HSE failure, HSE is turned off, SYSCLK falls back to MSI (or HSI16, see STOPWUCK)
+    /// and NMI is raised, see [is_css_failure](../fn.is_css_failure.html).
+    pub const fn with_css(self) -> Self {
+        HighSpeedExternalOSC { css: true, ..self }
+    }
+
     /// Turns on the HSE oscillator.
     ///
-    /// (Should this also configure the pin?)
+    /// Panics if HSE doesn't become ready within `READY_TIMEOUT`.
     pub fn configure(&self, rcc: &rcc::RegisterBlock) -> (u32, u8) {
-        rcc.cr.modify(|_, w| w.hseon().set_bit());
-        while rcc.cr.read().hserdy().bit_is_clear() {}
-        (self.freq(), 0b10)
+        self.try_configure(rcc).expect("HSE is not ready")
     }
+
+    /// Turns on the HSE oscillator, or returns error if it doesn't become ready within `READY_TIMEOUT`.
+    ///
+    /// HSE is turned off on error.
+    pub fn try_configure(&self, rcc: &rcc::RegisterBlock) -> Result<(u32, u8), ReadyTimeout> {
+        if rcc.cr.read().hserdy().bit_is_clear() {
+            // HSEBYP can be changed only while HSE is off
+            rcc.cr.modify(|_, w| w.hseon().clear_bit());
+            rcc.cr.modify(|_, w| w.hsebyp().bit(self.bypass));
+            rcc.cr.modify(|_, w| w.hseon().set_bit());
+
+            if !wait_ready(READY_TIMEOUT, || rcc.cr.read().hserdy().bit_is_set()) {
+                rcc.cr.modify(|_, w| w.hseon().clear_bit());
+                return Err(ReadyTimeout::Hse);
+            }
+        }
+
+        if self.css {
+            rcc.cr.modify(|_, w| w.csson().set_bit());
+        }
+        Ok((self.freq(), 0b10))
+    }
+}
+
+/// Selectable sources of 48 MHz clock (CLK48) of USB OTG FS, RNG and SDMMC
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Clk48Source {
+    /// CLK48 is off
+    None = 0b00,
+    /// PLL48M2CLK output of PLLSAI1, which is configured by user
+    PllSai1Q = 0b01,
+    /// PLL48M1CLK output of main PLL, see `PLLClkOutput::with_q`
+    PllQ = 0b10,
+    /// MSI at 48 MHz
+    Msi = 0b11,
+}
+
+/// PLL dedicated to SAI, ADC and 48 MHz domains, which is configured by user
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum PllSai {
+    /// PLLSAI1
+    Sai1,
+    /// PLLSAI2
+    Sai2,
+}
+
+/// Encodes PLLR or PLLQ divider, i.e. 0b00 = 2, 0b01 = 4, 0b10 = 6 and 0b11 = 8
+pub(crate) const fn pll_div_bits(div: u8) -> u8 {
+    div / 2 - 1
+}
+
+/// Drive strength of LSE oscillator
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum LseDrive {
+    /// Lowest drive, the value after backup domain reset
+    Low = 0b00,
+    /// Medium high drive, note that LSEDRV encoding isn't monotonic
+    MediumHigh = 0b01,
+    /// Medium low drive
+    MediumLow = 0b10,
+    /// Highest drive
+    High = 0b11,
 }
 
 /// Selectable input clocks to the RTC
@@ -155,8 +368,8 @@ impl RtcClkSource {
     pub fn freq(&self, hse: Option<HighSpeedExternalOSC>) -> Option<u32> {
         match *self {
             RtcClkSource::None => None,
-            RtcClkSource::LSI => Some(32_000),
-            RtcClkSource::LSE => Some(32_768),
+            RtcClkSource::LSI => Some(LSI_FREQ),
+            RtcClkSource::LSE => Some(LSE_FREQ),
             RtcClkSource::HSEDiv32 => {
                 if let Some(clk) = hse {
                     Some(clk.freq() / 32)
@@ -206,6 +419,8 @@ pub struct PLLClkOutput {
     pub m: u8,
     n: u8,
     r: u8,
+    p: Option<u8>,
+    q: Option<u8>,
     f: u32,
 }
 
@@ -223,22 +438,101 @@ impl PLLClkOutput {
         let f = src.freq() / m as u32 * n as u32 / r as u32;
         assert!(f < super::SYS_CLOCK_MAX);
 
-        PLLClkOutput { src, m, n, r, f }
+        PLLClkOutput { src, m, n, r, p: None, q: None, f }
+    }
+
+    /// Enables PLLSAI3CLK output (P divider), which can drive SAI.
+    ///
+    /// Panics if `p` is neither 7 nor 17
+    pub fn with_p(mut self, p: u8) -> Self {
+        assert!(p == 7 || p == 17);
+        self.p = Some(p);
+        self
+    }
+
+    /// Enables PLL48M1CLK output (Q divider), which can drive USB OTG FS, RNG and SDMMC.
+    ///
+    /// Panics if `q` is not one of 2, 4, 6, 8
+    pub fn with_q(mut self, q: u8) -> Self {
+        assert!(q == 2 || q == 4 || q == 6 || q == 8);
+        self.q = Some(q);
+        self
+    }
+
+    #[inline]
+    fn vco(&self) -> u32 {
+        self.src.freq() / self.m as u32 * self.n as u32
     }
 
-    /// Configure the PLL to enable the PLLCLK output. This explicitly does not (yet?)
-    /// support any PLL other than `PLL`, and no other outputs than `PLLCLK`, so this is
-    /// not suitable for driving e.g. USB.
+    /// Returns frequency of PLLSAI3CLK output, if it is enabled
+    pub fn p_freq(&self) -> Option<u32> {
+        self.p.map(|p| self.vco() / p as u32)
+    }
+
+    /// Returns frequency of PLL48M1CLK output, if it is enabled
+    pub fn q_freq(&self) -> Option<u32> {
+        self.q.map(|q| self.vco() / q as u32)
+    }
+
+    /// Creates PLL clock with PLLM, PLLN and PLLR chosen automatically, so that
+    /// output frequency is the closest possible to `target`.
+    ///
+    /// Returns configuration with absolute frequency error in Hz, or `None` if `src`
+    /// frequency is out of PLL input range.
+    pub fn from_target(src: PLLClkSource, target: u32) -> Option<(Self, u32)> {
+        PllPlan::from_target(src.freq(), target).map(|(plan, error)| {
+            let output = PLLClkOutput {
+                src,
+                m: plan.m,
+                n: plan.n,
+                r: plan.r,
+                p: None,
+                q: None,
+                f: plan.freq(),
+            };
+            (output, error)
+        })
+    }
+
+    /// Configure the PLL to enable the PLLCLK output, and PLLSAI3CLK/PLL48M1CLK outputs
+    /// if they were requested with `with_p`/`with_q`. This explicitly does not (yet?)
+    /// support any PLL other than `PLL`.
+    ///
+    /// Panics if source or PLL don't become ready within timeout.
     pub fn configure(&self, rcc: &rcc::RegisterBlock) -> (u32, u8) {
-        let pllsrc_bits = self.src.configure(rcc);
+        self.try_configure(rcc).expect("PLL is not ready")
+    }
+
+    /// Same as [configure](#method.configure), but returns error if source or PLL
+    /// don't become ready within timeout.
+    pub fn try_configure(&self, rcc: &rcc::RegisterBlock) -> Result<(u32, u8), ReadyTimeout> {
+        let pllsrc_bits = self.src.try_configure(rcc)?;
         rcc.cr.modify(|_, w| w.pllon().clear_bit());
-        while rcc.cr.read().pllrdy().bit_is_set() {}
-        rcc.pllcfgr
-            .modify(|_, w| unsafe { w.pllsrc().bits(pllsrc_bits).pllm().bits(self.m - 1).plln().bits(self.n).pllr().bits(self.r) });
+        if !wait_ready(READY_TIMEOUT, || rcc.cr.read().pllrdy().bit_is_clear()) {
+            return Err(ReadyTimeout::Pll);
+        }
+        rcc.pllcfgr.modify(|_, w| unsafe {
+            w.pllsrc()
+                .bits(pllsrc_bits)
+                .pllm()
+                .bits(self.m - 1)
+                .plln()
+                .bits(self.n)
+                .pllr()
+                .bits(pll_div_bits(self.r))
+                .pllp()
+                .bit(self.p == Some(17))
+                .pllq()
+                .bits(self.q.map(pll_div_bits).unwrap_or(0))
+        });
         rcc.cr.modify(|_, w| w.pllon().set_bit());
-        while rcc.cr.read().pllrdy().bit_is_clear() {}
-        rcc.pllcfgr.modify(|_, w| w.pllren().set_bit());
-        (self.freq(), 0b11)
+        if !wait_ready(READY_TIMEOUT, || rcc.cr.read().pllrdy().bit_is_set()) {
+            rcc.cr.modify(|_, w| w.pllon().clear_bit());
+            return Err(ReadyTimeout::Pll);
+        }
+        rcc.pllcfgr
+            .modify(|_, w| w.pllren().set_bit().pllpen().bit(self.p.is_some()).pllqen().bit(self.q.is_some()));
+        Ok((self.freq(), 0b11))
     }
 }
 
@@ -273,22 +567,29 @@ pub enum PLLClkSource {
 impl PLLClkSource {
     /// This configures the input to the PLL. It's usually only called by
     /// PLLClkOutput::configure.
+    ///
+    /// Panics if source doesn't become ready within timeout.
     pub fn configure(&self, rcc: &rcc::RegisterBlock) -> u8 {
-        match self {
+        self.try_configure(rcc).expect("PLL source is not ready")
+    }
+
+    /// Same as [configure](#method.configure), but returns error if source doesn't become ready.
+    pub fn try_configure(&self, rcc: &rcc::RegisterBlock) -> Result<u8, ReadyTimeout> {
+        Ok(match self {
             PLLClkSource::None => 0b00,
             PLLClkSource::MSI(s) => {
-                let _c = s.configure(rcc);
+                let _c = s.try_configure(rcc)?;
                 0b01
             },
             PLLClkSource::HSI16(s) => {
-                let _c = s.configure(rcc);
+                let _c = s.try_configure(rcc)?;
                 0b10
             },
             PLLClkSource::HSE(s) => {
-                let _c = s.configure(rcc);
+                let _c = s.try_configure(rcc)?;
                 0b11
             },
-        }
+        })
     }
 }
 
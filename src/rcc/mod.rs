@@ -6,13 +6,21 @@
 
 #![deny(missing_docs, unused_results)]
 
+use core::cell::Cell;
+
+use cortex_m::interrupt::{self, Mutex};
 use stm32l4::stm32l4x5::{rcc, PWR, RCC};
 
-use crate::common::Constrain;
+use crate::common::{ConfigError, Constrain};
 use crate::flash::ACR;
 use crate::time::Hertz;
+use crate::timer::timer_clock;
 
 pub mod clocking;
+pub mod notify;
+pub mod plan;
+
+pub use self::plan::{ClockError, ClockPlan};
 
 impl Constrain<Rcc> for RCC {
     /// Create an RCC peripheral handle.
@@ -35,12 +43,9 @@ impl Constrain<Rcc> for RCC {
             apb2: APB2(()),
             bdcr: BDCR(()),
             csr: CSR(()),
-            cfgr: CFGR {
-                hclk: None,
-                pclk1: None,
-                pclk2: None,
-                sysclk: clocking::SysClkSource::MSI(clocking::MediumSpeedInternalRC::new(4_000_000, false)),
-            },
+            ccipr: CCIPR(()),
+            sleep: SleepConfig(()),
+            cfgr: CFGR::default(),
         }
     }
 }
@@ -57,6 +62,10 @@ pub struct Rcc {
     pub bdcr: BDCR,
     /// Control/status register.
     pub csr: CSR,
+    /// Peripherals independent clock configuration register.
+    pub ccipr: CCIPR,
+    /// Peripheral clocks in Sleep mode.
+    pub sleep: SleepConfig,
     /// HW clock configuration.
     pub cfgr: CFGR,
 }
@@ -174,19 +183,60 @@ impl BDCR {
         self.inner().modify(|_, write| write.rtcen().bit(is_on));
     }
 
+    /// Sets drive strength of LSE oscillator.
+    ///
+    /// Crystals with higher ESR or load capacitance need higher drive to start,
+    /// while lower drive reduces consumption. Drive can be lowered once LSE is running.
+    ///
+    /// See Reference manual Ch. 6.2.5
+    pub fn set_lse_drive(&mut self, drive: clocking::LseDrive) {
+        self.inner().modify(|_, write| unsafe { write.lsedrv().bits(drive as u8) });
+    }
+
+    /// Returns drive strength of LSE oscillator.
+    pub fn lse_drive(&mut self) -> clocking::LseDrive {
+        match self.inner().read().lsedrv().bits() {
+            0b00 => clocking::LseDrive::Low,
+            0b01 => clocking::LseDrive::MediumHigh,
+            0b10 => clocking::LseDrive::MediumLow,
+            _ => clocking::LseDrive::High,
+        }
+    }
+
+    /// Sets LSE bypass, in which external 32 kHz clock is fed into OSC32_IN.
+    ///
+    /// # Pancis:
+    ///
+    /// In debug mode the function checks that LSE is off, as bypass can be changed only then.
+    pub fn set_lse_bypass(&mut self, is_bypassed: bool) {
+        debug_assert!(self.inner().read().lseon().bit_is_clear());
+
+        self.inner().modify(|_, write| write.lsebyp().bit(is_bypassed));
+    }
+
     /// Sets LSE on/off
+    ///
+    /// Panics if LSE doesn't become ready, see [try_lse_enable](#method.try_lse_enable).
     pub fn lse_enable(&mut self, is_on: bool) {
+        self.try_lse_enable(is_on).expect("LSE is not ready")
+    }
+
+    /// Sets LSE on/off, returns error if LSE doesn't become ready within `clocking::LSE_READY_TIMEOUT`.
+    ///
+    /// LSE is turned off on error, e.g. when 32 kHz crystal is not mounted.
+    pub fn try_lse_enable(&mut self, is_on: bool) -> Result<(), clocking::ReadyTimeout> {
         let inner = self.inner();
 
         if inner.read().lseon().bit() == is_on {
-            return;
+            return Ok(());
         }
 
         inner.modify(|_, write| write.lseon().bit(is_on));
-        match is_on {
-            true => while inner.read().lserdy().bit_is_clear() {},
-            false => while inner.read().lserdy().bit_is_set() {},
+        if !clocking::wait_ready(clocking::LSE_READY_TIMEOUT, || inner.read().lserdy().bit() == is_on) {
+            inner.modify(|_, write| write.lseon().clear_bit());
+            return Err(clocking::ReadyTimeout::Lse);
         }
+        Ok(())
     }
 }
 
@@ -202,21 +252,555 @@ impl CSR {
     }
 
     /// Turns on/off LSI oscillator.
+    ///
+    /// Panics if LSI doesn't become ready, see [try_lsi_enable](#method.try_lsi_enable).
     pub fn lsi_enable(&mut self, is_on: bool) {
+        self.try_lsi_enable(is_on).expect("LSI is not ready")
+    }
+
+    /// Turns on/off LSI oscillator, returns error if it doesn't become ready within `clocking::READY_TIMEOUT`.
+    pub fn try_lsi_enable(&mut self, is_on: bool) -> Result<(), clocking::ReadyTimeout> {
         let inner = self.inner();
 
         if inner.read().lsion().bit() == is_on {
-            return;
+            return Ok(());
         }
 
         inner.modify(|_, write| write.lsion().bit(is_on));
-        match is_on {
-            true => while inner.read().lsirdy().bit_is_clear() {},
-            false => while inner.read().lsirdy().bit_is_set() {},
+        if !clocking::wait_ready(clocking::READY_TIMEOUT, || inner.read().lsirdy().bit() == is_on) {
+            inner.modify(|_, write| write.lsion().clear_bit());
+            return Err(clocking::ReadyTimeout::Lsi);
+        }
+        Ok(())
+    }
+}
+
+/// Peripherals independent clock configuration register.
+///
+/// Selects kernel clocks of peripherals, which can differ from their bus clock.
+pub struct CCIPR(());
+impl CCIPR {
+    /// Return a raw pointer to the CCIPR register
+    #[inline]
+    pub fn inner(&mut self) -> &rcc::CCIPR {
+        unsafe { &(*RCC::ptr()).ccipr }
+    }
+
+    /// Selects `source` of 48 MHz clock (CLK48).
+    ///
+    /// Returns error if `source` doesn't run at 48 MHz, according to `clocks` for main PLL
+    /// and to current range for MSI. PLLSAI1 is not verified.
+    pub fn select_clk48(&mut self, source: clocking::Clk48Source, clocks: &Clocks) -> Result<(), ConfigError> {
+        check_clk48(source, clocks, msi_freq)?;
+
+        self.inner().modify(|_, w| unsafe { w.clk48sel().bits(source as u8) });
+        Ok(())
+    }
+
+    /// Turns on HSI16 oscillator, which is used as kernel clock of some peripheral.
+    ///
+    /// Panics if HSI16 doesn't become ready, see [try_hsi16_enable](#method.try_hsi16_enable).
+    pub fn hsi16_enable(&mut self) {
+        self.try_hsi16_enable().expect("HSI16 is not ready")
+    }
+
+    /// Turns on HSI16 oscillator, returns error if it doesn't become ready within `clocking::READY_TIMEOUT`.
+    ///
+    /// HSI16 is turned off on error.
+    pub fn try_hsi16_enable(&mut self) -> Result<(), clocking::ReadyTimeout> {
+        let cr = unsafe { &(*RCC::ptr()).cr };
+
+        cr.modify(|_, w| w.hsion().set_bit());
+        match clocking::wait_ready(clocking::READY_TIMEOUT, || cr.read().hsirdy().bit_is_set()) {
+            true => Ok(()),
+            false => {
+                cr.modify(|_, w| w.hsion().clear_bit());
+                Err(clocking::ReadyTimeout::Hsi16)
+            },
         }
     }
 }
 
+impl Rcc {
+    /// Access configuration of peripheral clocks in Sleep mode.
+    pub fn sleep_config(&mut self) -> &mut SleepConfig {
+        &mut self.sleep
+    }
+
+    /// Stops outputs of main PLL, for which argument is `true`: PLLSAI3CLK (`p`),
+    /// PLL48M1CLK (`q`) and PLLCLK (`r`). Other outputs keep running.
+    ///
+    /// E.g. 48 MHz domain is stopped between USB sessions with `disable_pll_outputs(false, true, false)`,
+    /// and restarted with [enable_pll_outputs](#method.enable_pll_outputs).
+    ///
+    /// Returns error, leaving all outputs running, if PLLCLK is to be stopped while it drives SYSCLK.
+    pub fn disable_pll_outputs(&mut self, p: bool, q: bool, r: bool) -> Result<(), SysClkInUse> {
+        let rcc = unsafe { &*RCC::ptr() };
+        if r && rcc.cfgr.read().sws().bits() == 0b11 {
+            return Err(SysClkInUse);
+        }
+
+        rcc.pllcfgr.modify(|read, w| {
+            w.pllpen()
+                .bit(read.pllpen().bit() && !p)
+                .pllqen()
+                .bit(read.pllqen().bit() && !q)
+                .pllren()
+                .bit(read.pllren().bit() && !r)
+        });
+        Ok(())
+    }
+
+    /// Restarts outputs of main PLL, for which argument is `true`, at frequencies it has been
+    /// configured with. Other outputs are not changed.
+    ///
+    /// # Pancis:
+    ///
+    /// In debug mode the function checks that main PLL is on.
+    pub fn enable_pll_outputs(&mut self, p: bool, q: bool, r: bool) {
+        let rcc = unsafe { &*RCC::ptr() };
+        debug_assert!(rcc.cr.read().pllrdy().bit_is_set());
+
+        rcc.pllcfgr.modify(|read, w| {
+            w.pllpen()
+                .bit(read.pllpen().bit() || p)
+                .pllqen()
+                .bit(read.pllqen().bit() || q)
+                .pllren()
+                .bit(read.pllren().bit() || r)
+        });
+    }
+
+    /// Sets `pll` on/off, keeping its configuration in PLLSAIxCFGR.
+    ///
+    /// SYSCLK is not affected, so PLLSAI domains can be stopped while core runs on MSI.
+    ///
+    /// Panics if `pll` doesn't lock, see [try_pllsai_enable](#method.try_pllsai_enable).
+    pub fn pllsai_enable(&mut self, pll: clocking::PllSai, is_on: bool) {
+        self.try_pllsai_enable(pll, is_on).expect("PLLSAI is not ready")
+    }
+
+    /// Sets `pll` on/off, returns error if it doesn't lock or stop within `clocking::READY_TIMEOUT`.
+    ///
+    /// `pll` is turned off on error.
+    pub fn try_pllsai_enable(&mut self, pll: clocking::PllSai, is_on: bool) -> Result<(), clocking::ReadyTimeout> {
+        let cr = unsafe { &(*RCC::ptr()).cr };
+
+        let is_ready = || match pll {
+            clocking::PllSai::Sai1 => cr.read().pllsai1rdy().bit_is_set(),
+            clocking::PllSai::Sai2 => cr.read().pllsai2rdy().bit_is_set(),
+        };
+
+        match pll {
+            clocking::PllSai::Sai1 => cr.modify(|_, w| w.pllsai1on().bit(is_on)),
+            clocking::PllSai::Sai2 => cr.modify(|_, w| w.pllsai2on().bit(is_on)),
+        }
+        if !is_on {
+            if !clocking::wait_ready(clocking::READY_TIMEOUT, || !is_ready()) {
+                return Err(match pll {
+                    clocking::PllSai::Sai1 => clocking::ReadyTimeout::PllSai1,
+                    clocking::PllSai::Sai2 => clocking::ReadyTimeout::PllSai2,
+                });
+            }
+            return Ok(());
+        }
+        if !clocking::wait_ready(clocking::READY_TIMEOUT, is_ready) {
+            self.try_pllsai_enable(pll, false)?;
+            return Err(match pll {
+                clocking::PllSai::Sai1 => clocking::ReadyTimeout::PllSai1,
+                clocking::PllSai::Sai2 => clocking::ReadyTimeout::PllSai2,
+            });
+        }
+        Ok(())
+    }
+}
+
+/// Clock can't be stopped, because it drives SYSCLK
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct SysClkInUse;
+
+/// Sleep mode clock enable registers (xxSMENR).
+///
+/// Peripheral which clock is enabled in corresponding ENR register keeps clocking
+/// in Sleep and Low-power sleep modes only if its bit is set here.
+/// After reset all bits are set.
+///
+/// See Reference manual Ch. 6.2.17
+pub struct SleepConfig(());
+impl SleepConfig {
+    /// Access AHB1 Sleep mode clock enable register
+    pub fn ahb1(&mut self) -> &rcc::AHB1SMENR {
+        unsafe { &(*RCC::ptr()).ahb1smenr }
+    }
+    /// Access AHB2 Sleep mode clock enable register
+    pub fn ahb2(&mut self) -> &rcc::AHB2SMENR {
+        unsafe { &(*RCC::ptr()).ahb2smenr }
+    }
+    /// Access AHB3 Sleep mode clock enable register
+    pub fn ahb3(&mut self) -> &rcc::AHB3SMENR {
+        unsafe { &(*RCC::ptr()).ahb3smenr }
+    }
+    /// Access APB1 Sleep mode clock enable register 1
+    pub fn apb1_1(&mut self) -> &rcc::APB1SMENR1 {
+        unsafe { &(*RCC::ptr()).apb1smenr1 }
+    }
+    /// Access APB1 Sleep mode clock enable register 2
+    pub fn apb1_2(&mut self) -> &rcc::APB1SMENR2 {
+        unsafe { &(*RCC::ptr()).apb1smenr2 }
+    }
+    /// Access APB2 Sleep mode clock enable register
+    pub fn apb2(&mut self) -> &rcc::APB2SMENR {
+        unsafe { &(*RCC::ptr()).apb2smenr }
+    }
+
+    /// Stops clocks of all peripherals in Sleep mode, except Flash and SRAM.
+    ///
+    /// Use register accessors afterwards to re-enable peripherals that
+    /// need to wake up the core (e.g. serial or timer).
+    pub fn gate_all(&mut self) {
+        self.ahb1().write(|w| w.flashsmen().set_bit().sram1smen().set_bit());
+        self.ahb2().write(|w| w.sram2smen().set_bit());
+        self.ahb3().write(|w| unsafe { w.bits(0) });
+        self.apb1_1().write(|w| unsafe { w.bits(0) });
+        self.apb1_2().write(|w| unsafe { w.bits(0) });
+        self.apb2().write(|w| unsafe { w.bits(0) });
+    }
+
+    /// Restores reset state: all enabled peripherals keep clocking in Sleep mode.
+    pub fn keep_all(&mut self) {
+        self.ahb1().reset();
+        self.ahb2().reset();
+        self.ahb3().reset();
+        self.apb1_1().reset();
+        self.apb1_2().reset();
+        self.apb2().reset();
+    }
+
+    /// Turns off MSI and HSI16 oscillators, when they are not in use.
+    ///
+    /// Oscillator is considered used when it drives SYSCLK or any of enabled PLL, PLLSAI1
+    /// and PLLSAI2, or is selected as peripheral kernel clock.
+    ///
+    /// Returns error if oscillator doesn't stop within `clocking::READY_TIMEOUT`.
+    pub fn disable_unused_oscillators(&mut self) -> Result<(), clocking::ReadyTimeout> {
+        const MSI: u8 = 0b00;
+        const HSI: u8 = 0b01;
+
+        let rcc = unsafe { &(*RCC::ptr()) };
+        let cr = rcc.cr.read();
+        let sws = rcc.cfgr.read().sws().bits();
+        // PLLSAI1 and PLLSAI2 share input of main PLL
+        let pll_src = match cr.pllon().bit_is_set() || cr.pllsai1on().bit_is_set() || cr.pllsai2on().bit_is_set() {
+            true => Some(rcc.pllcfgr.read().pllsrc().bits()),
+            false => None,
+        };
+        let ccipr = rcc.ccipr.read();
+
+        let hsi_kernel = [
+            ccipr.usart1sel().bits(),
+            ccipr.usart2sel().bits(),
+            ccipr.usart3sel().bits(),
+            ccipr.uart4sel().bits(),
+            ccipr.uart5sel().bits(),
+            ccipr.lpuart1sel().bits(),
+            ccipr.i2c1sel().bits(),
+            ccipr.i2c2sel().bits(),
+            ccipr.i2c3sel().bits(),
+        ]
+        .iter()
+        .any(|sel| *sel == 0b10)
+            || ccipr.lptim1sel().bits() == 0b10
+            || ccipr.lptim2sel().bits() == 0b10;
+        let hsi_used = sws == HSI || pll_src == Some(0b10) || cr.hsikeron().bit_is_set() || hsi_kernel;
+
+        let msi_used = sws == MSI || pll_src == Some(0b01) || ccipr.clk48sel().bits() == 0b11;
+
+        if !hsi_used && cr.hsion().bit_is_set() {
+            rcc.cr.modify(|_, w| w.hsion().clear_bit());
+            if !clocking::wait_ready(clocking::READY_TIMEOUT, || rcc.cr.read().hsirdy().bit_is_clear()) {
+                return Err(clocking::ReadyTimeout::Hsi16);
+            }
+        }
+        if !msi_used && cr.msion().bit_is_set() {
+            rcc.cr.modify(|_, w| w.msion().clear_bit());
+            if !clocking::wait_ready(clocking::READY_TIMEOUT, || rcc.cr.read().msirdy().bit_is_clear()) {
+                return Err(clocking::ReadyTimeout::Msi);
+            }
+        }
+        Ok(())
+    }
+}
+
+/// Snapshot of oscillators and peripheral clocks state.
+///
+/// Intended to be printed with `{:?}` when hunting for unexpected
+/// current consumption in low power modes.
+#[derive(Clone, Copy, Debug)]
+pub struct ClockReport {
+    /// MSI is on
+    pub msi: bool,
+    /// HSI16 is on
+    pub hsi: bool,
+    /// HSE is on
+    pub hse: bool,
+    /// LSI is on
+    pub lsi: bool,
+    /// LSE is on
+    pub lse: bool,
+    /// Main PLL is on
+    pub pll: bool,
+    /// PLLSAI1 is on
+    pub pllsai1: bool,
+    /// PLLSAI2 is on
+    pub pllsai2: bool,
+    /// AHB1, AHB2, AHB3 clock enable registers
+    pub ahb_enr: [u32; 3],
+    /// APB1 (1 and 2), APB2 clock enable registers
+    pub apb_enr: [u32; 3],
+    /// AHB1, AHB2, AHB3 Sleep mode clock enable registers
+    pub ahb_smenr: [u32; 3],
+    /// APB1 (1 and 2), APB2 Sleep mode clock enable registers
+    pub apb_smenr: [u32; 3],
+}
+
+/// Reads current state of all clocks.
+pub fn clock_report() -> ClockReport {
+    let rcc = unsafe { &(*RCC::ptr()) };
+    let cr = rcc.cr.read();
+
+    ClockReport {
+        msi: cr.msion().bit_is_set(),
+        hsi: cr.hsion().bit_is_set(),
+        hse: cr.hseon().bit_is_set(),
+        lsi: rcc.csr.read().lsion().bit_is_set(),
+        lse: rcc.bdcr.read().lseon().bit_is_set(),
+        pll: cr.pllon().bit_is_set(),
+        pllsai1: cr.pllsai1on().bit_is_set(),
+        pllsai2: cr.pllsai2on().bit_is_set(),
+        ahb_enr: [rcc.ahb1enr.read().bits(), rcc.ahb2enr.read().bits(), rcc.ahb3enr.read().bits()],
+        apb_enr: [rcc.apb1enr1.read().bits(), rcc.apb1enr2.read().bits(), rcc.apb2enr.read().bits()],
+        ahb_smenr: [rcc.ahb1smenr.read().bits(), rcc.ahb2smenr.read().bits(), rcc.ahb3smenr.read().bits()],
+        apb_smenr: [rcc.apb1smenr1.read().bits(), rcc.apb1smenr2.read().bits(), rcc.apb2smenr.read().bits()],
+    }
+}
+
+/// Returns whether Clock Security System has detected HSE failure.
+///
+/// Failure raises NMI, which is called until flag is cleared.
+pub fn is_css_failure() -> bool {
+    unsafe { (*RCC::ptr()).cifr.read().cssf().bit_is_set() }
+}
+
+/// Clears HSE failure flag of Clock Security System.
+pub fn clear_css_failure() {
+    unsafe { (*RCC::ptr()).cicr.write(|w| w.cssc().set_bit()) }
+}
+
+/// Resets all peripherals, except Flash interface, PWR and SYSCFG, and disables their clocks.
+///
+/// PWR keeps voltage range, which current clocks rely on, so function can be called
+/// before or after [reset_clocks](fn.reset_clocks.html). SYSCFG keeps memory mapping.
+/// Backup domain is not affected, use `BDCR::reset` for it.
+pub fn reset_all_peripherals(ahb: &mut AHB, apb1: &mut APB1, apb2: &mut APB2) {
+    ahb.rstr1().write(|w| w.tscrst().set_bit().crcrst().set_bit().dma2rst().set_bit().dma1rst().set_bit());
+    ahb.rstr2().write(|w| {
+        w.rngrst()
+            .set_bit()
+            .aesrst()
+            .set_bit()
+            .adcrst()
+            .set_bit()
+            .otgfsrst()
+            .set_bit()
+            .gpiohrst()
+            .set_bit()
+            .gpiogrst()
+            .set_bit()
+            .gpiofrst()
+            .set_bit()
+            .gpioerst()
+            .set_bit()
+            .gpiodrst()
+            .set_bit()
+            .gpiocrst()
+            .set_bit()
+            .gpiobrst()
+            .set_bit()
+            .gpioarst()
+            .set_bit()
+    });
+    ahb.rstr3().write(|w| w.qspirst().set_bit().fmcrst().set_bit());
+    apb1.rstr1().write(|w| {
+        w.lptim1rst()
+            .set_bit()
+            .opamprst()
+            .set_bit()
+            .dac1rst()
+            .set_bit()
+            .can1rst()
+            .set_bit()
+            .i2c3rst()
+            .set_bit()
+            .i2c2rst()
+            .set_bit()
+            .i2c1rst()
+            .set_bit()
+            .uart5rst()
+            .set_bit()
+            .uart4rst()
+            .set_bit()
+            .usart3rst()
+            .set_bit()
+            .usart2rst()
+            .set_bit()
+            .spi3rst()
+            .set_bit()
+            .spi2rst()
+            .set_bit()
+            .lcdrst()
+            .set_bit()
+            .tim7rst()
+            .set_bit()
+            .tim6rst()
+            .set_bit()
+            .tim5rst()
+            .set_bit()
+            .tim4rst()
+            .set_bit()
+            .tim3rst()
+            .set_bit()
+            .tim2rst()
+            .set_bit()
+    });
+    apb1.rstr2().write(|w| w.lptim2rst().set_bit().swpmi1rst().set_bit().lpuart1rst().set_bit());
+    apb2.rstr().write(|w| {
+        w.dfsdmrst()
+            .set_bit()
+            .sai2rst()
+            .set_bit()
+            .sai1rst()
+            .set_bit()
+            .tim17rst()
+            .set_bit()
+            .tim16rst()
+            .set_bit()
+            .tim15rst()
+            .set_bit()
+            .usart1rst()
+            .set_bit()
+            .tim8rst()
+            .set_bit()
+            .spi1rst()
+            .set_bit()
+            .tim1rst()
+            .set_bit()
+            .sdmmcrst()
+            .set_bit()
+    });
+
+    ahb.rstr1().reset();
+    ahb.rstr2().reset();
+    ahb.rstr3().reset();
+    apb1.rstr1().reset();
+    apb1.rstr2().reset();
+    apb2.rstr().reset();
+
+    ahb.enr1().reset();
+    ahb.enr2().reset();
+    ahb.enr3().reset();
+    apb1.enr1().reset();
+    apb1.enr2().reset();
+    apb2.enr().reset();
+}
+
+/// Restores reset state of clock configuration.
+///
+/// SYSCLK is switched to MSI at 4 MHz, while PLLs, HSE and HSI16 are turned off
+/// and Flash latency is set to zero wait states.
+///
+/// Previously frozen `Clocks` are no longer valid after this call.
+///
+/// Panics if oscillators don't switch within timeout, see [try_reset_clocks](fn.try_reset_clocks.html).
+pub fn reset_clocks(acr: &mut ACR) {
+    try_reset_clocks(acr).expect("clocks are not reset")
+}
+
+/// Same as [reset_clocks](fn.reset_clocks.html), but returns error if MSI doesn't become
+/// SYSCLK or PLLs don't stop within `clocking::READY_TIMEOUT`.
+pub fn try_reset_clocks(acr: &mut ACR) -> Result<(), clocking::ReadyTimeout> {
+    use clocking::{wait_ready, ReadyTimeout, READY_TIMEOUT};
+
+    let rcc = unsafe { &*RCC::ptr() };
+
+    // MSI is the clock after reset, switch to it first
+    rcc.cr.modify(|_, w| w.msion().set_bit());
+    if !wait_ready(READY_TIMEOUT, || rcc.cr.read().msirdy().bit_is_set()) {
+        return Err(ReadyTimeout::Msi);
+    }
+    rcc.cr.modify(|_, w| unsafe { w.msirange().bits(0b0110).msirgsel().set_bit() });
+
+    rcc.cfgr.reset();
+    if !wait_ready(READY_TIMEOUT, || rcc.cfgr.read().sws().bits() == 0b00) {
+        return Err(ReadyTimeout::Msi);
+    }
+
+    rcc.cr.modify(|_, w| {
+        w.pllon()
+            .clear_bit()
+            .pllsai1on()
+            .clear_bit()
+            .pllsai2on()
+            .clear_bit()
+            .csson()
+            .clear_bit()
+            .hseon()
+            .clear_bit()
+            .hsion()
+            .clear_bit()
+            .hsikeron()
+            .clear_bit()
+    });
+    if !wait_ready(READY_TIMEOUT, || rcc.cr.read().pllrdy().bit_is_clear()) {
+        return Err(ReadyTimeout::Pll);
+    }
+    if !wait_ready(READY_TIMEOUT, || rcc.cr.read().pllsai1rdy().bit_is_clear()) {
+        return Err(ReadyTimeout::PllSai1);
+    }
+    if !wait_ready(READY_TIMEOUT, || rcc.cr.read().pllsai2rdy().bit_is_clear()) {
+        return Err(ReadyTimeout::PllSai2);
+    }
+    rcc.cr.modify(|_, w| w.hsebyp().clear_bit());
+
+    rcc.pllcfgr.reset();
+    rcc.pllsai1cfgr.reset();
+    rcc.pllsai2cfgr.reset();
+
+    rcc.cier.reset();
+    rcc.cicr.write(|w| unsafe { w.bits(0xFFFF_FFFF) });
+
+    acr.acr().modify(|_, w| unsafe { w.latency().bits(0b000) });
+    Ok(())
+}
+
+/// Switches clocks to configuration of `cfgr` at runtime.
+///
+/// Clocks are reset first, see [reset_clocks](fn.reset_clocks.html), so PLL can be reprogrammed.
+/// Callbacks subscribed through [notify](notify/index.html) are called before and after the change,
+/// e.g. [Console](../serial/console/struct.Console.html) gets its baud rate updated for new clocks.
+/// Other drivers created with previous `Clocks` keep using stale frequencies.
+pub fn reconfigure(cfgr: CFGR, acr: &mut ACR) -> Clocks {
+    notify::notify(notify::ClockChange::Pending);
+
+    reset_clocks(acr);
+    let clocks = cfgr.freeze(acr);
+
+    notify::notify(notify::ClockChange::Done(&clocks));
+    clocks
+}
+
+/// Frequency of CLK48, which drives USB OTG FS, RNG and SDMMC
+pub const CLK48_FREQ: u32 = 48_000_000;
+
 /// Maximum value for System clock.
 ///
 /// Reference Ch. 6.2.8
@@ -234,6 +818,18 @@ pub struct CFGR {
     sysclk: clocking::SysClkSource,
 }
 
+impl Default for CFGR {
+    /// MSI at 4 MHz, which is clock after reset, with buses running at SYSCLK
+    fn default() -> Self {
+        CFGR {
+            hclk: None,
+            pclk1: None,
+            pclk2: None,
+            sysclk: clocking::SysClkSource::MSI(clocking::MediumSpeedInternalRC::new(clocking::MsiRange::R4M, false)),
+        }
+    }
+}
+
 impl CFGR {
     /// Sets a frequency for the AHB bus.
     pub fn hclk<T: Into<Hertz>>(mut self, freq: T) -> Self {
@@ -259,59 +855,75 @@ impl CFGR {
             if let clocking::PLLClkSource::None = s.src {
                 panic!("PLL must have input clock to drive SYSCLK");
             }
-        } else {
-            self.sysclk = src;
         }
+        self.sysclk = src;
         self
     }
 
     #[inline]
-    fn calc_ahb(sys_clock: u32, hclk: Option<u32>) -> (u8, u32) {
-        match hclk.map(|hclk| sys_clock / hclk) {
-            Some(0) => unreachable!(),
-            None | Some(1) => (0b0111, sys_clock),
-            Some(2) => (0b1000, sys_clock / 2),
-            Some(3...5) => (0b1001, sys_clock / 4),
-            Some(6...11) => (0b1010, sys_clock / 8),
-            Some(12...39) => (0b1011, sys_clock / 16),
-            Some(40...95) => (0b1100, sys_clock / 64),
-            Some(96...191) => (0b1101, sys_clock / 128),
-            Some(192...383) => (0b1110, sys_clock / 256),
+    const fn calc_ahb(sys_clock: u32, hclk: Option<u32>) -> (u8, u32) {
+        let div = match hclk {
+            Some(hclk) => sys_clock / hclk,
+            None => 1,
+        };
+        match div {
+            0 => unreachable!(),
+            1 => (0b0111, sys_clock),
+            2 => (0b1000, sys_clock / 2),
+            3..=5 => (0b1001, sys_clock / 4),
+            6..=11 => (0b1010, sys_clock / 8),
+            12..=39 => (0b1011, sys_clock / 16),
+            40..=95 => (0b1100, sys_clock / 64),
+            96..=191 => (0b1101, sys_clock / 128),
+            192..=383 => (0b1110, sys_clock / 256),
             _ => (0b1111, sys_clock / 512),
         }
     }
 
     #[inline]
-    fn calc_apb(ahb: u32, pclk: Option<u32>) -> (u8, u8) {
-        match pclk.map(|pclk| ahb / pclk) {
-            Some(0) => unreachable!(),
-            None | Some(1) => (0b011, 1),
-            Some(2) => (0b100, 2),
-            Some(3...5) => (0b101, 4),
-            Some(6...11) => (0b110, 8),
+    const fn calc_apb(ahb: u32, pclk: Option<u32>) -> (u8, u8) {
+        let div = match pclk {
+            Some(pclk) => ahb / pclk,
+            None => 1,
+        };
+        match div {
+            0 => unreachable!(),
+            1 => (0b011, 1),
+            2 => (0b100, 2),
+            3..=5 => (0b101, 4),
+            6..=11 => (0b110, 8),
             _ => (0b111, 16),
         }
     }
 
     /// Freezes the clock configuration, making it effective
+    ///
+    /// Panics if clock source doesn't become ready, see [try_freeze](#method.try_freeze).
     pub fn freeze(self, acr: &mut ACR) -> Clocks {
+        self.try_freeze(acr).expect("SYSCLK source is not ready")
+    }
+
+    /// Freezes the clock configuration, making it effective
+    ///
+    /// LSI and LSE are recorded in `Clocks` only if they are enabled beforehand,
+    /// through `CSR` and `BDCR`. Their nominal frequencies are recorded, not measured ones.
+    ///
+    /// Returns error if SYSCLK source doesn't become ready within `clocking::READY_TIMEOUT`,
+    /// in which case SYSCLK is left as it is.
+    pub fn try_freeze(self, acr: &mut ACR) -> Result<Clocks, clocking::ReadyTimeout> {
         let rcc = unsafe { &*RCC::ptr() };
 
         let (sys_clock, sw_bits) = match self.sysclk {
-            clocking::SysClkSource::MSI(s) => s.configure(rcc),
-            clocking::SysClkSource::HSI16(s) => s.configure(rcc),
-            clocking::SysClkSource::HSE(s) => s.configure(rcc),
-            clocking::SysClkSource::PLL(s) => s.configure(rcc),
+            clocking::SysClkSource::MSI(s) => s.try_configure(rcc)?,
+            clocking::SysClkSource::HSI16(s) => s.try_configure(rcc)?,
+            clocking::SysClkSource::HSE(s) => s.try_configure(rcc)?,
+            clocking::SysClkSource::PLL(s) => s.try_configure(rcc)?,
         };
 
         //Reference Ch. 6.4.3
         let (hpre_bits, ahb) = Self::calc_ahb(sys_clock, self.hclk);
-
-        let (ppre1_bits, ppre1) = Self::calc_apb(ahb, self.pclk1);
-        let apb1 = ahb / ppre1 as u32;
-
-        let (ppre2_bits, ppre2) = Self::calc_apb(ahb, self.pclk2);
-        let apb2 = ahb / ppre2 as u32;
+        let (ppre1_bits, _) = Self::calc_apb(ahb, self.pclk1);
+        let (ppre2_bits, _) = Self::calc_apb(ahb, self.pclk2);
 
         // Reference AN4621 note Figure. 4
         // from 0 wait state to 4
@@ -336,10 +948,30 @@ impl CFGR {
             (*PWR::ptr()).cr1.modify(|_, w| w.dbp().clear_bit());
         }
 
+        let lsi = match rcc.csr.read().lsirdy().bit_is_set() {
+            true => Some(Hertz(clocking::LSI_FREQ)),
+            false => None,
+        };
+        let lse = match rcc.bdcr.read().lserdy().bit_is_set() {
+            true => Some(Hertz(clocking::LSE_FREQ)),
+            false => None,
+        };
+        let clocks = self.clocks(sys_clock, lsi, lse);
+        interrupt::free(|cs| FROZEN.borrow(cs).set(Some(clocks)));
+
+        Ok(clocks)
+    }
+
+    /// Computes frequencies of configured clocks, once SYSCLK runs at `sys_clock`.
+    fn clocks(&self, sys_clock: u32, lsi: Option<Hertz>, lse: Option<Hertz>) -> Clocks {
+        let (_, ahb) = Self::calc_ahb(sys_clock, self.hclk);
+        let (_, ppre1) = Self::calc_apb(ahb, self.pclk1);
+        let (_, ppre2) = Self::calc_apb(ahb, self.pclk2);
+
         Clocks {
             hclk: Hertz(ahb),
-            pclk1: Hertz(apb1),
-            pclk2: Hertz(apb2),
+            pclk1: Hertz(ahb / ppre1 as u32),
+            pclk2: Hertz(ahb / ppre2 as u32),
             sysclk: Hertz(sys_clock),
             pll_src: match self.sysclk {
                 clocking::SysClkSource::PLL(s) => Some(s.src),
@@ -349,12 +981,63 @@ impl CFGR {
                 clocking::SysClkSource::PLL(s) => Some(s.m),
                 _ => None,
             },
+            pll_p: match self.sysclk {
+                clocking::SysClkSource::PLL(s) => s.p_freq().map(Hertz),
+                _ => None,
+            },
+            pll_q: match self.sysclk {
+                clocking::SysClkSource::PLL(s) => s.q_freq().map(Hertz),
+                _ => None,
+            },
             ppre1,
             ppre2,
+            lsi,
+            lse,
         }
     }
 }
 
+/// Returns frequency of MSI, if it is running
+fn msi_freq() -> Option<u32> {
+    let rcc = unsafe { &*RCC::ptr() };
+    let cr = rcc.cr.read();
+    // MSIRGSEL (CR bit 3) isn't readable through the PAC; when it is clear
+    // the range comes from CSR.MSISRANGE, which uses the same encoding.
+    let range = match cr.bits() & (1 << 3) != 0 {
+        true => cr.msirange().bits(),
+        false => rcc.csr.read().msisrange().bits(),
+    };
+    match cr.msion().bit_is_set() {
+        true => clocking::MsiRange::from_bits(range).map(|range| range.freq()),
+        false => None,
+    }
+}
+
+/// Checks that `source` of CLK48 runs at 48 MHz, MSI frequency is read by `msi_freq` on demand.
+fn check_clk48<F>(source: clocking::Clk48Source, clocks: &Clocks, msi_freq: F) -> Result<(), ConfigError>
+    where F: FnOnce() -> Option<u32> {
+    let freq = match source {
+        clocking::Clk48Source::None | clocking::Clk48Source::PllSai1Q => Some(CLK48_FREQ),
+        clocking::Clk48Source::PllQ => clocks.pll_q().map(|freq| freq.0),
+        clocking::Clk48Source::Msi => msi_freq(),
+    };
+
+    match freq {
+        None => Err(ConfigError::FrequencyTooLow),
+        Some(freq) if freq < CLK48_FREQ => Err(ConfigError::FrequencyTooLow),
+        Some(freq) if freq > CLK48_FREQ => Err(ConfigError::FrequencyTooHigh),
+        _ => Ok(()),
+    }
+}
+
+/// Reads kernel clock selections
+fn ccipr() -> rcc::ccipr::R {
+    unsafe { (*RCC::ptr()).ccipr.read() }
+}
+
+/// Clocks of the last freeze
+static FROZEN: Mutex<Cell<Option<Clocks>>> = Mutex::new(Cell::new(None));
+
 /// Frozen clock frequencies
 ///
 /// The existence of this value indicates that the clock configuration can no longer be changed
@@ -372,13 +1055,32 @@ pub struct Clocks {
     pub pll_src: Option<clocking::PLLClkSource>,
     /// PLL clock source prescaler, "M" in the clock tree
     pub pll_psc: Option<u8>,
+    /// Frequency of PLLSAI3CLK (PLL "P" output)
+    pub pll_p: Option<Hertz>,
+    /// Frequency of PLL48M1CLK (PLL "Q" output)
+    pub pll_q: Option<Hertz>,
     /// APB1 prescaler
     pub ppre1: u8,
     /// APB2 prescaler
     pub ppre2: u8,
+    /// Nominal frequency of LSI, if it was running at freeze
+    ///
+    /// LSI isn't measured, it's RC which actually runs anywhere in 29.5 - 34 kHz.
+    pub lsi: Option<Hertz>,
+    /// Nominal frequency of LSE, if it was running at freeze
+    ///
+    /// LSE isn't measured, it's assumed to be 32.768 kHz crystal.
+    pub lse: Option<Hertz>,
 }
 
 impl Clocks {
+    /// Returns copy of clocks of the last freeze, or `None` if clocks haven't been frozen yet.
+    ///
+    /// Allows drivers and interrupt handlers to get clocks without having them passed around.
+    pub fn get() -> Option<Clocks> {
+        interrupt::free(|cs| FROZEN.borrow(cs).get())
+    }
+
     /// Returns the frequency of the AHB
     pub fn hclk(&self) -> Hertz {
         self.hclk
@@ -410,6 +1112,111 @@ impl Clocks {
     pub fn sysclk(&self) -> Hertz {
         self.sysclk
     }
+
+    /// Returns the frequency of PLLSAI3CLK (PLL "P" output), if it is enabled
+    pub fn pll_p(&self) -> Option<Hertz> {
+        self.pll_p
+    }
+
+    /// Returns the frequency of PLL48M1CLK (PLL "Q" output), if it is enabled
+    pub fn pll_q(&self) -> Option<Hertz> {
+        self.pll_q
+    }
+
+    /// Returns the frequency of timers on APB1, twice PCLK1 unless APB1 prescaler is 1
+    pub fn timclk1(&self) -> Hertz {
+        Hertz(timer_clock(self.pclk1, self.ppre1))
+    }
+
+    /// Returns the frequency of timers on APB2, twice PCLK2 unless APB2 prescaler is 1
+    pub fn timclk2(&self) -> Hertz {
+        Hertz(timer_clock(self.pclk2, self.ppre2))
+    }
+
+    /// Returns kernel clock of USART1, as currently selected in CCIPR
+    pub fn usart1_clk(&self) -> Hertz {
+        self.usart_clk(self.pclk2, ccipr().usart1sel().bits())
+    }
+
+    /// Returns kernel clock of USART2, as currently selected in CCIPR
+    pub fn usart2_clk(&self) -> Hertz {
+        self.usart_clk(self.pclk1, ccipr().usart2sel().bits())
+    }
+
+    /// Returns kernel clock of USART3, as currently selected in CCIPR
+    pub fn usart3_clk(&self) -> Hertz {
+        self.usart_clk(self.pclk1, ccipr().usart3sel().bits())
+    }
+
+    /// Returns kernel clock of LPUART1, as currently selected in CCIPR
+    pub fn lpuart1_clk(&self) -> Hertz {
+        self.usart_clk(self.pclk1, ccipr().lpuart1sel().bits())
+    }
+
+    /// Returns kernel clock of I2C1, as currently selected in CCIPR
+    pub fn i2c1_clk(&self) -> Hertz {
+        self.i2c_clk(ccipr().i2c1sel().bits())
+    }
+
+    /// Returns kernel clock of I2C2, as currently selected in CCIPR
+    pub fn i2c2_clk(&self) -> Hertz {
+        self.i2c_clk(ccipr().i2c2sel().bits())
+    }
+
+    /// Returns kernel clock of I2C3, as currently selected in CCIPR
+    pub fn i2c3_clk(&self) -> Hertz {
+        self.i2c_clk(ccipr().i2c3sel().bits())
+    }
+
+    /// Returns kernel clock of LPTIM1, as currently selected in CCIPR
+    pub fn lptim1_clk(&self) -> Hertz {
+        self.lptim_clk(ccipr().lptim1sel().bits())
+    }
+
+    /// Returns kernel clock of LPTIM2, as currently selected in CCIPR
+    pub fn lptim2_clk(&self) -> Hertz {
+        self.lptim_clk(ccipr().lptim2sel().bits())
+    }
+
+    fn usart_clk(&self, pclk: Hertz, sel: u8) -> Hertz {
+        match sel {
+            0b00 => pclk,
+            0b01 => self.sysclk,
+            0b10 => Hertz(clocking::HSI16_FREQ),
+            _ => self.lse.unwrap_or(Hertz(clocking::LSE_FREQ)),
+        }
+    }
+
+    fn i2c_clk(&self, sel: u8) -> Hertz {
+        match sel {
+            0b00 => self.pclk1,
+            0b01 => self.sysclk,
+            _ => Hertz(clocking::HSI16_FREQ),
+        }
+    }
+
+    fn lptim_clk(&self, sel: u8) -> Hertz {
+        match sel {
+            0b00 => self.pclk1,
+            0b01 => self.lsi.unwrap_or(Hertz(clocking::LSI_FREQ)),
+            0b10 => Hertz(clocking::HSI16_FREQ),
+            _ => self.lse.unwrap_or(Hertz(clocking::LSE_FREQ)),
+        }
+    }
+
+    /// Returns the nominal frequency of LSI, if it was enabled before freeze
+    ///
+    /// This is not a measured value, actual LSI frequency varies in 29.5 - 34 kHz.
+    pub fn lsi(&self) -> Option<Hertz> {
+        self.lsi
+    }
+
+    /// Returns the nominal frequency of LSE, if it was enabled before freeze
+    ///
+    /// This is not a measured value, a 32.768 kHz crystal is assumed.
+    pub fn lse(&self) -> Option<Hertz> {
+        self.lse
+    }
 }
 
 #[cfg(test)]
@@ -496,4 +1303,56 @@ mod tests {
         assert_eq!(ahb, sys_clock / 512);
 
     }
+
+    #[test]
+    pub fn msi_range() {
+        use self::clocking::MsiRange;
+
+        assert_eq!(MsiRange::from_freq(48_000_000), Some(MsiRange::R48M));
+        assert_eq!(MsiRange::from_freq(100_000), Some(MsiRange::R100k));
+        assert_eq!(MsiRange::from_freq(12_000_000), None);
+        assert_eq!(MsiRange::from_bits(0b0110), Some(MsiRange::R4M));
+        assert_eq!(MsiRange::from_bits(0b1100), None);
+    }
+    #[test]
+    pub fn pll_divider_encoding() {
+        use self::clocking::pll_div_bits;
+
+        assert_eq!(pll_div_bits(2), 0b00);
+        assert_eq!(pll_div_bits(4), 0b01);
+        assert_eq!(pll_div_bits(6), 0b10);
+        assert_eq!(pll_div_bits(8), 0b11);
+    }
+
+    #[test]
+    pub fn pll_sysclk_outputs() {
+        use self::clocking::{HighSpeedInternal16RC, InputClock, PLLClkOutput, PLLClkSource, SysClkSource};
+
+        let hsi = HighSpeedInternal16RC { always_on: false, auto_start: false };
+        let pll = PLLClkOutput::new(PLLClkSource::HSI16(hsi), 1, 12, 4).with_q(4);
+        let clocks = CFGR::default().sysclk(SysClkSource::PLL(pll)).clocks(pll.freq(), None, None);
+
+        assert_eq!(clocks.sysclk().0, 48_000_000);
+        assert_eq!(clocks.pll_q().map(|freq| freq.0), Some(48_000_000));
+        assert_eq!(clocks.pll_p().map(|freq| freq.0), None);
+    }
+
+    #[test]
+    pub fn clk48_from_pll_q() {
+        use self::clocking::{Clk48Source, HighSpeedInternal16RC, PLLClkOutput, PLLClkSource, SysClkSource};
+
+        let hsi = HighSpeedInternal16RC { always_on: false, auto_start: false };
+        let pll = PLLClkOutput::new(PLLClkSource::HSI16(hsi), 1, 12, 4);
+
+        let clocks = CFGR::default().sysclk(SysClkSource::PLL(pll.with_q(4))).clocks(48_000_000, None, None);
+        assert_eq!(check_clk48(Clk48Source::PllQ, &clocks, || None), Ok(()));
+
+        let clocks = CFGR::default().sysclk(SysClkSource::PLL(pll.with_q(2))).clocks(48_000_000, None, None);
+        assert_eq!(check_clk48(Clk48Source::PllQ, &clocks, || None), Err(ConfigError::FrequencyTooHigh));
+
+        let clocks = CFGR::default().sysclk(SysClkSource::PLL(pll)).clocks(48_000_000, None, None);
+        assert_eq!(check_clk48(Clk48Source::PllQ, &clocks, || None), Err(ConfigError::FrequencyTooLow));
+
+        assert_eq!(check_clk48(Clk48Source::Msi, &clocks, || Some(48_000_000)), Ok(()));
+    }
 }
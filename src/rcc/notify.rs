@@ -0,0 +1,84 @@
+//! Notification of clock changes
+//!
+//! Drivers, which derive their prescalers from `Clocks`, subscribe callback to be called
+//! around [reconfigure](../fn.reconfigure.html), so they can recompute them for new frequencies:
+//!
+//! ```rust, ignore
+//! fn on_clock_change(change: &ClockChange) {
+//!     if let ClockChange::Done(clocks) = change {
+//!         // recompute prescalers for `clocks`
+//!     }
+//! }
+//!
+//! rcc::notify::subscribe(on_clock_change).unwrap();
+//! ```
+//!
+//! Callbacks are plain functions, so driver state must be reachable from static context.
+
+use core::cell::Cell;
+
+use cortex_m::interrupt::{self, Mutex};
+
+use super::Clocks;
+
+/// Maximal number of subscribed callbacks
+pub const MAX_CALLBACKS: usize = 8;
+
+/// Stage of clock change
+pub enum ClockChange<'a> {
+    /// Clocks are about to change, ongoing transfers should be completed
+    Pending,
+    /// Clocks have been changed to new frequencies
+    Done(&'a Clocks),
+}
+
+/// Callback called on clock change
+pub type Callback = fn(&ClockChange);
+
+static CALLBACKS: Mutex<Cell<[Option<Callback>; MAX_CALLBACKS]>> = Mutex::new(Cell::new([None; MAX_CALLBACKS]));
+
+/// Subscribes `callback` to clock changes.
+///
+/// Returns `callback` back if all `MAX_CALLBACKS` slots are taken.
+pub fn subscribe(callback: Callback) -> Result<(), Callback> {
+    interrupt::free(|cs| {
+        let cell = CALLBACKS.borrow(cs);
+        let mut callbacks = cell.get();
+
+        match callbacks.iter_mut().find(|slot| slot.is_none()) {
+            Some(slot) => {
+                *slot = Some(callback);
+                cell.set(callbacks);
+                Ok(())
+            },
+            None => Err(callback),
+        }
+    })
+}
+
+/// Unsubscribes `callback`, if it has been subscribed.
+pub fn unsubscribe(callback: Callback) {
+    interrupt::free(|cs| {
+        let cell = CALLBACKS.borrow(cs);
+        let subscribed = cell.get();
+        let mut callbacks = [None; MAX_CALLBACKS];
+
+        // Remaining callbacks are moved to front, so free slots are always after them
+        let remaining = subscribed.iter().flatten().filter(|subscribed| **subscribed as usize != callback as usize);
+        for (slot, subscribed) in callbacks.iter_mut().zip(remaining) {
+            *slot = Some(*subscribed);
+        }
+        cell.set(callbacks);
+    })
+}
+
+/// Calls all subscribed callbacks with `change`.
+///
+/// Callbacks are called outside of critical section, in order of subscription.
+pub(crate) fn notify(change: ClockChange) {
+    let callbacks = interrupt::free(|cs| CALLBACKS.borrow(cs).get());
+
+    for callback in callbacks.iter().flatten() {
+        callback(&change);
+    }
+}
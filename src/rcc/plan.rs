@@ -0,0 +1,297 @@
+//! Compile time validation of clock tree
+//!
+//! All checks are `const fn`, so clock configuration can be verified
+//! by the compiler:
+//!
+//! ```rust,ignore
+//! use stm32l4x5_hal::rcc::ClockPlan;
+//!
+//! // HSI16 / 2 * 20 / 2 = 80 MHz
+//! const PLAN: ClockPlan = ClockPlan::pll(16_000_000, 2, 20, 2).pclk1(40_000_000).assert_valid();
+//! ```
+
+use super::{CFGR, SYS_CLOCK_MAX};
+
+/// Minimal PLL VCO input frequency. Reference Ch. 6.4.4
+pub const PLL_VCO_INPUT_MIN: u32 = 4_000_000;
+/// Maximal PLL VCO input frequency. Reference Ch. 6.4.4
+pub const PLL_VCO_INPUT_MAX: u32 = 16_000_000;
+/// Minimal PLL VCO output frequency. Reference Ch. 6.4.4
+pub const PLL_VCO_OUTPUT_MIN: u32 = 64_000_000;
+/// Maximal PLL VCO output frequency. Reference Ch. 6.4.4
+pub const PLL_VCO_OUTPUT_MAX: u32 = 344_000_000;
+
+/// Invalid clock configuration
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum ClockError {
+    /// SYSCLK is above `SYS_CLOCK_MAX`
+    SysclkTooHigh,
+    /// HCLK cannot be derived from SYSCLK with AHB prescaler
+    Hclk,
+    /// PCLK1 cannot be derived from HCLK with APB1 prescaler
+    Pclk1,
+    /// PCLK2 cannot be derived from HCLK with APB2 prescaler
+    Pclk2,
+    /// PLLM is out of 1..=8 range
+    PllM,
+    /// PLLN is out of 8..=86 range
+    PllN,
+    /// PLLR is not one of 2, 4, 6, 8
+    PllR,
+    /// PLL input frequency divided by PLLM is out of VCO input range
+    VcoInput,
+    /// VCO output frequency is out of range
+    VcoOutput,
+}
+
+impl ClockError {
+    /// Returns description of the error
+    pub const fn description(&self) -> &'static str {
+        match self {
+            ClockError::SysclkTooHigh => "SYSCLK exceeds 80 MHz",
+            ClockError::Hclk => "HCLK is not reachable with AHB prescaler",
+            ClockError::Pclk1 => "PCLK1 is not reachable with APB1 prescaler",
+            ClockError::Pclk2 => "PCLK2 is not reachable with APB2 prescaler",
+            ClockError::PllM => "PLLM must be in range 1..=8",
+            ClockError::PllN => "PLLN must be in range 8..=86",
+            ClockError::PllR => "PLLR must be 2, 4, 6 or 8",
+            ClockError::VcoInput => "PLL VCO input must be in range 4..=16 MHz",
+            ClockError::VcoOutput => "PLL VCO output must be in range 64..=344 MHz",
+        }
+    }
+}
+
+/// PLL divisors
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct PllPlan {
+    /// Frequency of PLL input clock
+    pub src: u32,
+    /// PLLM
+    pub m: u8,
+    /// PLLN
+    pub n: u8,
+    /// PLLR
+    pub r: u8,
+}
+
+impl PllPlan {
+    /// Returns PLLCLK frequency
+    pub const fn freq(&self) -> u32 {
+        self.src / self.m as u32 * self.n as u32 / self.r as u32
+    }
+
+    /// Searches for divisors that give PLLCLK closest to `target` frequency,
+    /// not exceeding `SYS_CLOCK_MAX`.
+    ///
+    /// Returns plan with absolute error of frequency in Hz, or `None` if
+    /// `src` frequency cannot drive PLL.
+    pub fn from_target(src: u32, target: u32) -> Option<(PllPlan, u32)> {
+        let mut best: Option<(PllPlan, u32)> = None;
+
+        for m in 1..=8 {
+            for n in 8..=86 {
+                for &r in [2, 4, 6, 8].iter() {
+                    let plan = PllPlan { src, m, n, r };
+                    if plan.validate().is_err() || plan.freq() > SYS_CLOCK_MAX {
+                        continue;
+                    }
+
+                    let error = match plan.freq() > target {
+                        true => plan.freq() - target,
+                        false => target - plan.freq(),
+                    };
+                    match best {
+                        Some((_, best_error)) if best_error <= error => (),
+                        _ => best = Some((plan, error)),
+                    }
+                }
+            }
+        }
+
+        best
+    }
+
+    /// Checks PLL divisors and VCO frequency ranges
+    pub const fn validate(&self) -> Result<(), ClockError> {
+        if self.m < 1 || self.m > 8 {
+            return Err(ClockError::PllM);
+        }
+        if self.n < 8 || self.n > 86 {
+            return Err(ClockError::PllN);
+        }
+        match self.r {
+            2 | 4 | 6 | 8 => (),
+            _ => return Err(ClockError::PllR),
+        }
+
+        let vco_in = self.src / self.m as u32;
+        if vco_in < PLL_VCO_INPUT_MIN || vco_in > PLL_VCO_INPUT_MAX {
+            return Err(ClockError::VcoInput);
+        }
+        let vco_out = vco_in * self.n as u32;
+        if vco_out < PLL_VCO_OUTPUT_MIN || vco_out > PLL_VCO_OUTPUT_MAX {
+            return Err(ClockError::VcoOutput);
+        }
+
+        Ok(())
+    }
+}
+
+/// Description of clock tree frequencies
+///
+/// Unlike `CFGR`, which rounds requested bus frequencies to the closest prescaler,
+/// plan requires every frequency to be exactly reachable.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct ClockPlan {
+    /// PLL configuration if SYSCLK is driven by PLL
+    pub pll: Option<PllPlan>,
+    /// SYSCLK frequency
+    pub sysclk: u32,
+    /// AHB frequency
+    pub hclk: u32,
+    /// APB1 frequency
+    pub pclk1: u32,
+    /// APB2 frequency
+    pub pclk2: u32,
+}
+
+impl ClockPlan {
+    /// Creates plan with SYSCLK driven directly by oscillator of given frequency.
+    ///
+    /// All buses run at SYSCLK frequency.
+    pub const fn new(sysclk: u32) -> Self {
+        ClockPlan {
+            pll: None,
+            sysclk,
+            hclk: sysclk,
+            pclk1: sysclk,
+            pclk2: sysclk,
+        }
+    }
+
+    /// Creates plan with SYSCLK driven by PLL.
+    ///
+    /// All buses run at SYSCLK frequency.
+    pub const fn pll(src: u32, m: u8, n: u8, r: u8) -> Self {
+        let pll = PllPlan { src, m, n, r };
+        // Avoid division by zero, invalid divisors are reported by `validate`
+        let sysclk = match (m, r) {
+            (0, _) | (_, 0) => 0,
+            _ => pll.freq(),
+        };
+
+        ClockPlan {
+            pll: Some(pll),
+            sysclk,
+            hclk: sysclk,
+            pclk1: sysclk,
+            pclk2: sysclk,
+        }
+    }
+
+    /// Sets AHB frequency
+    pub const fn hclk(self, hclk: u32) -> Self {
+        ClockPlan { hclk, ..self }
+    }
+
+    /// Sets APB1 frequency
+    pub const fn pclk1(self, pclk1: u32) -> Self {
+        ClockPlan { pclk1, ..self }
+    }
+
+    /// Sets APB2 frequency
+    pub const fn pclk2(self, pclk2: u32) -> Self {
+        ClockPlan { pclk2, ..self }
+    }
+
+    /// Checks whether plan can be realized by hardware
+    pub const fn validate(&self) -> Result<(), ClockError> {
+        if let Some(pll) = self.pll {
+            if let Err(error) = pll.validate() {
+                return Err(error);
+            }
+        }
+
+        if self.sysclk > SYS_CLOCK_MAX {
+            return Err(ClockError::SysclkTooHigh);
+        }
+
+        if self.hclk == 0 || self.hclk > self.sysclk {
+            return Err(ClockError::Hclk);
+        }
+        let (_, hclk) = CFGR::calc_ahb(self.sysclk, Some(self.hclk));
+        if hclk != self.hclk {
+            return Err(ClockError::Hclk);
+        }
+
+        if !Self::is_apb_reachable(self.hclk, self.pclk1) {
+            return Err(ClockError::Pclk1);
+        }
+        if !Self::is_apb_reachable(self.hclk, self.pclk2) {
+            return Err(ClockError::Pclk2);
+        }
+
+        Ok(())
+    }
+
+    /// Returns `true` if plan is valid
+    pub const fn is_valid(&self) -> bool {
+        self.validate().is_ok()
+    }
+
+    /// Returns plan if it is valid.
+    ///
+    /// # Panics:
+    ///
+    /// If plan is not valid. In const context it results in compilation error.
+    pub const fn assert_valid(self) -> Self {
+        if let Err(error) = self.validate() {
+            panic!("{}", error.description());
+        }
+        self
+    }
+
+    const fn is_apb_reachable(hclk: u32, pclk: u32) -> bool {
+        if pclk == 0 || pclk > hclk {
+            return false;
+        }
+        let (_, ppre) = CFGR::calc_apb(hclk, Some(pclk));
+        hclk / ppre as u32 == pclk
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn validate_plan() {
+        const PLAN: ClockPlan = ClockPlan::pll(16_000_000, 2, 20, 2).pclk1(40_000_000).assert_valid();
+        assert_eq!(PLAN.sysclk, 80_000_000);
+
+        assert_eq!(ClockPlan::new(4_000_000).validate(), Ok(()));
+        assert_eq!(ClockPlan::new(4_000_000).hclk(3_000_000).validate(), Err(ClockError::Hclk));
+        assert_eq!(ClockPlan::new(4_000_000).pclk2(8_000_000).validate(), Err(ClockError::Pclk2));
+        assert_eq!(ClockPlan::pll(16_000_000, 1, 20, 2).validate(), Err(ClockError::SysclkTooHigh));
+        assert_eq!(ClockPlan::pll(16_000_000, 8, 40, 2).validate(), Err(ClockError::VcoInput));
+        assert_eq!(ClockPlan::pll(16_000_000, 4, 8, 2).validate(), Err(ClockError::VcoOutput));
+        assert_eq!(ClockPlan::pll(16_000_000, 2, 20, 3).validate(), Err(ClockError::PllR));
+    }
+
+    #[test]
+    fn pll_from_target() {
+        let (plan, error) = PllPlan::from_target(16_000_000, 80_000_000).unwrap();
+        assert_eq!(plan.freq(), 80_000_000);
+        assert_eq!(error, 0);
+        assert_eq!(plan.validate(), Ok(()));
+
+        let (plan, error) = PllPlan::from_target(4_000_000, 72_000_000).unwrap();
+        assert_eq!(plan.freq(), 72_000_000);
+        assert_eq!(error, 0);
+
+        let (plan, _) = PllPlan::from_target(16_000_000, 100_000_000).unwrap();
+        assert_eq!(plan.freq(), 80_000_000);
+
+        assert!(PllPlan::from_target(1_000_000, 80_000_000).is_none());
+    }
+}
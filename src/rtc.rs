@@ -0,0 +1,394 @@
+//! Real Time Clock (RTC)
+//!
+//! RTC clock source is selected through `BDCR`, this module deals only with RTC itself.
+//!
+//! Calendar keeps running in Stop modes, so it can serve as low power time base:
+//!
+//! ```rust, ignore
+//! let start = rtc.timestamp_ms();
+//! // Stop mode
+//! rtc.resync();
+//! let elapsed = rtc.timestamp_ms().wrapping_sub(start);
+//! ```
+//!
+//! See Reference manual Ch. 38
+
+use stm32l4::stm32l4x5::RTC;
+
+use crate::adc::Adc;
+use crate::power::Power;
+use crate::rcc::{APB1, Clocks};
+use crate::rcc::clocking::RtcClkSource;
+
+use stm32l4::stm32l4x5::ADC1;
+
+/// Resolution of smooth calibration in ppm, 1 / 2^20
+pub const CALIBRATION_STEP_PPM: f32 = 0.953_674_3;
+/// Number of RTCCLK pulses added by CALP per calibration window
+const CALP_PULSES: u16 = 512;
+/// Maximal value of CALM
+const CALM_MAX: u16 = 0x1FF;
+/// Maximal value of asynchronous prescaler
+const PREDIV_A_MAX: u32 = 0x7F;
+/// Maximal value of synchronous prescaler
+const PREDIV_S_MAX: u32 = 0x7FFF;
+
+/// Days before first day of month in non-leap year
+const DAYS_BEFORE_MONTH: [u32; 12] = [0, 31, 59, 90, 120, 151, 181, 212, 243, 273, 304, 334];
+const SECONDS_PER_DAY: u32 = 24 * 60 * 60;
+/// Unix timestamp of 2000-01-01 00:00:00, start of RTC calendar
+pub const EPOCH_2000: u64 = 946_684_800;
+/// Unix timestamp of 2100-01-01 00:00:00, end of RTC calendar
+const EPOCH_2100: u64 = 4_102_444_800;
+
+/// Real Time Clock
+pub struct Rtc {
+    rtc: RTC,
+}
+
+impl Rtc {
+    /// Enables access to RTC registers.
+    ///
+    /// RTC clock must be selected and enabled through `BDCR` beforehand.
+    pub fn new(rtc: RTC, apb: &mut APB1, pwr: &mut Power) -> Self {
+        apb.enr1().modify(|_, w| w.rtcapben().set_bit());
+        pwr.remove_bdp();
+
+        Self {
+            rtc,
+        }
+    }
+
+    /// Runs `f` with RTC registers write protection lifted
+    fn unprotected<R, F: FnOnce(&RTC) -> R>(&mut self, f: F) -> R {
+        self.rtc.wpr.write(|w| unsafe { w.key().bits(0xCA) });
+        self.rtc.wpr.write(|w| unsafe { w.key().bits(0x53) });
+        let result = f(&self.rtc);
+        self.rtc.wpr.write(|w| unsafe { w.key().bits(0xFF) });
+        result
+    }
+
+    /// Sets smooth calibration registers directly.
+    ///
+    /// `calp` adds 512 pulses, while `calm` masks given number of pulses within 32 seconds window.
+    pub fn set_smooth_calibration(&mut self, calp: bool, calm: u16) {
+        debug_assert!(calm <= CALM_MAX);
+
+        // Previous calibration must be applied first
+        while self.rtc.isr.read().recalpf().bit_is_set() {}
+        self.unprotected(|rtc| rtc.calr.write(|w| unsafe { w.calp().bit(calp).calm().bits(calm) }));
+    }
+
+    /// Adjusts RTC frequency by `ppm`, positive value speeds clock up.
+    ///
+    /// Value is limited to range `-487.1..=488.3` ppm.
+    pub fn set_calibration_ppm(&mut self, ppm: f32) {
+        let (calp, calm) = calc_calibration(ppm);
+        self.set_smooth_calibration(calp, calm);
+    }
+
+    /// Returns current calibration in ppm
+    pub fn calibration_ppm(&self) -> f32 {
+        let calr = self.rtc.calr.read();
+        let calp = match calr.calp().bit_is_set() {
+            true => CALP_PULSES as i32,
+            false => 0,
+        };
+
+        (calp - calr.calm().bits() as i32) as f32 * CALIBRATION_STEP_PPM
+    }
+
+    /// Waits for calendar shadow registers to be synchronized.
+    ///
+    /// Required after wakeup from Stop modes, as shadow registers aren't updated there.
+    pub fn resync(&mut self) {
+        self.unprotected(|rtc| rtc.isr.modify(|_, w| w.rsf().clear_bit()));
+        while self.rtc.isr.read().rsf().bit_is_clear() {}
+    }
+
+    /// Reads consistent sub-second, time and date registers.
+    fn read_calendar(&self) -> (u32, u32, u32) {
+        // Reading SSR locks TR and DR until DR is read
+        let ssr = self.rtc.ssr.read().bits();
+        let tr = self.rtc.tr.read().bits();
+        let dr = self.rtc.dr.read().bits();
+        (ssr, tr, dr)
+    }
+
+    /// Returns number of seconds since 2000-01-01 00:00:00 and milliseconds within second.
+    fn seconds(&self) -> (u32, u32) {
+        let (ss, tr, dr) = self.read_calendar();
+        let prediv_s = self.rtc.prer.read().prediv_s().bits() as u32;
+
+        let year = bcd(dr >> 16, 8);
+        let month = bcd(dr >> 8, 5);
+        let day = bcd(dr, 6);
+        let mut hours = bcd(tr >> 16, 6);
+        // 12 hour format
+        if self.rtc.cr.read().fmt().bit_is_set() {
+            hours = hours % 12 + if tr & (1 << 22) != 0 { 12 } else { 0 };
+        }
+        let minutes = bcd(tr >> 8, 7);
+        let seconds = bcd(tr, 7);
+
+        let total = days_since_2000(year, month, day) * SECONDS_PER_DAY + hours * 3600 + minutes * 60 + seconds;
+        // Sub-second counter counts down from PREDIV_S
+        let ms = prediv_s.saturating_sub(ss) * 1000 / (prediv_s + 1);
+
+        (total, ms)
+    }
+
+    /// Returns monotonic timestamp in milliseconds, derived from calendar and sub-second counter.
+    ///
+    /// Timestamp wraps around every ~49.7 days, use `wrapping_sub` to measure intervals.
+    /// Resolution is defined by synchronous prescaler, e.g. ~4 ms with default PREDIV_S = 255.
+    pub fn timestamp_ms(&self) -> u32 {
+        let (seconds, ms) = self.seconds();
+        seconds.wrapping_mul(1000).wrapping_add(ms)
+    }
+
+    /// Sets calendar to Unix timestamp `epoch`, in seconds, e.g. received from SNTP.
+    ///
+    /// Calendar is switched to 24 hour format, sub-seconds are reset.
+    ///
+    /// # Pancis:
+    ///
+    /// In debug mode the function checks that `epoch` is within calendar range of years 2000..=2099.
+    pub fn set_epoch(&mut self, epoch: u64) {
+        debug_assert!(epoch >= EPOCH_2000 && epoch < EPOCH_2100);
+
+        let seconds = (epoch - EPOCH_2000) as u32;
+        let days = seconds / SECONDS_PER_DAY;
+        let time = seconds % SECONDS_PER_DAY;
+        let (year, month, day) = civil_from_days(days);
+        // 2000-01-01 is Saturday, RTC weekdays start with Monday = 1
+        let weekday = (days + 5) % 7 + 1;
+
+        let tr = to_bcd(time / 3600) << 16 | to_bcd(time / 60 % 60) << 8 | to_bcd(time % 60);
+        let dr = to_bcd(year) << 16 | weekday << 13 | to_bcd(month) << 8 | to_bcd(day);
+
+        self.unprotected(|rtc| {
+            rtc.isr.modify(|_, w| w.init().set_bit());
+            while rtc.isr.read().initf().bit_is_clear() {}
+
+            rtc.cr.modify(|_, w| w.fmt().clear_bit());
+            rtc.tr.write(|w| unsafe { w.bits(tr) });
+            rtc.dr.write(|w| unsafe { w.bits(dr) });
+
+            rtc.isr.modify(|_, w| w.init().clear_bit());
+        });
+    }
+
+    /// Sets prescalers to derive 1 Hz calendar clock from RTC clock `source`.
+    ///
+    /// Frequency of LSE and LSI is taken from `clocks`, falling back to nominal one.
+    /// HSE/32 frequency is not known here, use [set_prescaler_freq](#method.set_prescaler_freq) for it.
+    ///
+    /// Returns `false` if source frequency is unknown or cannot be divided down to 1 Hz.
+    pub fn set_prescaler(&mut self, source: RtcClkSource, clocks: &Clocks) -> bool {
+        let freq = match source {
+            RtcClkSource::LSE => clocks.lse().map(|freq| freq.0).or_else(|| source.freq(None)),
+            RtcClkSource::LSI => clocks.lsi().map(|freq| freq.0).or_else(|| source.freq(None)),
+            _ => None,
+        };
+
+        freq.map_or(false, |freq| self.set_prescaler_freq(freq))
+    }
+
+    /// Sets prescalers to derive 1 Hz calendar clock from RTC clock of `freq` Hz.
+    ///
+    /// Returns `false` if `freq` cannot be divided down to 1 Hz.
+    pub fn set_prescaler_freq(&mut self, freq: u32) -> bool {
+        let (prediv_a, prediv_s) = match calc_prescaler(freq) {
+            Some(prescaler) => prescaler,
+            None => return false,
+        };
+
+        self.unprotected(|rtc| {
+            rtc.isr.modify(|_, w| w.init().set_bit());
+            while rtc.isr.read().initf().bit_is_clear() {}
+
+            // Both prescalers must be written by two separate accesses
+            rtc.prer.modify(|_, w| unsafe { w.prediv_s().bits(prediv_s) });
+            rtc.prer.modify(|_, w| unsafe { w.prediv_a().bits(prediv_a) });
+
+            rtc.isr.modify(|_, w| w.init().clear_bit());
+        });
+        true
+    }
+
+    /// Returns current Unix timestamp in seconds
+    pub fn epoch(&self) -> u64 {
+        EPOCH_2000 + self.seconds().0 as u64
+    }
+
+    /// Releases RTC peripheral
+    pub fn free(self) -> RTC {
+        self.rtc
+    }
+}
+
+/// Decodes BCD value of `width` bits at the bottom of `bits`
+fn bcd(bits: u32, width: u32) -> u32 {
+    let bits = bits & ((1 << width) - 1);
+    (bits >> 4) * 10 + (bits & 0xF)
+}
+
+/// Returns number of days since 2000-01-01, RTC `year` is counted from 2000
+fn days_since_2000(year: u32, month: u32, day: u32) -> u32 {
+    // Every 4th year is leap within 2000..=2099
+    let is_leap = year % 4 == 0;
+    let leap_days = (year + 3) / 4 + (is_leap && month > 2) as u32;
+
+    year * 365 + leap_days + DAYS_BEFORE_MONTH[(month as usize).max(1) - 1] + day.max(1) - 1
+}
+
+/// Converts number of days since 2000-01-01 to RTC year, month and day
+fn civil_from_days(mut days: u32) -> (u32, u32, u32) {
+    let mut year = 0;
+    loop {
+        let year_len = if year % 4 == 0 { 366 } else { 365 };
+        if days < year_len {
+            break;
+        }
+        days -= year_len;
+        year += 1;
+    }
+
+    let is_leap = year % 4 == 0;
+    let mut month = 12;
+    while month > 1 {
+        let start = DAYS_BEFORE_MONTH[month - 1] + (is_leap && month > 2) as u32;
+        if days >= start {
+            days -= start;
+            break;
+        }
+        month -= 1;
+    }
+
+    (year, month as u32, days + 1)
+}
+
+/// Encodes `value` below 100 as BCD
+fn to_bcd(value: u32) -> u32 {
+    ((value / 10) << 4) | (value % 10)
+}
+
+/// Calculates asynchronous and synchronous prescalers dividing `freq` down to 1 Hz.
+///
+/// Asynchronous prescaler is kept as high as possible to minimize power consumption.
+pub fn calc_prescaler(freq: u32) -> Option<(u8, u16)> {
+    (1..=PREDIV_A_MAX + 1).rev()
+        .filter(|prediv_a| freq % prediv_a == 0)
+        .map(|prediv_a| (prediv_a, freq / prediv_a))
+        .find(|&(_, prediv_s)| prediv_s >= 1 && prediv_s <= PREDIV_S_MAX + 1)
+        .map(|(prediv_a, prediv_s)| ((prediv_a - 1) as u8, (prediv_s - 1) as u16))
+}
+
+/// Calculates CALP and CALM for frequency adjustment by `ppm`
+pub fn calc_calibration(ppm: f32) -> (bool, u16) {
+    let steps = (ppm / CALIBRATION_STEP_PPM + 0.5 * ppm.signum()) as i32;
+
+    let (calp, calm) = match steps > 0 {
+        true => (true, CALP_PULSES as i32 - steps),
+        false => (false, -steps),
+    };
+
+    (calp, calm.max(0).min(CALM_MAX as i32) as u16)
+}
+
+/// Compensates frequency drift of 32.768 kHz crystal over temperature.
+///
+/// Tuning fork crystals follow parabolic curve `offset + coefficient * (T - turnover)^2` ppm.
+/// Call [update](#method.update) periodically, e.g. every minute.
+pub struct DriftCompensator {
+    /// Turnover temperature in °C
+    pub turnover: f32,
+    /// Parabolic coefficient in ppm/°C², usually negative (-0.034 typical)
+    pub coefficient: f32,
+    /// Frequency error at turnover temperature in ppm, e.g. measured in production
+    pub offset: f32,
+}
+
+impl DriftCompensator {
+    /// Creates compensator with typical tuning fork crystal curve.
+    pub fn new() -> Self {
+        DriftCompensator {
+            turnover: 25.0,
+            coefficient: -0.034,
+            offset: 0.0,
+        }
+    }
+
+    /// Returns crystal frequency error in ppm at `temperature`
+    pub fn drift(&self, temperature: f32) -> f32 {
+        let delta = temperature - self.turnover;
+        self.offset + self.coefficient * delta * delta
+    }
+
+    /// Measures temperature with internal sensor and updates RTC calibration.
+    ///
+    /// Returns measured temperature in °C.
+    pub fn update(&mut self, adc: &mut Adc<ADC1>, rtc: &mut Rtc) -> f32 {
+        let temperature = adc.read_temperature();
+        rtc.set_calibration_ppm(-self.drift(temperature));
+        temperature
+    }
+}
+
+impl Default for DriftCompensator {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn calibration_slows_down() {
+        assert_eq!(calc_calibration(0.0), (false, 0));
+        assert_eq!(calc_calibration(-10.0), (false, 10));
+        assert_eq!(calc_calibration(-1000.0), (false, CALM_MAX));
+    }
+
+    #[test]
+    fn calendar_days() {
+        assert_eq!(days_since_2000(0, 1, 1), 0);
+        assert_eq!(days_since_2000(0, 3, 1), 31 + 29);
+        assert_eq!(days_since_2000(1, 1, 1), 366);
+        assert_eq!(days_since_2000(1, 3, 1), 366 + 31 + 28);
+        // 2020-06-15
+        assert_eq!(days_since_2000(20, 6, 15), 7471);
+        assert_eq!(bcd(0x59, 7), 59);
+    }
+
+    #[test]
+    fn calendar_roundtrip() {
+        assert_eq!(civil_from_days(0), (0, 1, 1));
+        assert_eq!(civil_from_days(31 + 28), (0, 2, 29));
+        assert_eq!(civil_from_days(7471), (20, 6, 15));
+        assert_eq!(civil_from_days(366 + 364), (1, 12, 31));
+
+        for days in 0..36_524 {
+            let (year, month, day) = civil_from_days(days);
+            assert_eq!(days_since_2000(year, month, day), days);
+        }
+        assert_eq!(to_bcd(59), 0x59);
+    }
+
+    #[test]
+    fn prescaler() {
+        assert_eq!(calc_prescaler(32_768), Some((127, 255)));
+        assert_eq!(calc_prescaler(32_000), Some((127, 249)));
+        assert_eq!(calc_prescaler(40_000), Some((124, 319)));
+        assert_eq!(calc_prescaler(0), None);
+    }
+
+    #[test]
+    fn calibration_speeds_up() {
+        assert_eq!(calc_calibration(10.0), (true, 502));
+        assert_eq!(calc_calibration(1000.0), (true, 0));
+    }
+}
@@ -0,0 +1,60 @@
+//! Production self-test of communication interfaces
+//!
+//! Self-tests are meant to be run at boot in production test mode, detecting solder and
+//! bus faults before device leaves the line:
+//!
+//! ```rust, ignore
+//! // TX is internally connected to RX
+//! serial.selftest()?;
+//! // MOSI must be bridged to MISO by test fixture
+//! spi.selftest()?;
+//! // SCL and SDA must be released by pull-ups
+//! i2c.selftest()?;
+//! ```
+//!
+//! Interfaces are restored to their configuration once test completes.
+
+/// Fault detected by self-test
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum Fault {
+    /// Byte hasn't been transferred in time
+    Timeout,
+    /// Received byte differs from transmitted one
+    Mismatch {
+        /// Transmitted byte
+        sent: u8,
+        /// Received byte
+        received: u8,
+    },
+    /// Interface reported error during transfer
+    Transfer,
+    /// Bus lines are held low
+    BusStuck,
+}
+
+/// Bytes transferred by loopback tests, driving every bit both ways
+pub const PATTERN: [u8; 4] = [0x55, 0xAA, 0x00, 0xFF];
+
+/// Number of polls each byte is waited for, enough for 1200 bauds at 80 MHz
+const TIMEOUT: u32 = 1_000_000;
+
+/// Polls `op` until it completes, up to `TIMEOUT` times.
+pub(crate) fn poll<T, E, F: FnMut() -> nb::Result<T, E>>(mut op: F) -> Result<T, Fault> {
+    for _ in 0..TIMEOUT {
+        match op() {
+            Ok(value) => return Ok(value),
+            Err(nb::Error::WouldBlock) => (),
+            Err(nb::Error::Other(_)) => return Err(Fault::Transfer),
+        }
+    }
+
+    Err(Fault::Timeout)
+}
+
+/// Checks that `received` byte matches `sent` one.
+pub(crate) fn compare(sent: u8, received: u8) -> Result<(), Fault> {
+    match sent == received {
+        true => Ok(()),
+        false => Err(Fault::Mismatch { sent, received }),
+    }
+}
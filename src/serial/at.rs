@@ -0,0 +1,185 @@
+//! AT command transport
+//!
+//! Sends commands to modem and collects response lines until final result code,
+//! while unsolicited result codes (URC) are routed to handler.
+//!
+//! Serial must be non-blocking, e.g. [Shared](../buffered/struct.Shared.html) buffered serial,
+//! and timeouts are measured by `CountDown` timer.
+//!
+//! ```rust, ignore
+//! let mut modem = At::new(Shared::new(&MODEM), timer);
+//! modem.set_urc_handler(on_urc);
+//!
+//! let len = modem.command(b"AT+CSQ", Hertz(1), &mut response)?;
+//! ```
+
+use embedded_hal::serial;
+use embedded_hal::timer::CountDown;
+
+/// Maximal length of line, longer lines are truncated.
+pub const MAX_LINE_LEN: usize = 128;
+
+/// AT transport error
+#[derive(Copy, Clone, PartialEq, Eq, Debug)]
+pub enum Error {
+    /// Underlying serial error
+    Serial,
+    /// Final result code has not been received in time
+    Timeout,
+    /// Modem replied with `ERROR`, `+CME ERROR` or `+CMS ERROR`
+    Command,
+    /// Response doesn't fit into buffer
+    Overflow,
+}
+
+/// AT command transport
+pub struct At<S, T> {
+    serial: S,
+    timer: T,
+    line: [u8; MAX_LINE_LEN],
+    line_len: usize,
+    urc_handler: Option<fn(&[u8])>,
+}
+
+/// Returns prefix of information response to `command`, e.g. `+CSQ` for `AT+CSQ?`
+fn response_prefix(command: &[u8]) -> &[u8] {
+    let command = match command.len() > 2 {
+        true => &command[2..],
+        false => &[],
+    };
+    let end = command.iter().position(|byte| *byte == b'=' || *byte == b'?').unwrap_or(command.len());
+
+    &command[..end]
+}
+
+fn is_error(line: &[u8]) -> bool {
+    line == b"ERROR" || line.starts_with(b"+CME ERROR") || line.starts_with(b"+CMS ERROR")
+}
+
+impl<S: serial::Read<u8> + serial::Write<u8>, T: CountDown> At<S, T> {
+    /// Creates transport
+    pub fn new(serial: S, timer: T) -> Self {
+        Self {
+            serial,
+            timer,
+            line: [0; MAX_LINE_LEN],
+            line_len: 0,
+            urc_handler: None,
+        }
+    }
+
+    /// Sets handler of unsolicited result codes
+    pub fn set_urc_handler(&mut self, handler: fn(&[u8])) {
+        self.urc_handler = Some(handler);
+    }
+
+    /// Reads available bytes, returning `true` once line is complete.
+    fn read_line(&mut self) -> Result<bool, Error> {
+        loop {
+            match self.serial.read() {
+                Ok(b'\n') => return Ok(true),
+                Ok(b'\r') => (),
+                Ok(byte) => if self.line_len < MAX_LINE_LEN {
+                    self.line[self.line_len] = byte;
+                    self.line_len += 1;
+                },
+                Err(nb::Error::WouldBlock) => return Ok(false),
+                Err(nb::Error::Other(_)) => return Err(Error::Serial),
+            }
+        }
+    }
+
+    fn handle_urc(&self, line: &[u8]) {
+        if let Some(handler) = self.urc_handler {
+            handler(line);
+        }
+    }
+
+    /// Writes raw bytes, e.g. payload after `>` prompt.
+    pub fn write(&mut self, bytes: &[u8]) -> Result<(), Error> {
+        for byte in bytes {
+            nb::block!(self.serial.write(*byte)).map_err(|_| Error::Serial)?;
+        }
+        Ok(())
+    }
+
+    /// Sends `command` and waits up to `timeout` for final result code.
+    ///
+    /// Information response lines are copied into `response`, separated by `\n`,
+    /// and their total length is returned. Command echo and empty lines are skipped,
+    /// while lines starting with `+`, which don't belong to command, are routed to URC handler.
+    pub fn command<TIME: Into<T::Time>>(&mut self, command: &[u8], timeout: TIME, response: &mut [u8]) -> Result<usize, Error> {
+        self.write(command)?;
+        self.write(b"\r")?;
+
+        let prefix = response_prefix(command);
+        let mut len = 0;
+        self.line_len = 0;
+        self.timer.start(timeout);
+
+        loop {
+            if !self.read_line()? {
+                match self.timer.wait() {
+                    Ok(_) => return Err(Error::Timeout),
+                    Err(_) => continue,
+                }
+            }
+
+            let line_len = self.line_len;
+            self.line_len = 0;
+            let line = &self.line[..line_len];
+
+            if line == b"OK" {
+                return Ok(len);
+            } else if is_error(line) {
+                return Err(Error::Command);
+            } else if line.is_empty() || line == command {
+                continue;
+            } else if line[0] == b'+' && (prefix.is_empty() || !line.starts_with(prefix)) {
+                self.handle_urc(line);
+                continue;
+            }
+
+            let separator = (len > 0) as usize;
+            if len + separator + line.len() > response.len() {
+                return Err(Error::Overflow);
+            }
+            if separator > 0 {
+                response[len] = b'\n';
+            }
+            response[len + separator..len + separator + line.len()].copy_from_slice(line);
+            len += separator + line.len();
+        }
+    }
+
+    /// Routes received lines to URC handler, should be called while no command is in progress.
+    pub fn poll(&mut self) -> Result<(), Error> {
+        while self.read_line()? {
+            let line_len = self.line_len;
+            self.line_len = 0;
+            if line_len > 0 {
+                self.handle_urc(&self.line[..line_len]);
+            }
+        }
+        Ok(())
+    }
+
+    /// Releases serial and timer
+    pub fn free(self) -> (S, T) {
+        (self.serial, self.timer)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn prefix_of_command() {
+        assert_eq!(response_prefix(b"AT+CSQ"), b"+CSQ");
+        assert_eq!(response_prefix(b"AT+CREG?"), b"+CREG");
+        assert_eq!(response_prefix(b"AT+CPIN=1234"), b"+CPIN");
+        assert_eq!(response_prefix(b"ATI"), b"I");
+        assert_eq!(response_prefix(b"AT"), b"");
+    }
+}
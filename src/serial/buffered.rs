@@ -0,0 +1,227 @@
+//! Interrupt driven serial with software queues
+//!
+//! [BufferedSerial](struct.BufferedSerial.html) is shared between application and USART interrupt,
+//! usually through `cortex_m::interrupt::Mutex`. Its non-blocking methods are meant to be called
+//! within short critical sections, never block on them while interrupts are disabled.
+//!
+//! ```rust, ignore
+//! static CONSOLE: Mutex<RefCell<Option<BufferedSerial<USART1, PB6<AF7>, PB7<AF7>, DummyPin, 128, 128>>>> = Mutex::new(RefCell::new(None));
+//!
+//! let rx = singleton!(: Queue<u8, 128> = Queue::new()).unwrap();
+//! let tx = singleton!(: Queue<u8, 128> = Queue::new()).unwrap();
+//! let console = BufferedSerial::new(serial, rx, tx);
+//! interrupt::free(|cs| CONSOLE.borrow(cs).replace(Some(console)));
+//!
+//! // USART1 interrupt
+//! interrupt::free(|cs| CONSOLE.borrow(cs).borrow_mut().as_mut().map(|console| console.handle_interrupt()));
+//! ```
+
+use core::cell::RefCell;
+use core::ptr;
+
+use cortex_m::interrupt::{self, Mutex};
+use embedded_hal::serial;
+pub use heapless::spsc::{Consumer, Producer, Queue};
+
+use super::{CK, Error, Event, Instance, Serial, RX, TX};
+
+/// Serial with receiver and transmitter served by interrupt through `heapless::spsc` queues.
+///
+/// Interrupt side produces into RX queue and consumes TX queue, application side does the opposite.
+/// Each queue of `N` elements holds up to `N - 1` bytes.
+pub struct BufferedSerial<UART, T, R, C, const RXN: usize, const TXN: usize> {
+    serial: Serial<UART, T, R, C>,
+    rx_producer: Producer<'static, u8, RXN>,
+    rx_consumer: Consumer<'static, u8, RXN>,
+    tx_producer: Producer<'static, u8, TXN>,
+    tx_consumer: Consumer<'static, u8, TXN>,
+    // Number of complete lines in RX queue
+    lines: usize,
+    error: Option<Error>,
+}
+
+impl<UART: Instance, T: TX, R: RX, C: CK, const RXN: usize, const TXN: usize> BufferedSerial<UART, T, R, C, RXN, TXN> {
+    /// Creates buffered serial, splitting `rx_queue` and `tx_queue`, and subscribes to receiver interrupt.
+    pub fn new(serial: Serial<UART, T, R, C>, rx_queue: &'static mut Queue<u8, RXN>, tx_queue: &'static mut Queue<u8, TXN>) -> Self {
+        let (rx_producer, rx_consumer) = rx_queue.split();
+        let (tx_producer, tx_consumer) = tx_queue.split();
+
+        serial.subscribe(Event::Rxne);
+
+        Self {
+            serial,
+            rx_producer,
+            rx_consumer,
+            tx_producer,
+            tx_consumer,
+            lines: 0,
+            error: None,
+        }
+    }
+
+    /// Moves received byte into RX queue and next byte from TX queue into transmitter.
+    ///
+    /// Must be called from USART interrupt.
+    /// Errors are cleared and reported by the next `read`, as well as overflow of RX queue.
+    pub fn handle_interrupt(&mut self) {
+        let isr = self.serial.isr().read();
+
+        let error = if isr.pe().bit_is_set() {
+            Some(Error::Parity)
+        } else if isr.fe().bit_is_set() {
+            Some(Error::Framing)
+        } else if isr.nf().bit_is_set() {
+            Some(Error::Noise)
+        } else if isr.ore().bit_is_set() {
+            Some(Error::Overrun)
+        } else {
+            None
+        };
+        if error.is_some() {
+            self.serial.icr().write(|w| w.pecf().set_bit().fecf().set_bit().ncf().set_bit().orecf().set_bit());
+            self.error = error;
+        }
+
+        if isr.rxne().bit_is_set() {
+            let rdr = unsafe { ptr::addr_of!((*UART::register_block()).rdr) } as *const u8;
+            let byte = unsafe { ptr::read_volatile(rdr) };
+            match self.rx_producer.enqueue(byte) {
+                Ok(()) if byte == b'\n' => self.lines += 1,
+                Ok(()) => (),
+                Err(_) => self.error = Some(Error::Overrun),
+            }
+        }
+
+        if isr.txe().bit_is_set() {
+            match self.tx_consumer.dequeue() {
+                Some(byte) => {
+                    let tdr = unsafe { ptr::addr_of!((*UART::register_block()).tdr) } as *mut u8;
+                    unsafe { ptr::write_volatile(tdr, byte) }
+                }
+                None => self.serial.unsubscribe(Event::Txe),
+            }
+        }
+    }
+
+    /// Reads line terminated by `\n` into `buffer`, returning its length without terminator.
+    ///
+    /// Returns `None` until whole line is received. Trailing `\r` is removed, and
+    /// characters not fitting into `buffer` are discarded.
+    pub fn read_line(&mut self, buffer: &mut [u8]) -> Option<usize> {
+        if self.lines == 0 {
+            return None;
+        }
+        self.lines -= 1;
+
+        let mut len = 0;
+        while let Some(byte) = self.rx_consumer.dequeue() {
+            if byte == b'\n' {
+                break;
+            }
+            if len < buffer.len() {
+                buffer[len] = byte;
+                len += 1;
+            }
+        }
+
+        if len > 0 && buffer[len - 1] == b'\r' {
+            len -= 1;
+        }
+
+        Some(len)
+    }
+
+    /// Returns whether all queued bytes have been transmitted.
+    pub fn is_flushed(&self) -> bool {
+        !self.tx_consumer.ready() && self.serial.isr().read().tc().bit_is_set()
+    }
+
+    /// Stops interrupts and releases serial, discarding queued data.
+    pub fn free(self) -> Serial<UART, T, R, C> {
+        self.serial.unsubscribe(Event::Rxne);
+        self.serial.unsubscribe(Event::Txe);
+
+        self.serial
+    }
+}
+
+impl<UART: Instance, T: TX, R: RX, C: CK, const RXN: usize, const TXN: usize> serial::Read<u8> for BufferedSerial<UART, T, R, C, RXN, TXN> {
+    type Error = Error;
+
+    fn read(&mut self) -> nb::Result<u8, Error> {
+        if let Some(error) = self.error.take() {
+            return Err(nb::Error::Other(error));
+        }
+
+        match self.rx_consumer.dequeue() {
+            Some(byte) => {
+                if byte == b'\n' {
+                    self.lines = self.lines.saturating_sub(1);
+                }
+                Ok(byte)
+            }
+            None => Err(nb::Error::WouldBlock),
+        }
+    }
+}
+
+impl<UART: Instance, T: TX, R: RX, C: CK, const RXN: usize, const TXN: usize> serial::Write<u8> for BufferedSerial<UART, T, R, C, RXN, TXN> {
+    type Error = ();
+
+    fn flush(&mut self) -> nb::Result<(), ()> {
+        match self.is_flushed() {
+            true => Ok(()),
+            false => Err(nb::Error::WouldBlock),
+        }
+    }
+
+    fn write(&mut self, byte: u8) -> nb::Result<(), ()> {
+        self.tx_producer.enqueue(byte).map_err(|_| nb::Error::WouldBlock)?;
+        self.serial.subscribe(Event::Txe);
+        Ok(())
+    }
+}
+
+/// Buffered serial shared with interrupt, which is accessed in short critical sections.
+///
+/// Reports `WouldBlock` while serial is not put into the mutex yet.
+pub struct Shared<UART: 'static, T: 'static, R: 'static, C: 'static, const RXN: usize, const TXN: usize> {
+    serial: &'static Mutex<RefCell<Option<BufferedSerial<UART, T, R, C, RXN, TXN>>>>,
+}
+
+impl<UART: Instance, T: TX, R: RX, C: CK, const RXN: usize, const TXN: usize> Shared<UART, T, R, C, RXN, TXN> {
+    /// Creates accessor of buffered `serial`
+    pub fn new(serial: &'static Mutex<RefCell<Option<BufferedSerial<UART, T, R, C, RXN, TXN>>>>) -> Self {
+        Self {
+            serial,
+        }
+    }
+
+    /// Runs `f` with serial borrowed in critical section
+    pub fn with<F: FnOnce(&mut BufferedSerial<UART, T, R, C, RXN, TXN>) -> nb::Result<O, E>, O, E>(&mut self, f: F) -> nb::Result<O, E> {
+        interrupt::free(|cs| match self.serial.borrow(cs).borrow_mut().as_mut() {
+            Some(serial) => f(serial),
+            None => Err(nb::Error::WouldBlock),
+        })
+    }
+}
+
+impl<UART: Instance, T: TX, R: RX, C: CK, const RXN: usize, const TXN: usize> serial::Read<u8> for Shared<UART, T, R, C, RXN, TXN> {
+    type Error = Error;
+
+    fn read(&mut self) -> nb::Result<u8, Error> {
+        self.with(|serial| serial::Read::read(serial))
+    }
+}
+
+impl<UART: Instance, T: TX, R: RX, C: CK, const RXN: usize, const TXN: usize> serial::Write<u8> for Shared<UART, T, R, C, RXN, TXN> {
+    type Error = ();
+
+    fn flush(&mut self) -> nb::Result<(), ()> {
+        self.with(|serial| serial::Write::flush(serial))
+    }
+
+    fn write(&mut self, byte: u8) -> nb::Result<(), ()> {
+        self.with(|serial| serial::Write::write(serial, byte))
+    }
+}
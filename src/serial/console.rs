@@ -0,0 +1,157 @@
+//! Console surviving clock changes
+//!
+//! Serial registered as console has its baud rate generator reprogrammed on
+//! [clock change](../../rcc/notify/index.html), so output keeps its baud rate
+//! whatever new bus frequencies are.
+//!
+//! ```rust, ignore
+//! let serial = Serial::with_dummy(device.USART1, (tx, rx), Cfg, &clocks, &mut rcc.apb2)?;
+//! let mut console = Console::new(serial, Cfg);
+//!
+//! let clocks = rcc::reconfigure(cfgr, &mut flash.acr);
+//! block!(console.write(b'!'))?;
+//! ```
+//!
+//! Only one console can be registered at a time.
+
+use core::cell::Cell;
+use core::ops;
+
+use cortex_m::interrupt::{self, Mutex};
+use embedded_hal::serial;
+
+use crate::rcc::Clocks;
+use crate::rcc::notify::{self, ClockChange};
+use crate::time::Hertz;
+
+use super::{calc_brr, CK, Config, Error, Instance, RawSerial, Serial, RX, TX, USART1, USART2, USART3};
+use stm32l4::stm32l4x5::usart1;
+
+/// Registered console, as index of UART and its baud rate
+static CONSOLE: Mutex<Cell<Option<(u8, u32)>>> = Mutex::new(Cell::new(None));
+
+/// Returns registers of UART with index `idx`
+fn registers(idx: u8) -> &'static usart1::RegisterBlock {
+    unsafe {
+        match idx {
+            1 => &*USART1::register_block(),
+            2 => &*USART2::register_block(),
+            _ => &*USART3::register_block(),
+        }
+    }
+}
+
+/// Returns kernel clock of UART with index `idx`
+fn clock(idx: u8, clocks: &Clocks) -> Hertz {
+    match idx {
+        1 => USART1::get_clock_freq(clocks),
+        2 => USART2::get_clock_freq(clocks),
+        _ => USART3::get_clock_freq(clocks),
+    }
+}
+
+/// Follows clock change with console's baud rate
+fn on_clock_change(change: &ClockChange) {
+    match change {
+        ClockChange::Pending => flush(),
+        ClockChange::Done(clocks) => clocks_changed(clocks),
+    }
+}
+
+/// Waits until console finishes transmission, so no character is lost on clock switch.
+fn flush() {
+    if let Some((idx, _)) = interrupt::free(|cs| CONSOLE.borrow(cs).get()) {
+        let registers = registers(idx);
+        if registers.cr1.read().ue().bit_is_set() {
+            while registers.isr.read().tc().bit_is_clear() {}
+        }
+    }
+}
+
+/// Reprograms baud rate of console for new `clocks`.
+///
+/// Console is left disabled if its baud rate cannot be derived from new clock.
+fn clocks_changed(clocks: &Clocks) {
+    if let Some((idx, baud)) = interrupt::free(|cs| CONSOLE.borrow(cs).get()) {
+        let registers = registers(idx);
+
+        // BRR can be written only while interface is disabled
+        let cr1 = registers.cr1.read().bits();
+        registers.cr1.modify(|_, w| w.ue().clear_bit());
+        if let Ok(brr) = calc_brr(clock(idx, clocks).0, baud) {
+            registers.brr.write(|w| unsafe { w.bits(brr) });
+            registers.cr1.write(|w| unsafe { w.bits(cr1) });
+        }
+    }
+}
+
+/// Serial, which keeps its baud rate across clock changes
+pub struct Console<UART, T, R, C> {
+    serial: Serial<UART, T, R, C>,
+}
+
+impl<UART: Instance, T: TX, R: RX, C: CK> Console<UART, T, R, C> {
+    /// Registers `serial`, configured with `CFN`, as console.
+    ///
+    /// On clock change baud rate is derived from kernel clock of serial, as currently selected in CCIPR,
+    /// see [Clocks::usart1_clk](../../rcc/struct.Clocks.html#method.usart1_clk).
+    ///
+    /// # Pancis:
+    ///
+    /// In debug mode the function checks that no other console is registered,
+    /// and that there is free slot for clock change callback.
+    pub fn new<CFN: Config>(serial: Serial<UART, T, R, C>, _: CFN) -> Self {
+        interrupt::free(|cs| {
+            let console = CONSOLE.borrow(cs);
+            debug_assert!(console.get().is_none());
+            console.set(Some((UART::IDX, CFN::BAUD)));
+        });
+        let subscription = notify::subscribe(on_clock_change);
+        debug_assert!(subscription.is_ok());
+
+        Self {
+            serial,
+        }
+    }
+
+    /// Unregisters console and releases serial
+    pub fn free(self) -> Serial<UART, T, R, C> {
+        notify::unsubscribe(on_clock_change);
+        interrupt::free(|cs| CONSOLE.borrow(cs).set(None));
+        self.serial
+    }
+}
+
+impl<UART: Instance, T: TX, R: RX, C: CK> ops::Deref for Console<UART, T, R, C> {
+    type Target = Serial<UART, T, R, C>;
+
+    fn deref(&self) -> &Self::Target {
+        &self.serial
+    }
+}
+
+impl<UART: Instance, T: TX, R: RX, C: CK> ops::DerefMut for Console<UART, T, R, C> {
+    fn deref_mut(&mut self) -> &mut Self::Target {
+        &mut self.serial
+    }
+}
+
+impl<UART: Instance, T: TX, R: RX, C: CK> serial::Read<u8> for Console<UART, T, R, C> {
+    type Error = Error;
+
+    fn read(&mut self) -> nb::Result<u8, Error> {
+        self.serial.read()
+    }
+}
+
+impl<UART: Instance, T: TX, R: RX, C: CK> serial::Write<u8> for Console<UART, T, R, C> {
+    type Error = ();
+
+    fn flush(&mut self) -> nb::Result<(), ()> {
+        self.serial.flush()
+    }
+
+    fn write(&mut self, byte: u8) -> nb::Result<(), ()> {
+        self.serial.write(byte)
+    }
+}
@@ -0,0 +1,157 @@
+//! DMX512 transmitter
+//!
+//! Packet starts with break and mark after break, which are generated by sending zero
+//! character at reduced baud rate, then 513 slots (start code and 512 channels) are streamed
+//! by DMA at 250 kbaud with 2 stop bits.
+//!
+//! ```rust, ignore
+//! let serial = Serial::with_dummy(USART2, (tx, rx), Cfg, &clocks, &mut rcc.apb1)?;
+//! let mut dmx = Dmx::new(serial, dma1.C7, frame, &clocks)?;
+//!
+//! loop {
+//!     if let Some(frame) = dmx.frame_mut() {
+//!         frame[1] = dimmer;
+//!         dmx.start();
+//!     }
+//! }
+//! ```
+//!
+//! See ANSI E1.11
+
+use core::sync::atomic::{self, Ordering};
+
+use crate::common::ConfigError;
+use crate::dma::{self, DmaChannel, Direction, Event, WordSize};
+use crate::rcc::Clocks;
+
+use super::{calc_brr, Instance, Serial, TX, RX, CK};
+
+/// Baud rate of DMX512
+pub const BAUD: u32 = 250_000;
+/// Number of slots in packet, start code followed by 512 channels
+pub const SLOTS: usize = 513;
+/// Baud rate of zero character generating break, 9 low bits give 112.5 us of break
+/// (at least 88 us) and 2 stop bits give 25 us of mark after break (at least 8 us)
+const BREAK_BAUD: u32 = 80_000;
+
+/// DMX512 transmitter
+pub struct Dmx<S: Instance, TX, RX, CK> {
+    serial: Serial<S, TX, RX, CK>,
+    channel: S::TxChannel,
+    frame: &'static mut [u8; SLOTS],
+    brr: u32,
+    break_brr: u32,
+}
+
+impl<UART: Instance, T: TX, R: RX, C: CK> Dmx<UART, T, R, C> {
+    /// Creates transmitter of `frame`, switching serial to 250 kbaud with 2 stop bits.
+    ///
+    /// First slot of `frame` is start code, which is 0 for dimmer data.
+    ///
+    /// Returns error if baud rates cannot be derived from Serial's clock.
+    pub fn new(serial: Serial<UART, T, R, C>, mut channel: UART::TxChannel, frame: &'static mut [u8; SLOTS], clocks: &Clocks) -> Result<Self, ConfigError> {
+        let clock = UART::get_clock_freq(clocks).0;
+        let brr = calc_brr(clock, BAUD)?;
+        let break_brr = calc_brr(clock, BREAK_BAUD)?;
+
+        channel.stop();
+        channel.set_request(UART::TX_DMA_REQUEST);
+        // NOTE(unsafe) data register of owned Serial
+        unsafe { channel.set_peripheral_address(serial.tdr() as *const _ as u32, false) };
+
+        // BRR and STOP can be written only while interface is disabled
+        serial.cr1().modify(|_, w| w.ue().clear_bit());
+        serial.brr().write(|w| unsafe { w.bits(brr) });
+        serial.cr2().modify(|_, w| w.stop().bits(0b10));
+        serial.cr3().modify(|_, w| w.dmat().set_bit());
+        serial.cr1().modify(|_, w| w.ue().set_bit());
+
+        Ok(Self {
+            serial,
+            channel,
+            frame,
+            brr,
+            break_brr,
+        })
+    }
+
+    /// Switches baud rate by writing `brr`, keeping transmitter enabled.
+    fn set_brr(&self, brr: u32) {
+        self.serial.cr1().modify(|_, w| w.ue().clear_bit());
+        self.serial.brr().write(|w| unsafe { w.bits(brr) });
+        self.serial.cr1().modify(|_, w| w.ue().set_bit());
+    }
+
+    /// Sends break and mark after break, blocking for about 140 us.
+    fn send_break(&mut self) {
+        self.set_brr(self.break_brr);
+
+        let registers = self.serial.registers();
+        registers.icr.write(|w| w.tccf().set_bit());
+        registers.tdr.write(|w| unsafe { w.tdr().bits(0) });
+        while registers.isr.read().tc().bit_is_clear() {}
+
+        self.set_brr(self.brr);
+    }
+
+    /// Starts transmission of packet, unless previous one is still being transmitted.
+    ///
+    /// Break is generated before returning, while slots are streamed by DMA afterwards.
+    pub fn start(&mut self) {
+        if self.is_busy() {
+            return;
+        }
+
+        self.send_break();
+
+        self.channel.stop();
+        // NOTE(unsafe) frame is 'static and owned, DMA only reads it
+        unsafe { self.channel.set_memory_address(self.frame.as_ptr() as u32, true) };
+        self.channel.set_transfer_length(SLOTS as u16);
+        self.channel.configure(Direction::MemoryToPeripheral, false, WordSize::Byte);
+        self.serial.icr().write(|w| w.tccf().set_bit());
+
+        atomic::compiler_fence(Ordering::SeqCst);
+
+        self.channel.start();
+    }
+
+    fn is_busy(&self) -> bool {
+        self.channel.is_enabled() && self.is_done().map(|is_done| !is_done).unwrap_or(false)
+    }
+
+    /// Returns whether last slot of packet has left transmitter.
+    pub fn is_done(&self) -> Result<bool, dma::Error> {
+        if self.channel.is_event(Event::TransferError) {
+            return Err(dma::Error::Transfer);
+        }
+
+        Ok(!self.channel.in_progress() && self.serial.isr().read().tc().bit_is_set())
+    }
+
+    /// Blocks until packet has been transmitted.
+    pub fn wait(&self) -> Result<(), dma::Error> {
+        while self.is_busy() {}
+        self.is_done().map(|_| ())
+    }
+
+    /// Returns slots of packet to be updated, unless packet is being transmitted.
+    pub fn frame_mut(&mut self) -> Option<&mut [u8; SLOTS]> {
+        match self.is_busy() {
+            true => None,
+            false => Some(self.frame),
+        }
+    }
+
+    /// Stops transmission, returning Serial with DMA channel and frame
+    ///
+    /// Serial is left at 250 kbaud with 2 stop bits.
+    pub fn free(mut self) -> (Serial<UART, T, R, C>, UART::TxChannel, &'static mut [u8; SLOTS]) {
+        self.channel.stop();
+        self.serial.cr3().modify(|_, w| w.dmat().clear_bit());
+
+        atomic::compiler_fence(Ordering::SeqCst);
+
+        (self.serial, self.channel, self.frame)
+    }
+}
@@ -0,0 +1,141 @@
+//! Interrupt tokens of Serial
+//!
+//! Listening for event returns token typed by UART, which is moved into its interrupt handler.
+//! Handle obtained from token accesses flags of this UART only, so handler cannot clear
+//! flags of another one:
+//!
+//! ```rust, ignore
+//! static TOKEN: Mutex<RefCell<Option<Usart1Interrupt>>> = Mutex::new(RefCell::new(None));
+//!
+//! let token = serial.listen(Event::Idle);
+//! interrupt::free(|cs| TOKEN.borrow(cs).replace(Some(token)));
+//!
+//! #[interrupt]
+//! fn USART1() {
+//!     interrupt::free(|cs| if let Some(token) = TOKEN.borrow(cs).borrow_mut().as_mut() {
+//!         let mut usart = token.handle();
+//!         if usart.is_pending(Event::Idle) {
+//!             usart.clear(Event::Idle);
+//!         }
+//!     });
+//! }
+//! ```
+
+use core::marker::PhantomData;
+use core::sync::atomic::{AtomicU32, Ordering};
+
+use cortex_m::interrupt::Nr;
+use cortex_m::peripheral::SCB;
+use cortex_m::peripheral::scb::VectActive;
+use stm32l4::stm32l4x5::usart1;
+
+use super::{CK, Error, Event, Instance, Serial, RX, TX, USART1, USART2, USART3};
+
+/// Identifier of the next token, incremented on each `listen`
+static NEXT_TOKEN_ID: AtomicU32 = AtomicU32::new(0);
+
+/// Token of UART interrupt, proving that its events are subscribed
+pub struct InterruptToken<UART> {
+    id: u32,
+    _serial: PhantomData<UART>,
+}
+
+/// Interrupt token of USART1
+pub type Usart1Interrupt = InterruptToken<USART1>;
+/// Interrupt token of USART2
+pub type Usart2Interrupt = InterruptToken<USART2>;
+/// Interrupt token of USART3
+pub type Usart3Interrupt = InterruptToken<USART3>;
+
+/// Access to UART flags within its interrupt handler
+pub struct InterruptHandle<'a, UART> {
+    _token: &'a mut InterruptToken<UART>,
+}
+
+impl<UART: Instance, T: TX, R: RX, C: CK> Serial<UART, T, R, C> {
+    /// Starts listening for interrupt `event`, returning token for interrupt handler.
+    ///
+    /// Each token gets its own [id](struct.InterruptToken.html#method.id), taken from
+    /// monotonic counter shared by all UARTs.
+    pub fn listen(&mut self, event: Event) -> InterruptToken<UART> {
+        self.serial.subscribe(event);
+
+        InterruptToken {
+            id: NEXT_TOKEN_ID.fetch_add(1, Ordering::Relaxed),
+            _serial: PhantomData,
+        }
+    }
+}
+
+impl<UART: Instance> InterruptToken<UART> {
+    /// Returns identifier of token, unique among tokens returned by `listen`.
+    pub fn id(&self) -> u32 {
+        self.id
+    }
+
+    /// Returns handle to UART flags, to be called within UART interrupt handler.
+    ///
+    /// # Pancis:
+    ///
+    /// In debug mode the function checks that interrupt of this UART is being handled.
+    pub fn handle(&mut self) -> InterruptHandle<'_, UART> {
+        debug_assert!(SCB::vect_active() == VectActive::Interrupt { irqn: UART::INTERRUPT.nr() });
+
+        InterruptHandle {
+            _token: self,
+        }
+    }
+}
+
+impl<'a, UART: Instance> InterruptHandle<'a, UART> {
+    fn registers(&self) -> &usart1::RegisterBlock {
+        unsafe { &*UART::register_block() }
+    }
+
+    /// Returns whether `event` is pending
+    pub fn is_pending(&self, event: Event) -> bool {
+        let isr = self.registers().isr.read();
+        match event {
+            Event::Rxne => isr.rxne().bit_is_set(),
+            Event::Txe => isr.txe().bit_is_set(),
+            Event::Idle => isr.idle().bit_is_set(),
+            Event::ReceiverTimeout => isr.rtof().bit_is_set(),
+            Event::WakeUp => isr.wuf().bit_is_set(),
+        }
+    }
+
+    /// Clears flag of `event`.
+    ///
+    /// `Rxne` and `Txe` are cleared by [read](#method.read) and [write](#method.write) only.
+    pub fn clear(&mut self, event: Event) {
+        let icr = &self.registers().icr;
+        match event {
+            Event::Rxne | Event::Txe => (),
+            Event::Idle => icr.write(|w| w.idlecf().set_bit()),
+            Event::ReceiverTimeout => icr.write(|w| w.rtocf().set_bit()),
+            Event::WakeUp => icr.write(|w| w.wucf().set_bit()),
+        }
+    }
+
+    /// Stops listening for `event`, e.g. `Txe` once there is nothing to send.
+    pub fn unlisten(&mut self, event: Event) {
+        let registers = self.registers();
+        match event {
+            Event::Rxne => registers.cr1.modify(|_, w| w.rxneie().clear_bit()),
+            Event::Txe => registers.cr1.modify(|_, w| w.txeie().clear_bit()),
+            Event::Idle => registers.cr1.modify(|_, w| w.idleie().clear_bit()),
+            Event::ReceiverTimeout => registers.cr1.modify(|_, w| w.rtoie().clear_bit()),
+            Event::WakeUp => registers.cr3.modify(|_, w| w.wufie().clear_bit()),
+        }
+    }
+
+    /// Reads received byte
+    pub fn read(&mut self) -> nb::Result<u8, Error> {
+        super::read_byte(self.registers())
+    }
+
+    /// Writes byte to transmit
+    pub fn write(&mut self, byte: u8) -> nb::Result<(), ()> {
+        super::write_byte(self.registers(), byte)
+    }
+}
@@ -0,0 +1,102 @@
+//! Wakeup from Stop mode on LPUART1
+//!
+//! LPUART1 has its own register block, so it isn't driven by `Serial`. Once configured
+//! by application, it can wake up the MCU the same way as USARTs do:
+//!
+//! ```rust, ignore
+//! lpuart.enable_wakeup(WakeupSource::StartBit, &clocks, &mut rcc.ccipr);
+//! lpuart.subscribe_wakeup();
+//! // Enter Stop 2 mode, reception wakes the MCU up
+//! cortex_m::asm::wfi();
+//! ```
+
+use stm32l4::stm32l4x5::LPUART1;
+
+use crate::rcc::{CCIPR, Clocks};
+use crate::rcc::clocking::HSI16_FREQ;
+
+use super::{rescale_brr, WakeupSource};
+
+/// Wakeup from Stop mode of low-power UART
+pub trait LpUartWakeup {
+    /// Enables wakeup from Stop mode on `source`.
+    ///
+    /// Interface is switched to HSI16 kernel clock, which is the one available in Stop mode,
+    /// and baud rate is rescaled from the current clock, taken from `clocks`.
+    /// Wakeup interrupt must be subscribed with [subscribe_wakeup](#tymethod.subscribe_wakeup)
+    /// to wake up the MCU.
+    ///
+    /// Interface is disabled for the time of configuration.
+    ///
+    /// # Pancis:
+    ///
+    /// In debug mode the function checks that address fits into 7-bit ADD field.
+    fn enable_wakeup(&self, source: WakeupSource, clocks: &Clocks, ccipr: &mut CCIPR);
+
+    /// Disables wakeup from Stop mode, kernel clock is left as HSI16.
+    fn disable_wakeup(&self);
+
+    /// Starts listening for wakeup interrupt
+    fn subscribe_wakeup(&self);
+
+    /// Stops listening for wakeup interrupt
+    fn unsubscribe_wakeup(&self);
+
+    /// Returns whether wakeup condition has been met.
+    fn is_wakeup(&self) -> bool;
+
+    /// Clears wakeup flag.
+    ///
+    /// Unless cleared, Interrupt handler is going to be continiously called.
+    fn clear_wakeup(&self);
+}
+
+impl LpUartWakeup for LPUART1 {
+    fn enable_wakeup(&self, source: WakeupSource, clocks: &Clocks, ccipr: &mut CCIPR) {
+        if let WakeupSource::Address(address) = source {
+            debug_assert!(address < 0x80);
+        }
+
+        let is_enabled = self.cr1.read().ue().bit_is_set();
+        self.cr1.modify(|_, w| w.ue().clear_bit());
+
+        // BRR of LPUART is 256 * clock / baud rate, without oversampling
+        let brr = rescale_brr(self.brr.read().bits(), false, clocks.lpuart1_clk().0, HSI16_FREQ);
+        ccipr.hsi16_enable();
+        ccipr.inner().modify(|_, w| unsafe { w.lpuart1sel().bits(0b10) });
+        self.brr.write(|w| unsafe { w.bits(brr) });
+
+        let wus = match source {
+            WakeupSource::Address(address) => {
+                self.cr2.modify(|_, w| w.addm7().set_bit().add().bits(address));
+                0b00
+            },
+            WakeupSource::StartBit => 0b10,
+            WakeupSource::RxNotEmpty => 0b11,
+        };
+        self.cr3.modify(|_, w| unsafe { w.wus().bits(wus) });
+        self.cr1.modify(|_, w| w.uesm().set_bit());
+
+        self.cr1.modify(|_, w| w.ue().bit(is_enabled));
+    }
+
+    fn disable_wakeup(&self) {
+        self.cr1.modify(|_, w| w.uesm().clear_bit());
+    }
+
+    fn subscribe_wakeup(&self) {
+        self.cr3.modify(|_, w| w.wufie().set_bit());
+    }
+
+    fn unsubscribe_wakeup(&self) {
+        self.cr3.modify(|_, w| w.wufie().clear_bit());
+    }
+
+    fn is_wakeup(&self) -> bool {
+        self.isr.read().wuf().bit_is_set()
+    }
+
+    fn clear_wakeup(&self) {
+        self.icr.write(|w| w.wucf().set_bit());
+    }
+}
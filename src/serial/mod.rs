@@ -4,25 +4,49 @@ use core::ptr;
 use core::ops;
 
 use embedded_hal::serial;
+use embedded_hal::timer::CountDown;
 pub use stm32l4::stm32l4x5::{USART1, USART2, USART3};
+use stm32l4::stm32l4x5::{usart1, Interrupt};
 
-use crate::rcc::{APB1, APB2, Clocks};
+use crate::common::{block_timeout, ConfigError, TimedOut};
+use crate::dma::{dma1, DmaChannel};
+use crate::rcc::{APB1, APB2, CCIPR, Clocks};
+use crate::rcc::clocking::HSI16_FREQ;
+use crate::selftest;
 use crate::time::{Hertz};
 //We should define here only common pins
-use crate::gpio::{
+use crate::gpio::af::{self, Signal};
+use crate::gpio::{AltFunPin, 
     AF7,
-    //USART1: TX, RX, CK
-    PA9, PA10, PA8,
-    PB6, PB7, PB5,
-    //USART2: TX, RX, CK
-    PA2, PA3, PA4,
-    //USART3: TX, RX, CK
-    PB10, PB11, PB12,
+    //USART1: TX, RX, CK, DE
+    PA9, PA10, PA8, PA12,
+    PB6, PB7, PB5, PB3,
+    //USART2: TX, RX, CK, DE
+    PA2, PA3, PA4, PA1,
+    //USART3: TX, RX, CK, DE
+    PB10, PB11, PB12, PB1, PB14,
     PC10, PC11, PC12,
 };
 
 pub mod config;
 pub use self::config::Config;
+pub mod modbus;
+pub mod dmx;
+pub use self::dmx::Dmx;
+pub mod rx_dma;
+pub use self::rx_dma::{RxDma, RingBuffer};
+pub mod buffered;
+pub use self::buffered::{BufferedSerial, Shared};
+pub mod split;
+pub use self::split::{Rx, Tx};
+pub mod console;
+pub use self::console::Console;
+pub mod interrupt;
+pub mod lpuart;
+pub use self::lpuart::LpUartWakeup;
+pub use self::interrupt::{InterruptHandle, InterruptToken, Usart1Interrupt, Usart2Interrupt, Usart3Interrupt};
+#[cfg(feature = "at")]
+pub mod at;
 
 /// Interrupt event
 #[derive(PartialEq, Eq, Debug)]
@@ -32,7 +56,11 @@ pub enum Event {
     /// New data can be sent
     Txe,
     /// The line has gone idle
-    Idle
+    Idle,
+    /// No new data has been received within receiver timeout
+    ReceiverTimeout,
+    /// Wakeup from Stop mode condition is met
+    WakeUp,
 }
 
 /// Serial error
@@ -46,6 +74,14 @@ pub enum Error {
     Overrun,
     /// Parity check error
     Parity,
+    /// Transfer hasn't completed within timeout
+    Timeout,
+}
+
+impl From<TimedOut> for Error {
+    fn from(_: TimedOut) -> Self {
+        Error::Timeout
+    }
 }
 
 impl Into<nb::Error<Self>> for Error {
@@ -55,6 +91,40 @@ impl Into<nb::Error<Self>> for Error {
     }
 }
 
+///Calculates BRR value to derive `baud` from Serial's `clock`.
+fn calc_brr(clock: u32, baud: u32) -> Result<u32, ConfigError> {
+    //With oversampling by 16 USARTDIV must be at least 16, and fit into BRR
+    //See Ch. 40.5.4
+    match clock / baud {
+        0..=15 => return Err(ConfigError::FrequencyTooHigh),
+        0x1_0000..=u32::MAX => return Err(ConfigError::FrequencyTooLow),
+        _ => (),
+    }
+
+    //Took from stm32f4 HAL
+    let div = (clock * 25) / (4 * baud);
+    let mantissa = div / 100;
+    let fraction = ((div - mantissa * 100) * 16 + 50) / 100;
+
+    Ok(mantissa << 4 | fraction)
+}
+
+///Rescales `brr`, programmed for Serial's clock `from`, to keep baud rate with clock `to`.
+///
+///With oversampling by 8 (`over8`), BRR[2:0] holds USARTDIV[3:1], while BRR[3] must be kept clear.
+fn rescale_brr(brr: u32, over8: bool, from: u32, to: u32) -> u32 {
+    let div = match over8 {
+        true => (brr & !0xF) | (brr & 0x7) << 1,
+        false => brr,
+    } as u64;
+    let div = ((div * to as u64 + from as u64 / 2) / from as u64) as u32;
+
+    match over8 {
+        true => (div & !0xF) | (div & 0xF) >> 1,
+        false => div,
+    }
+}
+
 ///UxART pin definition
 pub trait Pin {
     ///UART index
@@ -88,6 +158,11 @@ pub trait RX: Pin {}
 ///Outputs the transmitter data clock for synchronous transmission
 pub trait CK: Pin {}
 
+///Driver enable pin
+///
+///Activates external transceiver (e.g. RS-485) while transmitting
+pub trait DE: Pin {}
+
 //impl it only for CK
 //other pins must be always used
 impl CK for DummyPin {}
@@ -125,6 +200,12 @@ impl_pins_trait!(1 => {
     PINS: [PA8, PB5,]
 });
 
+impl_pins_trait!(1 => {
+    TRAIT: DE,
+    AF: AF7,
+    PINS: [PA12, PB3,]
+});
+
 impl_pins_trait!(2 => {
     TRAIT: TX,
     AF: AF7,
@@ -141,6 +222,12 @@ impl_pins_trait!(2 => {
     PINS: [PA4,]
 });
 
+impl_pins_trait!(2 => {
+    TRAIT: DE,
+    AF: AF7,
+    PINS: [PA1,]
+});
+
 impl_pins_trait!(3 => {
     TRAIT: TX,
     AF: AF7,
@@ -156,6 +243,34 @@ impl_pins_trait!(3 => {
     AF: AF7,
     PINS: [PB12, PC12,]
 });
+impl_pins_trait!(3 => {
+    TRAIT: DE,
+    AF: AF7,
+    PINS: [PB1, PB14,]
+});
+
+///Condition to wake up MCU from Stop mode
+#[derive(Copy, Clone, PartialEq, Eq, Debug)]
+pub enum WakeupSource {
+    ///Received byte matches 7-bit address
+    Address(u8),
+    ///Start bit is detected
+    StartBit,
+    ///Byte is received
+    RxNotEmpty,
+}
+
+///Bit of 9-bit character, which marks it as address in multiprocessor mute mode
+pub const ADDRESS_MARK: u16 = 0x100;
+
+///Polarity of driver enable signal
+#[derive(Copy, Clone, PartialEq, Eq, Debug)]
+pub enum DePolarity {
+    ///DE is active high
+    High,
+    ///DE is active low
+    Low,
+}
 
 ///Describes raw UxART from device crate
 pub trait RawSerial where Self: Sized {
@@ -163,6 +278,14 @@ pub trait RawSerial where Self: Sized {
     const IDX: u8;
     ///Type of APB used by Serial interface.
     type APB;
+    ///DMA channel serving receiver.
+    type RxChannel: DmaChannel;
+    ///Request of receiver on its DMA channel (CSELR value).
+    const RX_DMA_REQUEST: u8;
+    ///DMA channel serving transmitter.
+    type TxChannel: DmaChannel;
+    ///Request of transmitter on its DMA channel (CSELR value).
+    const TX_DMA_REQUEST: u8;
 
     ///Access register block
     fn registers(&self) -> &stm32l4::stm32l4x5::usart1::RegisterBlock;
@@ -202,6 +325,16 @@ pub trait RawSerial where Self: Sized {
         &self.registers().brr
     }
 
+    ///Retrieves reference to RTOR registers
+    fn rtor(&self) -> &stm32l4::stm32l4x5::usart1::RTOR {
+        &self.registers().rtor
+    }
+
+    ///Retrieves reference to ICR registers
+    fn icr(&self) -> &stm32l4::stm32l4x5::usart1::ICR {
+        &self.registers().icr
+    }
+
     ///Retrieves clock frequency for interface.
     fn get_clock_freq(clocks: &Clocks) -> Hertz;
 
@@ -211,12 +344,17 @@ pub trait RawSerial where Self: Sized {
     ///Turns off interface by setting corresponding bits.
     fn disable(apb: &mut Self::APB);
 
+    ///Selects HSI16 as kernel clock of interface.
+    fn select_hsi16(ccipr: &mut CCIPR);
+
     /// Starts listening for an interrupt event
     fn subscribe(&self, event: Event) {
         match event {
             Event::Rxne => self.cr1().modify(|_, w| w.rxneie().set_bit()),
             Event::Txe => self.cr1().modify(|_, w| w.txeie().set_bit()),
             Event::Idle => self.cr1().modify(|_, w| w.idleie().set_bit()),
+            Event::ReceiverTimeout => self.cr1().modify(|_, w| w.rtoie().set_bit()),
+            Event::WakeUp => self.cr3().modify(|_, w| w.wufie().set_bit()),
         }
     }
 
@@ -226,17 +364,181 @@ pub trait RawSerial where Self: Sized {
             Event::Rxne => self.cr1().modify(|_, w| w.rxneie().clear_bit()),
             Event::Txe => self.cr1().modify(|_, w| w.txeie().clear_bit()),
             Event::Idle => self.cr1().modify(|_, w| w.idleie().clear_bit()),
+            Event::ReceiverTimeout => self.cr1().modify(|_, w| w.rtoie().clear_bit()),
+            Event::WakeUp => self.cr3().modify(|_, w| w.wufie().clear_bit()),
+        }
+    }
+
+    /// Sets receiver timeout in bit periods, `None` disables it.
+    ///
+    /// Timeout is counted from the end of the last received character,
+    /// so 3.5 characters of 11 bits would be `Some(39)`. Timeout is limited by 24-bit RTO field,
+    /// longer ones are clamped.
+    ///
+    /// See Reference Ch. 40.5.13
+    fn set_receiver_timeout(&self, bits: Option<u32>) {
+        const RTO_MAX: u32 = 0xFF_FFFF;
+
+        match bits {
+            Some(bits) => {
+                self.rtor().modify(|_, w| w.rto().bits(bits.min(RTO_MAX)));
+                self.cr2().modify(|_, w| w.rtoen().set_bit());
+            },
+            None => self.cr2().modify(|_, w| w.rtoen().clear_bit()),
+        }
+    }
+
+    /// Enables hardware driver enable output on DE pin.
+    ///
+    /// `assertion` and `deassertion` times are expressed in sample time units
+    /// (1/16 of bit period) and limited by 31.
+    ///
+    /// Interface is disabled for the time of configuration.
+    ///
+    /// See Reference Ch. 40.5.18
+    fn set_driver_enable(&self, polarity: DePolarity, assertion: u8, deassertion: u8) {
+        debug_assert!(assertion < 32);
+        debug_assert!(deassertion < 32);
+
+        let is_enabled = self.cr1().read().ue().bit_is_set();
+        self.cr1().modify(|_, w| w.ue().clear_bit());
+
+        self.cr1().modify(|_, w| w.deat().bits(assertion).dedt().bits(deassertion));
+        self.cr3().modify(|_, w| w.dem().set_bit().dep().bit(polarity == DePolarity::Low));
+
+        self.cr1().modify(|_, w| w.ue().bit(is_enabled));
+    }
+
+    /// Switches between 9 and 8 data bits per character.
+    ///
+    /// 9-bit characters are read and written as `u16` with `read_word` and `write_word`
+    /// of [Serial](struct.Serial.html). With parity enabled, MSB of character carries parity bit.
+    ///
+    /// Interface is disabled for the time of configuration.
+    fn set_nine_bits(&self, is_on: bool) {
+        let is_enabled = self.cr1().read().ue().bit_is_set();
+        self.cr1().modify(|_, w| w.ue().clear_bit());
+
+        self.cr1().modify(|_, w| w.m1().clear_bit().m0().bit(is_on));
+
+        self.cr1().modify(|_, w| w.ue().bit(is_enabled));
+    }
+
+    /// Enables multiprocessor mute mode with wakeup on address mark.
+    ///
+    /// Once muted by `request_mute`, receiver ignores characters until address mark,
+    /// i.e. character with MSB set (`ADDRESS_MARK | address` with 9 bits), matches 7-bit `address`.
+    /// Matching address mark is received, while non-matching one mutes receiver again.
+    ///
+    /// Interface is disabled for the time of configuration.
+    fn enable_mute_mode(&self, address: u8) {
+        debug_assert!(address < 0x80);
+
+        let is_enabled = self.cr1().read().ue().bit_is_set();
+        self.cr1().modify(|_, w| w.ue().clear_bit());
+
+        self.cr2().modify(|_, w| w.addm7().set_bit().add().bits(address));
+        self.cr1().modify(|_, w| w.wake().set_bit().mme().set_bit());
+
+        self.cr1().modify(|_, w| w.ue().bit(is_enabled));
+    }
+
+    /// Disables multiprocessor mute mode, receiver is no longer muted.
+    fn disable_mute_mode(&self) {
+        self.cr1().modify(|_, w| w.mme().clear_bit());
+    }
+
+    /// Mutes receiver until matching address mark, e.g. once message for other node starts.
+    fn request_mute(&self) {
+        self.registers().rqr.write(|w| w.mmrq().set_bit());
+    }
+
+    /// Returns whether receiver is muted.
+    fn is_muted(&self) -> bool {
+        self.isr().read().rwu().bit_is_set()
+    }
+
+    /// Enables wakeup from Stop mode on `source`.
+    ///
+    /// Interface is switched to HSI16 kernel clock, which is the one available in Stop mode,
+    /// and baud rate is rescaled from the current clock, taken from `clocks`.
+    /// [WakeUp](enum.Event.html) event must be subscribed to wake up the MCU.
+    ///
+    /// Interface is disabled for the time of configuration.
+    ///
+    /// See Reference Ch. 40.5.20
+    ///
+    /// # Pancis:
+    ///
+    /// In debug mode the function checks that address fits into 7-bit ADD field.
+    fn enable_wakeup(&self, source: WakeupSource, clocks: &Clocks, ccipr: &mut CCIPR) {
+        if let WakeupSource::Address(address) = source {
+            debug_assert!(address < 0x80);
         }
+
+        let is_enabled = self.cr1().read().ue().bit_is_set();
+        self.cr1().modify(|_, w| w.ue().clear_bit());
+
+        let clock = Self::get_clock_freq(clocks).0;
+        let brr = rescale_brr(self.brr().read().bits(), self.cr1().read().over8().bit_is_set(), clock, HSI16_FREQ);
+        Self::select_hsi16(ccipr);
+        self.brr().write(|w| unsafe { w.bits(brr) });
+
+        let wus = match source {
+            WakeupSource::Address(address) => {
+                self.cr2().modify(|_, w| w.addm7().set_bit().add().bits(address));
+                0b00
+            },
+            WakeupSource::StartBit => 0b10,
+            WakeupSource::RxNotEmpty => 0b11,
+        };
+        self.cr3().modify(|_, w| unsafe { w.wus().bits(wus) });
+        self.cr1().modify(|_, w| w.uesm().set_bit());
+
+        self.cr1().modify(|_, w| w.ue().bit(is_enabled));
+    }
+
+    /// Disables wakeup from Stop mode, kernel clock is left as HSI16.
+    fn disable_wakeup(&self) {
+        self.cr1().modify(|_, w| w.uesm().clear_bit());
+    }
+
+    /// Returns whether wakeup condition has been met.
+    fn is_wakeup(&self) -> bool {
+        self.isr().read().wuf().bit_is_set()
+    }
+
+    /// Clears wakeup flag.
+    ///
+    /// Unless cleared, Interrupt handler is going to be continiously called.
+    fn clear_wakeup(&self) {
+        self.icr().write(|w| w.wucf().set_bit());
+    }
+
+    /// Returns whether receiver timeout has been reached.
+    fn is_receiver_timeout(&self) -> bool {
+        self.isr().read().rtof().bit_is_set()
+    }
+
+    /// Clears receiver timeout flag.
+    ///
+    /// Unless cleared, Interrupt handler is going to be continiously called.
+    fn clear_receiver_timeout(&self) {
+        self.icr().write(|w| w.rtocf().set_bit());
     }
 }
 
 impl RawSerial for USART1 {
     const IDX: u8 = 1;
     type APB = APB2;
+    type RxChannel = dma1::C5;
+    const RX_DMA_REQUEST: u8 = 0b0010;
+    type TxChannel = dma1::C4;
+    const TX_DMA_REQUEST: u8 = 0b0010;
 
     #[inline]
     fn get_clock_freq(clocks: &Clocks) -> Hertz {
-        clocks.pclk2()
+        clocks.usart1_clk()
     }
 
     fn registers(&self) -> &stm32l4::stm32l4x5::usart1::RegisterBlock {
@@ -253,15 +555,24 @@ impl RawSerial for USART1 {
     fn disable(apb: &mut Self::APB) {
         apb.enr().modify(|_, w| w.usart1en().clear_bit());
     }
+
+    fn select_hsi16(ccipr: &mut CCIPR) {
+        ccipr.hsi16_enable();
+        ccipr.inner().modify(|_, w| unsafe { w.usart1sel().bits(0b10) });
+    }
 }
 
 impl RawSerial for USART2 {
     const IDX: u8 = 2;
     type APB = APB1;
+    type RxChannel = dma1::C6;
+    const RX_DMA_REQUEST: u8 = 0b0010;
+    type TxChannel = dma1::C7;
+    const TX_DMA_REQUEST: u8 = 0b0010;
 
     #[inline]
     fn get_clock_freq(clocks: &Clocks) -> Hertz {
-        clocks.pclk1()
+        clocks.usart2_clk()
     }
 
     fn registers(&self) -> &stm32l4::stm32l4x5::usart1::RegisterBlock {
@@ -277,15 +588,24 @@ impl RawSerial for USART2 {
     fn disable(apb: &mut Self::APB) {
         apb.enr1().modify(|_, w| w.usart2en().clear_bit());
     }
+
+    fn select_hsi16(ccipr: &mut CCIPR) {
+        ccipr.hsi16_enable();
+        ccipr.inner().modify(|_, w| unsafe { w.usart2sel().bits(0b10) });
+    }
 }
 
 impl RawSerial for USART3 {
     const IDX: u8 = 3;
     type APB = APB1;
+    type RxChannel = dma1::C3;
+    const RX_DMA_REQUEST: u8 = 0b0010;
+    type TxChannel = dma1::C2;
+    const TX_DMA_REQUEST: u8 = 0b0010;
 
     #[inline]
     fn get_clock_freq(clocks: &Clocks) -> Hertz {
-        clocks.pclk1()
+        clocks.usart3_clk()
     }
 
     fn registers(&self) -> &stm32l4::stm32l4x5::usart1::RegisterBlock {
@@ -301,15 +621,57 @@ impl RawSerial for USART3 {
     fn disable(apb: &mut Self::APB) {
         apb.enr1().modify(|_, w| w.usart3en().clear_bit());
     }
+
+    fn select_hsi16(ccipr: &mut CCIPR) {
+        ccipr.hsi16_enable();
+        ccipr.inner().modify(|_, w| unsafe { w.usart3sel().bits(0b10) });
+    }
+}
+
+mod sealed {
+    pub trait Sealed {}
+}
+
+///UxART peripheral supported by this HAL
+///
+///Allows code to be written once for any of USART1, USART2 and USART3,
+///e.g. `fn log<UART: Instance, T: TX, R: RX, C: CK>(serial: &mut Serial<UART, T, R, C>)`.
+///
+///Note: it is sealed and cannot be implemented outside of HAL.
+pub trait Instance: RawSerial + sealed::Sealed + Send {
+    ///Interrupt line of Serial
+    const INTERRUPT: Interrupt;
+
+    ///Returns pointer to register block, which is shared by halves of Serial.
+    fn register_block() -> *const stm32l4::stm32l4x5::usart1::RegisterBlock;
+}
+
+macro_rules! impl_instance {
+    ($($USARTx:ident,)+) => {
+        $(
+            impl sealed::Sealed for $USARTx {}
+
+            impl Instance for $USARTx {
+                const INTERRUPT: Interrupt = Interrupt::$USARTx;
+
+                #[inline]
+                fn register_block() -> *const stm32l4::stm32l4x5::usart1::RegisterBlock {
+                    $USARTx::ptr()
+                }
+            }
+        )+
+    }
 }
 
+impl_instance!(USART1, USART2, USART3,);
+
 ///Serial interface
 pub struct Serial<S, TX, RX, CK> {
     pub serial: S,
     pins: (TX, RX, CK)
 }
 
-impl<UART: RawSerial, T: TX, R: RX, C: CK> ops::Deref for Serial<UART, T, R, C> {
+impl<UART: Instance, T: TX, R: RX, C: CK> ops::Deref for Serial<UART, T, R, C> {
     type Target = UART;
 
     fn deref(&self) -> &Self::Target {
@@ -317,15 +679,16 @@ impl<UART: RawSerial, T: TX, R: RX, C: CK> ops::Deref for Serial<UART, T, R, C>
     }
 }
 
-impl<UART: RawSerial, T: TX, R: RX> Serial<UART, T, R, DummyPin> {
+impl<UART: Instance, T: TX, R: RX> Serial<UART, T, R, DummyPin> {
     #[inline]
     ///Initializes Serial with dummy CK
-    pub fn with_dummy<CFN: Config>(serial: UART, pins: (T, R), config: CFN, clocks: &Clocks, apb: &mut UART::APB) -> Self {
+    pub fn with_dummy<CFN: Config>(serial: UART, pins: (T, R), config: CFN, clocks: &Clocks, apb: &mut UART::APB) -> Result<Self, ConfigError>
+        where T: AltFunPin, R: AltFunPin {
         Self::new(serial, (pins.0, pins.1, DummyPin), config, clocks, apb)
     }
 }
 
-impl<UART: RawSerial, T: TX, R: RX, C: CK> Serial<UART, T, R, C> {
+impl<UART: Instance, T: TX, R: RX, C: CK> Serial<UART, T, R, C> {
     /// Creates new instance of serial interface
     ///
     /// # Arguments:
@@ -337,26 +700,30 @@ impl<UART: RawSerial, T: TX, R: RX, C: CK> Serial<UART, T, R, C> {
     ///
     /// It takes ownership of raw Serial object and corresponding PINs.
     ///
+    /// Returns error if baud rate cannot be derived from Serial's clock.
+    ///
     /// # Pancis:
     ///
-    /// In debug mode the function checks if index of each PIN corresponds to Serial's index.
-    pub fn new<CFN: Config>(serial: UART, pins: (T, R, C), _: CFN, clocks: &Clocks, apb: &mut UART::APB) -> Self {
+    /// In debug mode the function checks if index of each PIN corresponds to Serial's index,
+    /// and that TX/RX PINs carry USART signals according to [AF table](../gpio/af/index.html).
+    pub fn new<CFN: Config>(serial: UART, pins: (T, R, C), _: CFN, clocks: &Clocks, apb: &mut UART::APB) -> Result<Self, ConfigError>
+        where T: AltFunPin, R: AltFunPin {
         //TODO: Baurd can be auto-detected, should be configurable?
         //      See Ch. 40.5.6
         debug_assert!(T::does_belong(UART::IDX));
         debug_assert!(R::does_belong(UART::IDX));
         debug_assert!(C::does_belong(UART::IDX));
+        debug_assert!(af::has_af(&pins.0, Signal::UsartTx(UART::IDX)));
+        debug_assert!(af::has_af(&pins.1, Signal::UsartRx(UART::IDX)));
+
+        let brr = calc_brr(UART::get_clock_freq(clocks).0, CFN::BAUD)?;
 
         UART::enable(apb);
 
         //TODO: DMA requires to enable dmat bit
         //      Should configurable
 
-        //Took from stm32f4 HAL
-        let div = (UART::get_clock_freq(clocks).0 * 25) / (4 * CFN::BAUD);
-        let mantissa = div / 100;
-        let fraction = ((div - mantissa * 100) * 16 + 50) / 100;
-        serial.brr().write(|w| unsafe { w.bits(mantissa << 4 | fraction) });
+        serial.brr().write(|w| unsafe { w.bits(brr) });
 
         serial.cr2().reset();
         serial.cr3().reset();
@@ -364,15 +731,17 @@ impl<UART: RawSerial, T: TX, R: RX, C: CK> Serial<UART, T, R, C> {
         //Enables interface(UE), and receiver(RE) with transmitter(TE)
         serial.cr1().write(|w| w.ue().set_bit().re().set_bit().te().set_bit());
 
-        Self {
+        Ok(Self {
             serial,
             pins
-        }
+        })
     }
 
     ///Re-creates Serial instance from its components.
     ///
-    ///Note: it is up to user to ensure that Serial has been created using [new](#method.new) previously
+    ///# Safety
+    ///
+    ///It is up to user to ensure that Serial has been created using [new](#method.new) previously
     pub unsafe fn from_raw(serial: UART, pins: (T, R, C)) -> Self {
         Self {
             serial,
@@ -384,56 +753,239 @@ impl<UART: RawSerial, T: TX, R: RX, C: CK> Serial<UART, T, R, C> {
     pub fn into_raw(self) -> (UART, (T, R, C)) {
         (self.serial, self.pins)
     }
+
+    ///Disables interface and releases Serial and PINS
+    pub fn free(self, apb: &mut UART::APB) -> (UART, (T, R, C)) {
+        self.serial.cr1().reset();
+        UART::disable(apb);
+        (self.serial, self.pins)
+    }
+
+    /// Makes receiver to be served by DMA `channel`.
+    pub fn rx_dma(&mut self, mut channel: UART::RxChannel) -> RxDma<UART::RxChannel> {
+        channel.stop();
+        channel.set_request(UART::RX_DMA_REQUEST);
+        // NOTE(unsafe) data register of owned Serial
+        unsafe { channel.set_peripheral_address(self.serial.rdr() as *const _ as u32, false) };
+
+        self.serial.cr3().modify(|_, w| w.dmar().set_bit());
+
+        RxDma::new(channel)
+    }
+
+    /// Reads bytes into `buffer` until the line goes idle for receiver timeout.
+    ///
+    /// Blocks until at least one byte is received, then returns number of read bytes once
+    /// receiver timeout is reached or `buffer` is full.
+    ///
+    /// Receiver timeout must be configured using `set_receiver_timeout`.
+    pub fn read_until_idle(&mut self, buffer: &mut [u8]) -> Result<usize, Error> {
+        debug_assert!(self.serial.cr2().read().rtoen().bit_is_set());
+
+        self.serial.clear_receiver_timeout();
+
+        let mut len = 0;
+        while len < buffer.len() {
+            match serial::Read::read(self) {
+                Ok(byte) => {
+                    buffer[len] = byte;
+                    len += 1;
+                },
+                Err(nb::Error::WouldBlock) => if len > 0 && self.serial.is_receiver_timeout() {
+                    break;
+                },
+                Err(nb::Error::Other(error)) => return Err(error),
+            }
+        }
+
+        self.serial.clear_receiver_timeout();
+
+        Ok(len)
+    }
+
+    /// Reads frame into `buffer` until the line goes idle for receiver timeout.
+    ///
+    /// Unlike [read_until_idle](#method.read_until_idle), frame is always read to its end:
+    /// bytes that don't fit into `buffer` are discarded and `None` is returned.
+    fn read_frame(&mut self, buffer: &mut [u8]) -> Result<Option<usize>, Error> {
+        debug_assert!(self.serial.cr2().read().rtoen().bit_is_set());
+
+        self.serial.clear_receiver_timeout();
+
+        let mut len = 0;
+        let mut is_truncated = false;
+        loop {
+            match serial::Read::read(self) {
+                Ok(byte) => match buffer.get_mut(len) {
+                    Some(slot) => {
+                        *slot = byte;
+                        len += 1;
+                    },
+                    None => is_truncated = true,
+                },
+                Err(nb::Error::WouldBlock) => if len > 0 && self.serial.is_receiver_timeout() {
+                    break;
+                },
+                Err(nb::Error::Other(error)) => return Err(error),
+            }
+        }
+
+        self.serial.clear_receiver_timeout();
+
+        match is_truncated {
+            true => Ok(None),
+            false => Ok(Some(len)),
+        }
+    }
+
+    /// Reads byte, or returns [Timeout](enum.Error.html) once started `timer` expires.
+    pub fn read_with_timeout<TIM: CountDown>(&mut self, timer: &mut TIM) -> Result<u8, Error> {
+        let registers = self.serial.registers();
+        block_timeout(timer, || read_byte(registers))
+    }
+
+    /// Writes `bytes` and waits for transmission to complete,
+    /// or returns [Timeout](enum.Error.html) once started `timer` expires, e.g. with CTS held.
+    pub fn write_all_with_timeout<TIM: CountDown>(&mut self, bytes: &[u8], timer: &mut TIM) -> Result<(), Error> {
+        let registers = self.serial.registers();
+        //Transmitter reports no errors, so only waiting is left
+        for byte in bytes {
+            block_timeout(timer, || write_byte(registers, *byte).map_err(|_| nb::Error::<Error>::WouldBlock))?;
+        }
+        block_timeout(timer, || flush(registers).map_err(|_| nb::Error::<Error>::WouldBlock))
+    }
+
+    /// Reads character of up to 9 bits, see [set_nine_bits](trait.RawSerial.html#method.set_nine_bits).
+    pub fn read_word(&mut self) -> nb::Result<u16, Error> {
+        read_word(self.serial.registers())
+    }
+
+    /// Writes character of up to 9 bits, e.g. `ADDRESS_MARK | address` to wake up muted receivers.
+    pub fn write_word(&mut self, word: u16) -> nb::Result<(), ()> {
+        write_word(self.serial.registers(), word)
+    }
+
+    /// Runs loopback [self-test](../selftest/index.html), transmitting `PATTERN`.
+    ///
+    /// Interface is switched to half-duplex mode, where TX is internally connected to receiver,
+    /// so TX pin is driven during test while RX pin is ignored.
+    pub fn selftest(&mut self) -> Result<(), selftest::Fault> {
+        let registers = self.serial.registers();
+        let cr1 = registers.cr1.read().bits();
+
+        //HDSEL can be written only while interface is disabled
+        registers.cr1.modify(|_, w| w.ue().clear_bit());
+        registers.cr3.modify(|_, w| w.hdsel().set_bit());
+        registers.cr1.write(|w| unsafe { w.bits(cr1) });
+        registers.rqr.write(|w| w.rxfrq().set_bit());
+
+        let result = selftest::PATTERN.iter().try_for_each(|&sent| {
+            selftest::poll(|| write_byte(registers, sent))?;
+            let received = selftest::poll(|| read_byte(registers))?;
+            selftest::compare(sent, received)
+        });
+
+        registers.cr1.modify(|_, w| w.ue().clear_bit());
+        registers.cr3.modify(|_, w| w.hdsel().clear_bit());
+        registers.icr.write(|w| w.pecf().set_bit().fecf().set_bit().ncf().set_bit().orecf().set_bit());
+        registers.cr1.write(|w| unsafe { w.bits(cr1) });
+
+        result
+    }
+}
+
+///Reads received byte, reporting receiver errors first.
+fn read_byte(registers: &usart1::RegisterBlock) -> nb::Result<u8, Error> {
+    read_word(registers).map(|word| word as u8)
 }
 
-impl<UART: RawSerial, T: TX, R: RX, C: CK> serial::Read<u8> for Serial<UART, T, R, C> {
+///Reads received character of up to 9 bits, reporting receiver errors first.
+fn read_word(registers: &usart1::RegisterBlock) -> nb::Result<u16, Error> {
+    let isr = registers.isr.read();
+
+    Err(if isr.pe().bit_is_set() {
+        Error::Parity.into()
+    } else if isr.fe().bit_is_set() {
+        Error::Framing.into()
+    } else if isr.nf().bit_is_set() {
+        Error::Noise.into()
+    } else if isr.ore().bit_is_set() {
+        Error::Overrun.into()
+    } else if isr.rxne().bit_is_set() {
+        return Ok(unsafe {
+            ptr::read_volatile(&registers.rdr as *const _ as *const u16)
+        });
+    } else {
+        nb::Error::WouldBlock
+    })
+}
+
+///Waits for transmission to complete.
+fn flush(registers: &usart1::RegisterBlock) -> nb::Result<(), ()> {
+    if registers.isr.read().tc().bit_is_set() {
+        Ok(())
+    } else {
+        Err(nb::Error::WouldBlock)
+    }
+}
+
+///Puts byte into transmit data register once it is empty.
+pub(crate) fn write_byte(registers: &usart1::RegisterBlock, byte: u8) -> nb::Result<(), ()> {
+    write_word(registers, byte as u16)
+}
+
+///Puts character of up to 9 bits into transmit data register once it is empty.
+fn write_word(registers: &usart1::RegisterBlock, word: u16) -> nb::Result<(), ()> {
+    if registers.isr.read().txe().bit_is_set() {
+        unsafe {
+            ptr::write_volatile(ptr::addr_of!(registers.tdr) as *mut u16, word);
+        }
+        Ok(())
+    } else {
+        Err(nb::Error::WouldBlock)
+    }
+}
+
+impl<UART: Instance, T: TX, R: RX, C: CK> serial::Read<u8> for Serial<UART, T, R, C> {
     type Error = Error;
 
     fn read(&mut self) -> nb::Result<u8, Error> {
-        let isr = self.serial.isr().read();
-
-        Err(if isr.pe().bit_is_set() {
-            Error::Parity.into()
-        } else if isr.fe().bit_is_set() {
-            Error::Framing.into()
-        } else if isr.nf().bit_is_set() {
-            Error::Noise.into()
-        } else if isr.ore().bit_is_set() {
-            Error::Overrun.into()
-        } else if isr.rxne().bit_is_set() {
-            return Ok(unsafe {
-                ptr::read_volatile(self.serial.rdr() as *const _ as *const u8)
-            });
-        } else {
-            nb::Error::WouldBlock
-        })
+        read_byte(self.serial.registers())
     }
 }
 
-impl<UART: RawSerial, T: TX, R: RX, C: CK> serial::Write<u8> for Serial<UART, T, R, C> {
+impl<UART: Instance, T: TX, R: RX, C: CK> serial::Write<u8> for Serial<UART, T, R, C> {
     //TODO: Error handling for advanced use cases?
     type Error = ();
 
     fn flush(&mut self) -> nb::Result<(), Self::Error> {
-        let isr = self.serial.isr().read();
-
-        if isr.tc().bit_is_set() {
-            Ok(())
-        } else {
-            Err(nb::Error::WouldBlock)
-        }
+        flush(self.serial.registers())
     }
 
     fn write(&mut self, byte: u8) -> nb::Result<(), ()> {
-        let isr = self.serial.isr().read();
+        write_byte(self.serial.registers(), byte)
+    }
+}
 
-        if isr.txe().bit_is_set() {
-            unsafe {
-                ptr::write_volatile(self.serial.tdr() as *const _ as *mut u8, byte);
-            }
-            Ok(())
-        } else {
-            Err(nb::Error::WouldBlock)
-        }
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn calculate_brr() {
+        assert_eq!(calc_brr(80_000_000, 115_200), Ok(694));
+        assert_eq!(calc_brr(4_000_000, 9_600), Ok(417));
+        assert_eq!(calc_brr(16_000_000, 1_000_000), Ok(16));
+        assert_eq!(calc_brr(4_000_000, 1_000_000), Err(ConfigError::FrequencyTooHigh));
+        assert_eq!(calc_brr(80_000_000, 1_200), Err(ConfigError::FrequencyTooLow));
+    }
+
+    #[test]
+    fn rescale_brr_to_hsi16() {
+        // 115200 Bd at 80 MHz, USARTDIV = 694
+        assert_eq!(rescale_brr(694, false, 80_000_000, HSI16_FREQ), 139);
+        // Same USARTDIV with oversampling by 8: BRR[2:0] = USARTDIV[3:1]
+        assert_eq!(rescale_brr(0x2B0 | 0x3, true, 80_000_000, HSI16_FREQ), 0x80 | 0x5);
+        assert_eq!(rescale_brr(417, false, 4_000_000, 4_000_000), 417);
     }
 }
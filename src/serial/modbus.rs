@@ -0,0 +1,140 @@
+//! Modbus RTU transport
+//!
+//! Frames (ADUs) are delimited by 3.5 characters of silence, which is detected using receiver
+//! timeout. Transmission is done through RS-485 transceiver controlled by hardware DE signal.
+//!
+//! ```rust, ignore
+//! let serial = Serial::with_dummy(USART2, (tx, rx), Cfg, &clocks, &mut rcc.apb1)?;
+//! let mut modbus = Modbus::new(serial, de, crc);
+//!
+//! modbus.send(&[0x01, 0x03, 0x00, 0x00, 0x00, 0x01])?;
+//! let len = modbus.receive(&mut buffer)?;
+//! ```
+
+use embedded_hal::serial::Write;
+use nb::block;
+
+use crate::crc::{CRC, PolySize, ReverseInput};
+
+use super::{Instance, Serial, DePolarity, TX, RX, CK, DE};
+
+/// Modbus CRC16 polynomial.
+pub const CRC16_POLY: u32 = 0x8005;
+/// Modbus CRC16 initial value.
+pub const CRC16_INIT: u32 = 0xFFFF;
+/// Silence that delimits frames in bit periods, i.e. 3.5 characters of 11 bits
+/// (start, 8 data, parity and stop bits) rounded up.
+pub const FRAME_SILENCE_BITS: u32 = 39;
+/// Maximum size of ADU.
+pub const MAX_ADU_LEN: usize = 256;
+/// Minimal size of ADU: address, function code and CRC.
+pub const MIN_ADU_LEN: usize = 4;
+
+/// Modbus transport error
+#[derive(PartialEq, Eq, Debug)]
+pub enum Error {
+    /// Underlying serial error
+    Serial(super::Error),
+    /// Received frame has invalid CRC
+    Crc,
+    /// Received frame is too short to be an ADU
+    FrameTooShort,
+    /// Frame exceeds maximum size of ADU
+    FrameTooLong,
+    /// Transmission of frame failed
+    Transmit,
+}
+
+/// Modbus RTU transport over serial interface.
+pub struct Modbus<S, TX, RX, CK, DE> {
+    serial: Serial<S, TX, RX, CK>,
+    de: DE,
+    crc: CRC,
+}
+
+impl<UART: Instance, T: TX, R: RX, C: CK, D: DE> Modbus<UART, T, R, C, D> {
+    /// Creates new transport.
+    ///
+    /// Configures hardware DE signal with active high polarity and receiver timeout.
+    /// CRC peripheral must be enabled and is re-configured for Modbus CRC16.
+    ///
+    /// # Pancis:
+    ///
+    /// In debug mode the function checks if index of DE PIN corresponds to Serial's index.
+    pub fn new(serial: Serial<UART, T, R, C>, de: D, mut crc: CRC) -> Self {
+        debug_assert!(D::does_belong(UART::IDX));
+
+        serial.set_driver_enable(DePolarity::High, 16, 16);
+        serial.set_receiver_timeout(Some(FRAME_SILENCE_BITS));
+
+        crc.set_poly_size(PolySize::Half)
+           .set_poly(CRC16_POLY)
+           .set_init(CRC16_INIT)
+           .reverse_input(ReverseInput::Byte)
+           .reverse_output(true);
+
+        Self {
+            serial,
+            de,
+            crc,
+        }
+    }
+
+    /// Calculates CRC16 of `data`.
+    pub fn crc16(&mut self, data: &[u8]) -> u16 {
+        self.crc.reset();
+        for byte in data {
+            self.crc += *byte;
+        }
+        self.crc.result() as u16
+    }
+
+    /// Sends ADU made of `data` followed by its CRC16.
+    ///
+    /// Returns once the last byte leaves transmitter.
+    pub fn send(&mut self, data: &[u8]) -> Result<(), Error> {
+        if data.len() + 2 > MAX_ADU_LEN {
+            return Err(Error::FrameTooLong);
+        }
+
+        let crc = self.crc16(data);
+
+        for byte in data {
+            block!(self.serial.write(*byte)).map_err(|_| Error::Transmit)?;
+        }
+        // CRC is transmitted low byte first
+        block!(self.serial.write(crc as u8)).map_err(|_| Error::Transmit)?;
+        block!(self.serial.write((crc >> 8) as u8)).map_err(|_| Error::Transmit)?;
+        block!(self.serial.flush()).map_err(|_| Error::Transmit)?;
+
+        Ok(())
+    }
+
+    /// Receives ADU into `buffer`, waiting for the frame to start.
+    ///
+    /// Returns length of ADU without CRC, which is verified.
+    /// In order to fit any ADU, `buffer` should be `MAX_ADU_LEN` long. Frame, which doesn't fit,
+    /// is read to its end and discarded with `FrameTooLong` error.
+    pub fn receive(&mut self, buffer: &mut [u8]) -> Result<usize, Error> {
+        let len = self.serial.read_frame(buffer).map_err(Error::Serial)?.ok_or(Error::FrameTooLong)?;
+
+        if len < MIN_ADU_LEN {
+            return Err(Error::FrameTooShort);
+        }
+
+        let len = len - 2;
+        let expected = buffer[len] as u16 | (buffer[len + 1] as u16) << 8;
+        match self.crc16(&buffer[..len]) == expected {
+            true => Ok(len),
+            false => Err(Error::Crc),
+        }
+    }
+
+    /// Consumes self and returns Serial, DE PIN and CRC
+    ///
+    /// Hardware DE signal is left enabled.
+    pub fn into_raw(self) -> (Serial<UART, T, R, C>, D, CRC) {
+        self.serial.set_receiver_timeout(None);
+        (self.serial, self.de, self.crc)
+    }
+}
@@ -0,0 +1,138 @@
+//! Receiving over DMA
+
+use core::cmp;
+use core::ptr;
+use core::sync::atomic::{self, Ordering};
+
+use crate::dma::{DmaChannel, Direction, Error, Event, WordSize};
+
+/// Receiver served by DMA channel.
+///
+/// Created by `Serial::rx_dma`.
+pub struct RxDma<C> {
+    channel: C,
+}
+
+impl<C: DmaChannel> RxDma<C> {
+    pub(crate) fn new(channel: C) -> Self {
+        Self {
+            channel,
+        }
+    }
+
+    /// Starts continious reception into `buffer`, which is used as ring.
+    ///
+    /// Received data is consumed through `RingBuffer::read` without any copying by DMA itself.
+    pub fn ring_buffer(mut self, buffer: &'static mut [u8]) -> RingBuffer<C> {
+        debug_assert!(!buffer.is_empty() && buffer.len() <= u16::max_value() as usize);
+
+        // NOTE(unsafe) buffer is 'static and owned by ring buffer
+        unsafe { self.channel.set_memory_address(buffer.as_ptr() as u32, true) };
+        self.channel.set_transfer_length(buffer.len() as u16);
+        self.channel.configure(Direction::PeripheralToMemory, true, WordSize::Byte);
+
+        atomic::compiler_fence(Ordering::SeqCst);
+
+        self.channel.start();
+
+        RingBuffer {
+            buffer,
+            channel: self.channel,
+            read_pos: 0,
+            laps: 0,
+        }
+    }
+
+    /// Releases DMA channel.
+    ///
+    /// Receiver remains in DMA mode.
+    pub fn free(self) -> C {
+        self.channel
+    }
+}
+
+/// Ring buffer continiously filled by DMA.
+///
+/// Position of DMA is tracked through number of remaining data items (NDTR),
+/// while wrap around of DMA is tracked through transfer complete event.
+/// Reader must be polled at least once per lap of DMA, otherwise data is lost.
+pub struct RingBuffer<C> {
+    buffer: &'static mut [u8],
+    channel: C,
+    read_pos: usize,
+    // Laps of DMA ahead of reader, negative while reader has passed wrap not observed yet
+    laps: isize,
+}
+
+impl<C: DmaChannel> RingBuffer<C> {
+    /// Returns number of bytes that can be read.
+    pub fn available(&mut self) -> Result<usize, Error> {
+        if self.channel.is_event(Event::TransferError) {
+            return Err(Error::Transfer);
+        }
+
+        let len = self.buffer.len() as isize;
+
+        if self.channel.is_event(Event::TransferComplete) {
+            self.channel.clear_event(Event::TransferComplete);
+            self.laps += 1;
+        }
+
+        let write_pos = self.write_pos() as isize;
+        let mut available = self.laps * len + write_pos - self.read_pos as isize;
+        if available < 0 {
+            // DMA wrapped after event was checked, it is counted on the next call
+            available += len;
+        }
+
+        match available > len {
+            true => Err(Error::Overrun),
+            false => Ok(available as usize),
+        }
+    }
+
+    /// Returns position in buffer, which is written next by DMA.
+    fn write_pos(&self) -> usize {
+        (self.buffer.len() - self.channel.get_ndtr() as usize) % self.buffer.len()
+    }
+
+    /// Copies newly arrived bytes into `out`, returning their number.
+    ///
+    /// On `Error::Overrun` unread data is discarded and reading continues from DMA's position.
+    pub fn read(&mut self, out: &mut [u8]) -> Result<usize, Error> {
+        let available = match self.available() {
+            Ok(available) => available,
+            Err(Error::Overrun) => {
+                self.read_pos = self.write_pos();
+                self.laps = 0;
+                return Err(Error::Overrun);
+            },
+            Err(error) => return Err(error),
+        };
+
+        let count = cmp::min(available, out.len());
+        let len = self.buffer.len();
+
+        atomic::compiler_fence(Ordering::SeqCst);
+
+        for byte in out[..count].iter_mut() {
+            *byte = unsafe { ptr::read_volatile(self.buffer.as_ptr().add(self.read_pos)) };
+            self.read_pos += 1;
+            if self.read_pos == len {
+                self.read_pos = 0;
+                self.laps -= 1;
+            }
+        }
+
+        Ok(count)
+    }
+
+    /// Stops reception and returns buffer with DMA channel.
+    pub fn stop(mut self) -> (&'static mut [u8], C) {
+        self.channel.stop();
+
+        atomic::compiler_fence(Ordering::SeqCst);
+
+        (self.buffer, self.channel)
+    }
+}
@@ -0,0 +1,117 @@
+//! Independent halves of Serial
+//!
+//! Transmitter and receiver can be moved into different tasks or interrupt handlers,
+//! each controlling its own interrupt.
+//!
+//! ```rust, ignore
+//! let (mut tx, mut rx) = serial.split();
+//! rx.listen();
+//!
+//! // USART1 interrupt
+//! if let Ok(byte) = rx.read() {
+//!     ...
+//! }
+//!
+//! let serial = Serial::join(tx, rx);
+//! let (usart1, pins) = serial.free(&mut rcc.apb2);
+//! ```
+
+use core::marker::PhantomData;
+
+use cortex_m::interrupt;
+use embedded_hal::serial;
+use stm32l4::stm32l4x5::usart1;
+
+use super::{CK, Error, Instance, Serial, RX, TX};
+
+/// Transmitting half of Serial
+///
+/// Owns CK pin, as it outputs clock of transmitted data.
+pub struct Tx<UART, T, C> {
+    serial: UART,
+    pins: (T, C),
+}
+
+/// Receiving half of Serial
+pub struct Rx<UART, R> {
+    _serial: PhantomData<UART>,
+    pin: R,
+}
+
+/// Returns registers of Serial, which are shared by both halves
+fn registers<UART: Instance>() -> &'static usart1::RegisterBlock {
+    unsafe { &*UART::register_block() }
+}
+
+impl<UART: Instance, T: TX, R: RX, C: CK> Serial<UART, T, R, C> {
+    /// Splits Serial into transmitter and receiver.
+    pub fn split(self) -> (Tx<UART, T, C>, Rx<UART, R>) {
+        let (tx, rx, ck) = self.pins;
+
+        let tx = Tx {
+            serial: self.serial,
+            pins: (tx, ck),
+        };
+        let rx = Rx {
+            _serial: PhantomData,
+            pin: rx,
+        };
+
+        (tx, rx)
+    }
+
+    /// Recombines halves, created by [split](#method.split), back into Serial.
+    pub fn join(tx: Tx<UART, T, C>, rx: Rx<UART, R>) -> Self {
+        let (tx_pin, ck) = tx.pins;
+
+        Self {
+            serial: tx.serial,
+            pins: (tx_pin, rx.pin, ck),
+        }
+    }
+}
+
+impl<UART: Instance, T: TX, C: CK> Tx<UART, T, C> {
+    /// Starts listening for transmit data register empty interrupt
+    pub fn listen(&mut self) {
+        // CR1 is shared with receiver, which may be preempting us
+        interrupt::free(|_| registers::<UART>().cr1.modify(|_, w| w.txeie().set_bit()));
+    }
+
+    /// Stops listening for transmit data register empty interrupt
+    pub fn unlisten(&mut self) {
+        interrupt::free(|_| registers::<UART>().cr1.modify(|_, w| w.txeie().clear_bit()));
+    }
+}
+
+impl<UART: Instance, R: RX> Rx<UART, R> {
+    /// Starts listening for received data interrupt
+    pub fn listen(&mut self) {
+        interrupt::free(|_| registers::<UART>().cr1.modify(|_, w| w.rxneie().set_bit()));
+    }
+
+    /// Stops listening for received data interrupt
+    pub fn unlisten(&mut self) {
+        interrupt::free(|_| registers::<UART>().cr1.modify(|_, w| w.rxneie().clear_bit()));
+    }
+}
+
+impl<UART: Instance, T: TX, C: CK> serial::Write<u8> for Tx<UART, T, C> {
+    type Error = ();
+
+    fn flush(&mut self) -> nb::Result<(), ()> {
+        super::flush(registers::<UART>())
+    }
+
+    fn write(&mut self, byte: u8) -> nb::Result<(), ()> {
+        super::write_byte(registers::<UART>(), byte)
+    }
+}
+
+impl<UART: Instance, R: RX> serial::Read<u8> for Rx<UART, R> {
+    type Error = Error;
+
+    fn read(&mut self) -> nb::Result<u8, Error> {
+        super::read_byte(registers::<UART>())
+    }
+}
@@ -0,0 +1,107 @@
+//! Device electronic signature
+//!
+//! See Reference manual Ch. 47
+
+use core::ptr;
+
+use crate::crc::{CRC, PolySize, ReverseInput, DEFAULT_INIT, DEFAULT_POLY};
+
+/// Address of 96-bit unique device identifier.
+pub const UID_ADDR: usize = 0x1FFF_7590;
+/// Address of Flash memory size in KiB.
+pub const FLASH_SIZE_ADDR: usize = 0x1FFF_75E0;
+
+/// Reads 96-bit unique device identifier.
+pub fn uid() -> [u32; 3] {
+    let uid = UID_ADDR as *const u32;
+    unsafe {
+        [ptr::read_volatile(uid), ptr::read_volatile(uid.add(1)), ptr::read_volatile(uid.add(2))]
+    }
+}
+
+/// Reads size of Flash memory in KiB.
+pub fn flash_size() -> u16 {
+    unsafe { ptr::read_volatile(FLASH_SIZE_ADDR as *const u16) }
+}
+
+#[derive(Copy, Clone, PartialEq, Eq, Debug)]
+/// Pseudo-unique device serial derived from UID.
+///
+/// Note that it is a hash, and therefore uniqueness is not guaranteed.
+pub struct DeviceSerial(u64);
+
+impl DeviceSerial {
+    /// Creates serial out of raw value.
+    pub fn new(value: u64) -> Self {
+        DeviceSerial(value)
+    }
+
+    #[inline]
+    /// Returns 32-bit serial.
+    pub fn as_u32(&self) -> u32 {
+        self.0 as u32
+    }
+
+    #[inline]
+    /// Returns 64-bit serial.
+    pub fn as_u64(&self) -> u64 {
+        self.0
+    }
+
+    /// Returns EUI-48 (MAC address) made of `oui` and lower 24 bits of serial.
+    pub fn eui48(&self, oui: [u8; 3]) -> [u8; 6] {
+        let value = self.as_u32();
+        [oui[0], oui[1], oui[2], (value >> 16) as u8, (value >> 8) as u8, value as u8]
+    }
+
+    /// Returns EUI-64 made of `oui` and lower 40 bits of serial.
+    pub fn eui64(&self, oui: [u8; 3]) -> [u8; 8] {
+        let value = self.0;
+        [oui[0], oui[1], oui[2], (value >> 32) as u8, (value >> 24) as u8, (value >> 16) as u8, (value >> 8) as u8, value as u8]
+    }
+}
+
+/// Calculates device serial by hashing UID through CRC peripheral.
+///
+/// CRC is re-configured with default CRC-32 settings, and its previous configuration is lost.
+pub fn device_serial(crc: &mut CRC) -> DeviceSerial {
+    let uid = uid();
+
+    crc.set_poly_size(PolySize::Word)
+       .set_poly(DEFAULT_POLY)
+       .set_init(DEFAULT_INIT)
+       .reverse_input(ReverseInput::None)
+       .reverse_output(false);
+
+    crc.reset();
+    for word in uid.iter() {
+        *crc += *word;
+    }
+    let low = crc.result();
+
+    // Second pass goes in reverse order, seeded by first one.
+    crc.set_init(low);
+    crc.reset();
+    for word in uid.iter().rev() {
+        *crc += *word;
+    }
+    let high = crc.result();
+
+    crc.set_init(DEFAULT_INIT);
+
+    DeviceSerial((high as u64) << 32 | low as u64)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::DeviceSerial;
+
+    #[test]
+    pub fn format_eui() {
+        let serial = DeviceSerial::new(0x0123_4567_89AB_CDEF);
+
+        assert_eq!(serial.as_u32(), 0x89AB_CDEF);
+        assert_eq!(serial.eui48([0x02, 0x80, 0xE1]), [0x02, 0x80, 0xE1, 0xAB, 0xCD, 0xEF]);
+        assert_eq!(serial.eui64([0x02, 0x80, 0xE1]), [0x02, 0x80, 0xE1, 0x67, 0x89, 0xAB, 0xCD, 0xEF]);
+    }
+}
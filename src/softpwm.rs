@@ -0,0 +1,126 @@
+//! Software PWM on arbitrary GPIO pins
+//!
+//! Basic timer (TIM6 or TIM7) interrupts 256 times per PWM period, and each tick
+//! outputs of all channels are updated according to their 8-bit duty cycles.
+//! It is suitable for LED dimming, where jitter of interrupt latency is not visible.
+//!
+//! ```rust, ignore
+//! let mut channels = [Channel::new(&mut led1), Channel::new(&mut led2)];
+//! let mut pwm = SoftPwm::tim6(tim6, Hertz(200), &mut channels, clocks, &mut rcc.apb1);
+//! pwm.set_duty(1, 64);
+//!
+//! // TIM6 interrupt
+//! pwm.handle_interrupt();
+//! ```
+
+use embedded_hal::digital::OutputPin;
+use stm32l4::stm32l4x5::{TIM6, TIM7};
+
+use crate::rcc::{APB1, Clocks};
+use crate::time::Hertz;
+use crate::timer::{Event, Timer};
+
+/// Number of timer ticks per PWM period
+const STEPS: u32 = 256;
+
+/// Output pin with its duty cycle
+pub struct Channel<'a> {
+    pin: &'a mut dyn OutputPin,
+    duty: u8,
+}
+
+impl<'a> Channel<'a> {
+    /// Creates channel on `pin`, which is initially off.
+    pub fn new(pin: &'a mut dyn OutputPin) -> Self {
+        pin.set_low();
+
+        Self {
+            pin,
+            duty: 0,
+        }
+    }
+}
+
+/// Software PWM driven by timer interrupt
+pub struct SoftPwm<'a, TIM> {
+    timer: Timer<TIM>,
+    channels: &'a mut [Channel<'a>],
+    phase: u8,
+}
+
+impl<'a, TIM> SoftPwm<'a, TIM> {
+    /// Sets duty cycle of channel `idx`, where 0 is always off and 255 is on for 255/256 of period.
+    pub fn set_duty(&mut self, idx: usize, duty: u8) {
+        self.channels[idx].duty = duty;
+    }
+
+    /// Returns duty cycle of channel `idx`
+    pub fn duty(&self, idx: usize) -> u8 {
+        self.channels[idx].duty
+    }
+
+    /// Returns number of channels
+    pub fn len(&self) -> usize {
+        self.channels.len()
+    }
+
+    /// Returns whether there are no channels
+    pub fn is_empty(&self) -> bool {
+        self.channels.is_empty()
+    }
+
+    fn tick(&mut self) {
+        self.phase = self.phase.wrapping_add(1);
+
+        for channel in self.channels.iter_mut() {
+            if self.phase == 0 {
+                if channel.duty != 0 {
+                    channel.pin.set_high();
+                }
+            } else if self.phase == channel.duty {
+                channel.pin.set_low();
+            }
+        }
+    }
+}
+
+macro_rules! impl_softpwm {
+    ($($TIMx:ident: $timx:ident,)+) => {
+        $(
+            impl<'a> SoftPwm<'a, $TIMx> {
+                /// Starts timer, which ticks `STEPS` times per period of `freq`, and subscribes to its interrupt.
+                pub fn $timx<T: Into<Hertz>>(tim: $TIMx, freq: T, channels: &'a mut [Channel<'a>], clocks: Clocks, apb: &mut APB1) -> Self {
+                    let mut timer = Timer::$timx(tim, Hertz(freq.into().0 * STEPS), clocks, apb);
+                    timer.subscribe(Event::Timeout);
+
+                    Self {
+                        timer,
+                        channels,
+                        phase: 0,
+                    }
+                }
+
+                /// Updates outputs, must be called from timer interrupt.
+                pub fn handle_interrupt(&mut self) {
+                    self.timer.reset_overflow();
+                    self.tick();
+                }
+
+                /// Stops timer, turns all channels off and releases them
+                pub fn free(mut self) -> ($TIMx, &'a mut [Channel<'a>]) {
+                    self.timer.unsubscribe(Event::Timeout);
+                    for channel in self.channels.iter_mut() {
+                        channel.pin.set_low();
+                    }
+
+                    (self.timer.free(), self.channels)
+                }
+            }
+        )+
+    }
+}
+
+impl_softpwm!(
+    TIM6: tim6,
+    TIM7: tim7,
+);
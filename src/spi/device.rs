@@ -0,0 +1,94 @@
+//! Devices sharing SPI bus.
+//!
+//! Each [Device](struct.Device.html) keeps its own mode and frequency, which are applied
+//! to the bus at the start of every transaction, while its CS line is asserted.
+//!
+//! ```rust, ignore
+//! let mut flash = Device::<SPI1, _>::new(cs_flash, MegaHertz(20), MODE_0, &clocks)?;
+//! let mut sensor = Device::<SPI1, _>::new(cs_sensor, MegaHertz(1), MODE_3, &clocks)?.with_delays(1, 1, &clocks);
+//!
+//! let id = flash.transaction(&mut spi, |bus| {
+//!     let mut id = [0x9F, 0, 0, 0];
+//!     bus.transfer(&mut id)?;
+//!     Ok(id)
+//! })?;
+//! ```
+
+use core::marker::PhantomData;
+
+use cortex_m::asm;
+use embedded_hal::digital::OutputPin;
+use embedded_hal::spi::Mode;
+
+use crate::common::ConfigError;
+use crate::rcc::Clocks;
+use crate::time::Hertz;
+
+use super::{Error, InnerSpi, SoftNss, Spi, MISO, MOSI, SCK};
+
+/// Device on SPI bus with its own chip select line
+pub struct Device<SPI, CS> {
+    cs: CS,
+    br: u8,
+    mode: Mode,
+    cs_setup: u32,
+    cs_hold: u32,
+    _spi: PhantomData<SPI>,
+}
+
+impl<SPI: InnerSpi, CS: OutputPin> Device<SPI, CS> {
+    /// Creates device communicating at `freq` in `mode`, and deasserts its `cs`.
+    ///
+    /// Returns error if `freq` cannot be derived from SPI clock.
+    pub fn new<F: Into<Hertz>>(mut cs: CS, freq: F, mode: Mode, clocks: &Clocks) -> Result<Self, ConfigError> {
+        let br = SPI::calc_br(freq.into(), clocks)?;
+
+        cs.set_high();
+
+        Ok(Self {
+            cs,
+            br,
+            mode,
+            cs_setup: 0,
+            cs_hold: 0,
+            _spi: PhantomData,
+        })
+    }
+
+    /// Sets delays in microseconds after CS is asserted and before it is deasserted.
+    pub fn with_delays(mut self, setup_us: u32, hold_us: u32, clocks: &Clocks) -> Self {
+        let cycles_per_us = clocks.sysclk().0 / 1_000_000;
+        self.cs_setup = setup_us * cycles_per_us;
+        self.cs_hold = hold_us * cycles_per_us;
+        self
+    }
+
+    /// Performs transaction `f` on `bus`, reconfigured for the device, with CS asserted.
+    ///
+    /// CS is deasserted only once all data is transmitted, regardless of `f` result.
+    pub fn transaction<S, MI, MO, R, F>(&mut self, bus: &mut Spi<SPI, S, MI, MO, SoftNss>, f: F) -> Result<R, Error>
+        where S: SCK, MI: MISO, MO: MOSI, F: FnOnce(&mut Spi<SPI, S, MI, MO, SoftNss>) -> Result<R, Error>
+    {
+        bus.spi.reconfigure(self.br, self.mode);
+
+        self.cs.set_low();
+        if self.cs_setup > 0 {
+            asm::delay(self.cs_setup);
+        }
+
+        let result = f(bus);
+
+        bus.spi.wait_idle();
+        if self.cs_hold > 0 {
+            asm::delay(self.cs_hold);
+        }
+        self.cs.set_high();
+
+        result
+    }
+
+    /// Releases CS pin
+    pub fn free(self) -> CS {
+        self.cs
+    }
+}
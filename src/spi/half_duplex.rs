@@ -0,0 +1,183 @@
+//! Single data line SPI modes.
+//!
+//! Reference: Ch. 42.4.4 Communication formats
+
+use embedded_hal::spi::Mode;
+use nb::block;
+
+use crate::common::ConfigError;
+use crate::gpio::af::{self, Signal};
+use crate::gpio::AltFunPin;
+use crate::time::Hertz;
+use crate::rcc::Clocks;
+
+use super::{InnerSpi, Error, SCK, MISO, MOSI};
+
+/// SPI in 1-line bidirectional (3-wire) mode.
+///
+/// Master uses MOSI as its only data line.
+pub struct SpiBidi<SPI, SCK, MOSI> {
+    spi: SPI,
+    pins: (SCK, MOSI),
+}
+
+impl<SPI: InnerSpi, S: SCK, MO: MOSI> SpiBidi<SPI, S, MO> {
+    /// Creates new instance of SPI in bidirectional mode.
+    ///
+    /// SPI is left disabled until first transfer.
+    ///
+    /// Returns error if `freq` cannot be derived from SPI clock.
+    ///
+    /// # Pancis:
+    ///
+    /// In debug mode the function checks if index of each PIN corresponds to SPI's index,
+    /// and that PINs carry SPI signals according to [AF table](../../gpio/af/index.html).
+    pub fn new(spi: SPI, pins: (S, MO), freq: Hertz, mode: Mode, clocks: &Clocks, apb: &mut SPI::APB) -> Result<Self, ConfigError>
+        where S: AltFunPin, MO: AltFunPin {
+        debug_assert_eq!(SPI::IDX, S::SPI_IDX);
+        debug_assert_eq!(SPI::IDX, MO::SPI_IDX);
+        debug_assert!(af::has_af(&pins.0, Signal::SpiSck(SPI::IDX)));
+        debug_assert!(af::has_af(&pins.1, Signal::SpiMosi(SPI::IDX)));
+
+        let br = SPI::calc_br(freq, clocks)?;
+
+        SPI::enable(apb);
+
+        spi.configure_cr1(br, mode);
+        spi.configure_cr2();
+        spi.cr1().modify(|_, w| w.spe().clear_bit());
+        spi.cr1().modify(|_, w| w.bidimode().set_bit().bidioe().set_bit());
+
+        Ok(Self {
+            spi,
+            pins
+        })
+    }
+
+    /// Transmits `words` over data line.
+    pub fn write(&mut self, words: &[u8]) -> Result<(), Error> {
+        if self.spi.cr1().read().bidioe().bit_is_clear() {
+            self.spi.cr1().modify(|_, w| w.spe().clear_bit());
+            self.spi.cr1().modify(|_, w| w.bidioe().set_bit());
+        }
+        self.spi.cr1().modify(|_, w| w.spe().set_bit());
+
+        for word in words {
+            block!(self.spi.send_u8(*word))?;
+        }
+
+        while self.spi.sr().read().ftlvl().bits() != 0 {}
+        while self.spi.sr().read().bsy().bit_is_set() {}
+
+        Ok(())
+    }
+
+    /// Receives `buffer.len()` bytes from data line.
+    ///
+    /// Clock is generated only while reading.
+    pub fn read(&mut self, buffer: &mut [u8]) -> Result<(), Error> {
+        if buffer.is_empty() {
+            return Ok(());
+        }
+
+        self.spi.cr1().modify(|_, w| w.spe().clear_bit());
+        self.spi.cr1().modify(|_, w| w.bidioe().clear_bit());
+        // Clock starts as soon as SPI is enabled
+        self.spi.cr1().modify(|_, w| w.spe().set_bit());
+
+        read_until_stop(&self.spi, buffer)
+    }
+
+    ///Consumes self and returns SPI and PINS
+    pub fn into_raw(self) -> (SPI, (S, MO)) {
+        self.spi.cr1().modify(|_, w| w.spe().clear_bit());
+        (self.spi, self.pins)
+    }
+}
+
+/// SPI in receive-only mode.
+pub struct SpiRxOnly<SPI, SCK, MISO> {
+    spi: SPI,
+    pins: (SCK, MISO),
+}
+
+impl<SPI: InnerSpi, S: SCK, MI: MISO> SpiRxOnly<SPI, S, MI> {
+    /// Creates new instance of SPI in receive-only mode.
+    ///
+    /// SPI is left disabled until first transfer.
+    ///
+    /// Returns error if `freq` cannot be derived from SPI clock.
+    ///
+    /// # Pancis:
+    ///
+    /// In debug mode the function checks if index of each PIN corresponds to SPI's index,
+    /// and that PINs carry SPI signals according to [AF table](../../gpio/af/index.html).
+    pub fn new(spi: SPI, pins: (S, MI), freq: Hertz, mode: Mode, clocks: &Clocks, apb: &mut SPI::APB) -> Result<Self, ConfigError>
+        where S: AltFunPin, MI: AltFunPin {
+        debug_assert_eq!(SPI::IDX, S::SPI_IDX);
+        debug_assert_eq!(SPI::IDX, MI::SPI_IDX);
+        debug_assert!(af::has_af(&pins.0, Signal::SpiSck(SPI::IDX)));
+        debug_assert!(af::has_af(&pins.1, Signal::SpiMiso(SPI::IDX)));
+
+        let br = SPI::calc_br(freq, clocks)?;
+
+        SPI::enable(apb);
+
+        spi.configure_cr1(br, mode);
+        spi.configure_cr2();
+        spi.cr1().modify(|_, w| w.spe().clear_bit());
+        spi.cr1().modify(|_, w| w.rxonly().set_bit());
+
+        Ok(Self {
+            spi,
+            pins
+        })
+    }
+
+    /// Receives `buffer.len()` bytes.
+    ///
+    /// Clock is generated only while reading.
+    pub fn read(&mut self, buffer: &mut [u8]) -> Result<(), Error> {
+        if buffer.is_empty() {
+            return Ok(());
+        }
+
+        // Clock starts as soon as SPI is enabled
+        self.spi.cr1().modify(|_, w| w.spe().set_bit());
+
+        read_until_stop(&self.spi, buffer)
+    }
+
+    ///Consumes self and returns SPI and PINS
+    pub fn into_raw(self) -> (SPI, (S, MI)) {
+        self.spi.cr1().modify(|_, w| w.spe().clear_bit());
+        (self.spi, self.pins)
+    }
+}
+
+/// Reads bytes while master keeps generating clock.
+///
+/// Per Reference Ch. 42.4.9 SPI must be disabled once second-to-last byte is received,
+/// so that clock stops right after the last one.
+fn read_until_stop<SPI: InnerSpi>(spi: &SPI, buffer: &mut [u8]) -> Result<(), Error> {
+    let last = buffer.len() - 1;
+
+    for (idx, byte) in buffer.iter_mut().enumerate() {
+        if idx == last {
+            spi.cr1().modify(|_, w| w.spe().clear_bit());
+        }
+
+        match block!(spi.read_u8()) {
+            Ok(value) => *byte = value,
+            Err(error) => {
+                spi.cr1().modify(|_, w| w.spe().clear_bit());
+                return Err(error);
+            },
+        }
+    }
+
+    // Clock might have produced extra frames before SPI got disabled
+    spi.drain_rx();
+
+    Ok(())
+}
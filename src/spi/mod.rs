@@ -1,19 +1,25 @@
 //! Serial Peripheral Interface (SPI) module.
 
 use embedded_hal::spi::{FullDuplex, Mode, Phase, Polarity};
+use embedded_hal::timer::CountDown;
 use stm32l4::stm32l4x5::{SPI1, SPI2, SPI3};
 
+use crate::common::{block_timeout, ConfigError, TimedOut};
 use crate::time::Hertz;
 use crate::rcc::{APB1, APB2, Clocks};
+use crate::selftest;
 
 use core::ptr;
 
-use crate::gpio::{
+use nb::block;
+
+use crate::gpio::af::{self, Signal};
+use crate::gpio::{AltFunPin, 
     AF5,
     AF6, //Used for SPI3
     //SPI1
     //NSS
-    //PA4, PA15,
+    PA4, PA15,
     //SCK
     PA5, PB3,
     //MISO
@@ -22,7 +28,7 @@ use crate::gpio::{
     PA7, PB5,
     //SPI2
     //NSS
-    //PB9, PB12,
+    PB9, PB12,
     //SCK
     PB10, PB13,
     //MISO
@@ -58,6 +64,15 @@ pub trait MOSI {
     const SPI_IDX: u8;
 }
 
+///Describes NSS Pin
+pub trait NSS {
+    ///SPI index
+    const SPI_IDX: u8;
+}
+
+///Dummy NSS to be used when slave select is managed by software
+pub struct SoftNss;
+
 macro_rules! impl_pins_trait {
     ($IDX:expr => {
         TRAIT: $TRAIT:ident,
@@ -88,6 +103,12 @@ impl_pins_trait!(1 => {
     PINS: [PA7, PB5,]
 });
 
+impl_pins_trait!(1 => {
+    TRAIT: NSS,
+    AF: AF5,
+    PINS: [PA4, PA15,]
+});
+
 impl_pins_trait!(2 => {
     TRAIT: SCK,
     AF: AF5,
@@ -104,6 +125,12 @@ impl_pins_trait!(2 => {
     PINS: [PB15, PC3,]
 });
 
+impl_pins_trait!(2 => {
+    TRAIT: NSS,
+    AF: AF5,
+    PINS: [PB9, PB12,]
+});
+
 impl_pins_trait!(3 => {
     TRAIT: SCK,
     AF: AF6,
@@ -119,6 +146,50 @@ impl_pins_trait!(3 => {
     AF: AF6,
     PINS: [PC12,]
 });
+impl_pins_trait!(3 => {
+    TRAIT: NSS,
+    AF: AF6,
+    PINS: [PA4, PA15,]
+});
+
+#[derive(Copy, Clone, PartialEq, Eq, Debug)]
+///Hardware slave select management
+pub enum NssMode {
+    ///NSS is driven low as long as SPI is enabled
+    Output,
+    ///NSS pulse is generated between data frames.
+    ///
+    ///Can be used only with `Phase::CaptureOnFirstTransition`
+    Pulse,
+    ///TI synchronous frame format.
+    ///
+    ///NSS is driven by hardware, while clock polarity and phase are ignored.
+    TiFrame,
+}
+
+///Calculates baud rate prescaler (BR bits) to derive `freq` from SPI `clock`.
+///
+///Frequency is rounded down to the closest one reachable with prescaler,
+///so SCK never runs faster than requested.
+fn calc_br(clock: u32, freq: u32) -> Result<u8, ConfigError> {
+    if freq == 0 {
+        return Err(ConfigError::FrequencyTooLow);
+    } else if freq > clock / 2 {
+        return Err(ConfigError::FrequencyTooHigh);
+    }
+
+    //Smallest divider 2^(BR + 1), with which clock / divider <= freq
+    (0..=7u8).find(|br| (freq as u64) << (br + 1) >= clock as u64).ok_or(ConfigError::FrequencyTooLow)
+}
+
+#[derive(Copy, Clone, PartialEq, Eq, Debug)]
+///Length of hardware CRC
+pub enum CrcLength {
+    ///8-bit CRC
+    Bits8,
+    ///16-bit CRC
+    Bits16,
+}
 
 //Reference: Ch. 42.4.7 Configuration of SPI
 ///Describes raw SPI from device crate
@@ -143,20 +214,22 @@ pub trait InnerSpi where Self: Sized {
     ///Retrieves DR register block.
     fn dr(&self) -> & stm32l4::stm32l4x5::spi1::DR;
 
-    ///Configures CR1 register
-    fn configure_cr1(&self, freq: Hertz, clocks: &Clocks, mode: Mode) {
-        let br = match Self::get_clock_freq(clocks).0 / freq.0 {
-            0 => unreachable!(),
-            1...2 => 0b000,
-            3...5 => 0b001,
-            6...11 => 0b010,
-            12...23 => 0b011,
-            24...39 => 0b100,
-            40...95 => 0b101,
-            96...191 => 0b110,
-            _ => 0b111,
-        };
+    ///Retrieves CRCPR register block.
+    fn crcpr(&self) -> & stm32l4::stm32l4x5::spi1::CRCPR;
 
+    ///Retrieves RXCRCR register block.
+    fn rxcrcr(&self) -> & stm32l4::stm32l4x5::spi1::RXCRCR;
+
+    ///Retrieves TXCRCR register block.
+    fn txcrcr(&self) -> & stm32l4::stm32l4x5::spi1::TXCRCR;
+
+    ///Calculates baud rate prescaler (BR bits) for `freq`.
+    fn calc_br(freq: Hertz, clocks: &Clocks) -> Result<u8, ConfigError> {
+        calc_br(Self::get_clock_freq(clocks).0, freq.0)
+    }
+
+    ///Configures CR1 register with baud rate prescaler `br`
+    fn configure_cr1(&self, br: u8, mode: Mode) {
         self.cr1().write(|w| unsafe {
             w.br().bits(br)
              .cpol().bit(mode.polarity == Polarity::IdleHigh)
@@ -184,6 +257,178 @@ pub trait InnerSpi where Self: Sized {
         });
     }
 
+    ///Waits until transmission is complete and SPI is not busy
+    fn wait_idle(&self) {
+        while self.sr().read().ftlvl().bits() != 0 {}
+        while self.sr().read().bsy().bit_is_set() {}
+    }
+
+    ///Changes baud rate prescaler `br` and mode of enabled SPI
+    fn reconfigure(&self, br: u8, mode: Mode) {
+        let cr1 = self.cr1().read();
+        if cr1.br().bits() == br
+            && cr1.cpol().bit_is_set() == (mode.polarity == Polarity::IdleHigh)
+            && cr1.cpha().bit_is_set() == (mode.phase == Phase::CaptureOnSecondTransition) {
+            return;
+        }
+
+        self.wait_idle();
+        self.cr1().modify(|_, w| w.spe().clear_bit());
+        self.cr1().modify(|_, w| unsafe {
+            w.br().bits(br)
+             .cpol().bit(mode.polarity == Polarity::IdleHigh)
+             .cpha().bit(mode.phase == Phase::CaptureOnSecondTransition)
+        });
+        self.cr1().modify(|_, w| w.spe().set_bit());
+    }
+
+    ///Switches to hardware NSS management
+    fn configure_nss(&self, nss: NssMode) {
+        //SPI must be disabled when changing these settings
+        self.cr1().modify(|_, w| w.spe().clear_bit());
+        self.cr1().modify(|_, w| w.ssm().clear_bit().ssi().clear_bit());
+        self.cr2().modify(|_, w| {
+            w.ssoe().set_bit()
+             .nssp().bit(nss == NssMode::Pulse)
+             .frf().bit(nss == NssMode::TiFrame)
+        });
+        self.cr1().modify(|_, w| w.spe().set_bit());
+    }
+
+    ///Reads single byte from RX FIFO
+    fn read_u8(&self) -> nb::Result<u8, Error> {
+        let sr = self.sr().read();
+
+        Err(if sr.ovr().bit_is_set() {
+            nb::Error::Other(Error::Overrun)
+        } else if sr.modf().bit_is_set() {
+            nb::Error::Other(Error::ModeFault)
+        } else if sr.crcerr().bit_is_set() {
+            nb::Error::Other(Error::Crc)
+        } else if sr.rxne().bit_is_set() {
+            // NOTE(read_volatile) read only 1 byte (the svd2rust API only allows
+            // reading a half-word)
+            return Ok(unsafe {
+                ptr::read_volatile(self.dr() as *const _ as *const u8)
+            });
+        } else {
+            nb::Error::WouldBlock
+        })
+    }
+
+    ///Writes single byte into TX FIFO
+    fn send_u8(&self, byte: u8) -> nb::Result<(), Error> {
+        let sr = self.sr().read();
+
+        Err(if sr.ovr().bit_is_set() {
+            nb::Error::Other(Error::Overrun)
+        } else if sr.modf().bit_is_set() {
+            nb::Error::Other(Error::ModeFault)
+        } else if sr.crcerr().bit_is_set() {
+            nb::Error::Other(Error::Crc)
+        } else if sr.txe().bit_is_set() {
+            // NOTE(write_volatile) see note above
+            unsafe { ptr::write_volatile(ptr::addr_of!(*self.dr()) as *mut u8, byte) }
+            return Ok(());
+        } else {
+            nb::Error::WouldBlock
+        })
+    }
+
+    ///Discards data remaining in RX FIFO
+    fn drain_rx(&self) {
+        while self.sr().read().frlvl().bits() != 0 {
+            unsafe {
+                let _ = ptr::read_volatile(self.dr() as *const _ as *const u8);
+            }
+        }
+    }
+
+    ///Clears overrun flag, discarding data in RX FIFO.
+    ///
+    ///Reference: Ch. 42.4.11 Overrun error
+    fn clear_overrun(&self) {
+        //OVR is cleared by reading DR followed by reading SR
+        unsafe {
+            let _ = ptr::read_volatile(self.dr() as *const _ as *const u8);
+        }
+        self.drain_rx();
+        let _ = self.sr().read();
+    }
+
+    ///Clears mode fault flag and restores master mode.
+    ///
+    ///Reference: Ch. 42.4.11 Mode fault
+    fn clear_mode_fault(&self) {
+        //MODF is cleared by reading SR followed by writing CR1, fault resets both SPE and MSTR
+        let _ = self.sr().read();
+        self.cr1().modify(|_, w| w.mstr().set_bit());
+        self.cr1().modify(|_, w| w.spe().set_bit());
+    }
+
+    ///Clears CRC error flag.
+    fn clear_crc_error(&self) {
+        self.sr().write(|w| w.crcerr().clear_bit());
+    }
+
+    ///Enables hardware CRC calculation with `polynomial` or disables it, resetting CRC registers.
+    ///
+    ///Reference: Ch. 42.4.10 CRC calculation
+    fn configure_crc(&self, crc: Option<(u16, CrcLength)>) {
+        let is_enabled = self.cr1().read().spe().bit_is_set();
+        if is_enabled {
+            self.wait_idle();
+        }
+        //CRC must be configured while SPI is disabled
+        self.cr1().modify(|_, w| w.spe().clear_bit());
+        self.cr1().modify(|_, w| w.crcen().clear_bit());
+
+        if let Some((polynomial, length)) = crc {
+            self.crcpr().write(|w| unsafe { w.crcpoly().bits(polynomial) });
+            //CRCL is named DFF in device crate
+            self.cr1().modify(|_, w| w.dff().bit(length == CrcLength::Bits16));
+            self.cr1().modify(|_, w| w.crcen().set_bit());
+        }
+
+        self.cr1().modify(|_, w| w.spe().bit(is_enabled));
+    }
+
+    ///Resets CRC registers, keeping polynomial and length.
+    fn reset_crc(&self) {
+        let is_enabled = self.cr1().read().spe().bit_is_set();
+        if is_enabled {
+            self.wait_idle();
+        }
+        //CRC is reset by clearing CRCEN while SPI is disabled
+        self.cr1().modify(|_, w| w.spe().clear_bit());
+        self.cr1().modify(|_, w| w.crcen().clear_bit());
+        self.cr1().modify(|_, w| w.crcen().set_bit());
+        self.cr1().modify(|_, w| w.spe().bit(is_enabled));
+    }
+
+    ///Reads out received CRC, which follows the last data, and checks whether it matches.
+    fn check_crc(&self) -> Result<(), Error> {
+        let len = match self.cr1().read().dff().bit_is_set() {
+            true => 2,
+            false => 1,
+        };
+        for _ in 0..len {
+            while self.sr().read().rxne().bit_is_clear() {}
+            unsafe {
+                let _ = ptr::read_volatile(self.dr() as *const _ as *const u8);
+            }
+        }
+
+        self.wait_idle();
+        match self.sr().read().crcerr().bit_is_set() {
+            true => {
+                self.clear_crc_error();
+                Err(Error::Crc)
+            },
+            false => Ok(()),
+        }
+    }
+
     ///Enables SPI
     fn enable(apb: &mut Self::APB);
 }
@@ -213,6 +458,18 @@ impl InnerSpi for SPI1 {
         &self.dr
     }
 
+    fn crcpr(&self) -> &stm32l4::stm32l4x5::spi1::CRCPR {
+        &self.crcpr
+    }
+
+    fn rxcrcr(&self) -> &stm32l4::stm32l4x5::spi1::RXCRCR {
+        &self.rxcrcr
+    }
+
+    fn txcrcr(&self) -> &stm32l4::stm32l4x5::spi1::TXCRCR {
+        &self.txcrcr
+    }
+
     fn enable(apb: &mut Self::APB) {
         // enable and/or reset SPI
         apb.enr().modify(|_, w| w.spi1en().set_bit());
@@ -246,6 +503,18 @@ impl InnerSpi for SPI2 {
         &self.dr
     }
 
+    fn crcpr(&self) -> &stm32l4::stm32l4x5::spi1::CRCPR {
+        &self.crcpr
+    }
+
+    fn rxcrcr(&self) -> &stm32l4::stm32l4x5::spi1::RXCRCR {
+        &self.rxcrcr
+    }
+
+    fn txcrcr(&self) -> &stm32l4::stm32l4x5::spi1::TXCRCR {
+        &self.txcrcr
+    }
+
     fn enable(apb: &mut Self::APB) {
         // enable and/or reset SPI
         apb.enr1().modify(|_, w| w.spi2en().set_bit());
@@ -279,6 +548,18 @@ impl InnerSpi for SPI3 {
         &self.dr
     }
 
+    fn crcpr(&self) -> &::stm32l4::stm32l4x5::spi1::CRCPR {
+        &self.crcpr
+    }
+
+    fn rxcrcr(&self) -> &::stm32l4::stm32l4x5::spi1::RXCRCR {
+        &self.rxcrcr
+    }
+
+    fn txcrcr(&self) -> &::stm32l4::stm32l4x5::spi1::TXCRCR {
+        &self.txcrcr
+    }
+
     fn enable(apb: &mut Self::APB) {
         // enable and/or reset SPI
         apb.enr1().modify(|_, w| w.spi3en().set_bit());
@@ -289,6 +570,7 @@ impl InnerSpi for SPI3 {
 
 
 /// SPI errors.
+#[derive(Copy, Clone, PartialEq, Eq, Debug)]
 pub enum Error {
     /// Overrun occurred
     Overrun,
@@ -296,15 +578,24 @@ pub enum Error {
     ModeFault,
     /// CRC cheksum error.
     Crc,
+    /// Transfer hasn't completed within timeout
+    Timeout,
+}
+
+impl From<TimedOut> for Error {
+    fn from(_: TimedOut) -> Self {
+        Error::Timeout
+    }
 }
 
 /// SPI
-pub struct Spi<SPI, SCK, MISO, MOSI> {
+pub struct Spi<SPI, SCK, MISO, MOSI, NSS = SoftNss> {
     spi: SPI,
     pins: (SCK, MISO, MOSI),
+    nss: NSS,
 }
 
-impl<SPI: InnerSpi, S: SCK, MI: MISO, MO: MOSI> Spi<SPI, S, MI, MO> {
+impl<SPI: InnerSpi, S: SCK, MI: MISO, MO: MOSI> Spi<SPI, S, MI, MO, SoftNss> {
     /// Creates new instance of SPI.
     ///
     /// It takes ownership of raw SPI object and corresponding PINs.
@@ -315,32 +606,45 @@ impl<SPI: InnerSpi, S: SCK, MI: MISO, MO: MOSI> Spi<SPI, S, MI, MO> {
     /// - Configure CR1;
     /// - Configure CR2;
     ///
+    /// Returns error if `freq` cannot be derived from SPI clock.
+    ///
     /// # Pancis:
     ///
-    /// In debug mode the function checks if index of each PIN corresponds to SPI's index.
-    pub fn new(spi: SPI, pins: (S, MI, MO), freq: Hertz, mode: Mode, clocks: &Clocks, apb: &mut SPI::APB) -> Self {
+    /// In debug mode the function checks if index of each PIN corresponds to SPI's index,
+    /// and that PINs carry SPI signals according to [AF table](../gpio/af/index.html).
+    pub fn new(spi: SPI, pins: (S, MI, MO), freq: Hertz, mode: Mode, clocks: &Clocks, apb: &mut SPI::APB) -> Result<Self, ConfigError>
+        where S: AltFunPin, MI: AltFunPin, MO: AltFunPin {
         debug_assert_eq!(SPI::IDX, S::SPI_IDX);
         debug_assert_eq!(SPI::IDX, MI::SPI_IDX);
         debug_assert_eq!(SPI::IDX, MO::SPI_IDX);
+        debug_assert!(af::has_af(&pins.0, Signal::SpiSck(SPI::IDX)));
+        debug_assert!(af::has_af(&pins.1, Signal::SpiMiso(SPI::IDX)));
+        debug_assert!(af::has_af(&pins.2, Signal::SpiMosi(SPI::IDX)));
+
+        let br = SPI::calc_br(freq, clocks)?;
 
         SPI::enable(apb);
 
-        spi.configure_cr1(freq, clocks, mode);
+        spi.configure_cr1(br, mode);
         spi.configure_cr2();
 
-        Self {
+        Ok(Self {
             spi,
-            pins
-        }
+            pins,
+            nss: SoftNss,
+        })
     }
 
     ///Re-creates SPI instance from its components.
     ///
-    ///Note: it is up to user to ensure that SPI has been created using [new](#method.new) previously
+    ///# Safety
+    ///
+    ///It is up to user to ensure that SPI has been created using [new](#method.new) previously
     pub unsafe fn from_raw(spi: SPI, pins: (S, MI, MO)) -> Self {
         Self {
             spi,
-            pins
+            pins,
+            nss: SoftNss,
         }
     }
 
@@ -350,51 +654,256 @@ impl<SPI: InnerSpi, S: SCK, MI: MISO, MO: MOSI> Spi<SPI, S, MI, MO> {
     }
 }
 
-impl<SPI: InnerSpi, S: SCK, MI: MISO, MO: MOSI> FullDuplex<u8> for Spi<SPI, S, MI, MO> {
-    type Error = Error;
+impl<SPI: InnerSpi, S: SCK, MI: MISO, MO: MOSI, N: NSS> Spi<SPI, S, MI, MO, N> {
+    /// Creates new instance of SPI with hardware slave select management.
+    ///
+    /// Same as [new](#method.new), but NSS pin is driven by SPI itself according to `nss_mode`.
+    ///
+    /// # Pancis:
+    ///
+    /// In debug mode the function checks if index of each PIN corresponds to SPI's index,
+    /// and that PINs carry SPI signals according to [AF table](../gpio/af/index.html).
+    /// `NssMode::Pulse` requires `Phase::CaptureOnFirstTransition`.
+    pub fn with_nss(spi: SPI, pins: (S, MI, MO), nss: N, nss_mode: NssMode, freq: Hertz, mode: Mode, clocks: &Clocks, apb: &mut SPI::APB) -> Result<Self, ConfigError>
+        where S: AltFunPin, MI: AltFunPin, MO: AltFunPin, N: AltFunPin {
+        debug_assert_eq!(SPI::IDX, S::SPI_IDX);
+        debug_assert_eq!(SPI::IDX, MI::SPI_IDX);
+        debug_assert_eq!(SPI::IDX, MO::SPI_IDX);
+        debug_assert_eq!(SPI::IDX, N::SPI_IDX);
+        debug_assert!(af::has_af(&pins.0, Signal::SpiSck(SPI::IDX)));
+        debug_assert!(af::has_af(&pins.1, Signal::SpiMiso(SPI::IDX)));
+        debug_assert!(af::has_af(&pins.2, Signal::SpiMosi(SPI::IDX)));
+        debug_assert!(af::has_af(&nss, Signal::SpiNss(SPI::IDX)));
+        debug_assert!(nss_mode != NssMode::Pulse || mode.phase == Phase::CaptureOnFirstTransition);
 
-    fn read(&mut self) -> nb::Result<u8, Error> {
-        let sr = self.spi.sr().read();
+        let br = SPI::calc_br(freq, clocks)?;
 
-        Err(if sr.ovr().bit_is_set() {
-            nb::Error::Other(Error::Overrun)
-        } else if sr.modf().bit_is_set() {
-            nb::Error::Other(Error::ModeFault)
-        } else if sr.crcerr().bit_is_set() {
-            nb::Error::Other(Error::Crc)
-        } else if sr.rxne().bit_is_set() {
-            // NOTE(read_volatile) read only 1 byte (the svd2rust API only allows
-            // reading a half-word)
-            return Ok(unsafe {
-                ptr::read_volatile(self.spi.dr() as *const _ as *const u8)
-            });
-        } else {
-            nb::Error::WouldBlock
+        SPI::enable(apb);
+
+        spi.configure_cr1(br, mode);
+        spi.configure_cr2();
+        spi.configure_nss(nss_mode);
+
+        Ok(Self {
+            spi,
+            pins,
+            nss,
         })
     }
 
-    fn send(&mut self, byte: u8) -> nb::Result<(), Error> {
-        let sr = self.spi.sr().read();
+    ///Consumes self and returns SPI, PINS and NSS
+    pub fn into_raw_with_nss(self) -> (SPI, (S, MI, MO), N) {
+        (self.spi, self.pins, self.nss)
+    }
+}
 
-        Err(if sr.ovr().bit_is_set() {
-            nb::Error::Other(Error::Overrun)
-        } else if sr.modf().bit_is_set() {
-            nb::Error::Other(Error::ModeFault)
-        } else if sr.crcerr().bit_is_set() {
-            nb::Error::Other(Error::Crc)
-        } else if sr.txe().bit_is_set() {
-            // NOTE(write_volatile) see note above
-            unsafe { ptr::write_volatile(self.spi.dr() as *const _ as *mut u8, byte) }
+impl<SPI: InnerSpi, S: SCK, MI: MISO, MO: MOSI, N> Spi<SPI, S, MI, MO, N> {
+    ///Recovers from [Overrun](enum.Error.html), discarding received data.
+    ///
+    ///Until cleared, reception is stalled and every read fails.
+    pub fn clear_overrun(&mut self) {
+        self.spi.clear_overrun();
+    }
+
+    ///Recovers from [ModeFault](enum.Error.html), which happens when NSS is pulled low by another master.
+    pub fn clear_mode_fault(&mut self) {
+        self.spi.clear_mode_fault();
+    }
+
+    ///Recovers from [Crc](enum.Error.html) error.
+    pub fn clear_crc_error(&mut self) {
+        self.spi.clear_crc_error();
+    }
+
+    ///Enables hardware CRC with `polynomial` of `length`.
+    ///
+    ///CRC is calculated over both transmitted and received data, and reset by this call.
+    ///At the end of transfer, [send_crc](#method.send_crc) appends CRC to transmitted data,
+    ///while received CRC is compared by hardware, reporting mismatch as [Crc](enum.Error.html) error.
+    pub fn enable_crc(&mut self, polynomial: u16, length: CrcLength) {
+        self.spi.configure_crc(Some((polynomial, length)));
+    }
+
+    ///Disables hardware CRC
+    pub fn disable_crc(&mut self) {
+        self.spi.configure_crc(None);
+    }
+
+    ///Transmits CRC after the last data written into TX FIFO.
+    ///
+    ///Received CRC is placed into RX FIFO, and must be read out as data.
+    pub fn send_crc(&mut self) {
+        self.spi.cr1().modify(|_, w| w.crcnext().set_bit());
+    }
+
+    ///Transfers `words` in place as frame followed by CRC, which is validated by hardware.
+    ///
+    ///CRC is reset before transfer, so it covers only `words`, as in fixed-size frames of sensors.
+    ///Mismatch of received CRC is reported as [Crc](enum.Error.html) error, once whole frame is received.
+    ///Empty `words` are not transferred, as CRC can't follow them.
+    ///
+    ///# Pancis:
+    ///
+    ///In debug mode the function checks that CRC is enabled.
+    pub fn transfer_with_crc<'w>(&mut self, words: &'w mut [u8]) -> Result<&'w [u8], Error> {
+        debug_assert!(self.spi.cr1().read().crcen().bit_is_set());
+        if words.is_empty() {
+            return Ok(words);
+        }
+
+        self.spi.reset_crc();
+        let last = words.len() - 1;
+        for (idx, word) in words.iter_mut().enumerate() {
+            block!(self.spi.send_u8(*word))?;
+            //CRC follows immediately, once requested after the last data is written
+            if idx == last {
+                self.send_crc();
+            }
+            *word = block!(self.spi.read_u8())?;
+        }
+        self.spi.check_crc()?;
+
+        Ok(words)
+    }
+
+    ///Writes `words` as frame followed by CRC, discarding received data.
+    ///
+    ///CRC received at the same time is validated as in [transfer_with_crc](#method.transfer_with_crc).
+    ///Empty `words` are not transferred.
+    ///
+    ///# Pancis:
+    ///
+    ///In debug mode the function checks that CRC is enabled.
+    pub fn write_with_crc(&mut self, words: &[u8]) -> Result<(), Error> {
+        debug_assert!(self.spi.cr1().read().crcen().bit_is_set());
+        if words.is_empty() {
             return Ok(());
-        } else {
-            nb::Error::WouldBlock
-        })
+        }
+
+        self.spi.reset_crc();
+        let last = words.len() - 1;
+        for (idx, word) in words.iter().enumerate() {
+            block!(self.spi.send_u8(*word))?;
+            if idx == last {
+                self.send_crc();
+            }
+            block!(self.spi.read_u8())?;
+        }
+
+        self.spi.check_crc()
+    }
+
+    ///Returns CRC calculated over transmitted data
+    pub fn tx_crc(&self) -> u16 {
+        self.spi.txcrcr().read().tx_crc().bits()
+    }
+
+    ///Returns CRC calculated over received data
+    pub fn rx_crc(&self) -> u16 {
+        self.spi.rxcrcr().read().rx_crc().bits()
+    }
+
+    ///Transfers `words` in place, or returns [Timeout](enum.Error.html) once started `timer` expires.
+    ///
+    ///Timeout leaves SPI in the middle of transfer, nothing is cleaned up: byte of interrupted
+    ///transfer may still arrive in RX FIFO, so it must be drained with `read` before the next one.
+    pub fn transfer_with_timeout<'w, C: CountDown>(&mut self, words: &'w mut [u8], timer: &mut C) -> Result<&'w [u8], Error> {
+        let spi = &self.spi;
+        for word in words.iter_mut() {
+            block_timeout(timer, || spi.send_u8(*word))?;
+            *word = block_timeout(timer, || spi.read_u8())?;
+        }
+
+        Ok(words)
+    }
+
+    ///Writes `words`, or returns [Timeout](enum.Error.html) once started `timer` expires.
+    pub fn write_with_timeout<C: CountDown>(&mut self, words: &[u8], timer: &mut C) -> Result<(), Error> {
+        let spi = &self.spi;
+        for word in words {
+            block_timeout(timer, || spi.send_u8(*word))?;
+            block_timeout(timer, || spi.read_u8())?;
+        }
+
+        Ok(())
+    }
+
+    ///Runs loopback [self-test](../selftest/index.html), transferring `PATTERN`.
+    ///
+    ///MOSI must be bridged to MISO, e.g. by test fixture. Slave select is managed by software
+    ///during test, so NSS input cannot raise mode fault.
+    pub fn selftest(&mut self) -> Result<(), selftest::Fault> {
+        let cr1 = self.spi.cr1().read().bits();
+
+        //SSM and SSI must be changed while SPI is disabled
+        self.spi.wait_idle();
+        self.spi.cr1().modify(|_, w| w.spe().clear_bit());
+        self.spi.cr1().modify(|_, w| w.ssm().set_bit().ssi().set_bit());
+        self.spi.cr1().modify(|_, w| w.spe().set_bit());
+        self.spi.drain_rx();
+
+        let spi = &self.spi;
+        let result = selftest::PATTERN.iter().try_for_each(|&sent| {
+            selftest::poll(|| spi.send_u8(sent))?;
+            let received = selftest::poll(|| spi.read_u8())?;
+            selftest::compare(sent, received)
+        });
+
+        if result == Err(selftest::Fault::Transfer) {
+            self.spi.clear_overrun();
+            self.spi.clear_crc_error();
+        }
+        self.spi.wait_idle();
+        self.spi.drain_rx();
+        self.spi.cr1().modify(|_, w| w.spe().clear_bit());
+        self.spi.cr1().write(|w| unsafe { w.bits(cr1) });
+
+        result
+    }
+}
+
+impl<SPI: InnerSpi, S: SCK, MI: MISO, MO: MOSI, N> FullDuplex<u8> for Spi<SPI, S, MI, MO, N> {
+    type Error = Error;
+
+    #[inline]
+    fn read(&mut self) -> nb::Result<u8, Error> {
+        self.spi.read_u8()
+    }
+
+    #[inline]
+    fn send(&mut self, byte: u8) -> nb::Result<(), Error> {
+        self.spi.send_u8(byte)
     }
 }
 
-impl<SPI: InnerSpi, S: SCK, MI: MISO, MO: MOSI> embedded_hal::blocking::spi::transfer::Default<u8> for Spi<SPI, S, MI, MO> {}
+impl<SPI: InnerSpi, S: SCK, MI: MISO, MO: MOSI, N> embedded_hal::blocking::spi::transfer::Default<u8> for Spi<SPI, S, MI, MO, N> {}
+
+impl<SPI: InnerSpi, S: SCK, MI: MISO, MO: MOSI, N> embedded_hal::blocking::spi::write::Default<u8> for Spi<SPI, S, MI, MO, N> {}
+
+pub mod half_duplex;
+pub use self::half_duplex::{SpiBidi, SpiRxOnly};
+pub mod device;
+pub use self::device::Device;
+
+#[cfg(feature = "STM32L475VG")]
+mod stm32l475vg;
 
-impl<SPI: InnerSpi, S: SCK, MI: MISO, MO: MOSI> embedded_hal::blocking::spi::write::Default<u8> for Spi<SPI, S, MI, MO> {}
+#[cfg(test)]
+mod tests {
+    use super::*;
 
-#[cfg(feature = "STM32L476VG")]
-mod stm32l476vg;
+    #[test]
+    fn calculate_br() {
+        let clock = 80_000_000;
+
+        assert_eq!(calc_br(clock, clock), Err(ConfigError::FrequencyTooHigh));
+        assert_eq!(calc_br(clock, clock / 2), Ok(0b000));
+        assert_eq!(calc_br(clock, 10_000_000), Ok(0b010));
+        assert_eq!(calc_br(clock, 1_000_000), Ok(0b110));
+        assert_eq!(calc_br(clock, 1_250_000), Ok(0b101));
+        assert_eq!(calc_br(clock, 1_249_999), Ok(0b110));
+        assert_eq!(calc_br(clock, clock / 256), Ok(0b111));
+        assert_eq!(calc_br(clock, clock / 512), Err(ConfigError::FrequencyTooLow));
+        assert_eq!(calc_br(clock, 0), Err(ConfigError::FrequencyTooLow));
+    }
+}
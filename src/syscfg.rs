@@ -0,0 +1,193 @@
+//! System configuration controller (SYSCFG)
+//!
+//! See Reference manual Ch. 9
+
+use stm32l4::stm32l4x5::SYSCFG as Inner;
+use stm32l4::stm32l4x5::{GPIOA, GPIOB, GPIOC, GPIOD, GPIOE, GPIOF, GPIOG, GPIOH};
+use crate::rcc::APB2;
+
+#[derive(Copy, Clone, PartialEq, Eq, Debug)]
+#[repr(u8)]
+/// GPIO port that can be used as EXTI source.
+pub enum Port {
+    /// PAx
+    A = 0b000,
+    /// PBx
+    B = 0b001,
+    /// PCx
+    C = 0b010,
+    /// PDx
+    D = 0b011,
+    /// PEx
+    E = 0b100,
+    /// PFx
+    F = 0b101,
+    /// PGx
+    G = 0b110,
+    /// PHx
+    H = 0b111,
+}
+
+/// Describes GPIO port that can be routed to EXTI lines.
+///
+/// Note: MUST not be implemented by user.
+pub trait ExtiPort {
+    /// Value of EXTICR field
+    const PORT: Port;
+}
+
+macro_rules! impl_exti_port {
+    ($($GPIOX:ident: $port:ident,)+) => {
+        $(
+            impl ExtiPort for $GPIOX {
+                const PORT: Port = Port::$port;
+            }
+        )+
+    }
+}
+
+impl_exti_port!(
+    GPIOA: A,
+    GPIOB: B,
+    GPIOC: C,
+    GPIOD: D,
+    GPIOE: E,
+    GPIOF: F,
+    GPIOG: G,
+    GPIOH: H,
+);
+
+#[derive(Copy, Clone, PartialEq, Eq, Debug)]
+#[repr(u8)]
+/// Memory mapped at address 0x0000_0000
+pub enum MemoryMapping {
+    /// Main Flash memory
+    MainFlash = 0b000,
+    /// System Flash memory
+    SystemFlash = 0b001,
+    /// FMC bank 1 (NOR/PSRAM 1 and 2)
+    Fmc = 0b010,
+    /// SRAM1
+    Sram1 = 0b011,
+    /// QUADSPI memory
+    QuadSpi = 0b110,
+}
+
+#[derive(Copy, Clone, PartialEq, Eq, Debug)]
+#[repr(u8)]
+/// Floating point unit interrupts
+///
+/// Value is offset within CFGR1's FPU_IE field.
+pub enum FpuInterrupt {
+    /// Invalid operation
+    InvalidOperation = 0,
+    /// Divide-by-zero
+    DivideByZero = 1,
+    /// Underflow
+    Underflow = 2,
+    /// Overflow
+    Overflow = 3,
+    /// Input denormal
+    InputDenormal = 4,
+    /// Inexact
+    Inexact = 5,
+}
+
+/// VDDA in mV, below which analog switch booster is needed
+pub const BOOSTER_VDDA_MV: u16 = 2_400;
+
+/// SYSCFG peripheral
+pub struct SYSCFG {
+    inner: Inner,
+}
+
+impl SYSCFG {
+    /// Enables clock of SYSCFG.
+    pub fn enable(apb: &mut APB2) {
+        apb.enr().modify(|_, w| w.syscfgen().set_bit());
+    }
+
+    /// Creates new instance of SYSCFG.
+    ///
+    /// Takes ownership over device SYSCFG
+    pub fn new(inner: Inner) -> Self {
+        Self {
+            inner,
+        }
+    }
+
+    /// Selects GPIO port to be source of EXTI `line`.
+    ///
+    /// Each EXTI line `x` can be connected only to pin `x` of one of ports.
+    ///
+    /// # Panics:
+    ///
+    /// If `line` is greater than 15.
+    pub fn set_exti_source(&mut self, line: u8, port: Port) {
+        assert!(line < 16);
+
+        // EXTICR fields are 4 bits wide, each register holds 4 lines
+        let offset = (line % 4) * 4;
+        let port = port as u32;
+        match line / 4 {
+            0 => self.inner.exticr1.modify(|r, w| unsafe { w.bits((r.bits() & !(0b1111 << offset)) | (port << offset)) }),
+            1 => self.inner.exticr2.modify(|r, w| unsafe { w.bits((r.bits() & !(0b1111 << offset)) | (port << offset)) }),
+            2 => self.inner.exticr3.modify(|r, w| unsafe { w.bits((r.bits() & !(0b1111 << offset)) | (port << offset)) }),
+            _ => self.inner.exticr4.modify(|r, w| unsafe { w.bits((r.bits() & !(0b1111 << offset)) | (port << offset)) }),
+        }
+    }
+
+    /// Selects memory that is mapped at address 0x0000_0000.
+    pub fn set_memory_mapping(&mut self, mapping: MemoryMapping) {
+        self.inner.memrmp.modify(|_, w| unsafe { w.mem_mode().bits(mapping as u8) });
+    }
+
+    /// Returns memory that is mapped at address 0x0000_0000, or `None` for reserved value.
+    pub fn memory_mapping(&self) -> Option<MemoryMapping> {
+        match self.inner.memrmp.read().mem_mode().bits() {
+            0b000 => Some(MemoryMapping::MainFlash),
+            0b001 => Some(MemoryMapping::SystemFlash),
+            0b010 => Some(MemoryMapping::Fmc),
+            0b011 => Some(MemoryMapping::Sram1),
+            0b110 => Some(MemoryMapping::QuadSpi),
+            _ => None,
+        }
+    }
+
+    /// Swaps Flash bank 1 and bank 2 in memory map.
+    pub fn swap_flash_banks(&mut self, value: bool) {
+        self.inner.memrmp.modify(|_, w| w.fb_mode().bit(value));
+    }
+
+    /// Starts listening for FPU interrupt.
+    pub fn subscribe_fpu(&mut self, interrupt: FpuInterrupt) {
+        self.inner.cfgr1.modify(|r, w| unsafe { w.bits(r.bits() | (1 << (26 + interrupt as u8))) });
+    }
+
+    /// Stops listening for FPU interrupt.
+    pub fn unsubscribe_fpu(&mut self, interrupt: FpuInterrupt) {
+        self.inner.cfgr1.modify(|r, w| unsafe { w.bits(r.bits() & !(1 << (26 + interrupt as u8))) });
+    }
+
+    /// Sets I/O analog switch voltage booster on/off.
+    ///
+    /// Should be turned on when ADC is used with VDDA below `BOOSTER_VDDA_MV`
+    /// in order to keep analog switches performance.
+    pub fn analog_booster(&mut self, is_on: bool) {
+        self.inner.cfgr1.modify(|_, w| w.boosten().bit(is_on));
+    }
+
+    /// Sets analog switch voltage booster on, if `vdda_mv` is below `BOOSTER_VDDA_MV`, or off otherwise.
+    ///
+    /// Returns whether booster is on.
+    pub fn analog_booster_for(&mut self, vdda_mv: u16) -> bool {
+        let is_on = vdda_mv < BOOSTER_VDDA_MV;
+        self.analog_booster(is_on);
+        is_on
+    }
+
+    /// Consumes self and returns device's SYSCFG
+    pub fn into_raw(self) -> Inner {
+        self.inner
+    }
+}
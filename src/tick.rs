@@ -0,0 +1,279 @@
+//! System tick from selectable timer
+//!
+//! 1 kHz tick is generated by SysTick, basic timer (TIM6, TIM7) or low power timer
+//! (LPTIM1, LPTIM2), so RTOS ports and time drivers can bind to it while SysTick stays
+//! available for [Delay](../delay/struct.Delay.html).
+//! Whatever the source is, ticks are counted in [now](fn.now.html) and reported to callback:
+//!
+//! ```rust, ignore
+//! fn on_tick(ticks: u32) {
+//!     // advance RTOS time
+//! }
+//!
+//! let mut tick = Tick::tim6(device.TIM6, &clocks, &mut rcc.apb1);
+//! tick::set_callback(Some(on_tick));
+//!
+//! // TIM6_DACUNDER interrupt
+//! tick.handle_interrupt();
+//! ```
+//!
+//! Clocks, which are not multiple of 1 kHz, e.g. LSE, are followed by alternating length
+//! of tick periods, so tick rate is exact on average.
+
+use core::cell::Cell;
+
+use cortex_m::interrupt::{self, Mutex};
+use cortex_m::peripheral::syst::SystClkSource;
+use cortex_m::peripheral::SYST;
+use stm32l4::stm32l4x5::{LPTIM1, LPTIM2, TIM6, TIM7};
+
+use crate::pwm::lptim::ClockSource;
+use crate::rcc::{APB1, CCIPR, Clocks};
+
+/// Tick frequency in Hz
+pub const TICK_HZ: u32 = 1_000;
+
+/// Callback called on every tick with number of ticks since start
+pub type Callback = fn(u32);
+
+static TICKS: Mutex<Cell<u32>> = Mutex::new(Cell::new(0));
+static CALLBACK: Mutex<Cell<Option<Callback>>> = Mutex::new(Cell::new(None));
+
+/// Returns number of ticks since tick source has been started, wrapping around.
+pub fn now() -> u32 {
+    interrupt::free(|cs| TICKS.borrow(cs).get())
+}
+
+/// Sets `callback` called on every tick from interrupt handler, or removes it with `None`.
+pub fn set_callback(callback: Option<Callback>) {
+    interrupt::free(|cs| CALLBACK.borrow(cs).set(callback));
+}
+
+/// Timer generating ticks.
+///
+/// Note: MUST not be implemented by user.
+pub trait Source {
+    /// Checks and clears flag of elapsed period
+    fn take_period(&mut self) -> bool;
+
+    /// Sets length of following periods in source clock cycles
+    fn set_period(&mut self, cycles: u32);
+}
+
+/// Returns length of next tick period, distributing `remainder` cycles of clock, which are
+/// left over `period` cycles per tick, across `TICK_HZ` ticks.
+///
+/// `error` accumulates fraction of cycle between calls.
+pub fn next_period(period: u32, remainder: u32, error: &mut u32) -> u32 {
+    *error += remainder;
+    match *error >= TICK_HZ {
+        true => {
+            *error -= TICK_HZ;
+            period + 1
+        },
+        false => period,
+    }
+}
+
+/// Tick generator
+pub struct Tick<SRC> {
+    source: SRC,
+    period: u32,
+    remainder: u32,
+    error: u32,
+}
+
+impl<SRC: Source> Tick<SRC> {
+    /// Starts counting ticks of `source`, running at `clock` Hz
+    fn start(mut source: SRC, clock: u32) -> Self {
+        interrupt::free(|cs| TICKS.borrow(cs).set(0));
+
+        let period = clock / TICK_HZ;
+        source.set_period(period);
+
+        Self {
+            source,
+            period,
+            remainder: clock % TICK_HZ,
+            error: 0,
+        }
+    }
+
+    /// Counts elapsed tick and calls callback, to be called from interrupt of source.
+    pub fn handle_interrupt(&mut self) {
+        if !self.source.take_period() {
+            return;
+        }
+
+        if self.remainder != 0 {
+            let period = next_period(self.period, self.remainder, &mut self.error);
+            self.source.set_period(period);
+        }
+
+        let (ticks, callback) = interrupt::free(|cs| {
+            let ticks = TICKS.borrow(cs);
+            ticks.set(ticks.get().wrapping_add(1));
+            (ticks.get(), CALLBACK.borrow(cs).get())
+        });
+        if let Some(callback) = callback {
+            callback(ticks);
+        }
+    }
+
+    /// Releases source, which keeps running
+    pub fn free(self) -> SRC {
+        self.source
+    }
+}
+
+impl Source for SYST {
+    fn take_period(&mut self) -> bool {
+        self.has_wrapped()
+    }
+
+    fn set_period(&mut self, cycles: u32) {
+        // New reload value is applied at wrap
+        self.set_reload(cycles - 1);
+    }
+}
+
+impl Tick<SYST> {
+    /// Generates ticks with SysTick exception, clocked by core.
+    pub fn syst(mut syst: SYST, clocks: &Clocks) -> Self {
+        syst.disable_counter();
+        syst.set_clock_source(SystClkSource::Core);
+
+        let mut tick = Self::start(syst, clocks.hclk().0);
+        tick.source.clear_current();
+        tick.source.enable_interrupt();
+        tick.source.enable_counter();
+        tick
+    }
+}
+
+macro_rules! impl_basic_tick {
+    ($($TIMx:ident: [constructor: $timx:ident; $enr:ident: $en:ident, $rstr:ident: $rst:ident],)+) => {
+        $(
+            impl Source for $TIMx {
+                fn take_period(&mut self) -> bool {
+                    match self.sr.read().uif().bit_is_set() {
+                        true => {
+                            self.sr.modify(|_, w| w.uif().clear_bit());
+                            true
+                        },
+                        false => false,
+                    }
+                }
+
+                fn set_period(&mut self, cycles: u32) {
+                    self.arr.write(|w| unsafe { w.arr().bits((cycles - 1) as u16) });
+                }
+            }
+
+            impl Tick<$TIMx> {
+                /// Generates ticks with update interrupt of timer.
+                pub fn $timx(tim: $TIMx, clocks: &Clocks, apb: &mut APB1) -> Self {
+                    apb.$enr().modify(|_, w| w.$en().set_bit());
+                    apb.$rstr().modify(|_, w| w.$rst().set_bit());
+                    apb.$rstr().modify(|_, w| w.$rst().clear_bit());
+
+                    // Prescaler keeps period within 16 bit counter
+                    let timer_clock = clocks.timclk1().0;
+                    let psc = timer_clock / TICK_HZ / (1 << 16);
+                    tim.psc.write(|w| unsafe { w.psc().bits(psc as u16) });
+
+                    let tick = Self::start(tim, timer_clock / (psc + 1));
+                    // Load prescaler, without reporting tick
+                    tick.source.egr.write(|w| w.ug().set_bit());
+                    tick.source.sr.modify(|_, w| w.uif().clear_bit());
+                    tick.source.dier.write(|w| w.uie().set_bit());
+                    tick.source.cr1.modify(|_, w| w.cen().set_bit());
+                    tick
+                }
+            }
+        )+
+    }
+}
+
+impl_basic_tick!(
+    TIM6: [constructor: tim6; enr1: tim6en, rstr1: tim6rst],
+    TIM7: [constructor: tim7; enr1: tim7en, rstr1: tim7rst],
+);
+
+macro_rules! impl_lp_tick {
+    ($($LPTIMx:ident: [constructor: $lptimx:ident; $enr:ident: $en:ident, $rstr:ident: $rst:ident, $sel:ident, $clk:ident],)+) => {
+        $(
+            impl Source for $LPTIMx {
+                fn take_period(&mut self) -> bool {
+                    match self.isr.read().arrm().bit_is_set() {
+                        true => {
+                            self.icr.write(|w| w.arrmcf().set_bit().arrokcf().set_bit());
+                            true
+                        },
+                        false => false,
+                    }
+                }
+
+                fn set_period(&mut self, cycles: u32) {
+                    // Counter has just wrapped, so new value is above it
+                    self.arr.write(|w| unsafe { w.arr().bits((cycles - 1) as u16) });
+                }
+            }
+
+            impl Tick<$LPTIMx> {
+                /// Generates ticks with auto-reload match interrupt of low power timer.
+                ///
+                /// With LSE or LSI ticks continue in Stop modes (LPTIM2 down to Stop 1).
+                pub fn $lptimx(tim: $LPTIMx, clock: ClockSource, clocks: &Clocks, apb: &mut APB1, ccipr: &mut CCIPR) -> Self {
+                    if clock == ClockSource::Hsi16 {
+                        ccipr.hsi16_enable();
+                    }
+                    ccipr.inner().modify(|_, w| unsafe { w.$sel().bits(clock as u8) });
+                    apb.$enr().modify(|_, w| w.$en().set_bit());
+                    apb.$rstr().modify(|_, w| w.$rst().set_bit());
+                    apb.$rstr().modify(|_, w| w.$rst().clear_bit());
+
+                    // Prescaler is power of 2, keeping period within 16 bit counter
+                    let kernel_clock = clocks.$clk().0;
+                    let mut presc = 0;
+                    while (kernel_clock >> presc) / TICK_HZ >= 1 << 16 {
+                        presc += 1;
+                    }
+
+                    // CFGR and IER are writable only while disabled
+                    tim.cfgr.write(|w| unsafe { w.presc().bits(presc) });
+                    tim.ier.write(|w| w.arrmie().set_bit());
+                    tim.cr.write(|w| w.enable().set_bit());
+
+                    let tick = Self::start(tim, kernel_clock >> presc);
+                    while tick.source.isr.read().arrok().bit_is_clear() {}
+                    tick.source.icr.write(|w| w.arrokcf().set_bit());
+                    tick.source.cr.modify(|_, w| w.cntstrt().set_bit());
+                    tick
+                }
+            }
+        )+
+    }
+}
+
+impl_lp_tick!(
+    LPTIM1: [constructor: lptim1; enr1: lptim1en, rstr1: lptim1rst, lptim1sel, lptim1_clk],
+    LPTIM2: [constructor: lptim2; enr2: lptim2en, rstr2: lptim2rst, lptim2sel, lptim2_clk],
+);
+
+#[cfg(test)]
+mod tests {
+    use super::{next_period, TICK_HZ};
+
+    #[test]
+    fn fractional_period() {
+        // LSE gives 32.768 cycles per tick
+        let mut error = 0;
+        let total: u32 = (0..TICK_HZ).map(|_| next_period(32, 768, &mut error)).sum();
+        assert_eq!(total, 32_768);
+        assert_eq!(error, 0);
+
+        let mut error = 0;
+        assert_eq!(next_period(80_000, 0, &mut error), 80_000);
+    }
+}
@@ -13,7 +13,7 @@ macro_rules! impl_struct {
     }
 }
 
-impl_struct!(Bps, Hertz, KiloHertz, MegaHertz,);
+impl_struct!(Bps, Hertz, KiloHertz, MegaHertz, MilliSeconds,);
 
 impl Into<Hertz> for KiloHertz {
     fn into(self) -> Hertz {
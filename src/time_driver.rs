@@ -0,0 +1,264 @@
+//! Time driver of embassy on LPTIM1
+//!
+//! 16 bit counter of LPTIM1 is extended in software to 64 bits on its overflow interrupt,
+//! while compare match wakes up tasks, which wait for timers. Clocked from LSE, time keeps
+//! running in Stop modes. Driver is registered with `embassy-time-driver`, once
+//! `embassy-time-driver` feature is enabled, and only needs timer to be started and interrupt bound:
+//!
+//! ```rust, ignore
+//! let timer = TimeDriver::lptim1(device.LPTIM1, ClockSource::Lse, &clocks, &mut rcc.apb1, &mut rcc.ccipr);
+//!
+//! #[interrupt]
+//! fn LPTIM1() {
+//!     time_driver::on_interrupt();
+//! }
+//! ```
+//!
+//! Tick rate is kernel clock of LPTIM1, which must be LSE at 32768 Hz,
+//! the tick rate this crate selects for `embassy-time-driver`.
+//! Up to `QUEUE_LEN` tasks can wait at once, when queue is full the task with the earliest
+//! deadline is woken up early, and it reschedules itself.
+
+use core::cell::{Cell, RefCell};
+use core::cmp;
+use core::task::Waker;
+
+use cortex_m::interrupt::{self, CriticalSection, Mutex};
+use embassy_time_driver::Driver;
+use stm32l4::stm32l4x5::{lptim1, LPTIM1};
+
+use crate::pwm::lptim::ClockSource;
+use crate::rcc::{APB1, CCIPR, Clocks};
+use crate::time::Hertz;
+
+/// Maximal number of tasks waiting for time at once
+pub const QUEUE_LEN: usize = 8;
+
+/// No alarm is set
+const NO_ALARM: u64 = u64::MAX;
+/// Number of ticks per counter period
+const PERIOD_TICKS: u64 = 1 << 16;
+
+/// Number of counter periods since start
+static PERIODS: Mutex<Cell<u64>> = Mutex::new(Cell::new(0));
+static ALARM: Mutex<Cell<u64>> = Mutex::new(Cell::new(NO_ALARM));
+
+/// Deadline with waker of waiting task
+type Entry = Option<(u64, Waker)>;
+const NO_ENTRY: Entry = None;
+static QUEUE: Mutex<RefCell<[Entry; QUEUE_LEN]>> = Mutex::new(RefCell::new([NO_ENTRY; QUEUE_LEN]));
+
+fn registers() -> &'static lptim1::RegisterBlock {
+    unsafe { &*LPTIM1::ptr() }
+}
+
+/// Reads counter, which is reliable only when two reads match
+fn counter(registers: &lptim1::RegisterBlock) -> u16 {
+    loop {
+        let first = registers.cnt.read().cnt().bits();
+        if registers.cnt.read().cnt().bits() == first {
+            return first;
+        }
+    }
+}
+
+fn ticks(cs: &CriticalSection) -> u64 {
+    let registers = registers();
+    let periods = PERIODS.borrow(cs).get();
+    // Overflow is flagged on match with ARR, so time is shifted by one tick for
+    // period boundary to match counter wrap
+    let counter = counter(registers).wrapping_add(1);
+    // Counter wrapped before it was read, but overflow isn't handled yet
+    let is_wrapped = registers.isr.read().arrm().bit_is_set() && counter < 0x8000;
+
+    (periods + is_wrapped as u64) * PERIOD_TICKS + counter as u64
+}
+
+/// Programs compare match for alarm, once it is within one counter period.
+///
+/// Returns whether alarm is already due.
+fn arm(cs: &CriticalSection) -> bool {
+    let alarm = ALARM.borrow(cs).get();
+    if alarm == NO_ALARM {
+        return false;
+    }
+
+    let now = ticks(cs);
+    if now >= alarm {
+        return true;
+    } else if alarm - now >= PERIOD_TICKS {
+        // Armed on one of the following overflows
+        return false;
+    }
+
+    let registers = registers();
+    registers.cmp.write(|w| unsafe { w.cmp().bits((alarm as u16).wrapping_sub(1)) });
+    while registers.isr.read().cmpok().bit_is_clear() {}
+    registers.icr.write(|w| w.cmpokcf().set_bit());
+
+    // Counter may have passed compare value during write
+    ticks(cs) >= alarm
+}
+
+/// Returns current time in ticks since driver has been started.
+pub fn now() -> u64 {
+    interrupt::free(ticks)
+}
+
+/// Wakes tasks, which deadline has passed, and arms alarm for the earliest remaining one
+fn dispatch(cs: &CriticalSection) {
+    let mut queue = QUEUE.borrow(cs).borrow_mut();
+    loop {
+        let now = ticks(cs);
+        let mut next = NO_ALARM;
+        for entry in queue.iter_mut() {
+            let is_due = match entry {
+                Some((at, _)) => *at <= now,
+                None => false,
+            };
+            match is_due {
+                true => {
+                    if let Some((_, waker)) = entry.take() {
+                        waker.wake();
+                    }
+                },
+                false => {
+                    if let Some((at, _)) = entry {
+                        next = cmp::min(next, *at);
+                    }
+                },
+            }
+        }
+
+        ALARM.borrow(cs).set(next);
+        // Alarm may become due while it's being armed
+        if !arm(cs) {
+            break;
+        }
+    }
+}
+
+/// Schedules `waker` to be woken up at time `at` in ticks.
+///
+/// Task, which is already waiting, keeps the earliest of its deadlines.
+pub fn schedule_wake(at: u64, waker: &Waker) {
+    interrupt::free(|cs| {
+        {
+            let mut queue = QUEUE.borrow(cs).borrow_mut();
+            let same_task = queue.iter().position(|entry| match entry {
+                Some((_, queued)) => queued.will_wake(waker),
+                None => false,
+            });
+            let free = queue.iter().position(|entry| entry.is_none());
+
+            match (same_task, free) {
+                (Some(index), _) => {
+                    if let Some((queued_at, _)) = &mut queue[index] {
+                        *queued_at = cmp::min(*queued_at, at);
+                    }
+                },
+                (None, Some(index)) => queue[index] = Some((at, waker.clone())),
+                (None, None) => {
+                    // Queue is full, so the earliest task is woken up early to make room
+                    let earliest = (0..QUEUE_LEN).min_by_key(|&index| queue[index].as_ref().map(|(at, _)| *at)).unwrap_or(0);
+                    if let Some((_, woken)) = queue[earliest].replace((at, waker.clone())) {
+                        woken.wake();
+                    }
+                },
+            }
+        }
+
+        dispatch(cs);
+    });
+}
+
+/// Handles overflow and compare match, to be called from LPTIM1 interrupt.
+pub fn on_interrupt() {
+    interrupt::free(|cs| {
+        let registers = registers();
+        let isr = registers.isr.read();
+        if isr.arrm().bit_is_set() {
+            registers.icr.write(|w| w.arrmcf().set_bit());
+            let periods = PERIODS.borrow(cs);
+            periods.set(periods.get() + 1);
+        }
+        if isr.cmpm().bit_is_set() {
+            registers.icr.write(|w| w.cmpmcf().set_bit());
+        }
+
+        dispatch(cs);
+    });
+}
+
+/// Time driver registered with `embassy-time-driver`
+pub struct Lptim1Driver;
+
+impl Driver for Lptim1Driver {
+    fn now(&self) -> u64 {
+        now()
+    }
+
+    fn schedule_wake(&self, at: u64, waker: &Waker) {
+        schedule_wake(at, waker);
+    }
+}
+
+embassy_time_driver::time_driver_impl!(static DRIVER: Lptim1Driver = Lptim1Driver);
+
+/// Owner of LPTIM1, which runs time base
+pub struct TimeDriver {
+    tim: LPTIM1,
+    frequency: Hertz,
+}
+
+impl TimeDriver {
+    /// Starts time base on LPTIM1 clocked by `clock`, resetting time to zero.
+    ///
+    /// LPTIM1 interrupt must be unmasked in NVIC to extend counter and wake up tasks.
+    ///
+    /// # Pancis:
+    ///
+    /// In debug mode the function checks that kernel clock matches tick rate of `embassy-time-driver`.
+    pub fn lptim1(tim: LPTIM1, clock: ClockSource, clocks: &Clocks, apb: &mut APB1, ccipr: &mut CCIPR) -> Self {
+        if clock == ClockSource::Hsi16 {
+            ccipr.hsi16_enable();
+        }
+        ccipr.inner().modify(|_, w| unsafe { w.lptim1sel().bits(clock as u8) });
+        // Kernel clock is read back from CCIPR, so it's checked once source is selected
+        debug_assert_eq!(clocks.lptim1_clk().0 as u64, embassy_time_driver::TICK_HZ);
+        apb.enr1().modify(|_, w| w.lptim1en().set_bit());
+        apb.rstr1().modify(|_, w| w.lptim1rst().set_bit());
+        apb.rstr1().modify(|_, w| w.lptim1rst().clear_bit());
+
+        interrupt::free(|cs| {
+            PERIODS.borrow(cs).set(0);
+            ALARM.borrow(cs).set(NO_ALARM);
+        });
+
+        // CFGR and IER are writable only while disabled
+        tim.cfgr.reset();
+        tim.ier.write(|w| w.arrmie().set_bit().cmpmie().set_bit());
+        tim.cr.write(|w| w.enable().set_bit());
+        tim.arr.write(|w| unsafe { w.arr().bits(0xFFFF) });
+        while tim.isr.read().arrok().bit_is_clear() {}
+        tim.icr.write(|w| w.arrokcf().set_bit());
+        tim.cr.modify(|_, w| w.cntstrt().set_bit());
+
+        Self {
+            tim,
+            frequency: clocks.lptim1_clk(),
+        }
+    }
+
+    /// Returns tick rate of time base
+    pub fn frequency(&self) -> Hertz {
+        self.frequency
+    }
+
+    /// Stops time base, releasing LPTIM1
+    pub fn free(self) -> LPTIM1 {
+        self.tim.cr.write(|w| w.enable().clear_bit());
+        interrupt::free(|cs| ALARM.borrow(cs).set(NO_ALARM));
+        self.tim
+    }
+}
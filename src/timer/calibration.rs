@@ -0,0 +1,131 @@
+//! Measurement of internal clocks on TIM16/TIM17 input remap
+//!
+//! Input 1 of TIM16 and TIM17 can be connected to internal clocks instead of GPIO,
+//! so their frequency can be captured against accurate timer clock (HSE or calibrated HSI).
+//! It is used to measure actual LSI frequency, which varies a lot across parts and
+//! temperature, to compute precise IWDG timeouts:
+//!
+//! ```rust, ignore
+//! let lsi = timer::calibration::measure_lsi(&mut device.TIM16, &clocks, &mut rcc.apb2)?;
+//! let mut watchdog = IndependentWatchdog::with_lsi(device.IWDG, lsi);
+//! ```
+//!
+//! See Reference manual Ch. 30.6.21 and 30.6.22
+
+use stm32l4::stm32l4x5::{TIM16, TIM17};
+
+use crate::rcc::{APB2, Clocks};
+use crate::time::Hertz;
+
+use super::Timer;
+
+/// Input 1 (TI1) source of TIM16
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum Tim16Input {
+    /// GPIO pin
+    Gpio = 0b00,
+    /// LSI oscillator
+    Lsi = 0b01,
+    /// LSE oscillator
+    Lse = 0b10,
+    /// RTC wakeup interrupt
+    RtcWakeup = 0b11,
+}
+
+/// Input 1 (TI1) source of TIM17
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum Tim17Input {
+    /// GPIO pin
+    Gpio = 0b00,
+    /// MSI oscillator
+    Msi = 0b01,
+    /// HSE oscillator divided by 32
+    HseDiv32 = 0b10,
+    /// Microcontroller clock output (MCO)
+    Mco = 0b11,
+}
+
+impl Timer<TIM16> {
+    /// Connects input 1 of the timer to `input`.
+    pub fn remap_input(&mut self, input: Tim16Input) {
+        self.tim.or1.write(|w| unsafe { w.ti1_rmp().bits(input as u8) });
+    }
+}
+
+impl Timer<TIM17> {
+    /// Connects input 1 of the timer to `input`.
+    pub fn remap_input(&mut self, input: Tim17Input) {
+        self.tim.or1.write(|w| unsafe { w.ti1_rmp().bits(input as u8) });
+    }
+}
+
+/// Number of LSI periods between two captures, set by input capture prescaler
+const PERIODS_PER_CAPTURE: u32 = 8;
+/// Number of captured intervals, which are averaged
+const CAPTURES: u32 = 8;
+
+/// Waits for next capture on channel 1.
+///
+/// Returns `None` if counter wrapped twice without capture, so input clock isn't running.
+fn capture(tim: &TIM16) -> Option<u16> {
+    let mut overflows = 0;
+    loop {
+        let sr = tim.sr.read();
+        if sr.cc1if().bit_is_set() {
+            // reading CCR1 clears CC1IF
+            return Some(tim.ccr1.read().ccr1().bits());
+        }
+        if sr.uif().bit_is_set() {
+            tim.sr.modify(|_, w| w.uif().clear_bit());
+            overflows += 1;
+            if overflows > 1 {
+                return None;
+            }
+        }
+    }
+}
+
+/// Returns timer ticks elapsed during `CAPTURES` intervals between captures.
+fn capture_ticks(tim: &TIM16) -> Option<u32> {
+    let mut ticks = 0;
+    let mut last = capture(tim)?;
+    for _ in 0..CAPTURES {
+        let next = capture(tim)?;
+        ticks += u32::from(next.wrapping_sub(last));
+        last = next;
+    }
+
+    Some(ticks).filter(|&ticks| ticks > 0)
+}
+
+/// Measures frequency of LSI against timer clock, using TIM16.
+///
+/// LSI must be enabled beforehand. Measurement takes 64 LSI periods (about 2 ms),
+/// afterwards TIM16 is left in reset state.
+///
+/// Returns `None` if LSI is not running.
+pub fn measure_lsi(tim: &mut TIM16, clocks: &Clocks, apb: &mut APB2) -> Option<Hertz> {
+    apb.enr().modify(|_, w| w.tim16en().set_bit());
+    apb.rstr().modify(|_, w| w.tim16rst().set_bit());
+    apb.rstr().modify(|_, w| w.tim16rst().clear_bit());
+
+    tim.or1.write(|w| unsafe { w.ti1_rmp().bits(Tim16Input::Lsi as u8) });
+    tim.psc.write(|w| unsafe { w.psc().bits(0) });
+    tim.arr.write(|w| unsafe { w.arr().bits(0xFFFF) });
+    // IC1 is mapped on TI1 (CC1S), capturing every 8th rising edge (IC1PSC),
+    // input layout of CCMR1 shares register with output one
+    tim.ccmr1_output.write(|w| unsafe { w.bits(0b11 << 2 | 0b01) });
+    tim.ccer.write(|w| w.cc1e().set_bit());
+    tim.egr.write(|w| w.ug().set_bit());
+    tim.sr.write(|w| unsafe { w.bits(0) });
+    tim.cr1.modify(|_, w| w.cen().set_bit());
+
+    let ticks = capture_ticks(tim);
+
+    tim.cr1.modify(|_, w| w.cen().clear_bit());
+    apb.rstr().modify(|_, w| w.tim16rst().set_bit());
+    apb.rstr().modify(|_, w| w.tim16rst().clear_bit());
+
+    let periods = u64::from(PERIODS_PER_CAPTURE * CAPTURES);
+    ticks.map(|ticks| Hertz((u64::from(clocks.timclk2().0) * periods / u64::from(ticks)) as u32))
+}
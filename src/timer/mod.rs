@@ -5,6 +5,7 @@ use cortex_m::peripheral::SYST;
 use embedded_hal::timer::{CountDown, Periodic};
 use nb;
 
+use crate::common::ConfigError;
 use crate::config::SYST_MAX_RVR;
 use crate::rcc::{APB1, APB2, Clocks};
 use crate::time::Hertz;
@@ -30,12 +31,67 @@ use stm32l4::stm32l4x5::{
     TIM8, // stm32l4x5::rcc::apb2enr | apb2rstr:
 };
 
+pub mod pwm_input;
+pub use self::pwm_input::PwmInput;
+pub mod scheduler;
+pub use self::scheduler::Scheduler;
+pub mod protection;
+pub use self::protection::{BreakInput, BreakPolarity, BreakSource, Protection};
+pub mod pulse_counter;
+pub use self::pulse_counter::PulseCounter;
+pub mod calibration;
+pub use self::calibration::{Tim16Input, Tim17Input};
+
+/// Returns frequency of timer clock.
+///
+/// Timer clock is twice the APB frequency, unless APB prescaler is 1.
+/// See Reference manual Ch. 6.2
+#[inline]
+pub(crate) fn timer_clock(pclk: Hertz, ppre: u8) -> u32 {
+    match ppre {
+        1 => pclk.0,
+        _ => pclk.0 * 2,
+    }
+}
+
+/// Calculates prescaler and auto-reload values for the counter to overflow with `frequency`.
+///
+/// Returns error if `frequency` is zero or higher than `timer_clock`, or if it is
+/// too low to be reached with 16 bit prescaler.
+#[inline]
+pub(crate) fn calc_psc_arr(timer_clock: u32, frequency: u32) -> Result<(u16, u16), ConfigError> {
+    if frequency == 0 {
+        return Err(ConfigError::FrequencyTooLow);
+    }
+    let ticks = timer_clock / frequency;
+    if ticks == 0 {
+        return Err(ConfigError::FrequencyTooHigh);
+    }
+
+    let psc = u16(ticks / (1 << 16)).map_err(|_| ConfigError::FrequencyTooLow)?;
+    let arr = u16(ticks / (u32(psc) + 1)).map_err(|_| ConfigError::FrequencyTooLow)?;
+
+    Ok((psc, arr))
+}
+
 /// Possible timer events
 pub enum Event {
     /// Interrupt on timeout.
     Timeout,
 }
 
+/// Source of trigger output (TRGO), which drives ADC and DAC conversions
+/// or slave timers.
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum MasterMode {
+    /// Software reset (UG bit) or reset from slave mode controller
+    Reset = 0b000,
+    /// Counter enable
+    Enable = 0b001,
+    /// Update event, i.e. every timeout
+    Update = 0b010,
+}
+
 /// HW Timer
 pub struct Timer<TIM> {
     clocks: Clocks,
@@ -158,16 +214,10 @@ macro_rules! impl_timer {
                     //      Generally bits are the same but better to re-check later on.
                     //      TIM2 and TIM5 are 32bit timers so their ARR also can be set with high
                     //      bit which is not influenced by psc though?
-                    let ppre = match self.clocks.$ppre {
-                        1 => 1,
-                        _ => 2
-                    };
-                    let ticks = self.clocks.$apb.0 * ppre / frequency;
+                    let (psc, arr) = calc_psc_arr(timer_clock(self.clocks.$apb, self.clocks.$ppre), frequency)
+                        .expect("timeout is out of timer range");
 
-                    let psc = u16((ticks - 1) / (1 << 16)).unwrap();
                     self.tim.psc.write(|w| unsafe { w.psc().bits(psc) });
-
-                    let arr = u16(ticks / u32(psc + 1)).unwrap();
                     self.tim.arr.write(|w| unsafe { w.bits(u32(arr)) });
 
                     // Trigger an update event to load the prescaler value to the clock
@@ -196,6 +246,37 @@ macro_rules! impl_timer {
     }
 }
 
+macro_rules! impl_trgo {
+    ($($TIMx:ident,)+) => {
+        $(
+            impl Timer<$TIMx> {
+                /// Selects event of the timer, which is sent to its trigger output (TRGO).
+                #[allow(unused_unsafe)]
+                pub fn set_master_mode(&mut self, mode: MasterMode) {
+                    self.tim.cr2.modify(|_, w| unsafe { w.mms().bits(mode as u8) });
+                }
+
+                /// Returns event of the timer, which is sent to its trigger output (TRGO).
+                pub fn master_mode(&self) -> MasterMode {
+                    match self.tim.cr2.read().mms().bits() {
+                        0b001 => MasterMode::Enable,
+                        0b010 => MasterMode::Update,
+                        _ => MasterMode::Reset,
+                    }
+                }
+
+                /// Makes update event of the timer its trigger output (TRGO),
+                /// e.g. to trigger ADC conversions or DAC waveform updates.
+                pub fn trigger_on_update(&mut self) {
+                    self.set_master_mode(MasterMode::Update);
+                }
+            }
+        )+
+    }
+}
+
+impl_trgo!(TIM1, TIM8, TIM2, TIM3, TIM4, TIM5, TIM6, TIM7,);
+
 impl_timer!(
     TIM1: [
         alias: Tim1;
@@ -308,3 +389,31 @@ impl_timer!(
         }
     ]
 );
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn calculate_timer_clock() {
+        assert_eq!(timer_clock(Hertz(80_000_000), 1), 80_000_000);
+        assert_eq!(timer_clock(Hertz(40_000_000), 2), 80_000_000);
+        assert_eq!(timer_clock(Hertz(10_000_000), 8), 20_000_000);
+    }
+
+    #[test]
+    fn calculate_psc_arr() {
+        assert_eq!(calc_psc_arr(80_000_000, 1_000), Ok((1, 40_000)));
+        assert_eq!(calc_psc_arr(80_000_000, 1), Ok((1220, 65_520)));
+        assert_eq!(calc_psc_arr(4_000_000, 1_000), Ok((0, 4_000)));
+        // Exact multiple of 2^16 ticks must not overflow auto-reload
+        assert_eq!(calc_psc_arr(65_536_000, 1_000), Ok((1, 32_768)));
+    }
+
+    #[test]
+    fn calculate_psc_arr_out_of_range() {
+        assert_eq!(calc_psc_arr(80_000_000, 0), Err(ConfigError::FrequencyTooLow));
+        assert_eq!(calc_psc_arr(80_000_000, 80_000_001), Err(ConfigError::FrequencyTooHigh));
+        assert_eq!(calc_psc_arr(u32::MAX, 1), Ok((65_535, 65_535)));
+    }
+}
@@ -0,0 +1,255 @@
+//! Break input of advanced control timers (TIM1, TIM8)
+//!
+//! Break disables timer outputs (clears MOE) as soon as any of enabled sources becomes active,
+//! e.g. on overcurrent detected by comparator:
+//!
+//! ```rust, ignore
+//! let mut comp = device.COMP.split(&mut rcc.apb2);
+//! comp.comp1.configure(comp::Config { minus: comp::Inverting::HalfVref, ..Default::default() });
+//!
+//! device.TIM1.enable_break(BreakSource::Comp1, BreakPolarity::ActiveHigh, 4);
+//! ```
+//!
+//! Dead time between complementary outputs is configured together with break:
+//!
+//! ```rust, ignore
+//! let protection = Protection::default().dead_time_ns(500).break_input(BreakSource::Pin);
+//! device.TIM1.configure(protection, &clocks)?;
+//! ```
+//!
+//! See Reference manual Ch. 30.3.14 and 30.3.16
+
+use stm32l4::stm32l4x5::{TIM1, TIM8};
+
+use crate::common::ConfigError;
+use crate::rcc::Clocks;
+
+/// Source of break signal
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum BreakSource {
+    /// BKIN pin
+    Pin,
+    /// COMP1 output
+    Comp1,
+    /// COMP2 output
+    Comp2,
+}
+
+/// Active level of break signal
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum BreakPolarity {
+    /// Break is active when signal is low
+    ActiveLow,
+    /// Break is active when signal is high
+    ActiveHigh,
+}
+
+/// Offset of BKF field within BDTR
+const BDTR_BKF_OFFSET: u32 = 16;
+
+/// Largest dead time in dead time generator clock (tDTS) periods
+const DEAD_TIME_MAX: u32 = 1_008;
+/// Largest value of clock division (CKD)
+const CKD_MAX: u8 = 2;
+
+/// Encodes dead time of `ticks` tDTS periods into DTG, rounding it up.
+fn encode_dtg(ticks: u32) -> Option<u8> {
+    // DTG[7:5] selects multiplier and offset of DTG remaining bits
+    match ticks {
+        0..=127 => Some(ticks as u8),
+        128..=254 => Some(0x80 | ((ticks + 1) / 2 - 64) as u8),
+        255..=504 => Some(0xC0 | ((ticks + 7) / 8 - 32) as u8),
+        505..=DEAD_TIME_MAX => Some(0xE0 | ((ticks + 15) / 16 - 32) as u8),
+        _ => None,
+    }
+}
+
+/// Calculates clock division (CKD) and dead time generator (DTG) bits for at least
+/// `dead_time_ns` with timer kernel `clock`.
+///
+/// The smallest clock division, which reaches dead time, is selected.
+/// Note that clock division is also applied to sampling of input and break filters.
+///
+/// Returns `FrequencyTooLow` error if dead time is too long to be reached.
+pub fn calc_dead_time(clock: u32, dead_time_ns: u32) -> Result<(u8, u8), ConfigError> {
+    let clock_ticks = (dead_time_ns as u64 * clock as u64 + 999_999_999) / 1_000_000_000;
+
+    for ckd in 0..=CKD_MAX {
+        let ticks = (clock_ticks + (1 << ckd) - 1) >> ckd;
+        if ticks <= DEAD_TIME_MAX as u64 {
+            if let Some(dtg) = encode_dtg(ticks as u32) {
+                return Ok((ckd, dtg));
+            }
+        }
+    }
+
+    Err(ConfigError::FrequencyTooLow)
+}
+
+/// Builder of break and dead time configuration
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub struct Protection {
+    dead_time_ns: u32,
+    // Bit per BreakSource
+    sources: u8,
+    polarity: BreakPolarity,
+    filter: u8,
+    automatic_output: bool,
+}
+
+impl Default for Protection {
+    /// No dead time and no break sources
+    fn default() -> Self {
+        Self {
+            dead_time_ns: 0,
+            sources: 0,
+            polarity: BreakPolarity::ActiveHigh,
+            filter: 0,
+            automatic_output: false,
+        }
+    }
+}
+
+impl Protection {
+    /// Sets minimal delay between complementary outputs in nanoseconds
+    pub fn dead_time_ns(mut self, dead_time_ns: u32) -> Self {
+        self.dead_time_ns = dead_time_ns;
+        self
+    }
+
+    /// Adds break `source`, all added sources are OR-ed
+    pub fn break_input(mut self, source: BreakSource) -> Self {
+        self.sources |= 1 << source as u8;
+        self
+    }
+
+    /// Sets active level of break sources
+    pub fn break_polarity(mut self, polarity: BreakPolarity) -> Self {
+        self.polarity = polarity;
+        self
+    }
+
+    /// Sets break filter, see [enable_break](trait.BreakInput.html#tymethod.enable_break)
+    ///
+    /// # Pancis:
+    ///
+    /// In debug mode the function checks that `filter` fits 4 bits.
+    pub fn break_filter(mut self, filter: u8) -> Self {
+        debug_assert!(filter < 16);
+
+        self.filter = filter;
+        self
+    }
+
+    /// Re-enables outputs automatically at the next update event once break is gone
+    pub fn automatic_output(mut self, enable: bool) -> Self {
+        self.automatic_output = enable;
+        self
+    }
+}
+
+/// Break input configuration
+pub trait BreakInput {
+    /// Enables break from `source`.
+    ///
+    /// Multiple sources can be enabled, in which case they are OR-ed.
+    /// `filter` is value of BKF (0..=15), which is number of samples break must be stable.
+    ///
+    /// # Pancis:
+    ///
+    /// In debug mode the function checks that `filter` fits 4 bits.
+    fn enable_break(&mut self, source: BreakSource, polarity: BreakPolarity, filter: u8);
+    /// Disables all break sources
+    fn disable_break(&mut self);
+    /// Returns whether break has occurred
+    fn is_break(&self) -> bool;
+    /// Clears break flag
+    fn clear_break(&mut self);
+    /// Re-enables outputs (MOE) after break, it has no effect while break is active
+    fn resume_outputs(&mut self);
+    /// Applies `protection`, with dead time calculated from timer kernel clock.
+    ///
+    /// Returns error if dead time cannot be reached, in which case nothing is changed.
+    /// Dead time can be changed only while timer outputs are disabled.
+    fn configure(&mut self, protection: Protection, clocks: &Clocks) -> Result<(), ConfigError>;
+}
+
+macro_rules! impl_break_input {
+    ($($TIMx:ident,)+) => {
+        $(
+            impl BreakInput for $TIMx {
+                fn enable_break(&mut self, source: BreakSource, polarity: BreakPolarity, filter: u8) {
+                    debug_assert!(filter < 16);
+
+                    match source {
+                        BreakSource::Pin => self.or2.modify(|_, w| w.bkine().set_bit().bkinp().clear_bit()),
+                        BreakSource::Comp1 => self.or2.modify(|_, w| w.bkcmp1e().set_bit().bkcmp1p().clear_bit()),
+                        BreakSource::Comp2 => self.or2.modify(|_, w| w.bkcmp2e().set_bit().bkcmp2p().clear_bit()),
+                    }
+
+                    self.bdtr.modify(|r, w| unsafe {
+                        w.bits((r.bits() & !(0b1111 << BDTR_BKF_OFFSET)) | (filter as u32) << BDTR_BKF_OFFSET)
+                    });
+                    self.bdtr.modify(|_, w| w.bkp().bit(polarity == BreakPolarity::ActiveHigh).bke().set_bit());
+                }
+
+                fn disable_break(&mut self) {
+                    self.bdtr.modify(|_, w| w.bke().clear_bit());
+                    self.or2.modify(|_, w| w.bkine().clear_bit().bkcmp1e().clear_bit().bkcmp2e().clear_bit());
+                }
+
+                fn is_break(&self) -> bool {
+                    self.sr.read().bif().bit_is_set()
+                }
+
+                fn clear_break(&mut self) {
+                    self.sr.modify(|_, w| w.bif().clear_bit());
+                }
+
+                fn resume_outputs(&mut self) {
+                    self.bdtr.modify(|_, w| w.moe().set_bit());
+                }
+
+                fn configure(&mut self, protection: Protection, clocks: &Clocks) -> Result<(), ConfigError> {
+                    let clock = clocks.timclk2().0;
+                    let (ckd, dtg) = calc_dead_time(clock, protection.dead_time_ns)?;
+
+                    self.cr1.modify(|_, w| unsafe { w.ckd().bits(ckd) });
+                    self.bdtr.modify(|_, w| unsafe { w.dtg().bits(dtg).aoe().bit(protection.automatic_output) });
+
+                    self.disable_break();
+                    for &source in &[BreakSource::Pin, BreakSource::Comp1, BreakSource::Comp2] {
+                        if protection.sources & (1 << source as u8) != 0 {
+                            self.enable_break(source, protection.polarity, protection.filter);
+                        }
+                    }
+
+                    Ok(())
+                }
+            }
+        )+
+    }
+}
+
+impl_break_input!(TIM1, TIM8,);
+
+#[cfg(test)]
+mod tests {
+    use super::calc_dead_time;
+    use crate::common::ConfigError;
+
+    #[test]
+    fn dead_time() {
+        // 80 MHz, 12.5 ns per tick
+        assert_eq!(calc_dead_time(80_000_000, 0), Ok((0, 0)));
+        assert_eq!(calc_dead_time(80_000_000, 100), Ok((0, 8)));
+        assert_eq!(calc_dead_time(80_000_000, 101), Ok((0, 9)));
+        // 160 ticks are (64 + 16) * 2
+        assert_eq!(calc_dead_time(80_000_000, 2_000), Ok((0, 0x80 | 16)));
+        // 400 ticks are (32 + 18) * 8
+        assert_eq!(calc_dead_time(80_000_000, 5_000), Ok((0, 0xC0 | 18)));
+        // 1600 ticks are 800 tDTS with division by 2, rounded up to (32 + 18) * 16
+        assert_eq!(calc_dead_time(80_000_000, 20_000), Ok((1, 0xE0 | 18)));
+        assert_eq!(calc_dead_time(80_000_000, 60_000), Err(ConfigError::FrequencyTooLow));
+    }
+}
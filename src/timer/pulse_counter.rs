@@ -0,0 +1,169 @@
+//! Pulse counter on LPTIM input 1
+//!
+//! Counter is clocked by pulses on Input1 pin, while kernel clock is used only to
+//! filter them, so with LSE or LSI it keeps counting in Stop modes (LPTIM2 down to Stop 1).
+//! 16 bit hardware counter is extended in software on its overflow interrupt.
+//!
+//! ```rust, ignore
+//! let pin = gpio_b.PB5.into_alt_fun::<AF1>(&mut gpio_b.moder, &mut gpio_b.afrl);
+//! let mut meter = PulseCounter::lptim1(device.LPTIM1, pin, Edge::Falling, Filter::Clocks4,
+//!                                      ClockSource::Lse, &mut rcc.apb1, &mut rcc.ccipr);
+//!
+//! // LPTIM1 interrupt
+//! meter.handle_interrupt();
+//!
+//! let liters = meter.count() / PULSES_PER_LITER;
+//! ```
+//!
+//! See Reference manual Ch. 31.4.13
+
+use stm32l4::stm32l4x5::{LPTIM1, LPTIM2};
+
+use crate::gpio::{AF1, AF14, PB1, PB5, PC0};
+use crate::pwm::lptim::ClockSource;
+use crate::rcc::{APB1, CCIPR};
+
+/// Active edge of counted pulses
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum Edge {
+    /// Rising edge
+    Rising = 0b00,
+    /// Falling edge
+    Falling = 0b01,
+    /// Both edges
+    Both = 0b10,
+}
+
+/// Digital filter of input, in kernel clock periods the level must be stable
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum Filter {
+    /// Any level change is counted
+    None = 0b00,
+    /// 2 clock periods
+    Clocks2 = 0b01,
+    /// 4 clock periods
+    Clocks4 = 0b10,
+    /// 8 clock periods
+    Clocks8 = 0b11,
+}
+
+/// Describes pin connected to LPTIM input 1.
+///
+/// Note: MUST not be implemented by user.
+pub trait In1<LPTIM> {}
+
+impl In1<LPTIM1> for PB5<AF1> {}
+impl In1<LPTIM1> for PC0<AF1> {}
+impl In1<LPTIM2> for PB1<AF14> {}
+impl In1<LPTIM2> for PC0<AF14> {}
+
+/// Maximal value of hardware counter
+const ARR_MAX: u16 = 0xFFFF;
+
+/// Counter of pulses extended to 64 bits
+pub struct PulseCounter<LPTIM, PIN> {
+    tim: LPTIM,
+    pin: PIN,
+    overflows: u64,
+}
+
+macro_rules! impl_pulse_counter {
+    ($($LPTIMx:ident: [constructor: $lptimx:ident; $enr:ident: $en:ident, $rstr:ident: $rst:ident, $sel:ident],)+) => {
+        $(
+            impl<PIN: In1<$LPTIMx>> PulseCounter<$LPTIMx, PIN> {
+                /// Starts counting `edge`s on `pin`, filtered with `clock`.
+                ///
+                /// Overflow interrupt is enabled, [handle_interrupt](#method.handle_interrupt)
+                /// must be called from it.
+                pub fn $lptimx(tim: $LPTIMx, pin: PIN, edge: Edge, filter: Filter, clock: ClockSource,
+                               apb: &mut APB1, ccipr: &mut CCIPR) -> Self {
+                    if clock == ClockSource::Hsi16 {
+                        ccipr.hsi16_enable();
+                    }
+                    ccipr.inner().modify(|_, w| unsafe { w.$sel().bits(clock as u8) });
+                    apb.$enr().modify(|_, w| w.$en().set_bit());
+                    apb.$rstr().modify(|_, w| w.$rst().set_bit());
+                    apb.$rstr().modify(|_, w| w.$rst().clear_bit());
+
+                    // CFGR and IER are writable only while disabled
+                    tim.cfgr.write(|w| unsafe {
+                        w.countmode().set_bit()
+                         .ckpol().bits(edge as u8)
+                         .ckflt().bits(filter as u8)
+                    });
+                    tim.ier.write(|w| w.arrmie().set_bit());
+                    tim.cr.write(|w| w.enable().set_bit());
+                    tim.arr.write(|w| unsafe { w.arr().bits(ARR_MAX) });
+                    while tim.isr.read().arrok().bit_is_clear() {}
+                    tim.icr.write(|w| w.arrokcf().set_bit());
+                    tim.cr.modify(|_, w| w.cntstrt().set_bit());
+
+                    Self {
+                        tim,
+                        pin,
+                        overflows: 0,
+                    }
+                }
+
+                /// Accounts counter overflow, to be called from LPTIM interrupt.
+                pub fn handle_interrupt(&mut self) {
+                    if self.tim.isr.read().arrm().bit_is_set() {
+                        self.tim.icr.write(|w| w.arrmcf().set_bit());
+                        self.overflows += 1;
+                    }
+                }
+
+                /// Reads hardware counter, which is reliable only when two reads match
+                fn counter(&self) -> u16 {
+                    loop {
+                        let first = self.tim.cnt.read().cnt().bits();
+                        if self.tim.cnt.read().cnt().bits() == first {
+                            return first;
+                        }
+                    }
+                }
+
+                /// Returns number of pulses counted since start or `reset`
+                pub fn count(&self) -> u64 {
+                    let counter = self.counter();
+                    let mut overflows = self.overflows;
+                    // Match happened before counter was read, but isn't handled yet
+                    if self.tim.isr.read().arrm().bit_is_set() && (counter == ARR_MAX || counter < ARR_MAX / 2) {
+                        overflows += 1;
+                    }
+
+                    // Overflow is accounted on match with ARR, one pulse before counter wraps
+                    let counter = counter.wrapping_add(1) as u64;
+                    (overflows * (ARR_MAX as u64 + 1) + counter).saturating_sub(1)
+                }
+
+                /// Returns lower 32 bits of pulse count, which wraps around
+                pub fn count32(&self) -> u32 {
+                    self.count() as u32
+                }
+
+                /// Restarts counting from zero
+                pub fn reset(&mut self) {
+                    self.tim.cr.write(|w| w.enable().clear_bit());
+                    self.tim.cr.write(|w| w.enable().set_bit());
+                    self.tim.arr.write(|w| unsafe { w.arr().bits(ARR_MAX) });
+                    while self.tim.isr.read().arrok().bit_is_clear() {}
+                    self.tim.icr.write(|w| w.arrokcf().set_bit().arrmcf().set_bit());
+                    self.tim.cr.modify(|_, w| w.cntstrt().set_bit());
+                    self.overflows = 0;
+                }
+
+                /// Stops counting, releasing timer and pin
+                pub fn free(self) -> ($LPTIMx, PIN) {
+                    self.tim.cr.write(|w| w.enable().clear_bit());
+                    (self.tim, self.pin)
+                }
+            }
+        )+
+    }
+}
+
+impl_pulse_counter!(
+    LPTIM1: [constructor: lptim1; enr1: lptim1en, rstr1: lptim1rst, lptim1sel],
+    LPTIM2: [constructor: lptim2; enr2: lptim2en, rstr2: lptim2rst, lptim2sel],
+);
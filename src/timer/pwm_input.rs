@@ -0,0 +1,167 @@
+//! PWM input mode
+//!
+//! Both capture channels of the timer are connected to the same input (TI1):
+//! channel 1 captures period on rising edge, which also resets the counter,
+//! while channel 2 captures pulse width on falling edge.
+//!
+//! See Reference manual Ch. 31.3.8
+
+use stm32l4::stm32l4x5::{TIM2, TIM3, TIM4, TIM5};
+
+use cast::u32;
+
+use crate::common::ConfigError;
+use crate::gpio::{AF1, AF2};
+use crate::gpio::{PA0, PA5, PA6, PA15, PB4, PB6, PC6};
+use crate::rcc::{APB1, Clocks};
+use crate::time::Hertz;
+
+use super::calc_psc_arr;
+
+/// Describes pin connected to timer input 1 (TI1).
+///
+/// Note: MUST not be implemented by user.
+pub trait TI1<TIM> {}
+
+impl TI1<TIM2> for PA0<AF1> {}
+impl TI1<TIM2> for PA5<AF1> {}
+impl TI1<TIM2> for PA15<AF1> {}
+impl TI1<TIM3> for PA6<AF2> {}
+impl TI1<TIM3> for PB4<AF2> {}
+impl TI1<TIM3> for PC6<AF2> {}
+impl TI1<TIM4> for PB6<AF2> {}
+impl TI1<TIM5> for PA0<AF2> {}
+
+/// Result of measurement
+#[derive(Clone, Copy)]
+pub struct Measurement {
+    /// Frequency of the signal
+    pub frequency: Hertz,
+    /// Pulse width in timer ticks
+    pub pulse: u32,
+    /// Period in timer ticks
+    pub period: u32,
+}
+
+impl Measurement {
+    /// Returns duty cycle in percents, 0 while no period has been captured.
+    pub fn duty_percent(&self) -> u8 {
+        match self.period {
+            0 => 0,
+            period => (self.pulse as u64 * 100 / period as u64) as u8,
+        }
+    }
+}
+
+/// Timer in PWM input mode
+pub struct PwmInput<TIM, PIN> {
+    tim: TIM,
+    pin: PIN,
+    clock: u32,
+}
+
+macro_rules! impl_pwm_input {
+    ($($TIMx:ident: [constructor: $timx:ident; $enr:ident: $enr_bit:ident; $rstr:ident: $rstr_bit:ident],)+) => {
+        $(
+            impl<PIN: TI1<$TIMx>> PwmInput<$TIMx, PIN> {
+                /// Configures timer to measure signal on `pin`.
+                ///
+                /// `min_freq` is the lowest frequency that can be measured, lower values
+                /// give less precise result.
+                ///
+                /// Returns error if `min_freq` is zero or above timer clock.
+                pub fn $timx<T: Into<Hertz>>(tim: $TIMx, pin: PIN, min_freq: T, clocks: Clocks, apb: &mut APB1) -> Result<Self, ConfigError> {
+                    let timer_clock = clocks.timclk1().0;
+                    let (psc, _) = calc_psc_arr(timer_clock, min_freq.into().0)?;
+
+                    // enable and reset peripheral to a clean slate state
+                    apb.$enr().modify(|_, w| w.$enr_bit().set_bit());
+                    apb.$rstr().modify(|_, w| w.$rstr_bit().set_bit());
+                    apb.$rstr().modify(|_, w| w.$rstr_bit().clear_bit());
+
+                    tim.psc.write(|w| unsafe { w.psc().bits(psc) });
+                    tim.arr.write(|w| w.arr().bits(0xFFFF));
+
+                    unsafe {
+                        // IC1 and IC2 are both mapped on TI1 (CCxS fields are shared with output layout)
+                        tim.ccmr1_output.write(|w| w.cc1s().bits(0b01).cc2s().bits(0b10));
+                        // Trigger on TI1FP1 and reset counter on its rising edge
+                        tim.smcr.write(|w| w.ts().bits(0b101).sms().bits(0b100));
+                    }
+                    // IC1 on rising edge, IC2 on falling edge
+                    tim.ccer.write(|w| {
+                        w.cc1p().clear_bit()
+                         .cc1np().clear_bit()
+                         .cc2p().set_bit()
+                         .cc2np().clear_bit()
+                         .cc1e().set_bit()
+                         .cc2e().set_bit()
+                    });
+
+                    // Load prescaler
+                    tim.egr.write(|w| w.ug().set_bit());
+                    // Only counter overflow should raise update event, so it is used as no signal indicator
+                    tim.cr1.modify(|_, w| w.urs().set_bit());
+                    tim.sr.modify(|_, w| w.uif().clear_bit());
+                    tim.cr1.modify(|_, w| w.cen().set_bit());
+
+                    Ok(Self {
+                        tim,
+                        pin,
+                        clock: timer_clock / (u32(psc) + 1),
+                    })
+                }
+
+                /// Returns last measurement.
+                ///
+                /// Returns `None` if there was no edge within whole counter period.
+                pub fn read(&mut self) -> Option<Measurement> {
+                    if self.tim.sr.read().uif().bit_is_set() {
+                        self.tim.sr.modify(|_, w| w.uif().clear_bit());
+                        return None;
+                    }
+
+                    let period = self.tim.ccr1.read().ccr1().bits();
+                    let pulse = self.tim.ccr2.read().ccr2().bits();
+
+                    match period {
+                        0 => None,
+                        period => Some(Measurement {
+                            frequency: Hertz(self.clock / period),
+                            pulse,
+                            period,
+                        })
+                    }
+                }
+
+                /// Stops timer and releases the TIM peripheral with pin
+                pub fn free(self) -> ($TIMx, PIN) {
+                    self.tim.cr1.modify(|_, w| w.cen().clear_bit());
+                    (self.tim, self.pin)
+                }
+            }
+        )+
+    }
+}
+
+impl_pwm_input!(
+    TIM2: [constructor: tim2; enr1: tim2en; rstr1: tim2rst],
+    TIM3: [constructor: tim3; enr1: tim3en; rstr1: tim3rst],
+    TIM4: [constructor: tim4; enr1: tim4en; rstr1: tim4rst],
+    TIM5: [constructor: tim5; enr1: tim5en; rstr1: tim5rst],
+);
+
+#[cfg(test)]
+mod tests {
+    use super::Measurement;
+    use crate::time::Hertz;
+
+    #[test]
+    fn duty_percent() {
+        let measurement = Measurement { frequency: Hertz(1_000), pulse: 250, period: 1_000 };
+        assert_eq!(measurement.duty_percent(), 25);
+
+        let measurement = Measurement { frequency: Hertz(0), pulse: 0, period: 0 };
+        assert_eq!(measurement.duty_percent(), 0);
+    }
+}
@@ -0,0 +1,250 @@
+//! Periodic and one-shot task scheduler
+//!
+//! One 32-bit timer (TIM2 or TIM5) runs freely, while each of its four compare
+//! channels fires at its own interval. Expired tasks are reported via flags, or
+//! callbacks invoked from the timer interrupt:
+//!
+//! ```rust, ignore
+//! let mut scheduler = Scheduler::tim2(tim2, Hertz(10_000), clocks, &mut rcc.apb1).unwrap();
+//! scheduler.schedule(Channel::C1, 5_000, Mode::Periodic, Some(blink));
+//! scheduler.schedule(Channel::C2, 20_000, Mode::OneShot, None);
+//!
+//! // TIM2 interrupt
+//! scheduler.handle_interrupt();
+//! // main loop
+//! if scheduler.is_due(Channel::C2) { ... }
+//! ```
+
+use stm32l4::stm32l4x5::{TIM2, TIM5};
+
+use cast::u16;
+
+use crate::common::ConfigError;
+use crate::rcc::{APB1, Clocks};
+use crate::time::Hertz;
+
+/// Compare channel, used as task slot
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum Channel {
+    /// Channel 1
+    C1 = 0,
+    /// Channel 2
+    C2 = 1,
+    /// Channel 3
+    C3 = 2,
+    /// Channel 4
+    C4 = 3,
+}
+
+/// Task repetition mode
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum Mode {
+    /// Task is re-scheduled after it fires
+    Periodic,
+    /// Task fires once
+    OneShot,
+}
+
+#[derive(Clone, Copy)]
+struct Task {
+    interval: u32,
+    mode: Mode,
+    callback: Option<fn()>,
+    due: bool,
+}
+
+/// Calculates prescaler of `timer_clock`, which gives counter `resolution`.
+fn calc_psc(timer_clock: u32, resolution: u32) -> Result<u16, ConfigError> {
+    match resolution {
+        0 => Err(ConfigError::FrequencyTooLow),
+        r if r > timer_clock => Err(ConfigError::FrequencyTooHigh),
+        r => u16(timer_clock / r - 1).map_err(|_| ConfigError::FrequencyTooLow),
+    }
+}
+
+/// Scheduler on top of 32-bit timer
+pub struct Scheduler<TIM> {
+    tim: TIM,
+    tasks: [Option<Task>; 4],
+}
+
+macro_rules! impl_scheduler {
+    ($($TIMx:ident: [constructor: $timx:ident; $enr:ident: $enr_bit:ident; $rstr:ident: $rstr_bit:ident],)+) => {
+        $(
+            impl Scheduler<$TIMx> {
+                /// Starts free running timer, which counts with `resolution` frequency.
+                ///
+                /// Intervals of tasks are measured in ticks of `resolution`.
+                ///
+                /// Returns error if `resolution` is zero, above timer clock, or too low for 16-bit prescaler.
+                pub fn $timx<T: Into<Hertz>>(tim: $TIMx, resolution: T, clocks: Clocks, apb: &mut APB1) -> Result<Self, ConfigError> {
+                    let psc = calc_psc(clocks.timclk1().0, resolution.into().0)?;
+
+                    // enable and reset peripheral to a clean slate state
+                    apb.$enr().modify(|_, w| w.$enr_bit().set_bit());
+                    apb.$rstr().modify(|_, w| w.$rstr_bit().set_bit());
+                    apb.$rstr().modify(|_, w| w.$rstr_bit().clear_bit());
+
+                    tim.psc.write(|w| unsafe { w.psc().bits(psc) });
+                    tim.arr.write(|w| w.arr().bits(u32::max_value()));
+                    // Load prescaler
+                    tim.egr.write(|w| w.ug().set_bit());
+                    tim.sr.write(|w| unsafe { w.bits(0) });
+                    tim.cr1.modify(|_, w| w.cen().set_bit());
+
+                    Ok(Self {
+                        tim,
+                        tasks: [None; 4],
+                    })
+                }
+
+                fn set_compare(&mut self, channel: Channel, value: u32) {
+                    match channel {
+                        Channel::C1 => self.tim.ccr1.write(|w| w.ccr1().bits(value)),
+                        Channel::C2 => self.tim.ccr2.write(|w| w.ccr2().bits(value)),
+                        Channel::C3 => self.tim.ccr3.write(|w| w.ccr3().bits(value)),
+                        Channel::C4 => self.tim.ccr4.write(|w| w.ccr4().bits(value)),
+                    }
+                }
+
+                fn compare(&self, channel: Channel) -> u32 {
+                    match channel {
+                        Channel::C1 => self.tim.ccr1.read().ccr1().bits(),
+                        Channel::C2 => self.tim.ccr2.read().ccr2().bits(),
+                        Channel::C3 => self.tim.ccr3.read().ccr3().bits(),
+                        Channel::C4 => self.tim.ccr4.read().ccr4().bits(),
+                    }
+                }
+
+                fn set_interrupt(&mut self, channel: Channel, is_on: bool) {
+                    match channel {
+                        Channel::C1 => self.tim.dier.modify(|_, w| w.cc1ie().bit(is_on)),
+                        Channel::C2 => self.tim.dier.modify(|_, w| w.cc2ie().bit(is_on)),
+                        Channel::C3 => self.tim.dier.modify(|_, w| w.cc3ie().bit(is_on)),
+                        Channel::C4 => self.tim.dier.modify(|_, w| w.cc4ie().bit(is_on)),
+                    }
+                }
+
+                /// Clears compare flag of the channel, returning whether it was set
+                fn take_flag(&mut self, channel: Channel) -> bool {
+                    // CCxIF is bit 1..4 and cleared by writing zero
+                    let mask = 1 << (channel as u32 + 1);
+                    match self.tim.sr.read().bits() & mask {
+                        0 => false,
+                        _ => {
+                            self.tim.sr.write(|w| unsafe { w.bits(!mask) });
+                            true
+                        }
+                    }
+                }
+
+                /// Registers task on `channel` to fire after `interval` ticks.
+                ///
+                /// Previous task of the channel is replaced.
+                /// `callback` is invoked from [handle_interrupt](#method.handle_interrupt).
+                pub fn schedule(&mut self, channel: Channel, interval: u32, mode: Mode, callback: Option<fn()>) {
+                    self.set_interrupt(channel, false);
+
+                    let now = self.tim.cnt.read().bits();
+                    self.set_compare(channel, now.wrapping_add(interval));
+                    let _ = self.take_flag(channel);
+
+                    self.tasks[channel as usize] = Some(Task {
+                        interval,
+                        mode,
+                        callback,
+                        due: false,
+                    });
+                    self.set_interrupt(channel, true);
+                }
+
+                /// Removes task from `channel`.
+                pub fn cancel(&mut self, channel: Channel) {
+                    self.set_interrupt(channel, false);
+                    let _ = self.take_flag(channel);
+                    self.tasks[channel as usize] = None;
+                }
+
+                /// Returns whether task on `channel` has fired since last check.
+                pub fn is_due(&mut self, channel: Channel) -> bool {
+                    self.poll(channel);
+
+                    match self.tasks[channel as usize].as_mut() {
+                        Some(task) => {
+                            let due = task.due;
+                            task.due = false;
+                            if due && task.mode == Mode::OneShot {
+                                self.tasks[channel as usize] = None;
+                            }
+                            due
+                        },
+                        None => false,
+                    }
+                }
+
+                /// Processes expired tasks, invoking their callbacks.
+                ///
+                /// Intended to be called from timer's interrupt handler.
+                pub fn handle_interrupt(&mut self) {
+                    for &channel in [Channel::C1, Channel::C2, Channel::C3, Channel::C4].iter() {
+                        if let Some(callback) = self.poll(channel) {
+                            callback();
+                        }
+                    }
+                }
+
+                /// Re-arms task if it expired, returning its callback
+                fn poll(&mut self, channel: Channel) -> Option<fn()> {
+                    if !self.take_flag(channel) {
+                        return None;
+                    }
+
+                    let task = match self.tasks[channel as usize].as_mut() {
+                        Some(task) => task,
+                        None => return None,
+                    };
+                    task.due = true;
+                    let (interval, mode, callback) = (task.interval, task.mode, task.callback);
+
+                    match mode {
+                        Mode::Periodic => {
+                            let next = self.compare(channel).wrapping_add(interval);
+                            self.set_compare(channel, next);
+                        },
+                        Mode::OneShot => self.set_interrupt(channel, false),
+                    }
+
+                    callback
+                }
+
+                /// Stops timer and releases the TIM peripheral
+                pub fn free(self) -> $TIMx {
+                    self.tim.cr1.modify(|_, w| w.cen().clear_bit());
+                    self.tim.dier.reset();
+                    self.tim
+                }
+            }
+        )+
+    }
+}
+
+impl_scheduler!(
+    TIM2: [constructor: tim2; enr1: tim2en; rstr1: tim2rst],
+    TIM5: [constructor: tim5; enr1: tim5en; rstr1: tim5rst],
+);
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn prescaler_of_resolution() {
+        assert_eq!(calc_psc(80_000_000, 1_000_000), Ok(79));
+        assert_eq!(calc_psc(80_000_000, 80_000_000), Ok(0));
+        assert_eq!(calc_psc(4_000_000, 1_000), Ok(3_999));
+        assert_eq!(calc_psc(80_000_000, 0), Err(ConfigError::FrequencyTooLow));
+        assert_eq!(calc_psc(80_000_000, 80_000_001), Err(ConfigError::FrequencyTooHigh));
+        // Prescaler is 16 bit
+        assert_eq!(calc_psc(80_000_000, 1_000), Err(ConfigError::FrequencyTooLow));
+    }
+}
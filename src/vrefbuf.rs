@@ -0,0 +1,120 @@
+//! Voltage reference buffer (VREFBUF)
+//!
+//! Provides stable reference voltage on VREF+ pin for ADC and DAC.
+//!
+//! See Reference manual Ch. 20
+
+use stm32l4::stm32l4x5::VREFBUF as Inner;
+use crate::rcc::APB2;
+
+#[derive(Copy, Clone, PartialEq, Eq, Debug)]
+/// Output voltage of the buffer
+pub enum Scale {
+    /// VREF+ around 2.048 V
+    V2_048,
+    /// VREF+ around 2.5 V
+    V2_5,
+}
+
+#[derive(Copy, Clone, PartialEq, Eq, Debug)]
+/// Buffer operating mode.
+///
+/// See Reference manual Ch. 20.2 Table 93
+pub enum Mode {
+    /// Buffer is off and VREF+ pin is pulled down to VSSA
+    Off,
+    /// Buffer is off and VREF+ pin expects external voltage reference
+    External,
+    /// Buffer drives VREF+ pin
+    Internal,
+    /// Buffer is on, but VREF+ pin is in high impedance.
+    ///
+    /// Keeps capacitor on VREF+ pin charged while buffer is not driving it.
+    Hold,
+}
+
+/// VREFBUF peripheral
+pub struct VREFBUF {
+    inner: Inner,
+}
+
+impl VREFBUF {
+    /// Enables clock of VREFBUF.
+    ///
+    /// VREFBUF is clocked together with SYSCFG.
+    pub fn enable(apb: &mut APB2) {
+        apb.enr().modify(|_, w| w.syscfgen().set_bit());
+    }
+
+    /// Creates new instance of VREFBUF.
+    ///
+    /// Takes ownership over device VREFBUF
+    pub fn new(inner: Inner) -> Self {
+        Self {
+            inner,
+        }
+    }
+
+    /// Sets output voltage.
+    ///
+    /// Buffer must be in `Internal` mode before changing scale.
+    pub fn set_scale(&mut self, scale: Scale) -> &mut Self {
+        self.inner.csr.modify(|_, w| w.vrs().bit(scale == Scale::V2_5));
+        self
+    }
+
+    /// Returns currently selected output voltage.
+    pub fn scale(&self) -> Scale {
+        match self.inner.csr.read().vrs().bit_is_set() {
+            true => Scale::V2_5,
+            false => Scale::V2_048,
+        }
+    }
+
+    /// Sets operating mode.
+    pub fn set_mode(&mut self, mode: Mode) -> &mut Self {
+        let (envr, hiz) = match mode {
+            Mode::Off => (false, false),
+            Mode::External => (false, true),
+            Mode::Internal => (true, false),
+            Mode::Hold => (true, true),
+        };
+        self.inner.csr.modify(|_, w| w.envr().bit(envr).hiz().bit(hiz));
+        self
+    }
+
+    /// Turns on buffer driving VREF+ pin and waits for it to get ready.
+    pub fn on(&mut self) {
+        self.set_mode(Mode::Internal);
+        self.wait_ready();
+    }
+
+    /// Turns off buffer, pulling VREF+ pin down.
+    pub fn off(&mut self) {
+        self.set_mode(Mode::Off);
+    }
+
+    /// Sets VREF+ pin in high impedance mode.
+    ///
+    /// Buffer state is not changed.
+    pub fn high_impedance(&mut self, value: bool) -> &mut Self {
+        self.inner.csr.modify(|_, w| w.hiz().bit(value));
+        self
+    }
+
+    #[inline]
+    /// Returns whether output voltage reached its expected value.
+    pub fn is_ready(&self) -> bool {
+        self.inner.csr.read().vrr().bit_is_set()
+    }
+
+    /// Waits until output voltage is ready.
+    pub fn wait_ready(&self) {
+        while !self.is_ready() {}
+    }
+
+    /// Consumes self and returns device's VREFBUF
+    pub fn into_raw(self) -> Inner {
+        self.inner
+    }
+}
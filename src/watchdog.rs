@@ -0,0 +1,165 @@
+//! Independent watchdog (IWDG)
+//!
+//! IWDG is clocked by LSI (32 kHz) and, once started, cannot be stopped until reset.
+//! Construct it [with_clocks](struct.IndependentWatchdog.html#method.with_clocks) to use
+//! LSI frequency recorded in `Clocks`.
+//!
+//! Watchdog keeps counting while core is halted by debugger, unless frozen with
+//! [DebugMcu](../debug/struct.DebugMcu.html).
+//!
+//! See Reference manual Ch. 37
+
+use embedded_hal::watchdog::{Watchdog, WatchdogEnable};
+use stm32l4::stm32l4x5::IWDG;
+
+use crate::rcc::Clocks;
+use crate::rcc::clocking::LSI_FREQ;
+use crate::time::{Hertz, MilliSeconds};
+
+/// Maximal value of reload register
+const MAX_RELOAD: u32 = 0xFFF;
+
+const KEY_START: u16 = 0xCCCC;
+const KEY_UNLOCK: u16 = 0x5555;
+const KEY_RELOAD: u16 = 0xAAAA;
+
+/// Maximal timeout in milliseconds with nominal LSI, reached with prescaler 256.
+pub const MAX_TIMEOUT_MS: u32 = (MAX_RELOAD + 1) * 256 / (LSI_FREQ / 1_000);
+
+/// Returns prescaler (divider is `4 << pr`) and reload values for timeout of `ms` with `lsi` clock.
+///
+/// Reload is limited by `MAX_RELOAD`, so too long timeouts are shortened.
+fn prescaler_reload(ms: u32, lsi: u32) -> (u8, u32) {
+    // LSI cycles of timeout, wide enough for any `ms`
+    let cycles = ms as u64 * lsi as u64 / 1_000;
+    let mut pr = 0;
+    while pr < 6 && cycles / (4 << pr) > MAX_RELOAD as u64 {
+        pr += 1;
+    }
+    (pr, core::cmp::min(cycles / (4 << pr), MAX_RELOAD as u64) as u32)
+}
+
+/// Independent watchdog
+pub struct IndependentWatchdog {
+    iwdg: IWDG,
+    timeout: u32,
+    lsi: u32,
+}
+
+impl IndependentWatchdog {
+    /// Wraps IWDG peripheral. Watchdog is not started.
+    pub fn new(iwdg: IWDG) -> Self {
+        Self {
+            iwdg,
+            timeout: 0,
+            lsi: LSI_FREQ,
+        }
+    }
+
+    /// Wraps IWDG peripheral, computing timeouts from LSI frequency in `clocks`.
+    ///
+    /// `clocks` holds only nominal LSI frequency, so actual timeouts may differ by several
+    /// percent; use [with_lsi](#method.with_lsi) with measured frequency for accurate ones.
+    pub fn with_clocks(iwdg: IWDG, clocks: &Clocks) -> Self {
+        Self {
+            iwdg,
+            timeout: 0,
+            lsi: clocks.lsi().map_or(LSI_FREQ, |freq| freq.0),
+        }
+    }
+
+    /// Wraps IWDG peripheral, computing timeouts from measured `lsi` frequency,
+    /// see [measure_lsi](../timer/calibration/fn.measure_lsi.html).
+    pub fn with_lsi(iwdg: IWDG, lsi: Hertz) -> Self {
+        Self {
+            iwdg,
+            timeout: 0,
+            lsi: lsi.0,
+        }
+    }
+
+    /// Returns currently configured timeout in milliseconds.
+    pub fn timeout(&self) -> u32 {
+        self.timeout
+    }
+
+    /// Changes timeout of the watchdog, it is applied immediately with reload.
+    ///
+    /// Timeout is limited by `MAX_TIMEOUT_MS`.
+    pub fn set_timeout(&mut self, ms: u32) {
+        let (pr, reload) = prescaler_reload(ms, self.lsi);
+        self.write_prescaler_reload(pr, reload);
+    }
+
+    /// Reads prescaler and reload value in effect, once pending updates complete.
+    ///
+    /// Unlike `timeout`, these reflect watchdog started by option bytes or before wrapping.
+    fn read_prescaler_reload(&self) -> (u8, u32) {
+        while self.iwdg.sr.read().pvu().bit_is_set() || self.iwdg.sr.read().rvu().bit_is_set() {}
+        (self.iwdg.pr.read().pr().bits(), self.iwdg.rlr.read().rl().bits() as u32)
+    }
+
+    fn write_prescaler_reload(&mut self, pr: u8, reload: u32) {
+        self.iwdg.kr.write(|w| unsafe { w.key().bits(KEY_UNLOCK) });
+        self.iwdg.pr.write(|w| w.pr().bits(pr));
+        self.iwdg.rlr.write(|w| w.rl().bits(reload as u16));
+        while self.iwdg.sr.read().pvu().bit_is_set() || self.iwdg.sr.read().rvu().bit_is_set() {}
+
+        self.timeout = ((reload as u64 + 1) * (4 << pr) * 1_000 / self.lsi as u64) as u32;
+        self.feed();
+    }
+
+    /// Reloads counter, preventing reset.
+    #[inline]
+    pub fn feed(&mut self) {
+        self.iwdg.kr.write(|w| unsafe { w.key().bits(KEY_RELOAD) });
+    }
+}
+
+impl WatchdogEnable for IndependentWatchdog {
+    type Time = MilliSeconds;
+
+    fn start<T: Into<MilliSeconds>>(&mut self, period: T) {
+        // Start first, as it also enables LSI
+        self.iwdg.kr.write(|w| unsafe { w.key().bits(KEY_START) });
+        self.set_timeout(period.into().0);
+    }
+}
+
+impl Watchdog for IndependentWatchdog {
+    fn feed(&mut self) {
+        IndependentWatchdog::feed(self)
+    }
+}
+
+/// Runs long blocking operation `op` with watchdog timeout temporarily raised to `timeout` ms.
+///
+/// Watchdog is fed before and after operation, and original timeout is restored.
+/// It is read back from registers, so timeout of watchdog started by option bytes is kept too.
+/// `op` gets access to watchdog, so it can feed it between steps.
+pub fn with_watchdog<R, F: FnOnce(&mut IndependentWatchdog) -> R>(watchdog: &mut IndependentWatchdog, timeout: u32, op: F) -> R {
+    let (pr, reload) = watchdog.read_prescaler_reload();
+
+    // Feed first, so old timeout doesn't expire while new one is being written
+    watchdog.feed();
+    watchdog.set_timeout(timeout);
+    let result = op(watchdog);
+    watchdog.feed();
+    watchdog.write_prescaler_reload(pr, reload);
+
+    result
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn prescaler_reload_of_timeout() {
+        assert_eq!(prescaler_reload(100, LSI_FREQ), (0, 800));
+        assert_eq!(prescaler_reload(1_000, LSI_FREQ), (1, 4_000));
+        assert_eq!(prescaler_reload(MAX_TIMEOUT_MS, LSI_FREQ), (6, MAX_RELOAD));
+        // ms * kHz doesn't fit into u32
+        assert_eq!(prescaler_reload(u32::MAX, LSI_FREQ), (6, MAX_RELOAD));
+    }
+}